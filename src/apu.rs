@@ -0,0 +1,439 @@
+//! This module contains the [Apu] struct, which emulates the audio processing unit (APU) of the
+//! Game Boy. For more information, please refer to
+//! [Pan Docs - Audio Details](https://gbdev.io/pandocs/Audio_details.html).
+//!
+//! So far, no samples are generated. Only the frame sequencer is emulated, which clocks the
+//! length counters and the volume envelopes of the channels (see [channel]), as well as the
+//! frequency sweep of channel 1 (see [sweep]). Whether a channel is enabled can be read from
+//! NR52, which is what games use the length counters for.
+//!
+//! The frame sequencer is not a free-running counter, but is clocked by the falling edge of bit 4
+//! of the divider register (DIV 0xFF04), that is, at 512 Hz. Since resetting DIV by writing to it
+//! can cause such a falling edge as well, writing DIV shifts the timing of the frame sequencer,
+//! which some games rely on. See
+//! [Pan Docs - DIV-APU](https://gbdev.io/pandocs/Audio_details.html#div-apu).
+
+mod channel;
+mod sweep;
+
+use crate::RustBoyError;
+use crate::memory_bus::MemoryBus;
+use crate::save_state::{StateReader, StateWriter};
+use channel::{Channel, LENGTH_ENABLE_BIT, TRIGGER_BIT};
+use sweep::{FrequencySweep, MAX_FREQUENCY};
+
+/// The address of the first register of the sound channels (NR10).
+pub(crate) const SOUND_CHANNEL_REGISTERS_BEGIN: u16 = 0xFF10;
+/// The address of the last register before NR52, that is, the sound panning register (NR51).
+/// The registers up to this one are cleared when the APU is turned off.
+pub(crate) const SOUND_PANNING_REGISTER: u16 = 0xFF25;
+/// The address of the sound on/off register (NR52).
+pub(crate) const SOUND_ON_REGISTER: u16 = 0xFF26;
+/// The bit of NR52 which turns the APU on or off.
+const SOUND_ON_BIT: u8 = 0b1000_0000;
+/// The number of sound channels.
+const NUMBER_OF_CHANNELS: usize = 4;
+/// The number of registers of each channel (NRx0 - NRx4). Channels 2 and 4 have no NRx0, whose
+/// address is not mapped.
+const REGISTERS_PER_CHANNEL: u16 = 5;
+/// The index of the sweep register (NR10) among the registers of channel 1, which is the only
+/// channel with a frequency sweep.
+const SWEEP_REGISTER: u16 = 0;
+/// The index of the length register (NRx1) among the registers of a channel.
+const LENGTH_REGISTER: u16 = 1;
+/// The index of the volume register (NRx2) among the registers of a channel. For channel 3, the
+/// DAC is turned on and off by NR30 instead, see [Apu::dac_register].
+const VOLUME_REGISTER: u16 = 2;
+/// The index of the register holding the lower 8 bits of the frequency (NRx3) among the
+/// registers of a channel.
+const FREQUENCY_LOW_REGISTER: u16 = 3;
+/// The index of the control register (NRx4) among the registers of a channel.
+const CONTROL_REGISTER: u16 = 4;
+/// The bits of NRx4 which hold the upper 3 bits of the frequency.
+const FREQUENCY_HIGH_BITS: u8 = 0b0000_0111;
+/// The index of channel 1 (the pulse channel with the frequency sweep).
+const SWEEP_CHANNEL: usize = 0;
+/// The index of channel 3 (the wave channel), which has a longer length counter, no volume
+/// envelope and turns its DAC on and off using NR30.
+const WAVE_CHANNEL: usize = 2;
+/// The bit of NR30 which turns the DAC of channel 3 on.
+const WAVE_DAC_ON_BIT: u8 = 0b1000_0000;
+/// The bits of NRx2 which turn the DAC of channels 1, 2 and 4 off, if they are all clear, that is,
+/// the initial volume and the direction of the volume envelope.
+const DAC_ON_BITS: u8 = 0b1111_1000;
+/// The bit of the divider register whose falling edge clocks the frame sequencer. In double-speed
+/// mode of the Game Boy Color, this would be bit 5 instead, which is not emulated.
+const FRAME_SEQUENCER_DIVIDER_BIT: u8 = 0b0001_0000;
+/// The number of steps of the frame sequencer, after which it wraps around.
+const NUMBER_OF_FRAME_SEQUENCER_STEPS: u8 = 8;
+
+/// Struct to represent the state of the APU.
+///
+/// - `frame_sequencer_step`: The step the frame sequencer executes when it is clocked next
+///   (0 - 7). The length counters are clocked on the even steps, the frequency sweep on steps 2
+///   and 6 and the volume envelopes on step 7.
+/// - `channels`: The state of the four sound channels which is not part of their registers, see
+///   [Channel].
+/// - `sweep`: The state of the frequency sweep of channel 1, see [FrequencySweep].
+#[derive(Debug, Default)]
+pub(crate) struct Apu {
+    pub(crate) frame_sequencer_step: u8,
+    pub(crate) channels: [Channel; NUMBER_OF_CHANNELS],
+    sweep: FrequencySweep,
+}
+
+impl Apu {
+    /// Returns the state of the APU after the boot ROM ran. The boot ROM plays its sound on
+    /// channel 1, which stays enabled at volume 0 afterward, see
+    /// [Pan Docs - Power up Sequence](https://gbdev.io/pandocs/Power_Up_Sequence.html#hardware-registers).
+    pub(crate) fn after_boot() -> Apu {
+        let mut apu = Apu::default();
+        apu.channels[0].enabled = true;
+        apu
+    }
+
+    /// Writes the state of the APU to the provided save state. The registers are part of the
+    /// memory, which is saved by [MemoryBus::save_state].
+    pub(crate) fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_u8(self.frame_sequencer_step);
+        for channel in &self.channels {
+            channel.save_state(writer);
+        }
+        self.sweep.save_state(writer);
+    }
+
+    /// Restores the state of the APU written by [Apu::save_state].
+    pub(crate) fn load_state(&mut self, reader: &mut StateReader) -> Result<(), RustBoyError> {
+        self.frame_sequencer_step = reader.read_u8()? % NUMBER_OF_FRAME_SEQUENCER_STEPS;
+        for channel in &mut self.channels {
+            channel.load_state(reader)?;
+        }
+        self.sweep.load_state(reader)?;
+        Ok(())
+    }
+
+    /// Returns the address of the register with the provided index (see e.g. [LENGTH_REGISTER])
+    /// of the provided channel.
+    fn channel_register(channel_index: usize, register: u16) -> u16 {
+        SOUND_CHANNEL_REGISTERS_BEGIN + channel_index as u16 * REGISTERS_PER_CHANNEL + register
+    }
+
+    /// Returns the address of the register which turns the DAC of the provided channel on and
+    /// off, that is, NR30 for channel 3 and NRx2 for the other channels.
+    fn dac_register(channel_index: usize) -> u16 {
+        if channel_index == WAVE_CHANNEL {
+            Apu::channel_register(channel_index, 0)
+        } else {
+            Apu::channel_register(channel_index, VOLUME_REGISTER)
+        }
+    }
+
+    /// Returns the maximum length of the provided channel, which is 256 for channel 3 and 64 for
+    /// the other channels.
+    fn max_length(channel_index: usize) -> u16 {
+        if channel_index == WAVE_CHANNEL {
+            256
+        } else {
+            64
+        }
+    }
+}
+
+impl MemoryBus {
+    /// Handles a change of the divider register (DIV 0xFF04) from `old_divider` to
+    /// `new_divider`, either because it was incremented or because it was reset by a write. If
+    /// [FRAME_SEQUENCER_DIVIDER_BIT] went from 1 to 0, the frame sequencer is clocked, unless the
+    /// APU is turned off.
+    pub(crate) fn handle_divider_change(&mut self, old_divider: u8, new_divider: u8) {
+        let falling_edge = old_divider & FRAME_SEQUENCER_DIVIDER_BIT != 0
+            && new_divider & FRAME_SEQUENCER_DIVIDER_BIT == 0;
+        if falling_edge && self.sound_is_on() {
+            self.clock_frame_sequencer();
+        }
+    }
+
+    /// Executes the current step of the frame sequencer and advances it to the next step. The
+    /// length counters are clocked on the even steps, the frequency sweep on steps 2 and 6 and the
+    /// volume envelopes on step 7.
+    fn clock_frame_sequencer(&mut self) {
+        let step = self.apu.frame_sequencer_step;
+        if step == 2 || step == 6 {
+            self.clock_sweep();
+        }
+        for channel_index in 0..NUMBER_OF_CHANNELS {
+            if step.is_multiple_of(2) {
+                let length_enabled = self.memory
+                    [Apu::channel_register(channel_index, CONTROL_REGISTER) as usize]
+                    & LENGTH_ENABLE_BIT
+                    != 0;
+                self.apu.channels[channel_index].clock_length(length_enabled);
+            }
+            if step == 7 && channel_index != WAVE_CHANNEL {
+                self.apu.channels[channel_index].clock_envelope();
+            }
+        }
+        self.apu.frame_sequencer_step = (step + 1) % NUMBER_OF_FRAME_SEQUENCER_STEPS;
+    }
+
+    /// Clocks the frequency sweep of channel 1, see [FrequencySweep::clock]. A new frequency is
+    /// written to NR13 and NR14 and an overflow disables channel 1.
+    fn clock_sweep(&mut self) {
+        let sweep_register =
+            self.memory[Apu::channel_register(SWEEP_CHANNEL, SWEEP_REGISTER) as usize];
+        let sweep_step = self.apu.sweep.clock(sweep_register);
+        if let Some(frequency) = sweep_step.new_frequency {
+            let low_address = Apu::channel_register(SWEEP_CHANNEL, FREQUENCY_LOW_REGISTER);
+            let high_address = Apu::channel_register(SWEEP_CHANNEL, CONTROL_REGISTER);
+            self.memory[low_address as usize] = frequency as u8;
+            self.memory[high_address as usize] =
+                self.memory[high_address as usize] & !FREQUENCY_HIGH_BITS | (frequency >> 8) as u8;
+        }
+        if sweep_step.overflowed {
+            self.apu.channels[SWEEP_CHANNEL].enabled = false;
+        }
+    }
+
+    /// Returns the frequency (period value) of channel 1, which is split across NR13 (lower 8
+    /// bits) and NR14 (upper 3 bits).
+    fn sweep_channel_frequency(&self) -> u16 {
+        let low =
+            self.memory[Apu::channel_register(SWEEP_CHANNEL, FREQUENCY_LOW_REGISTER) as usize];
+        let high = self.memory[Apu::channel_register(SWEEP_CHANNEL, CONTROL_REGISTER) as usize]
+            & FREQUENCY_HIGH_BITS;
+        (u16::from(high) << 8 | u16::from(low)) & MAX_FREQUENCY
+    }
+
+    /// Returns true if the APU is turned on, see [SOUND_ON_BIT].
+    fn sound_is_on(&self) -> bool {
+        self.memory[SOUND_ON_REGISTER as usize] & SOUND_ON_BIT != 0
+    }
+
+    /// Reads the sound on/off register (NR52 0xFF26). Besides the bit turning the APU on, the
+    /// lower 4 bits report which of the channels are enabled. They are read-only.
+    pub(super) fn read_sound_on_register(&self) -> u8 {
+        let channels_enabled = self
+            .apu
+            .channels
+            .iter()
+            .enumerate()
+            .filter(|(_, channel)| channel.enabled)
+            .fold(0, |bits, (channel_index, _)| bits | 1 << channel_index);
+        self.memory[SOUND_ON_REGISTER as usize] & SOUND_ON_BIT | channels_enabled
+    }
+
+    /// Writes the sound on/off register (NR52 0xFF26). When the APU is turned on, the frame
+    /// sequencer is reset, such that its next step is step 0. When it is turned off, all channels
+    /// are disabled and the registers NR10 - NR51 are cleared. Only the bit turning the APU on is
+    /// writable.
+    pub(super) fn write_sound_on_register(&mut self, value: u8) {
+        let sound_was_on = self.sound_is_on();
+        if !sound_was_on && value & SOUND_ON_BIT != 0 {
+            self.apu.frame_sequencer_step = 0;
+        }
+        if sound_was_on && value & SOUND_ON_BIT == 0 {
+            self.memory[SOUND_CHANNEL_REGISTERS_BEGIN as usize..=SOUND_PANNING_REGISTER as usize]
+                .fill(0);
+            for channel in &mut self.apu.channels {
+                channel.enabled = false;
+            }
+        }
+        self.memory[SOUND_ON_REGISTER as usize] = value & SOUND_ON_BIT;
+    }
+
+    /// Writes one of the registers NR10 - NR51 (0xFF10 - 0xFF25) and handles its effects on the
+    /// channels:
+    /// - Writing NRx1 reloads the length counter, see [Channel::load_length].
+    /// - Turning the DAC off (see [Apu::dac_register]) disables the channel.
+    /// - Writing NRx4 can trigger the channel and enable its length counter, see
+    ///   [Channel::write_control]. Triggering channel 1 restarts its frequency sweep, see
+    ///   [FrequencySweep::trigger].
+    /// - Clearing the negate bit of NR10 can disable channel 1, see
+    ///   [FrequencySweep::write_sweep_register].
+    ///
+    /// While the APU is turned off, the registers can not be written. Only the length counters
+    /// can still be reloaded, since they are not powered off on the DMG.
+    pub(super) fn write_sound_register(&mut self, address: u16, value: u8) {
+        let offset = address - SOUND_CHANNEL_REGISTERS_BEGIN;
+        let channel_index = (offset / REGISTERS_PER_CHANNEL) as usize;
+        let register = offset % REGISTERS_PER_CHANNEL;
+        let is_channel_register = channel_index < NUMBER_OF_CHANNELS;
+
+        if !self.sound_is_on() {
+            if is_channel_register && register == LENGTH_REGISTER {
+                self.apu.channels[channel_index].load_length(value, Apu::max_length(channel_index));
+            }
+            return;
+        }
+
+        let old_value = self.memory[address as usize];
+        self.memory[address as usize] = value;
+        if !is_channel_register {
+            return;
+        }
+
+        let dac_register = self.memory[Apu::dac_register(channel_index) as usize];
+        let dac_enabled = if channel_index == WAVE_CHANNEL {
+            dac_register & WAVE_DAC_ON_BIT != 0
+        } else {
+            dac_register & DAC_ON_BITS != 0
+        };
+        let channel = &mut self.apu.channels[channel_index];
+        if address == Apu::dac_register(channel_index) && !dac_enabled {
+            channel.enabled = false;
+        }
+        match register {
+            SWEEP_REGISTER
+                if channel_index == SWEEP_CHANNEL && self.apu.sweep.write_sweep_register(value) =>
+            {
+                channel.enabled = false;
+            }
+            LENGTH_REGISTER => channel.load_length(value, Apu::max_length(channel_index)),
+            CONTROL_REGISTER => channel.write_control(
+                value,
+                old_value & LENGTH_ENABLE_BIT != 0,
+                self.apu.frame_sequencer_step.is_multiple_of(2),
+                Apu::max_length(channel_index),
+                dac_enabled,
+                // Channel 3 has no volume envelope, its NR32 selects the output level instead
+                if channel_index == WAVE_CHANNEL {
+                    0
+                } else {
+                    self.memory[Apu::channel_register(channel_index, VOLUME_REGISTER) as usize]
+                },
+            ),
+            _ => {}
+        }
+
+        if channel_index == SWEEP_CHANNEL
+            && register == CONTROL_REGISTER
+            && value & TRIGGER_BIT != 0
+        {
+            let sweep_register =
+                self.memory[Apu::channel_register(SWEEP_CHANNEL, SWEEP_REGISTER) as usize];
+            let frequency = self.sweep_channel_frequency();
+            if self.apu.sweep.trigger(sweep_register, frequency) {
+                self.apu.channels[SWEEP_CHANNEL].enabled = false;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::debugging::DebugInfo;
+
+    /// The address of the divider register (DIV).
+    const DIVIDER_REGISTER_ADDRESS: u16 = 0xFF04;
+    /// The number of m-cycles between two falling edges of [FRAME_SEQUENCER_DIVIDER_BIT], that
+    /// is, between two clocks of the frame sequencer (512 Hz).
+    const M_CYCLES_PER_FRAME_SEQUENCER_CLOCK: u32 = 2048;
+
+    /// Returns a memory bus with the APU turned on, whose internal divider counter has the
+    /// provided value.
+    fn memory_bus_with_sound_on(divider_counter: u16) -> MemoryBus {
+        let mut memory_bus = MemoryBus::new_before_boot(&DebugInfo::default());
+        memory_bus.being_initialized = false;
+        memory_bus.write_byte(SOUND_ON_REGISTER, SOUND_ON_BIT);
+        memory_bus.timer_registers.divider_counter = divider_counter;
+        memory_bus
+    }
+
+    #[test]
+    fn frame_sequencer_is_clocked_by_falling_edges_of_the_divider() {
+        let mut memory_bus = memory_bus_with_sound_on(0x0000);
+        memory_bus.handle_timer(M_CYCLES_PER_FRAME_SEQUENCER_CLOCK - 1);
+        assert_eq!(memory_bus.apu.frame_sequencer_step, 0);
+        memory_bus.handle_timer(1);
+        assert_eq!(memory_bus.apu.frame_sequencer_step, 1);
+        memory_bus.handle_timer(7 * M_CYCLES_PER_FRAME_SEQUENCER_CLOCK);
+        assert_eq!(memory_bus.apu.frame_sequencer_step, 0);
+    }
+
+    #[test]
+    fn writing_the_divider_while_bit_4_is_set_clocks_the_frame_sequencer() {
+        // Bit 4 of DIV is set, so the next falling edge would be 1024 m-cycles away
+        let mut memory_bus = memory_bus_with_sound_on(0x1000);
+        memory_bus.write_byte(DIVIDER_REGISTER_ADDRESS, 0);
+        memory_bus.handle_timer(0);
+        assert_eq!(memory_bus.apu.frame_sequencer_step, 1);
+        // Afterward, the divider starts over, which shifts the timing of the following clocks
+        memory_bus.handle_timer(M_CYCLES_PER_FRAME_SEQUENCER_CLOCK - 1);
+        assert_eq!(memory_bus.apu.frame_sequencer_step, 1);
+        memory_bus.handle_timer(1);
+        assert_eq!(memory_bus.apu.frame_sequencer_step, 2);
+    }
+
+    #[test]
+    fn writing_the_divider_while_bit_4_is_clear_does_not_clock_the_frame_sequencer() {
+        let mut memory_bus = memory_bus_with_sound_on(0x0F00);
+        memory_bus.write_byte(DIVIDER_REGISTER_ADDRESS, 0);
+        memory_bus.handle_timer(0);
+        assert_eq!(memory_bus.apu.frame_sequencer_step, 0);
+    }
+
+    #[test]
+    fn frame_sequencer_is_not_clocked_while_the_apu_is_off() {
+        let mut memory_bus = memory_bus_with_sound_on(0x0000);
+        memory_bus.write_byte(SOUND_ON_REGISTER, 0);
+        memory_bus.handle_timer(4 * M_CYCLES_PER_FRAME_SEQUENCER_CLOCK);
+        assert_eq!(memory_bus.apu.frame_sequencer_step, 0);
+    }
+
+    #[test]
+    fn panning_register_is_cleared_and_read_only_while_the_apu_is_off() {
+        // There is no mixer yet, so NR51 only holds the panning until one uses it
+        let mut memory_bus = memory_bus_with_sound_on(0x0000);
+        memory_bus.write_byte(SOUND_PANNING_REGISTER, 0b1010_0101);
+        assert_eq!(memory_bus.read_byte(SOUND_PANNING_REGISTER), 0b1010_0101);
+
+        memory_bus.write_byte(SOUND_ON_REGISTER, 0);
+        assert_eq!(memory_bus.read_byte(SOUND_PANNING_REGISTER), 0);
+        memory_bus.write_byte(SOUND_PANNING_REGISTER, 0xFF);
+        assert_eq!(memory_bus.read_byte(SOUND_PANNING_REGISTER), 0);
+
+        memory_bus.write_byte(SOUND_ON_REGISTER, SOUND_ON_BIT);
+        memory_bus.write_byte(SOUND_PANNING_REGISTER, 0xFF);
+        assert_eq!(memory_bus.read_byte(SOUND_PANNING_REGISTER), 0xFF);
+    }
+
+    #[test]
+    fn nr52_reports_a_channel_as_disabled_once_its_length_expires() {
+        let mut memory_bus = memory_bus_with_sound_on(0x0000);
+        // Channel 1 with the DAC on and a length of 2, triggered with the length counter enabled
+        memory_bus.write_byte(Apu::channel_register(0, VOLUME_REGISTER), 0xF0);
+        memory_bus.write_byte(Apu::channel_register(0, LENGTH_REGISTER), 62);
+        memory_bus.write_byte(
+            Apu::channel_register(0, CONTROL_REGISTER),
+            TRIGGER_BIT | LENGTH_ENABLE_BIT,
+        );
+        assert_eq!(memory_bus.read_byte(SOUND_ON_REGISTER) & 0x0F, 0b0001);
+
+        // The length counters are clocked on steps 0 and 2
+        memory_bus.handle_timer(2 * M_CYCLES_PER_FRAME_SEQUENCER_CLOCK);
+        assert_eq!(memory_bus.read_byte(SOUND_ON_REGISTER) & 0x0F, 0b0001);
+        memory_bus.handle_timer(M_CYCLES_PER_FRAME_SEQUENCER_CLOCK);
+        assert_eq!(memory_bus.read_byte(SOUND_ON_REGISTER) & 0x0F, 0);
+    }
+
+    #[test]
+    fn frequency_sweep_writes_the_new_frequency_to_nr13_and_nr14() {
+        let mut memory_bus = memory_bus_with_sound_on(0x0000);
+        // Period 1, shift 2: 0x400 + 0x100 = 0x500
+        memory_bus.write_byte(Apu::channel_register(SWEEP_CHANNEL, SWEEP_REGISTER), 0x12);
+        memory_bus.write_byte(Apu::channel_register(SWEEP_CHANNEL, VOLUME_REGISTER), 0xF0);
+        memory_bus.write_byte(
+            Apu::channel_register(SWEEP_CHANNEL, FREQUENCY_LOW_REGISTER),
+            0x00,
+        );
+        memory_bus.write_byte(
+            Apu::channel_register(SWEEP_CHANNEL, CONTROL_REGISTER),
+            TRIGGER_BIT | 0x04,
+        );
+
+        // The sweep is clocked on step 2
+        memory_bus.handle_timer(3 * M_CYCLES_PER_FRAME_SEQUENCER_CLOCK);
+        assert_eq!(memory_bus.sweep_channel_frequency(), 0x500);
+        assert_eq!(memory_bus.read_byte(SOUND_ON_REGISTER) & 0x0F, 0b0001);
+    }
+}
@@ -0,0 +1,273 @@
+//! This module contains the [Channel] struct, which holds the state of a sound channel which is
+//! not part of its registers: whether it is enabled, its length counter and its volume envelope.
+//! For more information, please refer to
+//! [Pan Docs - Audio Registers](https://gbdev.io/pandocs/Audio_Registers.html) and
+//! [Pan Docs - Audio Details](https://gbdev.io/pandocs/Audio_details.html).
+
+use crate::RustBoyError;
+use crate::save_state::{StateReader, StateWriter};
+
+/// The bit of NRx4 which triggers (restarts) the channel.
+pub(super) const TRIGGER_BIT: u8 = 0b1000_0000;
+/// The bit of NRx4 which enables the length counter, that is, which makes the channel turn off
+/// once its length expired.
+pub(super) const LENGTH_ENABLE_BIT: u8 = 0b0100_0000;
+/// The bit of NRx2 which selects whether the volume envelope increases (if set) or decreases
+/// (if clear) the volume.
+const ENVELOPE_INCREASE_BIT: u8 = 0b0000_1000;
+/// The bits of NRx2 which hold the period of the volume envelope in ticks of 64 Hz.
+const ENVELOPE_PERIOD_BITS: u8 = 0b0000_0111;
+/// The maximum volume of a channel.
+const MAX_VOLUME: u8 = 0x0F;
+
+/// Struct to represent the state of a sound channel.
+///
+/// - `enabled`: Whether the channel is enabled, which is reported in NR52. A channel is enabled
+///   by triggering it, if its DAC is on, and disabled once its length expires or its DAC is
+///   turned off.
+/// - `length_counter`: The number of ticks of 256 Hz until the channel is disabled, if the length
+///   counter is enabled (NRx4 bit 6). Is reloaded by writing NRx1.
+/// - `volume`: The current volume of the channel (0 - 15), which is set to the initial volume of
+///   NRx2 when triggered and then adjusted by the volume envelope. Is not used by channel 3,
+///   which has no volume envelope.
+/// - `envelope_period`: The period of the volume envelope, as latched from NRx2 when triggered.
+///   If 0, the volume envelope is stopped.
+/// - `envelope_increases`: Whether the volume envelope increases the volume, as latched from NRx2
+///   when triggered.
+/// - `envelope_timer`: The number of ticks of 64 Hz until the volume envelope adjusts the volume
+///   next.
+#[derive(Clone, Copy, Debug, Default)]
+pub(crate) struct Channel {
+    pub(crate) enabled: bool,
+    length_counter: u16,
+    pub(crate) volume: u8,
+    envelope_period: u8,
+    envelope_increases: bool,
+    envelope_timer: u8,
+}
+
+impl Channel {
+    /// Reloads the length counter from the length written to NRx1, such that the channel is
+    /// disabled after `max_length - length` ticks. `max_length` is 256 for channel 3 and 64 for
+    /// the other channels, whose lengths use only the lower 6 bits of NRx1.
+    pub(super) fn load_length(&mut self, value: u8, max_length: u16) {
+        self.length_counter = max_length - (value as u16 & (max_length - 1));
+    }
+
+    /// Handles a write of the provided value to NRx4. `length_was_enabled` is the length enable
+    /// bit before the write and `next_step_clocks_length` whether the next step of the frame
+    /// sequencer clocks the length counters. `dac_enabled` is whether the DAC of the channel is on
+    /// and `envelope_register` is the value of NRx2.
+    ///
+    /// Besides triggering the channel, this emulates two quirks of the length counter, see
+    /// [Pan Docs - Obscure Behavior](https://gbdev.io/pandocs/Audio_details.html#obscure-behavior):
+    /// - Enabling the length counter while the next step of the frame sequencer does not clock it
+    ///   clocks it once right away. If this makes it expire and the channel is not triggered, the
+    ///   channel is disabled.
+    /// - Triggering the channel while its length expired reloads it with `max_length`, which is
+    ///   clocked once right away under the same condition.
+    pub(super) fn write_control(
+        &mut self,
+        value: u8,
+        length_was_enabled: bool,
+        next_step_clocks_length: bool,
+        max_length: u16,
+        dac_enabled: bool,
+        envelope_register: u8,
+    ) {
+        let length_enabled = value & LENGTH_ENABLE_BIT != 0;
+        let triggered = value & TRIGGER_BIT != 0;
+        let extra_length_clock = length_enabled && !next_step_clocks_length;
+
+        if extra_length_clock && !length_was_enabled && self.length_counter > 0 {
+            self.length_counter -= 1;
+            if self.length_counter == 0 && !triggered {
+                self.enabled = false;
+            }
+        }
+
+        if triggered {
+            self.enabled = dac_enabled;
+            if self.length_counter == 0 {
+                self.length_counter = max_length;
+                if extra_length_clock {
+                    self.length_counter -= 1;
+                }
+            }
+            self.volume = envelope_register >> 4;
+            self.envelope_period = envelope_register & ENVELOPE_PERIOD_BITS;
+            self.envelope_increases = envelope_register & ENVELOPE_INCREASE_BIT != 0;
+            self.envelope_timer = self.envelope_period;
+        }
+    }
+
+    /// Clocks the length counter, which is done by the frame sequencer at 256 Hz. If the length
+    /// counter is enabled (see [LENGTH_ENABLE_BIT]) and expires, the channel is disabled.
+    pub(super) fn clock_length(&mut self, length_enabled: bool) {
+        if length_enabled && self.length_counter > 0 {
+            self.length_counter -= 1;
+            if self.length_counter == 0 {
+                self.enabled = false;
+            }
+        }
+    }
+
+    /// Clocks the volume envelope, which is done by the frame sequencer at 64 Hz. Every
+    /// `envelope_period` ticks, the volume is increased or decreased by 1, until it reaches 15 or
+    /// 0 respectively.
+    pub(super) fn clock_envelope(&mut self) {
+        if self.envelope_period == 0 {
+            return;
+        }
+        self.envelope_timer = self.envelope_timer.saturating_sub(1);
+        if self.envelope_timer == 0 {
+            self.envelope_timer = self.envelope_period;
+            if self.envelope_increases && self.volume < MAX_VOLUME {
+                self.volume += 1;
+            } else if !self.envelope_increases && self.volume > 0 {
+                self.volume -= 1;
+            }
+        }
+    }
+
+    /// Writes the state of the channel to the provided save state.
+    pub(super) fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_bool(self.enabled);
+        writer.write_u16(self.length_counter);
+        writer.write_u8(self.volume);
+        writer.write_u8(self.envelope_period);
+        writer.write_bool(self.envelope_increases);
+        writer.write_u8(self.envelope_timer);
+    }
+
+    /// Restores the state of the channel written by [Channel::save_state].
+    pub(super) fn load_state(&mut self, reader: &mut StateReader) -> Result<(), RustBoyError> {
+        self.enabled = reader.read_bool()?;
+        self.length_counter = reader.read_u16()?;
+        self.volume = reader.read_u8()? & MAX_VOLUME;
+        self.envelope_period = reader.read_u8()? & ENVELOPE_PERIOD_BITS;
+        self.envelope_increases = reader.read_bool()?;
+        self.envelope_timer = reader.read_u8()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The maximum length of the channels other than channel 3.
+    const MAX_LENGTH: u16 = 64;
+
+    /// Returns a channel which was triggered with the provided value of NRx2 and the length
+    /// counter loaded with the provided value of NRx1 and enabled, while the next step of the
+    /// frame sequencer clocks the length counters.
+    fn triggered_channel(length: u8, envelope_register: u8) -> Channel {
+        let mut channel = Channel::default();
+        channel.load_length(length, MAX_LENGTH);
+        channel.write_control(
+            TRIGGER_BIT | LENGTH_ENABLE_BIT,
+            false,
+            true,
+            MAX_LENGTH,
+            true,
+            envelope_register,
+        );
+        channel
+    }
+
+    #[test]
+    fn channel_is_disabled_once_its_length_expires() {
+        let mut channel = triggered_channel(62, 0xF0);
+        assert!(channel.enabled);
+        channel.clock_length(true);
+        assert!(channel.enabled);
+        channel.clock_length(true);
+        assert!(!channel.enabled);
+    }
+
+    #[test]
+    fn length_counter_is_only_clocked_while_enabled() {
+        let mut channel = triggered_channel(63, 0xF0);
+        channel.clock_length(false);
+        assert!(channel.enabled);
+        channel.clock_length(true);
+        assert!(!channel.enabled);
+    }
+
+    #[test]
+    fn enabling_the_length_counter_can_clock_it_right_away() {
+        let mut channel = triggered_channel(62, 0xF0);
+        // Disable the length counter and enable it again while the next step of the frame
+        // sequencer does not clock it
+        channel.write_control(0, true, false, MAX_LENGTH, true, 0xF0);
+        channel.write_control(LENGTH_ENABLE_BIT, false, false, MAX_LENGTH, true, 0xF0);
+        assert!(channel.enabled);
+        channel.clock_length(true);
+        assert!(!channel.enabled);
+
+        // If the next step clocks it anyway, there is no extra clock
+        let mut channel = triggered_channel(62, 0xF0);
+        channel.write_control(0, true, true, MAX_LENGTH, true, 0xF0);
+        channel.write_control(LENGTH_ENABLE_BIT, false, true, MAX_LENGTH, true, 0xF0);
+        channel.clock_length(true);
+        assert!(channel.enabled);
+    }
+
+    #[test]
+    fn triggering_with_an_expired_length_reloads_the_maximum_length() {
+        let mut channel = triggered_channel(63, 0xF0);
+        channel.clock_length(true);
+        assert!(!channel.enabled);
+
+        channel.write_control(
+            TRIGGER_BIT | LENGTH_ENABLE_BIT,
+            true,
+            true,
+            MAX_LENGTH,
+            true,
+            0xF0,
+        );
+        for _ in 0..MAX_LENGTH - 1 {
+            channel.clock_length(true);
+        }
+        assert!(channel.enabled);
+        channel.clock_length(true);
+        assert!(!channel.enabled);
+    }
+
+    #[test]
+    fn triggering_does_not_enable_the_channel_while_its_dac_is_off() {
+        let mut channel = Channel::default();
+        channel.write_control(TRIGGER_BIT, false, true, MAX_LENGTH, false, 0x00);
+        assert!(!channel.enabled);
+    }
+
+    #[test]
+    fn volume_envelope_adjusts_the_volume_every_period() {
+        // Initial volume 2, decreasing with a period of 2
+        let mut channel = triggered_channel(0, 0x22);
+        assert_eq!(channel.volume, 2);
+        let mut volumes = Vec::new();
+        for _ in 0..6 {
+            channel.clock_envelope();
+            volumes.push(channel.volume);
+        }
+        assert_eq!(volumes, [2, 1, 1, 0, 0, 0]);
+
+        // Initial volume 14, increasing with a period of 1
+        let mut channel = triggered_channel(0, 0xE9);
+        channel.clock_envelope();
+        channel.clock_envelope();
+        assert_eq!(channel.volume, MAX_VOLUME);
+    }
+
+    #[test]
+    fn volume_envelope_with_a_period_of_0_is_stopped() {
+        let mut channel = triggered_channel(0, 0x78);
+        for _ in 0..16 {
+            channel.clock_envelope();
+        }
+        assert_eq!(channel.volume, 7);
+    }
+}
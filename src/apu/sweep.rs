@@ -0,0 +1,201 @@
+//! This module contains the [FrequencySweep] struct, which emulates the frequency sweep unit of
+//! channel 1. It periodically shifts the frequency of the channel up or down, which games use for
+//! sliding sound effects (e.g. jumps and power-ups). It is configured by NR10 (0xFF10), see
+//! [Pan Docs - Audio Registers](https://gbdev.io/pandocs/Audio_Registers.html#ff10--nr10-channel-1-sweep)
+//! and [Pan Docs - Obscure Behavior](https://gbdev.io/pandocs/Audio_details.html#obscure-behavior).
+
+use crate::RustBoyError;
+use crate::save_state::{StateReader, StateWriter};
+
+/// The bits of NR10 which hold the period of the sweep in ticks of 128 Hz.
+const PERIOD_BITS: u8 = 0b0111_0000;
+/// The bit of NR10 which selects whether the sweep decreases (if set) or increases (if clear)
+/// the frequency.
+pub(super) const NEGATE_BIT: u8 = 0b0000_1000;
+/// The bits of NR10 which hold the shift, by which the frequency is shifted right to compute the
+/// change of the frequency.
+const SHIFT_BITS: u8 = 0b0000_0111;
+/// The highest frequency (period value) channel 1 can have. If the sweep computes a higher one,
+/// the channel is disabled.
+pub(super) const MAX_FREQUENCY: u16 = 2047;
+
+/// The result of clocking the sweep, see [FrequencySweep::clock].
+///
+/// - `new_frequency`: The frequency the sweep set, which has to be written to NR13 and NR14, if
+///   any.
+/// - `overflowed`: Whether a computed frequency exceeded [MAX_FREQUENCY], in which case channel 1
+///   has to be disabled.
+#[derive(Debug, Default, PartialEq)]
+pub(super) struct SweepStep {
+    pub(super) new_frequency: Option<u16>,
+    pub(super) overflowed: bool,
+}
+
+/// Struct to represent the state of the frequency sweep which is not part of NR10.
+///
+/// - `enabled`: Whether the sweep is enabled, which it is if channel 1 was triggered with a
+///   non-zero period or shift.
+/// - `shadow_frequency`: The copy of the frequency of channel 1 the sweep computes the next
+///   frequency from. It is set when channel 1 is triggered.
+/// - `timer`: The number of ticks of 128 Hz until the sweep computes the next frequency.
+/// - `negate_used`: Whether a frequency was computed in negate mode since channel 1 was last
+///   triggered. If so, clearing the negate bit of NR10 disables channel 1.
+#[derive(Clone, Copy, Debug, Default)]
+pub(crate) struct FrequencySweep {
+    enabled: bool,
+    shadow_frequency: u16,
+    timer: u8,
+    negate_used: bool,
+}
+
+impl FrequencySweep {
+    /// Restarts the sweep when channel 1 is triggered with the provided frequency. If the shift
+    /// is non-zero, the next frequency is computed right away to check it for an overflow.
+    ///
+    /// Returns true if the computed frequency overflowed, in which case channel 1 has to be
+    /// disabled.
+    pub(super) fn trigger(&mut self, sweep_register: u8, frequency: u16) -> bool {
+        self.shadow_frequency = frequency;
+        self.timer = timer_period(sweep_register);
+        self.negate_used = false;
+        let shift = sweep_register & SHIFT_BITS;
+        self.enabled = sweep_register & PERIOD_BITS != 0 || shift != 0;
+        shift != 0 && self.compute_frequency(sweep_register) > MAX_FREQUENCY
+    }
+
+    /// Clocks the sweep, which is done by the frame sequencer at 128 Hz. Once the timer expires
+    /// and the period is non-zero, the next frequency is computed. If it does not overflow and the
+    /// shift is non-zero, it becomes the new frequency of channel 1 and is immediately computed
+    /// again to check for an overflow, without storing the result.
+    pub(super) fn clock(&mut self, sweep_register: u8) -> SweepStep {
+        let mut sweep_step = SweepStep::default();
+        self.timer = self.timer.saturating_sub(1);
+        if self.timer > 0 {
+            return sweep_step;
+        }
+        self.timer = timer_period(sweep_register);
+        if !self.enabled || sweep_register & PERIOD_BITS == 0 {
+            return sweep_step;
+        }
+
+        let new_frequency = self.compute_frequency(sweep_register);
+        if new_frequency > MAX_FREQUENCY {
+            sweep_step.overflowed = true;
+        } else if sweep_register & SHIFT_BITS != 0 {
+            self.shadow_frequency = new_frequency;
+            sweep_step.new_frequency = Some(new_frequency);
+            sweep_step.overflowed = self.compute_frequency(sweep_register) > MAX_FREQUENCY;
+        }
+        sweep_step
+    }
+
+    /// Handles a write of the provided value to NR10. Returns true if the negate bit was cleared
+    /// after a frequency was computed in negate mode since channel 1 was last triggered, in which
+    /// case channel 1 has to be disabled.
+    pub(super) fn write_sweep_register(&self, value: u8) -> bool {
+        self.negate_used && value & NEGATE_BIT == 0
+    }
+
+    /// Computes the next frequency from the shadow frequency, which is shifted right by the shift
+    /// and then added to or subtracted from the shadow frequency.
+    fn compute_frequency(&mut self, sweep_register: u8) -> u16 {
+        let change = self.shadow_frequency >> (sweep_register & SHIFT_BITS);
+        if sweep_register & NEGATE_BIT != 0 {
+            self.negate_used = true;
+            self.shadow_frequency - change
+        } else {
+            self.shadow_frequency + change
+        }
+    }
+
+    /// Writes the state of the sweep to the provided save state.
+    pub(super) fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_bool(self.enabled);
+        writer.write_u16(self.shadow_frequency);
+        writer.write_u8(self.timer);
+        writer.write_bool(self.negate_used);
+    }
+
+    /// Restores the state of the sweep written by [FrequencySweep::save_state].
+    pub(super) fn load_state(&mut self, reader: &mut StateReader) -> Result<(), RustBoyError> {
+        self.enabled = reader.read_bool()?;
+        self.shadow_frequency = reader.read_u16()? & MAX_FREQUENCY;
+        self.timer = reader.read_u8()?;
+        self.negate_used = reader.read_bool()?;
+        Ok(())
+    }
+}
+
+/// Returns the number of ticks of 128 Hz between two computations of the sweep for the provided
+/// value of NR10. A period of 0 is treated as 8 by the timer.
+fn timer_period(sweep_register: u8) -> u8 {
+    match (sweep_register & PERIOD_BITS) >> 4 {
+        0 => 8,
+        period => period,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn trigger_disables_the_channel_if_the_first_frequency_overflows() {
+        let mut sweep = FrequencySweep::default();
+        // Period 1, shift 1
+        assert!(sweep.trigger(0x11, MAX_FREQUENCY));
+        // Without a shift, the frequency is not computed on trigger
+        assert!(!sweep.trigger(0x10, MAX_FREQUENCY));
+    }
+
+    #[test]
+    fn clock_writes_the_new_frequency_and_checks_the_next_one_for_an_overflow() {
+        let mut sweep = FrequencySweep::default();
+        // Period 2, shift 1: 0x500 + 0x280 = 0x780 does not overflow, but 0x780 + 0x3C0 does
+        assert!(!sweep.trigger(0x21, 0x500));
+        assert_eq!(sweep.clock(0x21), SweepStep::default());
+        assert_eq!(
+            sweep.clock(0x21),
+            SweepStep {
+                new_frequency: Some(0x780),
+                overflowed: true,
+            }
+        );
+    }
+
+    #[test]
+    fn negate_mode_decreases_the_frequency() {
+        let mut sweep = FrequencySweep::default();
+        // Period 1, negate, shift 2: 0x400 - 0x100 = 0x300
+        assert!(!sweep.trigger(0x1A, 0x400));
+        assert_eq!(
+            sweep.clock(0x1A),
+            SweepStep {
+                new_frequency: Some(0x300),
+                overflowed: false,
+            }
+        );
+    }
+
+    #[test]
+    fn clearing_negate_after_a_negate_mode_computation_disables_the_channel() {
+        let mut sweep = FrequencySweep::default();
+        assert!(!sweep.trigger(0x00, 0x400));
+        assert!(!sweep.write_sweep_register(0x00));
+
+        // Negate, shift 1, which computes a frequency on trigger
+        assert!(!sweep.trigger(0x09, 0x400));
+        assert!(!sweep.write_sweep_register(0x09));
+        assert!(sweep.write_sweep_register(0x01));
+    }
+
+    #[test]
+    fn a_period_of_0_does_not_compute_frequencies() {
+        let mut sweep = FrequencySweep::default();
+        // Shift 1, period 0
+        assert!(!sweep.trigger(0x01, 0x500));
+        for _ in 0..2 * timer_period(0x01) {
+            assert_eq!(sweep.clock(0x01), SweepStep::default());
+        }
+    }
+}
@@ -0,0 +1,186 @@
+//! This module contains the [Breakpoints] struct, which is used to pause the emulation once the
+//! CPU is about to execute the instruction at one of the provided addresses. With the `frontend`
+//! feature, hitting a breakpoint pauses the emulator and logs the registers of the CPU. The
+//! emulation can then be resumed by pressing `P`.
+//!
+//! Besides the breakpoints at fixed addresses, a temporary breakpoint is used to step over a
+//! subroutine call, see [RustBoy::start_step_over]. With the `frontend` feature, this is
+//! triggered by pressing `N` while the emulator is paused.
+
+use crate::RustBoy;
+use crate::cpu::instructions::Instruction;
+
+/// Struct to keep track of the breakpoints.
+///
+/// - `addresses`: The addresses of the instructions to break at.
+/// - `hit`: The address of the breakpoint that was hit and not yet handled, if any.
+/// - `resumed_at`: The address of the last breakpoint that was hit. Is used to not break at the
+///   same address again right away when the emulation is resumed. Is reset as soon as the program
+///   counter (PC) moves on.
+/// - `step_over`: The call which is currently stepped over, if any, see [StepOver].
+#[derive(Debug, Default)]
+pub(crate) struct Breakpoints {
+    addresses: Vec<u16>,
+    hit: Option<u16>,
+    resumed_at: Option<u16>,
+    step_over: Option<StepOver>,
+}
+
+/// Struct to represent the temporary breakpoint of a call (CALL or RST) which is stepped over.
+///
+/// - `return_address`: The address of the instruction after the call, which the subroutine
+///   returns to.
+/// - `stack_pointer`: The stack pointer (SP) before the call. A recursive call to the same
+///   subroutine returns to the same address, but with a lower stack pointer, since the return
+///   address of the outer call is still on the stack. So the breakpoint is only hit once the
+///   stack pointer is back at (or above) this value.
+#[derive(Clone, Copy, Debug)]
+struct StepOver {
+    return_address: u16,
+    stack_pointer: u16,
+}
+
+impl Breakpoints {
+    /// Returns true if the emulation should break before executing the instruction at the
+    /// provided program counter (PC), where `sp` is the current stack pointer (SP). In that case,
+    /// the breakpoint is marked as hit, see [RustBoy::take_breakpoint_hit].
+    pub(crate) fn should_break_at(&mut self, pc: u16, sp: u16) -> bool {
+        if (self.addresses.is_empty() && self.step_over.is_none()) || self.resumed_at == Some(pc) {
+            return false;
+        }
+        self.resumed_at = None;
+        let call_returned = self.step_over.is_some_and(|step_over| {
+            pc == step_over.return_address && sp >= step_over.stack_pointer
+        });
+        if self.addresses.contains(&pc) || call_returned {
+            // Any break ends the step over, also if a breakpoint within the subroutine is hit
+            self.step_over = None;
+            self.hit = Some(pc);
+            self.resumed_at = Some(pc);
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Returns true if a breakpoint was hit and not yet handled.
+    pub(crate) fn was_hit(&self) -> bool {
+        self.hit.is_some()
+    }
+
+    /// Cancels the step over started with [RustBoy::start_step_over], if any.
+    pub(crate) fn cancel_step_over(&mut self) {
+        self.step_over = None;
+    }
+}
+
+impl RustBoy {
+    /// Adds a breakpoint at the provided address. Once the CPU is about to execute the
+    /// instruction at this address, [RustBoy::run_frame] returns early and
+    /// [RustBoy::take_breakpoint_hit] returns the address.
+    pub fn add_breakpoint(&mut self, address: u16) {
+        if !self.breakpoints.addresses.contains(&address) {
+            self.breakpoints.addresses.push(address);
+        }
+    }
+
+    /// Removes the breakpoint at the provided address. Returns false, if there was none.
+    pub fn remove_breakpoint(&mut self, address: u16) -> bool {
+        let number_of_breakpoints = self.breakpoints.addresses.len();
+        self.breakpoints
+            .addresses
+            .retain(|breakpoint| *breakpoint != address);
+        number_of_breakpoints != self.breakpoints.addresses.len()
+    }
+
+    /// Returns the address of the breakpoint that was hit since the last call, if any. Continuing
+    /// the emulation afterward executes the instruction at the breakpoint without breaking again.
+    pub fn take_breakpoint_hit(&mut self) -> Option<u16> {
+        self.breakpoints.hit.take()
+    }
+
+    /// Starts stepping over the next instruction, if it is a subroutine call (CALL or RST). That
+    /// is, a temporary breakpoint is set at the instruction after the call, which is hit once the
+    /// subroutine returned, see [StepOver]. The emulation then has to be continued (e.g. using
+    /// [RustBoy::run_frame]) until [RustBoy::take_breakpoint_hit] returns the return address. If
+    /// another breakpoint is hit first, the step over ends there.
+    ///
+    /// Returns false without doing anything, if the next instruction is not a call or the CPU is
+    /// halted. The instruction can then simply be stepped, see [RustBoy::step].
+    pub fn start_step_over(&mut self) -> bool {
+        if self.cpu.halted {
+            return false;
+        }
+        let pc = self.cpu.pc;
+        let return_address = match Instruction::from_byte(self.memory_bus.peek(pc), false) {
+            // CALL a16 and CALL cc, a16 are 3 bytes long, RST vec only 1 byte
+            Some(Instruction::CALL(_)) => pc.wrapping_add(3),
+            Some(Instruction::RST(_)) => pc.wrapping_add(1),
+            _ => return false,
+        };
+        self.breakpoints.step_over = Some(StepOver {
+            return_address,
+            stack_pointer: self.cpu.sp,
+        });
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::RustBoy;
+    use crate::tests::rust_boy_with_program;
+
+    #[test]
+    fn step_over_of_a_recursive_call_stops_after_its_return() {
+        let mut rust_boy = rust_boy_with_program(&[
+            0x06, 0x03, // 0x0100: LD B,3
+            0x0E, 0x00, // 0x0102: LD C,0
+            0xCD, 0x10, 0x01, // 0x0104: CALL 0x0110
+            0x18, 0xFE, // 0x0107: JR -2
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // 0x0109: NOP
+            0x05, // 0x0110: DEC B
+            0xC8, // 0x0111: RET Z
+            0xCD, 0x10, 0x01, // 0x0112: CALL 0x0110
+            0x0C, // 0x0115: INC C
+            0xC9, // 0x0116: RET
+        ]);
+        while rust_boy.cpu.pc != 0x0112 {
+            rust_boy.step().expect("The program should run");
+        }
+        let stack_pointer = rust_boy.cpu.sp;
+        assert!(rust_boy.start_step_over());
+
+        // The nested call returns to 0x0115 first, but deeper in the stack, so the step over
+        // only stops once the outer call returned
+        rust_boy.run_frame().expect("The program should run");
+        assert_eq!(rust_boy.take_breakpoint_hit(), Some(0x0115));
+        assert_eq!(rust_boy.cpu.pc, 0x0115);
+        assert_eq!(rust_boy.cpu.sp, stack_pointer);
+        assert_eq!(rust_boy.cpu.registers.b, 0);
+        assert_eq!(rust_boy.cpu.registers.c, 1);
+    }
+
+    #[test]
+    fn step_over_of_a_rst_stops_after_the_rst() {
+        let mut rom_data = vec![0; 0x8000];
+        // RET as the handler of RST 0x08
+        rom_data[0x0008] = 0xC9;
+        // RST 0x08; JR -2
+        rom_data[0x0100..0x0103].copy_from_slice(&[0xCF, 0x18, 0xFE]);
+        let mut rust_boy = RustBoy::from_rom(&rom_data).expect("The ROM should be loadable");
+        assert!(rust_boy.start_step_over());
+        rust_boy.run_frame().expect("The program should run");
+        assert_eq!(rust_boy.take_breakpoint_hit(), Some(0x0101));
+        assert_eq!(rust_boy.cpu.sp, 0xFFFE);
+    }
+
+    #[test]
+    fn step_over_of_other_instructions_does_nothing() {
+        // INC A; JR -3
+        let mut rust_boy = rust_boy_with_program(&[0x3C, 0x18, 0xFD]);
+        assert!(!rust_boy.start_step_over());
+        rust_boy.run_frame().expect("The program should run");
+        assert_eq!(rust_boy.take_breakpoint_hit(), None);
+    }
+}
@@ -2,16 +2,51 @@
 //! The execution of instructions is handled/implemented in the [instructions] (sub-)module.
 
 pub(crate) mod instructions;
+mod memory_access_timing;
+pub(crate) mod opcode_coverage;
 pub mod registers;
 
+use crate::apu::Apu;
+use crate::cpu::opcode_coverage::OpcodeCoverage;
 use crate::cpu::registers::CPURegisters;
 use crate::debugging::{DebugInfo, LOG_FILE_NAME};
 #[cfg(debug_assertions)]
 use crate::debugging::{doctor_log_helper, instruction_log};
 use crate::interrupts::{InterruptEnableRegister, InterruptFlagRegister};
-use crate::{MemoryBus, PPU};
+use crate::save_state::{StateReader, StateWriter};
+use crate::timer::TimerRegisters;
+use crate::{MemoryBus, PPU, RustBoyError};
 use instructions::Instruction;
 
+/// The values of the I/O registers of the DMG after the boot ROM ran. The registers which are not
+/// listed are not mapped on the DMG or, like the wave RAM, hold random values on the real
+/// hardware and are left at 0. Bits which are not used always read as 1 regardless of the stored
+/// value, see the `io_registers` module of the memory bus.
+///
+/// See [Pan Docs - Power up Sequence](https://gbdev.io/pandocs/Power_Up_Sequence.html#hardware-registers).
+#[rustfmt::skip]
+const POWER_UP_IO_REGISTER_VALUES: [(u16, u8); 37] = [
+    // Joypad and serial. The timer registers are set by TimerRegisters::after_boot
+    (0xFF00, 0xCF), (0xFF01, 0x00), (0xFF02, 0x7E),
+    // Interrupt flags
+    (0xFF0F, 0xE1),
+    // Sound channel 1
+    (0xFF10, 0x80), (0xFF11, 0xBF), (0xFF12, 0xF3), (0xFF13, 0xFF), (0xFF14, 0xBF),
+    // Sound channel 2
+    (0xFF16, 0x3F), (0xFF17, 0x00), (0xFF18, 0xFF), (0xFF19, 0xBF),
+    // Sound channel 3
+    (0xFF1A, 0x7F), (0xFF1B, 0xFF), (0xFF1C, 0x9F), (0xFF1D, 0xFF), (0xFF1E, 0xBF),
+    // Sound channel 4
+    (0xFF20, 0xFF), (0xFF21, 0x00), (0xFF22, 0x00), (0xFF23, 0xBF),
+    // Sound control
+    (0xFF24, 0x77), (0xFF25, 0xF3), (0xFF26, 0xF1),
+    // PPU. The object palettes are not initialized by the boot ROM, 0xFF is what most emulators
+    // (and most DMG units) use
+    (0xFF40, 0x91), (0xFF41, 0x85), (0xFF42, 0x00), (0xFF43, 0x00), (0xFF44, 0x00),
+    (0xFF45, 0x00), (0xFF46, 0xFF), (0xFF47, 0xFC), (0xFF48, 0xFF), (0xFF49, 0xFF),
+    (0xFF4A, 0x00), (0xFF4B, 0x00),
+];
+
 /// Struct to represent the CPU of the RustBoy.
 ///
 /// - `registers`: The 8 general-purpose registers of the CPU, including the accumulator and flag register.
@@ -28,6 +63,9 @@ use instructions::Instruction;
 /// - `just_entered_halt`: A flag to track if the CPU has just entered the halt state, used to handle the halt bug.
 ///     See [Pan Docs - Halt Bug](https://gbdev.io/pandocs/halt.html#halt-bug) for more details.
 /// - `debugging_flags`: Flags used for debugging purposes, such as logging the state of the CPU.
+/// - `instruction_hook`: An optional callback invoked with the program counter (PC) and the decoded
+///   instruction right before each instruction is executed. See [InstructionHook].
+/// - `opcode_coverage`: Records which opcodes were executed, if enabled. See [OpcodeCoverage].
 ///
 /// For implementations of the CPU instructions, please see [instructions].
 pub struct CPU {
@@ -38,17 +76,24 @@ pub struct CPU {
     pub pc: u16,
     /// The stack pointer, which points at the top of the stack. Note that the stack grows downwards.
     pub sp: u16,
-    cycle_counter: u64,
+    pub(crate) cycle_counter: u64,
     pub(crate) cycles_current_instruction: Option<u8>,
     pub(crate) ime: bool,
-    ime_to_be_set: bool,
-    halted: bool,
+    pub(crate) ime_to_be_set: bool,
+    pub(crate) halted: bool,
     just_entered_halt: bool,
 
     // Debugging Flags
     pub(crate) debugging_flags: DebugInfo,
+    pub(crate) instruction_hook: Option<InstructionHook>,
+    pub(crate) opcode_coverage: Option<OpcodeCoverage>,
 }
 
+/// A callback invoked with the program counter (PC) and the decoded [Instruction] right before
+/// the CPU executes it. Can be used to build opcode histograms or profile which code of a game
+/// runs hottest, see [crate::RustBoy::set_instruction_hook].
+pub type InstructionHook = Box<dyn FnMut(u16, Instruction)>;
+
 impl CPU {
     /// Sets the stack pointer (sp) to the provided value.
     fn set_sp(&mut self, value: u16) {
@@ -72,7 +117,9 @@ impl CPU {
     ///
     /// Needs access to the memory bus to read the instruction byte, execute it and possibly change
     /// memory during execution of the instruction.
-    pub fn cpu_step(&mut self, memory_bus: &mut MemoryBus, ppu: &PPU) {
+    ///
+    /// Returns an error, if the instruction byte does not encode a valid instruction.
+    pub fn cpu_step(&mut self, memory_bus: &mut MemoryBus, ppu: &PPU) -> Result<(), RustBoyError> {
         // Log the current state of the registers if in debug mode.
         #[cfg(debug_assertions)]
         if !self.halted {
@@ -101,8 +148,16 @@ impl CPU {
             // the check_if_interrupt_is_requested function, so we don't need to do it again here.
 
             // Push the current program counter (PC) onto the stack and set the program counter to
-            // the interrupt location.
-            self.push(memory_bus, self.pc);
+            // the interrupt location. If the interrupt was already pending when the CPU entered
+            // halt mode (which is only possible if EI was executed right before the HALT), the
+            // halt bug keeps the PC from advancing past the HALT. So the handler returns to the
+            // HALT, which is executed again.
+            let return_address = if self.halted && self.just_entered_halt {
+                self.pc.wrapping_sub(1)
+            } else {
+                self.pc
+            };
+            self.push(memory_bus, return_address);
             self.pc = interrupt_location;
             self.increment_cycle_counter(5);
 
@@ -130,17 +185,21 @@ impl CPU {
         let mut halt_bug = false;
 
         if self.halted {
-            // Check if an interrupt is requested. If so, go out of halt mode.
+            // Check if an interrupt is requested. If so, go out of halt mode. If the IME flag is
+            // set to 0, the interrupt is not serviced, that is, its bit of the interrupt flag
+            // register stays set and the CPU continues with the instruction after the halt. The
+            // interrupt is only serviced once the IME flag is set again.
             if InterruptFlagRegister::get_interrupt_flag_register(memory_bus)
                 & InterruptEnableRegister::get_interrupt_enable_register(memory_bus)
                 != 0
                 || interrupt_requested
             {
                 // The cpu wakes up from halt mode and the next instruction is executed twice
-                // due to the halt bug
+                // due to the halt bug. If the interrupt was serviced, the IME flag was set, so
+                // the halt bug affects the return address of the handler instead, see above
                 // TODO: Handle edge cases of the halt bug, see https://gbdev.io/pandocs/halt.html#halt-bug
                 self.halted = false;
-                if self.just_entered_halt {
+                if self.just_entered_halt && !interrupt_requested {
                     halt_bug = true;
                 }
                 self.increment_cycle_counter(1);
@@ -163,7 +222,7 @@ impl CPU {
                 // bug, because it just triggers if the cpu just entered halt mode.
                 self.just_entered_halt = false;
 
-                return;
+                return Ok(());
             }
         }
 
@@ -175,6 +234,10 @@ impl CPU {
             instruction_byte = memory_bus.read_byte(self.pc.wrapping_add(1));
         }
 
+        if let Some(opcode_coverage) = &mut self.opcode_coverage {
+            opcode_coverage.record(instruction_byte, prefixed);
+        }
+
         let next_pc = if let Some(instruction) = Instruction::from_byte(instruction_byte, prefixed)
         {
             // Log the instruction byte if in debug mode.
@@ -183,24 +246,23 @@ impl CPU {
                 instruction_log(&self, memory_bus, LOG_FILE_NAME, Some(instruction), None);
             }
 
+            if let Some(instruction_hook) = &mut self.instruction_hook {
+                instruction_hook(self.pc, instruction);
+            }
+
             self.execute(memory_bus, instruction)
         } else {
-            let panic_description = format!(
-                "0x{}{:02x}",
-                if prefixed { "CB" } else { "" },
-                instruction_byte
-            );
-            panic!("Invalid instruction found for: {}", panic_description);
+            return Err(RustBoyError::InvalidInstruction {
+                opcode: instruction_byte,
+                prefixed,
+                address: self.pc,
+            });
         };
 
-        if memory_bus.dma_happened && !self.debugging_flags.binjgb_mode {
-            self.increment_cycle_counter(160);
-            memory_bus.dma_happened = false;
-        }
-
         if !halt_bug {
             self.pc = next_pc;
         }
+        Ok(())
     }
 
     /// Creates a new CPU instance with all registers and flags set to 0 and/or false. The debugging
@@ -219,50 +281,116 @@ impl CPU {
             halted: false,
             just_entered_halt: false,
             debugging_flags,
+            instruction_hook: None,
+            opcode_coverage: None,
         }
     }
 
-    /// Initializes the hardware registers to their default values after the boot rom ran.
-    /// See [Pan Docs - Power up Sequence](https://gbdev.io/pandocs/Power_Up_Sequence.html#obp)
+    /// Returns a string representation of the registers of the CPU, e.g. for logging when a
+    /// breakpoint is hit.
+    #[cfg(feature = "frontend")]
+    pub(crate) fn registers_to_string(&self) -> String {
+        format!(
+            "A:{:02X} F:{:02X} B:{:02X} C:{:02X} D:{:02X} E:{:02X} H:{:02X} L:{:02X} SP:{:04X} PC:{:04X} IME:{}",
+            self.registers.a,
+            self.registers.get_af() as u8,
+            self.registers.b,
+            self.registers.c,
+            self.registers.d,
+            self.registers.e,
+            self.registers.h,
+            self.registers.l,
+            self.sp,
+            self.pc,
+            u8::from(self.ime)
+        )
+    }
+
+    /// Writes the registers and flags of the CPU to the provided save state, see
+    /// [crate::save_state].
+    pub(crate) fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_u16(self.registers.get_af());
+        writer.write_u16(self.registers.get_bc());
+        writer.write_u16(self.registers.get_de());
+        writer.write_u16(self.registers.get_hl());
+        writer.write_u16(self.pc);
+        writer.write_u16(self.sp);
+        writer.write_u64(self.cycle_counter);
+        writer.write_bool(self.ime);
+        writer.write_bool(self.ime_to_be_set);
+        writer.write_bool(self.halted);
+        writer.write_bool(self.just_entered_halt);
+    }
+
+    /// Restores the registers and flags of the CPU written by [CPU::save_state].
+    pub(crate) fn load_state(&mut self, reader: &mut StateReader) -> Result<(), RustBoyError> {
+        self.registers.set_af(reader.read_u16()?);
+        self.registers.set_bc(reader.read_u16()?);
+        self.registers.set_de(reader.read_u16()?);
+        self.registers.set_hl(reader.read_u16()?);
+        self.pc = reader.read_u16()?;
+        self.sp = reader.read_u16()?;
+        self.cycle_counter = reader.read_u64()?;
+        self.cycles_current_instruction = None;
+        self.ime = reader.read_bool()?;
+        self.ime_to_be_set = reader.read_bool()?;
+        self.halted = reader.read_bool()?;
+        self.just_entered_halt = reader.read_bool()?;
+        Ok(())
+    }
+
+    /// Initializes the hardware registers to their default values after the boot rom ran, see
+    /// [POWER_UP_IO_REGISTER_VALUES] and
+    /// [Pan Docs - Power up Sequence](https://gbdev.io/pandocs/Power_Up_Sequence.html#obp).
+    ///
+    /// The values are stored directly instead of being written using [MemoryBus::write_byte],
+    /// since they are a snapshot of the registers and not the result of writes by the program.
+    /// Writing DIV for example resets it to 0 and the PPU mode and LY=LYC bits of STAT can not be
+    /// written.
     pub(crate) fn initialize_hardware_registers(memory_bus: &mut MemoryBus) {
-        memory_bus.write_byte(0xFF00, 0xCF);
-        memory_bus.write_byte(0xFF01, 0x00);
-        memory_bus.write_byte(0xFF02, 0x7E);
-        memory_bus.write_byte(0xFF04, 0xAB);
-        memory_bus.write_byte(0xFF05, 0x00);
-        memory_bus.write_byte(0xFF06, 0x00);
-        memory_bus.write_byte(0xFF07, 0xF8);
-        memory_bus.write_byte(0xFF0F, 0xE1);
-        memory_bus.write_byte(0xFF10, 0x80);
-        memory_bus.write_byte(0xFF11, 0xBF);
-        memory_bus.write_byte(0xFF12, 0xF3);
-        memory_bus.write_byte(0xFF13, 0xFF);
-        memory_bus.write_byte(0xFF14, 0xBF);
-        memory_bus.write_byte(0xFF16, 0x3F);
-        memory_bus.write_byte(0xFF17, 0x00);
-        memory_bus.write_byte(0xFF19, 0xBF);
-        memory_bus.write_byte(0xFF1A, 0x7F);
-        memory_bus.write_byte(0xFF1B, 0xFF);
-        memory_bus.write_byte(0xFF1C, 0x9F);
-        memory_bus.write_byte(0xFF1D, 0xFF);
-        memory_bus.write_byte(0xFF1E, 0xBF);
-        memory_bus.write_byte(0xFF20, 0xFF);
-        memory_bus.write_byte(0xFF21, 0x00);
-        memory_bus.write_byte(0xFF22, 0x00);
-        memory_bus.write_byte(0xFF23, 0xBF);
-        memory_bus.write_byte(0xFF24, 0x77);
-        memory_bus.write_byte(0xFF25, 0xF3);
-        memory_bus.write_byte(0xFF26, 0xF1);
-        memory_bus.write_byte(0xFF40, 0x91);
-        memory_bus.write_byte(0xFF41, 0x85);
-        memory_bus.write_byte(0xFF42, 0x00);
-        memory_bus.write_byte(0xFF43, 0x00);
-        memory_bus.write_byte(0xFF44, 0x00);
-        memory_bus.write_byte(0xFF45, 0x00);
-        memory_bus.write_byte(0xFF46, 0xFF);
-        memory_bus.write_byte(0xFF47, 0xFC);
-        memory_bus.write_byte(0xFF4A, 0x00);
-        memory_bus.write_byte(0xFF4B, 0x00);
-        memory_bus.write_byte(0xFFFF, 0x00);
+        for (address, value) in POWER_UP_IO_REGISTER_VALUES {
+            memory_bus.memory[address as usize] = value;
+        }
+        memory_bus.memory[0xFFFF] = 0x00;
+        memory_bus.timer_registers = TimerRegisters::after_boot();
+        memory_bus.apu = Apu::after_boot();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::RustBoy;
+
+    /// The values of the I/O registers and IE after the boot ROM of the DMG as read by the CPU,
+    /// as listed in [Pan Docs - Power up Sequence](https://gbdev.io/pandocs/Power_Up_Sequence.html#hardware-registers).
+    /// The object palettes are not initialized by the boot ROM and therefore left out.
+    #[rustfmt::skip]
+    const PAN_DOCS_POWER_UP_VALUES: [(u16, u8); 43] = [
+        (0xFF00, 0xCF), (0xFF01, 0x00), (0xFF02, 0x7E), (0xFF04, 0xAB), (0xFF05, 0x00),
+        (0xFF06, 0x00), (0xFF07, 0xF8), (0xFF0F, 0xE1), (0xFF10, 0x80), (0xFF11, 0xBF),
+        (0xFF12, 0xF3), (0xFF13, 0xFF), (0xFF14, 0xBF), (0xFF16, 0x3F), (0xFF17, 0x00),
+        (0xFF18, 0xFF), (0xFF19, 0xBF), (0xFF1A, 0x7F), (0xFF1B, 0xFF), (0xFF1C, 0x9F),
+        (0xFF1D, 0xFF), (0xFF1E, 0xBF), (0xFF20, 0xFF), (0xFF21, 0x00), (0xFF22, 0x00),
+        (0xFF23, 0xBF), (0xFF24, 0x77), (0xFF25, 0xF3), (0xFF26, 0xF1), (0xFF40, 0x91),
+        (0xFF41, 0x85), (0xFF42, 0x00), (0xFF43, 0x00), (0xFF44, 0x00), (0xFF45, 0x00),
+        (0xFF46, 0xFF), (0xFF47, 0xFC), (0xFF4A, 0x00), (0xFF4B, 0x00), (0xFF4D, 0xFF),
+        (0xFF4F, 0xFF), (0xFF70, 0xFF), (0xFFFF, 0x00),
+    ];
+
+    #[test]
+    fn io_registers_have_their_power_up_values_after_boot() {
+        let rust_boy = RustBoy::new_after_boot(DebugInfo::default());
+        let mismatches: Vec<String> = PAN_DOCS_POWER_UP_VALUES
+            .iter()
+            .filter(|(address, value)| rust_boy.memory_bus.read_byte(*address) != *value)
+            .map(|(address, value)| {
+                format!(
+                    "{address:#06X}: expected {value:#04X}, read {:#04X}",
+                    rust_boy.memory_bus.read_byte(*address)
+                )
+            })
+            .collect();
+        assert!(mismatches.is_empty(), "{mismatches:#?}");
     }
 }
@@ -29,6 +29,7 @@ mod res_and_set;
 mod rlc_rrc_rl_and_rr;
 mod rlca_rrca_rla_and_rra;
 mod sla_sra_and_srl;
+mod stop;
 mod sub_and_sbc;
 mod swap;
 
@@ -51,51 +52,97 @@ use std::cmp::PartialEq;
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum Instruction {
     // 8 Bit Opcodes
+    /// No operation.
     NOP,
+    /// Adds the source to register A.
     ADDByte(ArithmeticOrLogicalSource),
+    /// Adds the 16 bit source to the 16 bit target (HL or SP).
     ADDWord(AddWordTarget, AddWordSource),
+    /// Adds the source and the carry flag to register A.
     ADC(ArithmeticOrLogicalSource),
+    /// Subtracts the source from register A.
     SUB(ArithmeticOrLogicalSource),
+    /// Subtracts the source and the carry flag from register A.
     SBC(ArithmeticOrLogicalSource),
+    /// Bitwise AND of register A and the source.
     AND(ArithmeticOrLogicalSource),
+    /// Bitwise OR of register A and the source.
     OR(ArithmeticOrLogicalSource),
+    /// Bitwise XOR of register A and the source.
     XOR(ArithmeticOrLogicalSource),
+    /// Compares register A and the source by subtracting without storing the result.
     CP(ArithmeticOrLogicalSource),
+    /// Increments the target.
     INC(IncDecTarget),
+    /// Decrements the target.
     DEC(IncDecTarget),
+    /// Jumps to an absolute address.
     JP(JumpType),
+    /// Loads a value from the source into the target.
     LD(LoadType),
+    /// Loads a value from or into the high memory area (0xFF00 - 0xFFFF).
     LDH(LDHType),
+    /// Pushes a 16 bit register onto the stack.
     PUSH(PushSource),
+    /// Pops a 16 bit register from the stack.
     POP(PopTarget),
+    /// Calls a subroutine, if the condition is met.
     CALL(InstructionCondition),
+    /// Returns from a subroutine, if the condition is met.
     RET(InstructionCondition),
+    /// Calls the subroutine at the provided fixed address.
     RST(u16),
+    /// Jumps relative to the program counter, if the condition is met.
     JR(InstructionCondition),
+    /// Decimal adjusts register A after a BCD addition or subtraction.
     DAA,
+    /// Sets the carry flag.
     SCF,
+    /// Complements (inverts) register A.
     CPL,
+    /// Complements (inverts) the carry flag.
     CCF,
+    /// Disables the interrupts.
     DI,
+    /// Enables the interrupts after the next instruction.
     EI,
+    /// Returns from a subroutine and enables the interrupts.
     RETI,
+    /// Halts the CPU until an interrupt is pending.
     HALT,
+    /// Resets the divider register and enters the low power mode (not emulated).
+    STOP,
 
     // 16 bit Opcodes
+    /// Rotates the target left.
     RLC(SixteenBitInstructionTarget),
+    /// Rotates the target right.
     RRC(SixteenBitInstructionTarget),
+    /// Rotates the target left through the carry flag.
     RL(SixteenBitInstructionTarget),
+    /// Rotates the target right through the carry flag.
     RR(SixteenBitInstructionTarget),
+    /// Shifts the target left arithmetically.
     SLA(SixteenBitInstructionTarget),
+    /// Shifts the target right arithmetically.
     SRA(SixteenBitInstructionTarget),
+    /// Swaps the upper and lower nibble of the target.
     SWAP(SixteenBitInstructionTarget),
+    /// Shifts the target right logically.
     SRL(SixteenBitInstructionTarget),
+    /// Rotates register A left.
     RLCA,
+    /// Rotates register A right.
     RRCA,
+    /// Rotates register A left through the carry flag.
     RLA,
+    /// Rotates register A right through the carry flag.
     RRA,
+    /// Tests a bit of the target.
     BIT(BitInstructionType),
+    /// Resets a bit of the target.
     RES(ResAndSetInstructionType),
+    /// Sets a bit of the target.
     SET(ResAndSetInstructionType),
 }
 
@@ -239,6 +286,7 @@ impl CPU {
             RLA => self.handle_rla_instruction(),
             RRA => self.handle_rra_instruction(),
             HALT => self.handle_halt_instruction(),
+            STOP => self.handle_stop_instruction(memory_bus),
 
             // 16-bit Opcodes
             RLC(target) => self.handle_rlc_instruction(memory_bus, target),
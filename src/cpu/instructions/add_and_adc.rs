@@ -107,7 +107,7 @@ impl CPU {
                 let value = value.expect(
                     "Should be a valid add instruction and therefore value should be present",
                 );
-                let new_value = self.add_word(self.registers.get_hl(), value, false);
+                let new_value = self.add_word(self.registers.get_hl(), value);
                 self.registers.set_hl(new_value);
                 self.pc.wrapping_add(1)
             }
@@ -126,14 +126,15 @@ impl CPU {
     }
 
     /// Adds a 16bit value to the target and sets the corresponding flags in the flags register
-    /// [super::registers::FlagsRegister].
-    ///
-    /// The zero flag is reset if the target is the stack pointer. Otherwise, it is not changed.
-    fn add_word(&mut self, target: u16, value: u16, sp_is_target: bool) -> u16 {
+    /// [super::registers::FlagsRegister]. This is used for ADD HL, r16, which sets the half carry
+    /// and carry flag according to the carries out of bit 11 and 15 and does not change the zero
+    /// flag. ADD SP, e8 instead sets the flags like an 8bit addition, see
+    /// [CPU::handle_add_word_instruction].
+    fn add_word(&mut self, target: u16, value: u16) -> u16 {
         let new_value = target.wrapping_add(value);
         self.registers.f.set_subtract_flag(false);
         // The carry flag is set if there is an overflow from the 15th bit to the "16"th bit.
-        // This is the case if the sum of the A register and the value are greater than 0xFFFF = 0b 1111 1111 1111 1111 (binary).
+        // This is the case if the sum of the target and the value are greater than 0xFFFF = 0b 1111 1111 1111 1111 (binary).
         self.registers
             .f
             .set_carry_flag(target as u32 + value as u32 > 0xFFFF);
@@ -143,9 +144,6 @@ impl CPU {
         self.registers
             .f
             .set_half_carry_flag(((target & 0x0FFF) + (value & 0x0FFF)) > 0x0FFF);
-        if sp_is_target {
-            self.registers.f.set_zero_flag(false);
-        }
         new_value
     }
 
@@ -165,3 +163,68 @@ impl CPU {
         new_pc
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::tests::rust_boy_with_program;
+
+    const ADD_HL_BC: u8 = 0x09;
+    const ADD_HL_HL: u8 = 0x29;
+    const ADD_HL_SP: u8 = 0x39;
+    const ZERO_FLAG: u8 = 0x80;
+    const SUBTRACT_FLAG: u8 = 0x40;
+    const HALF_CARRY_FLAG: u8 = 0x20;
+    const CARRY_FLAG: u8 = 0x10;
+
+    /// Executes the provided ADD HL, r16 instruction with the provided values of HL, the other
+    /// register and the flags and returns HL and the flags afterward.
+    fn add_hl(instruction: u8, hl: u16, value: u16, flags: u8) -> (u16, u8) {
+        let mut rust_boy = rust_boy_with_program(&[instruction]);
+        rust_boy.cpu.registers.set_af(flags as u16);
+        rust_boy.cpu.registers.set_hl(hl);
+        rust_boy.cpu.registers.set_bc(value);
+        rust_boy.cpu.sp = value;
+        rust_boy.step().expect("ADD HL should be executed");
+        (
+            rust_boy.cpu.registers.get_hl(),
+            rust_boy.cpu.registers.f.get(),
+        )
+    }
+
+    #[test]
+    fn add_hl_sets_half_carry_and_carry_from_bits_11_and_15() {
+        for instruction in [ADD_HL_BC, ADD_HL_SP] {
+            // Carry out of bit 11 only
+            assert_eq!(
+                add_hl(instruction, 0x0FFF, 0x0001, 0),
+                (0x1000, HALF_CARRY_FLAG)
+            );
+            // A carry out of bit 3 does not set the half carry flag
+            assert_eq!(add_hl(instruction, 0x000F, 0x0001, 0), (0x0010, 0));
+            // Carry out of bit 15 only
+            assert_eq!(add_hl(instruction, 0x8000, 0x8000, 0), (0x0000, CARRY_FLAG));
+            // Carries out of both bits
+            assert_eq!(
+                add_hl(instruction, 0xFFFF, 0x0001, 0),
+                (0x0000, HALF_CARRY_FLAG | CARRY_FLAG)
+            );
+        }
+        assert_eq!(
+            add_hl(ADD_HL_HL, 0x8800, 0, 0),
+            (0x1000, HALF_CARRY_FLAG | CARRY_FLAG)
+        );
+    }
+
+    #[test]
+    fn add_hl_keeps_zero_and_clears_subtract() {
+        assert_eq!(
+            add_hl(ADD_HL_BC, 0x1234, 0x1111, ZERO_FLAG | SUBTRACT_FLAG),
+            (0x2345, ZERO_FLAG)
+        );
+        // A result of 0 does not set the zero flag
+        assert_eq!(
+            add_hl(ADD_HL_BC, 0xF000, 0x1000, SUBTRACT_FLAG),
+            (0x0000, CARRY_FLAG)
+        );
+    }
+}
@@ -45,7 +45,8 @@ impl CPU {
         self.pc.wrapping_add(1)
     }
 
-    /// Set Carry Flag. Also clears the half carry and subtract flags.
+    /// Set Carry Flag. Also clears the half carry and subtract flags. The zero flag is not
+    /// affected.
     fn scf(&mut self) {
         self.registers.f.set_carry_flag(true);
         self.registers.f.set_subtract_flag(false);
@@ -61,7 +62,8 @@ impl CPU {
         self.pc.wrapping_add(1)
     }
 
-    /// Complement A. Sets the subtract flag and the half carry flag.
+    /// Complement A. Sets the subtract flag and the half carry flag. The zero and carry flags
+    /// are not affected.
     fn cpl(&mut self) {
         self.registers.a = !self.registers.a;
         self.registers.f.set_subtract_flag(true);
@@ -77,7 +79,8 @@ impl CPU {
         self.pc.wrapping_add(1)
     }
 
-    /// Complement Carry Flag. Sets the subtract flag and clears the half carry flag.
+    /// Complement Carry Flag. Clears the subtract flag and the half carry flag. The zero flag
+    /// is not affected.
     fn ccf(&mut self) {
         self.registers
             .f
@@ -86,3 +89,57 @@ impl CPU {
         self.registers.f.set_half_carry_flag(false);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::tests::rust_boy_with_program;
+
+    const SCF: u8 = 0x37;
+    const CPL: u8 = 0x2F;
+    const CCF: u8 = 0x3F;
+    /// The flag values the instructions are executed with, from no flags to all flags (Z, N, H
+    /// and C from the highest bit on).
+    const FLAG_INPUTS: [u8; 6] = [0x00, 0x80, 0x40, 0x20, 0x10, 0xF0];
+
+    /// Executes the provided instruction with A = 0x35 and the provided flags and returns A and
+    /// the flags afterward.
+    fn execute_with_flags(instruction: u8, flags: u8) -> (u8, u8) {
+        let mut rust_boy = rust_boy_with_program(&[instruction]);
+        rust_boy.cpu.registers.set_af(0x3500 | flags as u16);
+        rust_boy.step().expect("The instruction should be executed");
+        (rust_boy.cpu.registers.a, rust_boy.cpu.registers.f.get())
+    }
+
+    #[test]
+    fn scf_sets_carry_and_clears_subtract_and_half_carry() {
+        for flags in FLAG_INPUTS {
+            assert_eq!(
+                execute_with_flags(SCF, flags),
+                (0x35, flags & 0x80 | 0x10),
+                "flags {flags:#04X}"
+            );
+        }
+    }
+
+    #[test]
+    fn cpl_complements_a_and_sets_subtract_and_half_carry() {
+        for flags in FLAG_INPUTS {
+            assert_eq!(
+                execute_with_flags(CPL, flags),
+                (0xCA, flags & 0x90 | 0x60),
+                "flags {flags:#04X}"
+            );
+        }
+    }
+
+    #[test]
+    fn ccf_flips_carry_and_clears_subtract_and_half_carry() {
+        for flags in FLAG_INPUTS {
+            assert_eq!(
+                execute_with_flags(CCF, flags),
+                (0x35, flags & 0x80 | !flags & 0x10),
+                "flags {flags:#04X}"
+            );
+        }
+    }
+}
@@ -11,8 +11,14 @@ impl CPU {
     }
 
     /// Sets the IME (Interrupt Master Enable) flag to false, which disables interrupts.
+    ///
+    /// DI takes effect right after its own execution, so the interrupt check of the next
+    /// [CPU::cpu_step] does not service any interrupt anymore, even if one became pending while DI
+    /// was executed. A pending EI (e.g. in `EI; DI`) is cancelled as well, as otherwise the IME
+    /// flag would be set after DI and re-enable interrupts.
     fn di(&mut self) {
         self.ime = false;
+        self.ime_to_be_set = false;
     }
 
     /// Handles the ei instruction
@@ -30,3 +36,62 @@ impl CPU {
         self.ime_to_be_set = true;
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::interrupts::{Interrupt, InterruptEnableRegister, InterruptFlagRegister};
+    use crate::tests::rust_boy_with_program;
+
+    const VBLANK_AND_TIMER: u8 = 0b0000_0101;
+
+    #[test]
+    fn di_right_after_ei_keeps_interrupts_disabled() {
+        // EI, DI, NOP
+        let mut rust_boy = rust_boy_with_program(&[0xFB, 0xF3, 0x00]);
+        InterruptEnableRegister::set_interrupt_enable_register(
+            &mut rust_boy.memory_bus,
+            VBLANK_AND_TIMER,
+        );
+        InterruptFlagRegister::set_interrupt_flag_register(&mut rust_boy.memory_bus, 0);
+        rust_boy.step().expect("EI should be executed");
+        InterruptFlagRegister::set_flag(&mut rust_boy.memory_bus, Interrupt::Timer, true);
+        rust_boy.step().expect("DI should be executed");
+        rust_boy.step().expect("NOP should be executed");
+
+        assert_eq!(rust_boy.cpu.pc, 0x0103);
+        assert!(!rust_boy.cpu.ime);
+        assert!(InterruptFlagRegister::get_flag(
+            &rust_boy.memory_bus,
+            Interrupt::Timer
+        ));
+    }
+
+    #[test]
+    fn dispatching_an_interrupt_cancels_a_pending_ei() {
+        // EI while IME is already set. The VBlank handler at 0x0040 consists of NOPs, so a pending
+        // EI would set IME again after its first instruction
+        let mut rust_boy = rust_boy_with_program(&[0xFB]);
+        rust_boy.cpu.ime = true;
+        InterruptEnableRegister::set_interrupt_enable_register(
+            &mut rust_boy.memory_bus,
+            VBLANK_AND_TIMER,
+        );
+        InterruptFlagRegister::set_interrupt_flag_register(&mut rust_boy.memory_bus, 0);
+        rust_boy.step().expect("EI should be executed");
+        InterruptFlagRegister::set_flag(&mut rust_boy.memory_bus, Interrupt::VBlank, true);
+        InterruptFlagRegister::set_flag(&mut rust_boy.memory_bus, Interrupt::Timer, true);
+
+        // The VBlank interrupt is dispatched and the first instruction of the handler executed
+        rust_boy.step().expect("The interrupt should be dispatched");
+        assert_eq!(rust_boy.cpu.pc, 0x0041);
+        assert!(!rust_boy.cpu.ime);
+
+        // The timer interrupt stays pending instead of interrupting the VBlank handler
+        rust_boy.step().expect("NOP should be executed");
+        assert_eq!(rust_boy.cpu.pc, 0x0042);
+        assert!(InterruptFlagRegister::get_flag(
+            &rust_boy.memory_bus,
+            Interrupt::Timer
+        ));
+    }
+}
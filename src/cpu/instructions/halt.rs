@@ -17,3 +17,92 @@ impl CPU {
         self.just_entered_halt = true;
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::RustBoy;
+    use crate::interrupts::{Interrupt, InterruptEnableRegister, InterruptFlagRegister};
+    use crate::tests::rust_boy_with_program;
+
+    const HALT: u8 = 0x76;
+    const INC_A: u8 = 0x3C;
+    const EI: u8 = 0xFB;
+    const RETI: u8 = 0xD9;
+    const TIMER_INTERRUPT_BIT: u8 = 0b0000_0100;
+
+    /// Returns a RustBoy running the provided program, with only the timer interrupt enabled and
+    /// no interrupt requested.
+    fn rust_boy_with_timer_interrupt_enabled(program: &[u8]) -> RustBoy {
+        let mut rust_boy = rust_boy_with_program(program);
+        InterruptEnableRegister::set_interrupt_enable_register(
+            &mut rust_boy.memory_bus,
+            TIMER_INTERRUPT_BIT,
+        );
+        InterruptFlagRegister::set_interrupt_flag_register(&mut rust_boy.memory_bus, 0);
+        rust_boy
+    }
+
+    /// Returns whether the timer interrupt is requested in the provided RustBoy.
+    fn timer_interrupt_is_requested(rust_boy: &RustBoy) -> bool {
+        InterruptFlagRegister::get_flag(&rust_boy.memory_bus, Interrupt::Timer)
+    }
+
+    #[test]
+    fn halt_with_ime_off_continues_after_the_halt_once_woken() {
+        let mut rust_boy = rust_boy_with_timer_interrupt_enabled(&[HALT, INC_A]);
+        let a = rust_boy.cpu.registers.a;
+        for _ in 0..3 {
+            rust_boy.step().expect("HALT should be executed");
+        }
+        assert!(rust_boy.cpu.halted);
+        assert_eq!(rust_boy.cpu.pc, 0x0101);
+
+        InterruptFlagRegister::set_flag(&mut rust_boy.memory_bus, Interrupt::Timer, true);
+        rust_boy.step().expect("The CPU should wake up");
+        assert!(!rust_boy.cpu.halted);
+        assert_eq!(rust_boy.cpu.pc, 0x0102);
+        assert_eq!(rust_boy.cpu.registers.a, a.wrapping_add(1));
+        // The interrupt is not serviced, so it stays requested
+        assert!(timer_interrupt_is_requested(&rust_boy));
+    }
+
+    #[test]
+    fn halt_with_ime_off_and_a_pending_interrupt_triggers_the_halt_bug() {
+        let mut rust_boy = rust_boy_with_timer_interrupt_enabled(&[HALT, INC_A]);
+        let a = rust_boy.cpu.registers.a;
+        InterruptFlagRegister::set_flag(&mut rust_boy.memory_bus, Interrupt::Timer, true);
+        rust_boy.step().expect("HALT should be executed");
+        rust_boy.step().expect("The CPU should wake up");
+        rust_boy.step().expect("INC A should be executed again");
+
+        // The byte after the HALT is read twice
+        assert_eq!(rust_boy.cpu.pc, 0x0102);
+        assert_eq!(rust_boy.cpu.registers.a, a.wrapping_add(2));
+        assert!(timer_interrupt_is_requested(&rust_boy));
+    }
+
+    #[test]
+    fn ei_right_before_halt_returns_to_the_halt() {
+        // EI; HALT; INC A with a RETI as the handler of the timer interrupt
+        let mut rom_data = vec![0; 0x8000];
+        rom_data[0x0050] = RETI;
+        rom_data[0x0100..0x0103].copy_from_slice(&[EI, HALT, INC_A]);
+        let mut rust_boy = RustBoy::from_rom(&rom_data).expect("The ROM should be loadable");
+        InterruptEnableRegister::set_interrupt_enable_register(
+            &mut rust_boy.memory_bus,
+            TIMER_INTERRUPT_BIT,
+        );
+        InterruptFlagRegister::set_flag(&mut rust_boy.memory_bus, Interrupt::Timer, true);
+        let a = rust_boy.cpu.registers.a;
+        rust_boy.step().expect("EI should be executed");
+        rust_boy.step().expect("HALT should be executed");
+        rust_boy.step().expect("The interrupt should be dispatched");
+
+        // The halt bug makes the handler return to the HALT instead of the instruction after it
+        assert_eq!(rust_boy.cpu.pc, 0x0101);
+        assert!(!timer_interrupt_is_requested(&rust_boy));
+        rust_boy.step().expect("HALT should be executed again");
+        assert!(rust_boy.cpu.halted);
+        assert_eq!(rust_boy.cpu.registers.a, a);
+    }
+}
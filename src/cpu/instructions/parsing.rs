@@ -54,6 +54,7 @@ impl Instruction {
             0x0F => Some(Instruction::RRCA),
 
             // TODO: Add missing instructions
+            0x10 => Some(Instruction::STOP),
             0x11 => Some(Instruction::LD(LoadType::Word(
                 LoadWordTarget::DE,
                 LoadWordSource::D16,
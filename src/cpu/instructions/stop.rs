@@ -0,0 +1,38 @@
+use crate::{CPU, MemoryBus};
+
+/// The address of the divider register (DIV).
+const DIVIDER_REGISTER_ADDRESS: u16 = 0xFF04;
+
+impl CPU {
+    /// Handles the STOP instruction. STOP resets the divider register (DIV) to 0, like a write to
+    /// it does. The byte following the opcode is skipped, since STOP is 2 bytes long.
+    ///
+    /// The low power mode STOP enters on the original Game Boy until a button is pressed is not
+    /// emulated, the CPU just continues with the next instruction. See
+    /// [Pan Docs - Using the STOP Instruction](https://gbdev.io/pandocs/Reducing_Power_Consumption.html#using-the-stop-instruction).
+    ///
+    /// Takes 1 cycle to execute.
+    pub fn handle_stop_instruction(&mut self, memory_bus: &mut MemoryBus) -> u16 {
+        self.increment_cycle_counter(1);
+        // The value is ignored, writing to DIV always resets it
+        memory_bus.write_byte(DIVIDER_REGISTER_ADDRESS, 0);
+        self.pc.wrapping_add(2)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::rust_boy_with_program;
+
+    #[test]
+    fn stop_resets_the_divider_register() {
+        // STOP, followed by the byte which is skipped
+        let mut rust_boy = rust_boy_with_program(&[0x10, 0x00]);
+        rust_boy.set_divider_counter(0xABCD);
+        rust_boy.step().expect("STOP should be executed");
+        assert_eq!(rust_boy.memory_bus.read_byte(DIVIDER_REGISTER_ADDRESS), 0);
+        assert_eq!(rust_boy.divider_counter() >> 8, 0);
+        assert_eq!(rust_boy.cpu.pc, 0x0102);
+    }
+}
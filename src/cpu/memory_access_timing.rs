@@ -0,0 +1,158 @@
+//! This module contains the methods used to determine at which m-cycle of an instruction the
+//! memory is accessed. For more information on the timing of the memory accesses of each
+//! instruction, please refer to
+//! [gbctr - Game Boy: Complete Technical Reference](https://gekkio.fi/files/gb-docs/gbctr.pdf).
+//!
+//! The CPU executes each instruction at once, after which the timer and the PPU are advanced by
+//! the number of m-cycles the instruction took. For most memory accesses this is fine, but some
+//! registers are polled tightly by games (e.g. LY while waiting for a specific scanline). For
+//! these, the timer and the PPU are advanced up to the m-cycle of the access before the
+//! instruction is executed, see [CPU::m_cycles_before_timing_sensitive_access].
+
+use super::CPU;
+use crate::MemoryBus;
+use crate::interrupts::{InterruptEnableRegister, InterruptFlagRegister};
+
+/// The registers whose value depends on (or influences) the exact m-cycle they are accessed in.
+/// These are DIV, TIMA, TMA, TAC, IF, LCDC, STAT, LY, LYC and DMA, whose transfer starts at the
+/// m-cycle of the write.
+const TIMING_SENSITIVE_REGISTERS: [u16; 10] = [
+    0xFF04, 0xFF05, 0xFF06, 0xFF07, 0xFF0F, 0xFF40, 0xFF41, 0xFF44, 0xFF45, 0xFF46,
+];
+
+impl CPU {
+    /// Returns the number of m-cycles the next instruction takes before it accesses one of the
+    /// [TIMING_SENSITIVE_REGISTERS]. Returns None, if the next instruction does not access one
+    /// of them, or it is not known when it does.
+    ///
+    /// Only the instructions with a single memory access (apart from fetching the instruction
+    /// itself) are considered. These access the memory in their last m-cycle. If an interrupt
+    /// is dispatched or the CPU is halted, the next instruction is not executed right away, so
+    /// None is returned as well.
+    pub(crate) fn m_cycles_before_timing_sensitive_access(
+        &self,
+        memory_bus: &MemoryBus,
+    ) -> Option<u32> {
+        let interrupt_is_pending = InterruptFlagRegister::get_interrupt_flag_register(memory_bus)
+            & InterruptEnableRegister::get_interrupt_enable_register(memory_bus)
+            & 0x1F
+            != 0;
+        if self.halted || (self.ime && interrupt_is_pending) {
+            return None;
+        }
+
+        let immediate_byte = || memory_bus.read_byte(self.pc.wrapping_add(1));
+        let immediate_word = || {
+            u16::from_le_bytes([
+                memory_bus.read_byte(self.pc.wrapping_add(1)),
+                memory_bus.read_byte(self.pc.wrapping_add(2)),
+            ])
+        };
+        let hl = self.registers.get_hl();
+
+        let (address, m_cycles_before_access) = match memory_bus.read_byte(self.pc) {
+            // LDH [a8], A and LDH A, [a8]
+            0xE0 | 0xF0 => (0xFF00 + immediate_byte() as u16, 2),
+            // LDH [C], A and LDH A, [C]
+            0xE2 | 0xF2 => (0xFF00 + self.registers.c as u16, 1),
+            // LD [a16], A and LD A, [a16]
+            0xEA | 0xFA => (immediate_word(), 3),
+            // LD [HL], n8
+            0x36 => (hl, 2),
+            // LD r, [HL], LD [HL], r, the arithmetic instructions with [HL] as the operand and
+            // LD [HL+], A, LD [HL-], A, LD A, [HL+] and LD A, [HL-]
+            0x46
+            | 0x4E
+            | 0x56
+            | 0x5E
+            | 0x66
+            | 0x6E
+            | 0x7E
+            | 0x70..=0x75
+            | 0x77
+            | 0x86
+            | 0x8E
+            | 0x96
+            | 0x9E
+            | 0xA6
+            | 0xAE
+            | 0xB6
+            | 0xBE
+            | 0x22
+            | 0x2A
+            | 0x32
+            | 0x3A => (hl, 1),
+            // LD [BC], A and LD A, [BC]
+            0x02 | 0x0A => (self.registers.get_bc(), 1),
+            // LD [DE], A and LD A, [DE]
+            0x12 | 0x1A => (self.registers.get_de(), 1),
+            // BIT n, [HL]
+            0xCB if immediate_byte() & 0b1100_0111 == 0b0100_0110 => (hl, 2),
+            _ => return None,
+        };
+
+        TIMING_SENSITIVE_REGISTERS
+            .contains(&address)
+            .then_some(m_cycles_before_access)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::RustBoy;
+    use crate::tests::rust_boy_with_program;
+
+    /// The address of the scanline register (LY).
+    const SCANLINE_REGISTER_ADDRESS: u16 = 0xFF44;
+    /// The address of the LDH A,[LY] instruction in the test program.
+    const READ_SCANLINE_ADDRESS: u16 = 0x0200;
+
+    /// Returns the value of LY, without advancing the PPU.
+    fn scanline(rust_boy: &RustBoy) -> u8 {
+        rust_boy.memory_bus.read_byte(SCANLINE_REGISTER_ADDRESS)
+    }
+
+    #[test]
+    fn ly_is_read_in_the_last_m_cycle_of_ldh() {
+        // NOPs from 0x0100 on, followed by LDH A,[LY] at READ_SCANLINE_ADDRESS
+        let mut program = vec![0x00; (READ_SCANLINE_ADDRESS - 0x0100) as usize];
+        program.extend_from_slice(&[0xF0, 0x44]);
+        let mut rust_boy = rust_boy_with_program(&program);
+
+        // Try each m-cycle of the scanlines around the start and the end of VBlank as the start
+        // of LDH, which reads LY after its first two m-cycles
+        let mut scanlines_changed_during_ldh = 0;
+        for _ in 0..154 * 114 {
+            if !matches!(scanline(&rust_boy), 0 | 142..=145 | 152 | 153) {
+                rust_boy.cpu.pc = 0x0100;
+                rust_boy.step().expect("NOP should be executed");
+                continue;
+            }
+            // LDH has to read the value LY has after two NOPs, that is, after two m-cycles
+            let state = rust_boy.save_state_to_bytes();
+            rust_boy.cpu.pc = 0x0100;
+            rust_boy.step().expect("NOP should be executed");
+            rust_boy.step().expect("NOP should be executed");
+            let scanline_at_access = scanline(&rust_boy);
+
+            rust_boy
+                .load_state_from_bytes(&state)
+                .expect("The state should be loadable");
+            let scanline_before = scanline(&rust_boy);
+            rust_boy.cpu.pc = READ_SCANLINE_ADDRESS;
+            rust_boy.step().expect("LDH should be executed");
+            assert_eq!(rust_boy.cpu.registers.a, scanline_at_access);
+            if scanline_at_access != scanline_before {
+                scanlines_changed_during_ldh += 1;
+            }
+
+            // Continue with the next m-cycle as the start of LDH
+            rust_boy
+                .load_state_from_bytes(&state)
+                .expect("The state should be loadable");
+            rust_boy.cpu.pc = 0x0100;
+            rust_boy.step().expect("NOP should be executed");
+        }
+        assert!(scanlines_changed_during_ldh > 0);
+    }
+}
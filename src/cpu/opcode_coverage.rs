@@ -0,0 +1,88 @@
+//! This module contains the [OpcodeCoverage] struct, which records which of the 512 opcodes (256
+//! base opcodes and 256 opcodes prefixed with 0xCB) the CPU executed. It is used to check whether
+//! a test suite exercises the whole instruction set and to spot opcodes the decoder does not
+//! support yet, see the `--COVERAGE` option.
+
+use super::instructions::Instruction;
+
+/// The byte prefixing the extended (CB) opcodes. It is not an instruction on its own.
+const PREFIX_BYTE: u8 = 0xCB;
+
+/// Struct to record which opcodes were executed.
+///
+/// - `executed`: A bitset of the executed opcodes. The first 256 bits represent the base opcodes
+///   and the last 256 bits the opcodes prefixed with 0xCB, see [OpcodeCoverage::bit_index].
+#[derive(Debug, Default)]
+pub(crate) struct OpcodeCoverage {
+    executed: [u64; 8],
+}
+
+impl OpcodeCoverage {
+    /// Returns the index of the bit representing the provided opcode in `executed`.
+    fn bit_index(opcode: u8, prefixed: bool) -> usize {
+        if prefixed {
+            256 + opcode as usize
+        } else {
+            opcode as usize
+        }
+    }
+
+    /// Records that the provided opcode was executed (or at least fetched, if it turns out to be
+    /// invalid).
+    pub(crate) fn record(&mut self, opcode: u8, prefixed: bool) {
+        let bit_index = Self::bit_index(opcode, prefixed);
+        self.executed[bit_index / 64] |= 1 << (bit_index % 64);
+    }
+
+    /// Returns true if the provided opcode was executed.
+    fn was_executed(&self, opcode: u8, prefixed: bool) -> bool {
+        let bit_index = Self::bit_index(opcode, prefixed);
+        self.executed[bit_index / 64] & (1 << (bit_index % 64)) != 0
+    }
+
+    /// Returns the report of the coverage, which consists of the number of executed opcodes and
+    /// lists of the opcodes which were never executed and of those the decoder does not support,
+    /// each for the base and the CB opcodes. The opcodes which do not exist on the Game Boy
+    /// (e.g. 0xD3) are listed as not supported by the decoder as well.
+    pub(crate) fn report(&self) -> String {
+        let mut report = String::from("Opcode coverage:");
+        for (prefixed, name) in [(false, "Base"), (true, "CB")] {
+            let mut executed = Vec::new();
+            let mut never_executed = Vec::new();
+            let mut not_decoded = Vec::new();
+            for opcode in 0..=u8::MAX {
+                if !prefixed && opcode == PREFIX_BYTE {
+                    continue;
+                }
+                let opcode_string = format!("{opcode:02X}");
+                if Instruction::from_byte(opcode, prefixed).is_none() {
+                    not_decoded.push(opcode_string);
+                } else if self.was_executed(opcode, prefixed) {
+                    executed.push(opcode_string);
+                } else {
+                    never_executed.push(opcode_string);
+                }
+            }
+            let number_of_decoded_opcodes = executed.len() + never_executed.len();
+            report.push_str(&format!(
+                "\n{name} opcodes executed: {}/{}\n\
+                 {name} opcodes never executed: {}\n\
+                 {name} opcodes not supported by the decoder: {}",
+                executed.len(),
+                number_of_decoded_opcodes,
+                opcodes_to_string(&never_executed),
+                opcodes_to_string(&not_decoded)
+            ));
+        }
+        report
+    }
+}
+
+/// Returns the provided opcodes separated by spaces, or "none", if there are none.
+fn opcodes_to_string(opcodes: &[String]) -> String {
+    if opcodes.is_empty() {
+        "none".to_string()
+    } else {
+        opcodes.join(" ")
+    }
+}
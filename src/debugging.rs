@@ -3,7 +3,13 @@
 //! The functions and structs in this module are primarily used during development and testing.
 use wasm_timer::Instant;
 
+#[cfg(feature = "frontend")]
+use crate::RustBoy;
+#[cfg(feature = "frontend")]
+use crate::cpu::instructions::Instruction;
 use crate::interrupts::{InterruptEnableRegister, InterruptFlagRegister};
+#[cfg(all(feature = "frontend", debug_assertions, not(target_arch = "wasm32")))]
+use crate::memory_bus::{OAM_END, OAM_START, VRAM_BEGIN, VRAM_END};
 use crate::ppu::registers::{LCDCRegister, PPURegisters};
 use crate::ppu::tile_handling::{Tile, TilePixelValue};
 use crate::{CPU, MemoryBus, PPU};
@@ -11,6 +17,15 @@ use std::fs;
 use std::io::Write;
 
 pub const LOG_FILE_NAME: &str = "extensive_logs";
+/// The path of the JSON-lines trace of the executed instructions, see [json_trace_log].
+pub const JSON_TRACE_FILE_PATH: &str = "logs/trace.jsonl";
+/// The directory the VRAM and OAM dumps and the state dumps are written to, see
+/// [RustBoy::dump_vram_and_oam] and [RustBoy::dump_state].
+#[cfg(feature = "frontend")]
+pub(crate) const DUMPS_DIRECTORY: &str = "dumps";
+/// The number of bytes per line of the memory in the state dumps, see [RustBoy::dump_state].
+#[cfg(feature = "frontend")]
+const BYTES_PER_LINE_OF_MEMORY_DUMP: u16 = 16;
 
 /// Struct to represent the debugging information/flags.
 /// This struct contains various flags and handles used for debugging the emulator.
@@ -18,22 +33,27 @@ pub const LOG_FILE_NAME: &str = "extensive_logs";
 /// Fields:
 /// - `file_handle_doctor_logs`: Optional file handle for writing doctor logs.
 /// - `file_handle_extensive_logs`: Optional file handle for writing extensive logs.
+/// - `file_handle_json_trace`: Optional file handle for writing the JSON trace, see [json_trace_log].
 /// - `log_file_index`: Index of the current log file.
 /// - `current_number_of_lines_in_log_file`: Number of lines written to the current log file.
 /// - `doctor`: Flag indicating if the emulator runs in Game Boy Doctor compatible mode.
 /// - `file_logs`: Flag indicating if logs should be written to a file.
+/// - `json_trace`: Flag indicating if a JSON-lines trace of the executed instructions should be
+///   written to [JSON_TRACE_FILE_PATH].
 /// - `binjgb_mode`: Flag indicating if the emulator runs in binjgb mode.
 /// - `timing_mode`: Flag indicating if the emulator runs in timing mode.
 /// - `start_time`: Optional start time of the emulator, used in timing mode.
 /// - `sb_to_terminal`: Flag indicating if serial output should be printed to the terminal.
-#[derive(Debug)]
+#[derive(Debug, Default)]
 pub struct DebugInfo {
     pub file_handle_doctor_logs: Option<std::fs::File>,
     pub file_handle_extensive_logs: Option<std::fs::File>,
+    pub file_handle_json_trace: Option<std::fs::File>,
     pub log_file_index: u8,
     pub current_number_of_lines_in_log_file: u32,
     pub doctor: bool,
     pub file_logs: bool,
+    pub json_trace: bool,
     pub binjgb_mode: bool,
     pub timing_mode: bool,
     pub start_time: Option<Instant>,
@@ -103,7 +123,19 @@ pub fn setup_debugging_logs_files(debugging_flags: &mut DebugInfo) {
     }
 }
 
+/// Creates the file the JSON trace is written to, see [json_trace_log]. Unlike the other log
+/// files, the trace is not split into multiple files.
+#[cfg(debug_assertions)]
+pub fn setup_json_trace_file(debugging_flags: &mut DebugInfo) {
+    fs::create_dir_all("logs").unwrap();
+    debugging_flags.file_handle_json_trace = Some(
+        fs::File::create(JSON_TRACE_FILE_PATH)
+            .unwrap_or_else(|_| panic!("{JSON_TRACE_FILE_PATH} File should be openable")),
+    );
+}
+
 /// Helper function to log debugging information. Calls [doctor_log] for [LOG_FILE_NAME] and a provided log file name
+/// and [json_trace_log], if the JSON trace is enabled.
 #[cfg(debug_assertions)]
 pub fn doctor_log_helper(
     cpu: &mut CPU,
@@ -119,6 +151,78 @@ pub fn doctor_log_helper(
     if file_logs_flag {
         doctor_log(cpu, memory_bus, ppu, LOG_FILE_NAME)
     }
+    if cpu.debugging_flags.json_trace {
+        json_trace_log(cpu, memory_bus);
+    }
+}
+
+/// Writes the state of the emulator before the next instruction is executed as a line of JSON to
+/// [JSON_TRACE_FILE_PATH]. In contrast to the fixed-width doctor logs, this is meant to be parsed
+/// by external tools and scripts. Each line is an object with the following fields:
+/// - `pc`, `opcode` and `cb_prefixed`: The address and the opcode of the instruction. If
+///   `cb_prefixed` is true, `opcode` is the byte after the 0xCB prefix.
+/// - `mnemonic`: The decoded instruction, including its immediate operands, or null if the opcode
+///   is invalid.
+/// - `a`, `f`, `b`, `c`, `d`, `e`, `h`, `l` and `sp`: The registers of the CPU.
+/// - `flags`: The flags `z`, `n`, `h` and `c` of the F register as booleans.
+/// - `ime`: The interrupt master enable flag.
+/// - `cycles`: The number of m-cycles executed since the start of the emulation.
+/// - `ppu_mode` and `ly`: The mode of the PPU (0 - 3) and the current scanline.
+///
+/// All numbers are written as decimal numbers, since JSON has no hexadecimal numbers.
+#[cfg(debug_assertions)]
+pub fn json_trace_log(cpu: &CPU, memory_bus: &MemoryBus) {
+    let opcode_byte = memory_bus.peek(cpu.pc);
+    let cb_prefixed = opcode_byte == 0xCB;
+    let opcode = if cb_prefixed {
+        memory_bus.peek(cpu.pc.wrapping_add(1))
+    } else {
+        opcode_byte
+    };
+    // The mnemonic only consists of the debug representation of the instruction and its operands
+    // in hexadecimal, so it does not contain any characters which would have to be escaped
+    let mnemonic = match crate::cpu::instructions::Instruction::from_byte(opcode, cb_prefixed) {
+        Some(instruction) => format!(
+            "\"{}\"",
+            entire_instruction_to_string(cpu, memory_bus, instruction)
+                .split_whitespace()
+                .collect::<Vec<_>>()
+                .join(" ")
+        ),
+        None => "null".to_string(),
+    };
+    let registers = &cpu.registers;
+    let line = format!(
+        "{{\"pc\":{},\"opcode\":{},\"cb_prefixed\":{},\"mnemonic\":{},\"a\":{},\"f\":{},\"b\":{},\"c\":{},\"d\":{},\"e\":{},\"h\":{},\"l\":{},\"sp\":{},\"flags\":{{\"z\":{},\"n\":{},\"h\":{},\"c\":{}}},\"ime\":{},\"cycles\":{},\"ppu_mode\":{},\"ly\":{}}}\n",
+        cpu.pc,
+        opcode,
+        cb_prefixed,
+        mnemonic,
+        registers.a,
+        registers.f.get(),
+        registers.b,
+        registers.c,
+        registers.d,
+        registers.e,
+        registers.h,
+        registers.l,
+        cpu.sp,
+        registers.f.get_zero_flag(),
+        registers.f.get_subtract_flag(),
+        registers.f.get_half_carry_flag(),
+        registers.f.get_carry_flag(),
+        cpu.ime,
+        cpu.cycle_counter,
+        PPURegisters::get_ppu_mode(memory_bus).as_u8(),
+        // We peek the current scanline (LY) to get it without the sync checks of the CPU
+        memory_bus.peek(0xFF44),
+    );
+    cpu.debugging_flags
+        .file_handle_json_trace
+        .as_ref()
+        .expect("JSON trace file handle should be created")
+        .write_all(line.as_bytes())
+        .expect("Should be able to write data to JSON trace file");
 }
 
 /// Logs the state of the emulator to a log file.
@@ -138,25 +242,25 @@ pub fn doctor_log(cpu: &mut CPU, memory_bus: &MemoryBus, ppu: &PPU, log_file: &s
         cpu.registers.l,
         cpu.sp,
         cpu.pc,
-        memory_bus.read_byte(cpu.pc),
-        memory_bus.read_byte(cpu.pc.wrapping_add(1)),
-        memory_bus.read_byte(cpu.pc.wrapping_add(2)),
-        memory_bus.read_byte(cpu.pc.wrapping_add(3))
+        memory_bus.peek(cpu.pc),
+        memory_bus.peek(cpu.pc.wrapping_add(1)),
+        memory_bus.peek(cpu.pc.wrapping_add(2)),
+        memory_bus.peek(cpu.pc.wrapping_add(3))
     );
 
     if log_file == LOG_FILE_NAME {
         data.pop();
         data.push_str(&format!(
             " SPMEM:{:02X},{:02X},{:02X},{:02X},CURR:{:02X},{:02X},{:02X},{:02X},{:02X}",
-            memory_bus.read_byte(cpu.sp.saturating_sub(4)),
-            memory_bus.read_byte(cpu.sp.saturating_sub(3)),
-            memory_bus.read_byte(cpu.sp.saturating_sub(2)),
-            memory_bus.read_byte(cpu.sp.saturating_sub(1)),
-            memory_bus.read_byte(cpu.sp),
-            memory_bus.read_byte(cpu.sp.saturating_add(1)),
-            memory_bus.read_byte(cpu.sp.saturating_add(2)),
-            memory_bus.read_byte(cpu.sp.saturating_add(3)),
-            memory_bus.read_byte(cpu.sp.saturating_add(4)),
+            memory_bus.peek(cpu.sp.saturating_sub(4)),
+            memory_bus.peek(cpu.sp.saturating_sub(3)),
+            memory_bus.peek(cpu.sp.saturating_sub(2)),
+            memory_bus.peek(cpu.sp.saturating_sub(1)),
+            memory_bus.peek(cpu.sp),
+            memory_bus.peek(cpu.sp.saturating_add(1)),
+            memory_bus.peek(cpu.sp.saturating_add(2)),
+            memory_bus.peek(cpu.sp.saturating_add(3)),
+            memory_bus.peek(cpu.sp.saturating_add(4)),
         ));
 
         let stat_register = PPURegisters::get_lcd_status(memory_bus);
@@ -173,15 +277,15 @@ pub fn doctor_log(cpu: &mut CPU, memory_bus: &MemoryBus, ppu: &PPU, log_file: &s
         };
         data.push_str(&format!(" PPU:{}{}", ppu_mode_sign, ppu_mode_as_u8));
 
-        // We use the get scanline internal function to get the current scanline immediately
-        // from the memory without any additional sync checks done for syncing GPU and CPU.
-        let current_scanline = PPURegisters::get_scanline_internal(memory_bus);
+        // We peek the current scanline (LY) to get it immediately from the memory without any
+        // additional sync checks done for syncing GPU and CPU.
+        let current_scanline = memory_bus.peek(0xFF44);
         data.push_str(&format!(" SCANLINE:{:<3}", current_scanline));
 
         data.push_str(&format!(" IME:{}", u8::from(cpu.ime)));
         data.push_str(&format!(
             " IF:{:02X}",
-            InterruptFlagRegister::get_interrupt_flag_register(memory_bus)
+            InterruptFlagRegister::read_interrupt_flag_register(memory_bus)
         ));
         data.push_str(&format!(
             " IE:{:02X}",
@@ -333,7 +437,7 @@ fn push_next_immediate_byte_as_hex_to_string(
     memory_bus: &MemoryBus,
     string: &mut String,
 ) {
-    let first_immediate_byte = memory_bus.read_byte(cpu.pc + 1);
+    let first_immediate_byte = memory_bus.peek(cpu.pc + 1);
     string.push_str(&format!(" {:02X} ", first_immediate_byte,));
 }
 
@@ -343,14 +447,14 @@ fn push_next_immediate_byte_as_signed_integer_to_string(
     memory_bus: &MemoryBus,
     string: &mut String,
 ) {
-    let first_immediate_byte = memory_bus.read_byte(cpu.pc + 1) as i8;
+    let first_immediate_byte = memory_bus.peek(cpu.pc + 1) as i8;
     string.push_str(&format!(" {} ", first_immediate_byte,));
 }
 
 #[cfg(debug_assertions)]
 fn push_next_two_immediate_bytes_to_string(cpu: &CPU, memory_bus: &MemoryBus, string: &mut String) {
-    let first_immediate_byte = memory_bus.read_byte(cpu.pc + 1);
-    let second_immediate_byte = memory_bus.read_byte(cpu.pc + 2);
+    let first_immediate_byte = memory_bus.peek(cpu.pc + 1);
+    let second_immediate_byte = memory_bus.peek(cpu.pc + 2);
     string.push_str(&format!(
         " {:08b} {:08b} ",
         first_immediate_byte, second_immediate_byte
@@ -363,8 +467,8 @@ fn push_next_two_immediate_bytes_as_hex_big_endian_to_string(
     memory_bus: &MemoryBus,
     string: &mut String,
 ) {
-    let first_immediate_byte = memory_bus.read_byte(cpu.pc + 1);
-    let second_immediate_byte = memory_bus.read_byte(cpu.pc + 2);
+    let first_immediate_byte = memory_bus.peek(cpu.pc + 1);
+    let second_immediate_byte = memory_bus.peek(cpu.pc + 2);
     string.push_str(&format!(
         " {:02X} {:02X} ",
         second_immediate_byte, first_immediate_byte
@@ -377,10 +481,10 @@ fn push_next_four_immediate_bytes_as_hex_to_string(
     memory_bus: &MemoryBus,
     string: &mut String,
 ) {
-    let first_immediate_byte = memory_bus.read_byte(cpu.pc + 1);
-    let second_immediate_byte = memory_bus.read_byte(cpu.pc + 2);
-    let third_immediate_byte = memory_bus.read_byte(cpu.pc + 3);
-    let fourth_immediate_byte = memory_bus.read_byte(cpu.pc + 4);
+    let first_immediate_byte = memory_bus.peek(cpu.pc + 1);
+    let second_immediate_byte = memory_bus.peek(cpu.pc + 2);
+    let third_immediate_byte = memory_bus.peek(cpu.pc + 3);
+    let fourth_immediate_byte = memory_bus.peek(cpu.pc + 4);
     string.push_str(&format!(
         " {:02X} {:02X} {:02X} {:02X} ",
         first_immediate_byte, second_immediate_byte, third_immediate_byte, fourth_immediate_byte
@@ -399,6 +503,147 @@ fn push_match_interrupt_location_to_interrupt_name(interrupt_location: u16) -> O
     }
 }
 
+#[cfg(feature = "frontend")]
+impl RustBoy {
+    /// Logs the current state of the registers, that is, the registers of the CPU together with
+    /// IME, IF and IE, the mode of the PPU together with LY and the timer registers DIV, TIMA, TMA
+    /// and TAC. Is triggered by pressing `D` and is used as a quick alternative to reading the
+    /// doctor logs.
+    pub(crate) fn log_register_dump(&self) {
+        log::info!("Registers:\n{}", self.registers_to_dump_string());
+    }
+
+    /// Executes a single instruction using [RustBoy::step] and logs it together with the registers
+    /// afterward. Is triggered by pressing `,` while the emulator is paused and is used to trace
+    /// code by hand. If the instruction is at a breakpoint, it is executed anyway, like when the
+    /// emulation is resumed. While the CPU is halted, a single m-cycle passes instead. If an
+    /// interrupt is dispatched, the first instruction of its handler is executed as well.
+    pub(crate) fn step_instruction_and_log(&mut self) {
+        let pc = self.cpu.pc;
+        let halted = self.cpu.halted;
+        let interrupt_is_dispatched = self.cpu.ime
+            && InterruptFlagRegister::get_interrupt_flag_register(&self.memory_bus)
+                & InterruptEnableRegister::get_interrupt_enable_register(&self.memory_bus)
+                & 0x1F
+                != 0;
+        let instruction = match self.memory_bus.peek(pc) {
+            0xCB => Instruction::from_byte(self.memory_bus.peek(pc.wrapping_add(1)), true),
+            instruction_byte => Instruction::from_byte(instruction_byte, false),
+        };
+
+        let mut result = self.step();
+        // Stepping stops before an instruction at a breakpoint, so it is stepped again to execute
+        // it. The hit is taken, so the emulation does not pause again once it is resumed
+        if result.is_ok() && self.take_breakpoint_hit().is_some() {
+            result = self.step();
+        }
+        if let Err(error) = result {
+            log::error!("Failed to step: {error}");
+            return;
+        }
+
+        if halted {
+            log::info!("Stepped while halted: {}", self.cpu.registers_to_string());
+        } else if interrupt_is_dispatched {
+            log::info!(
+                "Dispatched an interrupt at {pc:#06X}: {}",
+                self.cpu.registers_to_string()
+            );
+        } else {
+            // Executing an invalid instruction fails, so the instruction is known here
+            let instruction = instruction.expect("Executed instruction should be valid");
+            log::info!(
+                "Stepped {pc:#06X}: {instruction:?}: {}",
+                self.cpu.registers_to_string()
+            );
+        }
+    }
+
+    /// Returns the registers logged by [RustBoy::log_register_dump] as a string with one line per
+    /// component.
+    fn registers_to_dump_string(&self) -> String {
+        let memory_bus = &self.memory_bus;
+        format!(
+            "CPU:   {}\n\
+             IRQ:   IF:{:02X} IE:{:02X}\n\
+             PPU:   Mode:{:?} LY:{} LCD on:{}\n\
+             Timer: DIV:{:02X} TIMA:{:02X} TMA:{:02X} TAC:{:02X}\n\
+             APU:   NR52:{:02X} Frame sequencer step:{} Channel volumes:{:?}",
+            self.cpu.registers_to_string(),
+            InterruptFlagRegister::read_interrupt_flag_register(memory_bus),
+            InterruptEnableRegister::get_interrupt_enable_register(memory_bus),
+            PPURegisters::get_ppu_mode(memory_bus),
+            PPURegisters::get_scanline_internal(memory_bus),
+            LCDCRegister::get_display_on_flag(memory_bus),
+            memory_bus.peek(0xFF04),
+            memory_bus.peek(0xFF05),
+            memory_bus.peek(0xFF06),
+            memory_bus.peek(0xFF07),
+            memory_bus.read_byte(0xFF26),
+            memory_bus.apu.frame_sequencer_step,
+            memory_bus.apu.channels.map(|channel| channel.volume),
+        )
+    }
+
+    /// Writes the current state to a text file at the provided path, so it can be compared to
+    /// the state of a reference emulator at the same point, see the `--DUMP-AFTER` option. The
+    /// file contains the number of m-cycles executed so far, the registers (see
+    /// [RustBoy::log_register_dump]) and the entire memory as seen by [MemoryBus::peek], with
+    /// [BYTES_PER_LINE_OF_MEMORY_DUMP] bytes per line, each line prefixed with its address.
+    pub(crate) fn dump_state(&self, path: &std::path::Path) -> std::io::Result<()> {
+        let mut dump = format!(
+            "M-cycles: {}\n{}\n\nMemory:\n",
+            self.cpu.cycle_counter,
+            self.registers_to_dump_string()
+        );
+        for line_begin in (0..=u16::MAX).step_by(BYTES_PER_LINE_OF_MEMORY_DUMP as usize) {
+            dump.push_str(&format!("{line_begin:04X}:"));
+            for address in line_begin..=line_begin + (BYTES_PER_LINE_OF_MEMORY_DUMP - 1) {
+                dump.push_str(&format!(" {:02X}", self.memory_bus.peek(address)));
+            }
+            dump.push('\n');
+        }
+        if let Some(directory) = path.parent() {
+            fs::create_dir_all(directory)?;
+        }
+        fs::write(path, dump)
+    }
+
+    /// Writes the current VRAM (0x8000 - 0x9FFF) and OAM (0xFE00 - 0xFE9F) to the binary files
+    /// `vram_<timestamp>.bin` and `oam_<timestamp>.bin` in [DUMPS_DIRECTORY], where the timestamp
+    /// is the number of milliseconds since the Unix epoch. Is triggered by pressing `V` and is
+    /// used to analyze the graphics data with external tools, like tile viewers.
+    ///
+    /// Only available in debug builds and not on the web, since there is neither a file system
+    /// nor a system clock there.
+    #[cfg(all(debug_assertions, not(target_arch = "wasm32")))]
+    pub(crate) fn dump_vram_and_oam(&self) {
+        let milliseconds_since_epoch = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|duration| duration.as_millis())
+            .unwrap_or_default();
+        let directory = std::path::Path::new(DUMPS_DIRECTORY);
+        if let Err(error) = fs::create_dir_all(directory) {
+            log::warn!(
+                "Failed to create dump directory {}: {}",
+                directory.display(),
+                error
+            );
+            return;
+        }
+        for (name, begin, end) in [("vram", VRAM_BEGIN, VRAM_END), ("oam", OAM_START, OAM_END)] {
+            let data = (begin..=end)
+                .map(|address| self.memory_bus.peek(address))
+                .collect::<Vec<u8>>();
+            let path = directory.join(format!("{name}_{milliseconds_since_epoch}.bin"));
+            match fs::write(&path, data) {
+                Ok(()) => log::info!("Dumped {} to {}", name.to_uppercase(), path.display()),
+                Err(error) => log::warn!("Failed to write {}: {}", path.display(), error),
+            }
+        }
+    }
+}
+
 impl PPU {
     /// Returns the current tile set for the background and window. Switches the addressing mode
     /// automatically according to LCDC bit 4 (background_and_window_tile_data) as tile structs.
@@ -0,0 +1,67 @@
+//! This module contains the [RustBoyError] enum, which represents the errors that can occur when
+//! loading or running a ROM. Instead of aborting the whole process, these are returned by the
+//! fallible public entry points, like [crate::RustBoy::from_rom] and [crate::RustBoy::run_frame].
+
+use std::fmt;
+
+/// Represents the errors that can occur when loading or running a ROM. These are caused by
+/// invalid, unsupported or buggy ROMs and not by bugs of the emulator itself.
+///
+/// - `MissingCartridgeHeader`: The ROM is too short to contain a (complete) cartridge header, see
+///   [Pan Docs - The Cartridge Header](https://gbdev.io/pandocs/The_Cartridge_Header.html).
+/// - `UnsupportedCartridgeType`: The memory bank controller (MBC) the cartridge type in the
+///   header indicates is not supported (yet).
+/// - `InvalidInstruction`: The CPU tried executing an opcode that does not exist. On the original
+///   Game Boy, this locks up the CPU. See
+///   [Pan Docs - CPU Instruction Set](https://gbdev.io/pandocs/CPU_Instruction_Set.html).
+/// - `InvalidSaveState`: The provided save state could not be loaded. Contains the reason why.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RustBoyError {
+    /// The ROM is too short to contain a (complete) cartridge header.
+    MissingCartridgeHeader {
+        /// The size of the ROM in bytes.
+        rom_size: usize,
+    },
+    /// The cartridge type is not supported.
+    UnsupportedCartridgeType(u8),
+    /// The CPU tried executing an opcode that does not exist.
+    InvalidInstruction {
+        /// The opcode that does not exist.
+        opcode: u8,
+        /// Whether the opcode was prefixed with 0xCB.
+        prefixed: bool,
+        /// The address the opcode was read from.
+        address: u16,
+    },
+    /// The provided save state could not be loaded.
+    InvalidSaveState(&'static str),
+}
+
+impl fmt::Display for RustBoyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RustBoyError::MissingCartridgeHeader { rom_size } => write!(
+                f,
+                "The ROM is too short to contain a cartridge header ({rom_size} bytes)"
+            ),
+            RustBoyError::UnsupportedCartridgeType(cartridge_type) => write!(
+                f,
+                "The cartridge type {cartridge_type:#04X} is not supported yet"
+            ),
+            RustBoyError::InvalidInstruction {
+                opcode,
+                prefixed,
+                address,
+            } => write!(
+                f,
+                "Invalid instruction 0x{}{opcode:02X} at {address:#06X}",
+                if *prefixed { "CB" } else { "" }
+            ),
+            RustBoyError::InvalidSaveState(reason) => {
+                write!(f, "The save state is invalid: {reason}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for RustBoyError {}
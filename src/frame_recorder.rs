@@ -0,0 +1,174 @@
+//! This module contains the [FrameRecorder] struct and its methods, which are used to record
+//! short clips of the emulator's screen. The recording is toggled by pressing `R`.
+//!
+//! The frames are written as a sequence of binary PPM images (`frame_00000.ppm`,
+//! `frame_00001.ppm`, ...) to a new directory within [RECORDINGS_DIRECTORY]. These can then be
+//! converted to a video or GIF with external tools, e.g. using ffmpeg:
+//! `ffmpeg -framerate 30 -i frame_%05d.ppm recording.gif`.
+//!
+//! The recording is not available on the web, since there is neither a file system nor a system
+//! clock (which names the directories) there. Only [encode_ppm] is compiled for the web.
+
+use std::io::Write;
+#[cfg(not(target_arch = "wasm32"))]
+use std::path::PathBuf;
+
+#[cfg(not(target_arch = "wasm32"))]
+use crate::TARGET_FPS;
+#[cfg(not(target_arch = "wasm32"))]
+use crate::notifications::Notifications;
+use crate::{ORIGINAL_SCREEN_HEIGHT, ORIGINAL_SCREEN_WIDTH};
+
+/// The directory the recordings are written to. Each recording is written to its own
+/// subdirectory named after the time the recording was started.
+#[cfg(not(target_arch = "wasm32"))]
+const RECORDINGS_DIRECTORY: &str = "recordings";
+/// The maximum number of frames of a single recording. If it is reached, the recording is
+/// stopped automatically to avoid unbounded disk usage.
+#[cfg(not(target_arch = "wasm32"))]
+const MAX_FRAMES_PER_RECORDING: u32 = 3600;
+
+/// Struct to keep track of the recording of frames.
+///
+/// - `frame_interval`: Only every `frame_interval`-th frame is captured, to downsample the
+///   [TARGET_FPS] of the emulator to the framerate of the recording.
+/// - `recording`: The recording currently running, if any.
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) struct FrameRecorder {
+    frame_interval: u32,
+    recording: Option<Recording>,
+}
+
+/// Struct to represent a running recording.
+///
+/// - `directory`: The directory the frames are written to.
+/// - `frames_until_next_capture`: The number of frames until the next frame is captured.
+/// - `number_of_captured_frames`: The number of frames captured so far.
+#[cfg(not(target_arch = "wasm32"))]
+struct Recording {
+    directory: PathBuf,
+    frames_until_next_capture: u32,
+    number_of_captured_frames: u32,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl FrameRecorder {
+    /// Creates a new instance of FrameRecorder, which records with the provided framerate once
+    /// it is started. The framerate is capped at [TARGET_FPS].
+    pub(crate) fn new(target_fps: u32) -> FrameRecorder {
+        FrameRecorder {
+            frame_interval: (TARGET_FPS / target_fps.max(1) as f64).round().max(1.0) as u32,
+            recording: None,
+        }
+    }
+
+    /// Starts a new recording, if none is running. Otherwise, stops the running recording.
+    pub(crate) fn toggle_recording(&mut self, notifications: &mut Notifications) {
+        if self.recording.is_some() {
+            self.stop_recording(notifications);
+        } else {
+            self.start_recording(notifications);
+        }
+    }
+
+    /// Starts a new recording by creating the directory the frames are written to.
+    fn start_recording(&mut self, notifications: &mut Notifications) {
+        let seconds_since_epoch = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|duration| duration.as_secs())
+            .unwrap_or_default();
+        let directory =
+            PathBuf::from(RECORDINGS_DIRECTORY).join(format!("recording_{seconds_since_epoch}"));
+        match std::fs::create_dir_all(&directory) {
+            Ok(()) => {
+                notifications.post("Started recording");
+                log::info!("Recording frames to {}", directory.display());
+                self.recording = Some(Recording {
+                    directory,
+                    frames_until_next_capture: 0,
+                    number_of_captured_frames: 0,
+                });
+            }
+            Err(error) => log::warn!(
+                "Failed to create recording directory {}: {}",
+                directory.display(),
+                error
+            ),
+        }
+    }
+
+    /// Stops the running recording, if any.
+    fn stop_recording(&mut self, notifications: &mut Notifications) {
+        if let Some(recording) = self.recording.take() {
+            notifications.post(format!(
+                "Stopped recording after {} frames",
+                recording.number_of_captured_frames
+            ));
+            log::info!("Recorded frames to {}", recording.directory.display());
+        }
+    }
+
+    /// Is called once per frame and returns true, if a recording is running and the current
+    /// frame should be captured using [FrameRecorder::capture_frame].
+    pub(crate) fn frame_is_due(&mut self) -> bool {
+        let Some(recording) = &mut self.recording else {
+            return false;
+        };
+        if recording.frames_until_next_capture == 0 {
+            recording.frames_until_next_capture = self.frame_interval - 1;
+            true
+        } else {
+            recording.frames_until_next_capture -= 1;
+            false
+        }
+    }
+
+    /// Writes the provided RGBA framebuffer of size [ORIGINAL_SCREEN_WIDTH] x
+    /// [ORIGINAL_SCREEN_HEIGHT] as the next frame of the running recording. Stops the recording,
+    /// if writing fails or [MAX_FRAMES_PER_RECORDING] is reached.
+    pub(crate) fn capture_frame(&mut self, framebuffer: &[u8], notifications: &mut Notifications) {
+        let Some(recording) = &mut self.recording else {
+            return;
+        };
+        let path = recording.directory.join(format!(
+            "frame_{:05}.ppm",
+            recording.number_of_captured_frames
+        ));
+        if let Err(error) = write_ppm(&path, framebuffer) {
+            log::warn!("Failed to write frame {}: {}", path.display(), error);
+            self.stop_recording(notifications);
+            return;
+        }
+
+        recording.number_of_captured_frames += 1;
+        if recording.number_of_captured_frames >= MAX_FRAMES_PER_RECORDING {
+            log::info!("Maximum recording length of {MAX_FRAMES_PER_RECORDING} frames reached");
+            self.stop_recording(notifications);
+        }
+    }
+}
+
+/// Writes the provided RGBA framebuffer as a binary PPM image (P6) to the provided path. The alpha
+/// channel is dropped.
+#[cfg(not(target_arch = "wasm32"))]
+fn write_ppm(path: &PathBuf, framebuffer: &[u8]) -> std::io::Result<()> {
+    let mut file = std::io::BufWriter::new(std::fs::File::create(path)?);
+    encode_ppm(&mut file, framebuffer)?;
+    file.flush()
+}
+
+/// Encodes the provided RGBA framebuffer of size [ORIGINAL_SCREEN_WIDTH] x
+/// [ORIGINAL_SCREEN_HEIGHT] as a binary PPM image (P6) into the provided writer. The alpha channel
+/// is dropped. Since each image starts with its own header, several images can be written to the
+/// same stream, which tools like ffmpeg read as a video (`-f image2pipe`).
+pub(crate) fn encode_ppm(writer: &mut impl Write, framebuffer: &[u8]) -> std::io::Result<()> {
+    write!(
+        writer,
+        "P6\n{} {}\n255\n",
+        ORIGINAL_SCREEN_WIDTH, ORIGINAL_SCREEN_HEIGHT
+    )?;
+    for pixel in framebuffer.chunks_exact(4) {
+        writer.write_all(&pixel[..3])?;
+    }
+    Ok(())
+}
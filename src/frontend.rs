@@ -2,34 +2,138 @@
 //! It uses a compute shader to render the screen scanline by scanline to a offscreen (not actually
 //! framebuffer) texture. The texture is then rendered to the screen using a render shader.
 
+mod font;
 pub(crate) mod shader;
+mod text_overlay;
 
-use winit::event::WindowEvent;
+#[cfg(target_arch = "wasm32")]
+use std::cell::RefCell;
+#[cfg(target_arch = "wasm32")]
+use std::rc::Rc;
+
+use winit::event::{ElementState, KeyEvent, WindowEvent};
+use winit::keyboard::{KeyCode, PhysicalKey};
 use winit::window::Window;
 
-use super::{MemoryBus, ORIGINAL_SCREEN_WIDTH};
+use super::{MemoryBus, ORIGINAL_SCREEN_HEIGHT, ORIGINAL_SCREEN_WIDTH};
+use crate::frontend::font::GLYPH_SIZE;
 use crate::frontend::shader::{
-    ObjectsInScanline, TileData, TilemapUniform, setup_render_shader_pipeline,
-    setup_scanline_shader_pipeline,
+    TileData, TilemapUniform, UpscalingMode, setup_lcd_overlay_pipeline,
+    setup_render_shader_pipeline, setup_scanline_shader_pipeline, setup_text_overlay_pipeline,
+};
+use crate::frontend::text_overlay::{
+    TEXT_OVERLAY_HEIGHT, TEXT_OVERLAY_WIDTH, TextOverlay, with_opacity,
 };
+use crate::notifications::Notifications;
 use crate::ppu::PPU;
 use crate::ppu::information_for_shader::ChangesToPropagateToShader;
-use crate::ppu::object_handling::custom_ordering;
+
+/// The width of the bezel drawn by the LCD overlay, relative to the shorter side of the window.
+const LCD_BEZEL_WIDTH: f32 = 0.06;
+/// The largest frame-skip which is reached by pressing `F`, before it wraps around to 0, see
+/// [State::present_is_due].
+const MAX_FRAME_SKIP_OF_HOTKEY: u32 = 3;
+
+/// The color of the text of notifications (RGBA).
+const NOTIFICATION_TEXT_COLOR: [u8; 4] = [0xFF, 0xFF, 0xFF, 0xFF];
+/// The color of the box behind the text of notifications (RGBA), which keeps the text readable on
+/// top of bright and dark games alike.
+const NOTIFICATION_BACKGROUND_COLOR: [u8; 4] = [0x10, 0x10, 0x10, 0xB0];
+/// The space between the text of a notification and the edge of its box and between the boxes
+/// and the edge of the screen in pixels of the text overlay.
+const NOTIFICATION_PADDING: u32 = 3;
+
+/// Enum to represent the present mode requested for the surface, which trades input latency
+/// against tearing. If the requested mode is not supported, the closest supported one is used
+/// instead, see [PresentMode::select].
+///
+/// - `Auto`: The first mode the surface supports, which is the preferred one of the platform. Is
+///   the default.
+/// - `Vsync`: Frames are presented in sync with the display (Fifo), which avoids tearing, but
+///   adds latency. Is supported everywhere.
+/// - `Mailbox`: Frames are presented in sync with the display, but newer frames replace the
+///   waiting ones, which avoids tearing with less latency than `Vsync`.
+/// - `Immediate`: Frames are presented right away, which has the lowest latency, but may tear.
+#[cfg_attr(target_arch = "wasm32", wasm_bindgen::prelude::wasm_bindgen)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum PresentMode {
+    /// The preferred mode of the platform.
+    #[default]
+    Auto,
+    /// Presenting in sync with the display without tearing (Fifo).
+    Vsync,
+    /// Presenting in sync with the display, replacing the waiting frames.
+    Mailbox,
+    /// Presenting right away.
+    Immediate,
+}
+
+impl std::str::FromStr for PresentMode {
+    type Err = String;
+
+    /// Parses the mode from its name, that is, `auto`, `vsync`, `mailbox` or `immediate`.
+    fn from_str(name: &str) -> Result<PresentMode, String> {
+        match name.to_lowercase().as_str() {
+            "auto" => Ok(PresentMode::Auto),
+            "vsync" => Ok(PresentMode::Vsync),
+            "mailbox" => Ok(PresentMode::Mailbox),
+            "immediate" => Ok(PresentMode::Immediate),
+            _ => Err(format!(
+                "Invalid present mode '{name}', expected 'auto', 'vsync', 'mailbox' or 'immediate'"
+            )),
+        }
+    }
+}
+
+impl PresentMode {
+    /// Returns the mode of wgpu to configure the surface with, given the provided modes the
+    /// surface supports (of which the first one is the preferred one). If the requested mode is
+    /// not supported, the other low latency mode is tried for `Mailbox` and `Immediate`, before
+    /// falling back to Fifo, which every surface supports.
+    fn select(self, supported_modes: &[wgpu::PresentMode]) -> wgpu::PresentMode {
+        let candidates: &[wgpu::PresentMode] = match self {
+            PresentMode::Auto => return supported_modes[0],
+            PresentMode::Vsync => &[wgpu::PresentMode::Fifo],
+            PresentMode::Mailbox => &[wgpu::PresentMode::Mailbox, wgpu::PresentMode::Immediate],
+            PresentMode::Immediate => &[wgpu::PresentMode::Immediate, wgpu::PresentMode::Mailbox],
+        };
+        let selected_mode = candidates
+            .iter()
+            .find(|mode| supported_modes.contains(mode))
+            .copied()
+            .unwrap_or(wgpu::PresentMode::Fifo);
+        if selected_mode != candidates[0] {
+            log::warn!(
+                "The present mode {:?} is not supported, using {selected_mode:?} instead",
+                candidates[0]
+            );
+        }
+        selected_mode
+    }
+}
 
 /// Big struct capturing the current state of the window and shader pipeline, including its buffers.
 pub struct State<'a> {
-    /// The surface to render to (the window's screen).
-    surface: wgpu::Surface<'a>,
+    /// The surface to render to (the window's screen). Is only None while the state is rebuilt,
+    /// see [State::rebuild].
+    surface: Option<wgpu::Surface<'a>>,
     /// The device to use for rendering (the GPU).
     device: wgpu::Device,
     /// The queue to use for rendering (the command queue).
     queue: wgpu::Queue,
     /// The configuration for the surface.
     config: wgpu::SurfaceConfiguration,
+    /// The present mode requested for the surface, which is kept when the state is rebuilt, see
+    /// [State::rebuild].
+    present_mode: PresentMode,
     /// The size of the window.
     pub(super) size: winit::dpi::PhysicalSize<u32>,
     /// The window to render to, which "owns" the surface.
     pub(super) window: &'a Window,
+    /// The state which is rebuilt in the background on the web, see [State::rebuild]. Is None
+    /// until the new state is ready, after which it is swapped in by [State::poll_rebuild].
+    #[cfg(target_arch = "wasm32")]
+    rebuilt_state: Rc<RefCell<Option<State<'a>>>>,
 
     /// The render pipeline to use for rendering.
     render_pipeline: wgpu::RenderPipeline,
@@ -46,6 +150,52 @@ pub struct State<'a> {
     /// The bind group corresponding to the render pipeline which renders the
     /// `framebuffer_texture` to the screen.
     render_bind_group: wgpu::BindGroup,
+    /// The mode used to upscale the `framebuffer_texture` to the size of the window. Determines
+    /// the fragment shader of the render pipeline.
+    upscaling_mode: UpscalingMode,
+
+    /// The pipeline drawing the optional LCD overlay (bezel, pixel grid and shadow) on top of the
+    /// framebuffer texture, see [shader::setup_lcd_overlay_pipeline].
+    lcd_overlay_pipeline: wgpu::RenderPipeline,
+    /// The buffer holding the rectangle the Game Boy screen is drawn to, that is, its width,
+    /// height and the offset of its top left corner within the window (in pixels).
+    lcd_overlay_screen_rect_buffer: wgpu::Buffer,
+    /// The bind group corresponding to the LCD overlay pipeline.
+    lcd_overlay_bind_group: wgpu::BindGroup,
+    /// Whether the LCD overlay is drawn. Is toggled by pressing `G` and off by default.
+    lcd_overlay_enabled: bool,
+
+    /// The pipeline drawing the text overlay (e.g. notifications) on top of the Game Boy screen,
+    /// see [shader::setup_text_overlay_pipeline].
+    text_overlay_pipeline: wgpu::RenderPipeline,
+    /// The texture the text overlay is uploaded to, see [TextOverlay].
+    text_overlay_texture: wgpu::Texture,
+    /// The buffer holding the rectangle the Game Boy screen is drawn to, like
+    /// `lcd_overlay_screen_rect_buffer`.
+    text_overlay_screen_rect_buffer: wgpu::Buffer,
+    /// The bind group corresponding to the text overlay pipeline.
+    text_overlay_bind_group: wgpu::BindGroup,
+    /// The text drawn on top of the Game Boy screen. It is cleared after every presented frame,
+    /// so the notifications and the text drawn using [State::draw_text] are drawn anew for every
+    /// frame.
+    text_overlay: TextOverlay,
+    /// Whether any text was drawn in the last presented frame. If so, the frame has to be
+    /// presented again once the text is gone, even if the emulator is paused, see
+    /// [State::text_overlay_needs_redraw].
+    text_overlay_shown: bool,
+
+    /// The number of frames which are not presented to the screen after each presented frame,
+    /// see [State::present_is_due]. Is cycled by pressing `F` and 0 by default.
+    frame_skip: u32,
+    /// The number of frames which were skipped since the last presented frame.
+    frames_skipped_since_last_present: u32,
+    /// Whether `S` is held, in which case the emulator runs in slow motion, see
+    /// [SLOW_MOTION_SPEED](crate::SLOW_MOTION_SPEED).
+    pub(super) slow_motion_held: bool,
+    /// Whether `.` was pressed to advance a single frame while the emulator is paused. Is reset
+    /// once the frame was emulated, see
+    /// [handle_redraw_requested_event](crate::handle_redraw_requested_event).
+    pub(super) frame_advance_requested: bool,
 
     /// The compute pipeline that runs the compute shader. This shader writes to the
     /// framebuffer texture for every RustBoy render line (144 times per frame).
@@ -57,6 +207,10 @@ pub struct State<'a> {
     scanline_buffer_pipeline_num_vertices: u32,
     /// The bind group corresponding to the compute pipeline.
     scanline_buffer_bind_group: wgpu::BindGroup,
+    /// The first and last scanline which were not rendered yet. Instead of rendering every
+    /// scanline on its own, the scanlines are batched and rendered at once, see
+    /// [State::render_scanline].
+    pending_scanlines: Option<(u8, u8)>,
 
     /// The buffer to hold the background and window tile data. It consists of 16 x 16 tiles in a
     /// 2D grid, each of which is 8 x 8 pixels. Each pixel takes up two bits, which results in 16
@@ -73,16 +227,19 @@ pub struct State<'a> {
     /// where the first two are the x and y position of the background and the last two the x and y
     /// positions of the window. Note that for the background, this can be interpreted as the position
     /// of the screen within the background tilemap, whereas for the window it can be seen as the
-    /// position of the window (tilemap) within the screen.
+    /// position of the window (tilemap) within the screen. Holds one entry per scanline, like
+    /// the other per-scanline buffers below, see [State::render_scanline].
     bg_and_wd_viewport_buffer: wgpu::Buffer,
     /// The buffer to hold the object/sprite tile data. It consists of 16 x 16 tiles in a
     /// 2D grid, each of which is 8 x 8 pixels. Each pixel takes up two bits, which results in 16
     /// bytes per tile and a total size of 4096 bytes for the buffer.
     object_tile_data_buffer: wgpu::Buffer,
-    /// This buffer contains the objects that should be drawn on the current scanline. It always
-    /// has length 10, but the number of objects that are in the current scanline might be less.
-    /// In that case, the rest of the buffer is filled with zeroes. Each object consists of four
-    /// bytes which give the information about the object. The bytes are as follows:
+    /// This buffer contains the objects that should be drawn on each scanline. Each entry always
+    /// has length 10, but the number of objects that are in the scanline might be less.
+    /// In that case, the rest of the entry is filled with zeroes. Each object consists of four
+    /// bytes which are packed into a single u32, see
+    /// [PackedObjectsInScanline](crate::ppu::information_for_shader::PackedObjectsInScanline).
+    /// The bytes are as follows:
     /// - Byte 0: The y coordinate of the object (with some extras, see [Pan Docs - OAM](https://gbdev.io/pandocs/OAM.html)).
     /// - Byte 1: The x coordinate of the object (with some extras, see [Pan Docs - OAM](https://gbdev.io/pandocs/OAM.html)).
     /// - Byte 2: The tile index of the object.
@@ -90,7 +247,8 @@ pub struct State<'a> {
     ///
     /// See also [Pan Docs - OAM](https://gbdev.io/pandocs/OAM.html).
     objects_in_scanline_buffer: wgpu::Buffer,
-    /// Buffer to hold the palette data (a u32 array of 4 elements). The first three elements
+    /// Buffer to hold the palette data of each scanline (a u32 array of 4 elements and the Super
+    /// Game Boy colors). The first three elements
     /// mirror the registers FF47, FF48, and FF49 as specified in the Pandocs
     /// (https://gbdev.io/pandocs/Palettes.html), making them available to the shader.
     /// - The first entry is the background palette (FF47).
@@ -98,8 +256,8 @@ pub struct State<'a> {
     /// - The third entry is the object palette 1 (FF49).
     /// - The fourth entry is empty (zero).
     palette_buffer: wgpu::Buffer,
-    /// Buffer to hold different rendering info.
-    /// This includes the current scanline, the LCD control register, and the window
+    /// Buffer to hold different rendering info of each scanline.
+    /// This includes the scanline, the LCD control register, and the window
     /// internal line info. More precisely the entries are as follows:
     /// - The first entry is the current scanline index.
     /// - The second entry is the LCD control register (FF40).
@@ -117,10 +275,50 @@ pub struct State<'a> {
     framebuffer_texture: wgpu::Texture,
 }
 
+/// On the web, the adapter and the device can only be requested asynchronously, since the browser
+/// resolves the requests in its event loop, which blocking on them would stall. So the state is
+/// rebuilt in the background instead, which requires the window to live for the rest of the
+/// program.
+#[cfg(target_arch = "wasm32")]
+impl State<'static> {
+    /// Rebuilds the whole state in the background, see the native version of this function for
+    /// when this is needed. Until the new state is swapped in by [State::poll_rebuild], nothing is
+    /// presented. Calls while a rebuild is in progress are ignored.
+    pub(crate) fn rebuild(&mut self, _memory_bus: &mut MemoryBus) {
+        // The surface is only None while a rebuild is in progress
+        if self.surface.is_none() {
+            return;
+        }
+        log::warn!("Rebuilding the renderer to recover the surface");
+        self.surface = None;
+
+        let (window, present_mode) = (self.window, self.present_mode);
+        let rebuilt_state = Rc::clone(&self.rebuilt_state);
+        wasm_bindgen_futures::spawn_local(async move {
+            let state = State::new(window, present_mode).await;
+            *rebuilt_state.borrow_mut() = Some(state);
+        });
+    }
+
+    /// Swaps in the state rebuilt by [State::rebuild], if it is ready. Returns false, if a rebuild
+    /// is still in progress, in which case the screen can not be presented yet.
+    pub(crate) fn poll_rebuild(&mut self, memory_bus: &mut MemoryBus) -> bool {
+        if self.surface.is_some() {
+            return true;
+        }
+        let Some(state) = self.rebuilt_state.borrow_mut().take() else {
+            return false;
+        };
+        self.replace_with_rebuilt_state(state, memory_bus);
+        true
+    }
+}
+
 impl<'a> State<'a> {
     /// Creates a new instance of [State]. This function is called once at the beginning of the
-    /// program to set up the GPU (of the Host) and the window.
-    pub async fn new(window: &'a Window) -> State<'a> {
+    /// program to set up the GPU (of the Host) and the window. The surface is configured with the
+    /// provided present mode, or the closest supported one, see [PresentMode::select].
+    pub async fn new(window: &'a Window, present_mode: PresentMode) -> State<'a> {
         let size = window.inner_size();
 
         // The instance is a handle to our GPU
@@ -175,7 +373,7 @@ impl<'a> State<'a> {
             format: surface_format,
             width: size.width,
             height: size.height,
-            present_mode: surface_caps.present_modes[0],
+            present_mode: present_mode.select(&surface_caps.present_modes),
             alpha_mode: surface_caps.alpha_modes[0],
             view_formats: vec![],
             desired_maximum_frame_latency: 2,
@@ -203,25 +401,59 @@ impl<'a> State<'a> {
             screensize_buffer,
             render_pipeline_num_vertices,
             render_bind_group,
-        ) = setup_render_shader_pipeline(&device, &config, &framebuffer_texture);
+        ) = setup_render_shader_pipeline(
+            &device,
+            &config,
+            &framebuffer_texture,
+            UpscalingMode::default(),
+        );
+
+        let (lcd_overlay_pipeline, lcd_overlay_screen_rect_buffer, lcd_overlay_bind_group) =
+            setup_lcd_overlay_pipeline(&device, &config);
+
+        let (
+            text_overlay_pipeline,
+            text_overlay_texture,
+            text_overlay_screen_rect_buffer,
+            text_overlay_bind_group,
+        ) = setup_text_overlay_pipeline(&device, &config);
 
         Self {
-            surface,
+            surface: Some(surface),
             device,
             queue,
             config,
+            present_mode,
             size,
             window,
+            #[cfg(target_arch = "wasm32")]
+            rebuilt_state: Rc::default(),
             render_pipeline,
             render_pipeline_vertex_buffer,
             screensize_buffer,
             screensize_changed: false,
             render_pipeline_num_vertices,
             render_bind_group,
+            upscaling_mode: UpscalingMode::default(),
+            lcd_overlay_pipeline,
+            lcd_overlay_screen_rect_buffer,
+            lcd_overlay_bind_group,
+            lcd_overlay_enabled: false,
+            text_overlay_pipeline,
+            text_overlay_texture,
+            text_overlay_screen_rect_buffer,
+            text_overlay_bind_group,
+            text_overlay: TextOverlay::new(),
+            text_overlay_shown: false,
+            frame_skip: 0,
+            frames_skipped_since_last_present: 0,
+            slow_motion_held: false,
+            frame_advance_requested: false,
             scanline_buffer_pipeline,
             scanline_buffer_pipeline_vertex_buffer,
             scanline_buffer_pipeline_num_vertices,
             scanline_buffer_bind_group,
+            pending_scanlines: None,
             bg_and_wd_tile_data_buffer,
             background_tilemap_buffer,
             window_tilemap_buffer,
@@ -234,6 +466,40 @@ impl<'a> State<'a> {
         }
     }
 
+    /// Rebuilds the whole state, that is, the surface, the device and all pipelines, buffers and
+    /// bind groups, while keeping the settings changed by hotkeys (e.g. the upscaling mode). This
+    /// recovers from surface errors which reconfiguring the surface does not fix, e.g. after the
+    /// GPU was reset or the device was lost while the browser tab was in the background.
+    ///
+    /// The old surface is dropped before the new one is created, since some platforms do not
+    /// allow two surfaces for the same window. Since the buffers of the scanline shader are empty
+    /// afterward, all of them are marked as changed in the provided memory bus, so they are
+    /// uploaded again when the next scanline is rendered.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub(crate) fn rebuild(&mut self, memory_bus: &mut MemoryBus) {
+        log::warn!("Rebuilding the renderer to recover the surface");
+        self.surface = None;
+        let state = pollster::block_on(State::new(self.window, self.present_mode));
+        self.replace_with_rebuilt_state(state, memory_bus);
+    }
+
+    /// Replaces the state with the provided rebuilt one, see [State::rebuild]. The settings
+    /// changed by hotkeys are carried over and all buffers of the scanline shader are marked as
+    /// changed in the provided memory bus.
+    fn replace_with_rebuilt_state(&mut self, mut state: State<'a>, memory_bus: &mut MemoryBus) {
+        if self.upscaling_mode != UpscalingMode::default() {
+            state.set_upscaling_mode(self.upscaling_mode);
+        }
+        state.lcd_overlay_enabled = self.lcd_overlay_enabled;
+        state.frame_skip = self.frame_skip;
+        state.slow_motion_held = self.slow_motion_held;
+        // The new surface has not been configured yet
+        state.resize(self.size);
+        *self = state;
+        memory_bus.memory_changed = ChangesToPropagateToShader::new_true();
+        log::info!("Rebuilt the renderer");
+    }
+
     /// Get a reference to the window.
     pub fn window(&self) -> &Window {
         &self.window
@@ -246,19 +512,159 @@ impl<'a> State<'a> {
             self.config.width = new_size.width;
             self.config.height = new_size.height;
             self.screensize_changed = true;
-            self.surface.configure(&self.device, &self.config);
+            if let Some(surface) = &self.surface {
+                surface.configure(&self.device, &self.config);
+            }
+        }
+    }
+
+    /// Check if an event is a valid input event. Returns true if the event was handled by the
+    /// state, in which case it is not passed on to the RustBoy. This is the case for pressing `U`,
+    /// which switches to the next [UpscalingMode], and for pressing `G`, which toggles the LCD
+    /// overlay. The changed settings are posted to the provided notifications.
+    pub(crate) fn input(&mut self, event: &WindowEvent, notifications: &mut Notifications) -> bool {
+        match event {
+            WindowEvent::KeyboardInput {
+                event:
+                    KeyEvent {
+                        state: ElementState::Pressed,
+                        physical_key: PhysicalKey::Code(KeyCode::KeyU),
+                        ..
+                    },
+                ..
+            } => {
+                self.set_upscaling_mode(self.upscaling_mode.next());
+                notifications.post(format!("Upscaling mode: {:?}", self.upscaling_mode));
+                true
+            }
+            WindowEvent::KeyboardInput {
+                event:
+                    KeyEvent {
+                        state: ElementState::Pressed,
+                        physical_key: PhysicalKey::Code(KeyCode::KeyG),
+                        ..
+                    },
+                ..
+            } => {
+                self.lcd_overlay_enabled = !self.lcd_overlay_enabled;
+                // The screen has to be shrunk or grown to make room for the bezel
+                self.screensize_changed = true;
+                notifications.post(format!(
+                    "LCD overlay: {}",
+                    if self.lcd_overlay_enabled {
+                        "on"
+                    } else {
+                        "off"
+                    }
+                ));
+                true
+            }
+            WindowEvent::KeyboardInput {
+                event:
+                    KeyEvent {
+                        state: ElementState::Pressed,
+                        physical_key: PhysicalKey::Code(KeyCode::KeyF),
+                        ..
+                    },
+                ..
+            } => {
+                if self.frame_skip >= MAX_FRAME_SKIP_OF_HOTKEY {
+                    self.set_frame_skip(0);
+                } else {
+                    self.set_frame_skip(self.frame_skip + 1);
+                }
+                notifications.post(format!("Frame-skip: {}", self.frame_skip));
+                true
+            }
+            WindowEvent::KeyboardInput {
+                event:
+                    KeyEvent {
+                        state,
+                        physical_key: PhysicalKey::Code(KeyCode::KeyS),
+                        repeat: false,
+                        ..
+                    },
+                ..
+            } => {
+                self.slow_motion_held = *state == ElementState::Pressed;
+                log::info!(
+                    "Slow motion: {}",
+                    if self.slow_motion_held { "on" } else { "off" }
+                );
+                true
+            }
+            WindowEvent::KeyboardInput {
+                event:
+                    KeyEvent {
+                        state: ElementState::Pressed,
+                        physical_key: PhysicalKey::Code(KeyCode::Period),
+                        ..
+                    },
+                ..
+            } => {
+                self.frame_advance_requested = true;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Sets the number of frames which are not presented to the screen after each presented
+    /// frame, see [State::present_is_due].
+    pub(crate) fn set_frame_skip(&mut self, frame_skip: u32) {
+        self.frame_skip = frame_skip;
+        self.frames_skipped_since_last_present = 0;
+    }
+
+    /// Returns true if the current frame should be presented to the screen using
+    /// [State::render_screen]. With a frame-skip of N, only every (N + 1)-th frame is presented,
+    /// which saves GPU time on weak hardware. The frames which are skipped are still emulated and
+    /// rendered to the framebuffer texture, so the timing of the emulation is not affected.
+    pub(crate) fn present_is_due(&mut self) -> bool {
+        if self.frames_skipped_since_last_present >= self.frame_skip {
+            self.frames_skipped_since_last_present = 0;
+            true
+        } else {
+            self.frames_skipped_since_last_present += 1;
+            false
         }
     }
 
-    /// Check if an event is a valid input event.
-    pub fn input(&mut self, _: &WindowEvent) -> bool {
-        false
+    /// Switches to the provided [UpscalingMode] by recreating the render pipeline with the
+    /// corresponding fragment shader.
+    pub(crate) fn set_upscaling_mode(&mut self, upscaling_mode: UpscalingMode) {
+        (
+            self.render_pipeline,
+            self.render_pipeline_vertex_buffer,
+            self.screensize_buffer,
+            self.render_pipeline_num_vertices,
+            self.render_bind_group,
+        ) = setup_render_shader_pipeline(
+            &self.device,
+            &self.config,
+            &self.framebuffer_texture,
+            upscaling_mode,
+        );
+        self.upscaling_mode = upscaling_mode;
+        // The new screensize buffer is initialized with the original screen size, so the current
+        // size has to be written to it again
+        self.screensize_changed = true;
     }
 
     /// Render the screen. This function is called once per frame to render the
-    /// current framebuffer to the screen using the render shader pipeline.
-    pub fn render_screen(&mut self) -> Result<(), wgpu::SurfaceError> {
-        let output = self.surface.get_current_texture()?;
+    /// current framebuffer to the screen using the render shader pipeline, with the visible
+    /// notifications of the provided ones drawn on top.
+    pub(crate) fn render_screen(
+        &mut self,
+        notifications: &mut Notifications,
+    ) -> Result<(), wgpu::SurfaceError> {
+        self.update_text_overlay(notifications);
+
+        let output = self
+            .surface
+            .as_ref()
+            .ok_or(wgpu::SurfaceError::Lost)?
+            .get_current_texture()?;
         let view = output
             .texture
             .create_view(&wgpu::TextureViewDescriptor::default());
@@ -297,16 +703,40 @@ impl<'a> State<'a> {
             render_pass.set_bind_group(0, &self.render_bind_group, &[]);
             render_pass.set_vertex_buffer(0, self.render_pipeline_vertex_buffer.slice(..));
             render_pass.draw(0..self.render_pipeline_num_vertices, 0..1);
+
+            // Draw the LCD overlay on top of the framebuffer, if it is enabled
+            if self.lcd_overlay_enabled {
+                render_pass.set_pipeline(&self.lcd_overlay_pipeline);
+                render_pass.set_bind_group(0, &self.lcd_overlay_bind_group, &[]);
+                render_pass.draw(0..self.render_pipeline_num_vertices, 0..1);
+            }
+
+            // Draw the text overlay on top of everything else, if there is any text
+            if self.text_overlay_shown {
+                render_pass.set_pipeline(&self.text_overlay_pipeline);
+                render_pass.set_bind_group(0, &self.text_overlay_bind_group, &[]);
+                render_pass.draw(0..self.render_pipeline_num_vertices, 0..1);
+            }
         }
 
-        // Update the screensize for the fragment shader, if the size has changed
+        // Update the screensize for the fragment shaders, if the size has changed
         if self.screensize_changed {
-            // Update the screensize buffer with the new size
-            let screensize = [self.size.width, self.size.height, 0, 0];
+            // Update the screensize buffers with the new size
+            let screen_rect = self.screen_rect();
             self.queue.write_buffer(
                 &self.screensize_buffer,
                 0,
-                bytemuck::cast_slice(&screensize),
+                bytemuck::cast_slice(&screen_rect),
+            );
+            self.queue.write_buffer(
+                &self.lcd_overlay_screen_rect_buffer,
+                0,
+                bytemuck::cast_slice(&screen_rect),
+            );
+            self.queue.write_buffer(
+                &self.text_overlay_screen_rect_buffer,
+                0,
+                bytemuck::cast_slice(&screen_rect),
             );
             self.screensize_changed = false;
         }
@@ -316,63 +746,170 @@ impl<'a> State<'a> {
         self.queue.submit(std::iter::once(encoder.finish()));
         output.present();
 
+        // The text of this frame was uploaded already, the next frame starts without any text
+        self.text_overlay.clear();
+
         Ok(())
     }
 
+    /// Draws the provided text on top of the Game Boy screen in the next presented frame, using
+    /// the embedded 8 x 8 bitmap font (see [font]). The text is only shown for a single frame, so
+    /// text which should stay on the screen has to be drawn again before every frame.
+    ///
+    /// - `text`: The text, which is drawn in a single line. Characters other than printable ASCII
+    ///   are drawn as a question mark.
+    /// - `x` and `y`: The position of the top left corner of the text in pixels of the text
+    ///   overlay, which has twice the resolution of the Game Boy screen (320 x 288 pixels). Text
+    ///   outside the overlay is cut off.
+    /// - `color`: The RGBA color of the text. The alpha channel determines its opacity.
+    pub fn draw_text(&mut self, text: &str, x: u32, y: u32, color: [u8; 4]) {
+        self.draw_text_scaled(text, x, y, 1, color);
+    }
+
+    /// Draws the provided text like [State::draw_text], but with each pixel of the font drawn as
+    /// a square of `scale` x `scale` pixels of the text overlay. With a scale of 2, the glyphs
+    /// have the size of the 8 x 8 tiles of the Game Boy.
+    pub fn draw_text_scaled(&mut self, text: &str, x: u32, y: u32, scale: u32, color: [u8; 4]) {
+        self.text_overlay.draw_text(text, x, y, scale, color);
+    }
+
+    /// Draws the currently visible notifications of the provided ones (see
+    /// [crate::notifications]) stacked in the bottom left corner of the screen, with the newest
+    /// one at the bottom. Each message is drawn in a box of its own, which fades out together
+    /// with the message. Messages which are too long for the screen are cut off.
+    fn draw_notifications(&mut self, notifications: &mut Notifications) {
+        let box_height = GLYPH_SIZE + 2 * NOTIFICATION_PADDING;
+        let mut box_y = TEXT_OVERLAY_HEIGHT;
+        for (message, opacity) in notifications.visible().iter().rev() {
+            box_y = box_y.saturating_sub(box_height + NOTIFICATION_PADDING);
+            let box_width = message.chars().count() as u32 * GLYPH_SIZE + 2 * NOTIFICATION_PADDING;
+            self.text_overlay.fill_rect(
+                NOTIFICATION_PADDING,
+                box_y,
+                box_width,
+                box_height,
+                with_opacity(NOTIFICATION_BACKGROUND_COLOR, *opacity),
+            );
+            self.draw_text(
+                message,
+                2 * NOTIFICATION_PADDING,
+                box_y + NOTIFICATION_PADDING,
+                with_opacity(NOTIFICATION_TEXT_COLOR, *opacity),
+            );
+        }
+    }
+
+    /// Draws the currently visible notifications on top of the text drawn using
+    /// [State::draw_text] (see [State::draw_notifications]) and uploads the text overlay to its
+    /// texture. If nothing was drawn, the text overlay is not drawn at all, so nothing has to be
+    /// uploaded.
+    fn update_text_overlay(&mut self, notifications: &mut Notifications) {
+        self.draw_notifications(notifications);
+        self.text_overlay_shown = !self.text_overlay.is_empty();
+        if !self.text_overlay_shown {
+            return;
+        }
+
+        self.queue.write_texture(
+            wgpu::TexelCopyTextureInfo {
+                texture: &self.text_overlay_texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+                aspect: wgpu::TextureAspect::All,
+            },
+            self.text_overlay.pixels(),
+            wgpu::TexelCopyBufferLayout {
+                offset: 0,
+                bytes_per_row: Some(4 * TEXT_OVERLAY_WIDTH),
+                rows_per_image: Some(TEXT_OVERLAY_HEIGHT),
+            },
+            wgpu::Extent3d {
+                width: TEXT_OVERLAY_WIDTH,
+                height: TEXT_OVERLAY_HEIGHT,
+                depth_or_array_layers: 1,
+            },
+        );
+    }
+
+    /// Returns true if the screen has to be presented again to update the text overlay, that is,
+    /// if a notification is visible (and fading out), text was drawn using [State::draw_text] or
+    /// the last presented frame still shows text which is gone by now. This is used to keep
+    /// presenting the last frame while the emulator is paused, see
+    /// [handle_redraw_requested_event](crate::handle_redraw_requested_event).
+    pub(crate) fn text_overlay_needs_redraw(&self, notifications: &mut Notifications) -> bool {
+        self.text_overlay_shown
+            || !self.text_overlay.is_empty()
+            || !notifications.visible().is_empty()
+    }
+
+    /// Returns the rectangle of the window the Game Boy screen is drawn to as its width, height
+    /// and the x and y offset of its top left corner (in pixels). This is the whole window,
+    /// unless the LCD overlay is enabled, in which case room is left for the bezel, which is
+    /// [LCD_BEZEL_WIDTH] of the shorter side of the window wide.
+    fn screen_rect(&self) -> [u32; 4] {
+        if !self.lcd_overlay_enabled {
+            return [self.size.width, self.size.height, 0, 0];
+        }
+        let bezel_width =
+            (self.size.width.min(self.size.height) as f32 * LCD_BEZEL_WIDTH).round() as u32;
+        [
+            self.size.width.saturating_sub(2 * bezel_width).max(1),
+            self.size.height.saturating_sub(2 * bezel_width).max(1),
+            bezel_width,
+            bezel_width,
+        ]
+    }
+
     /// Render the provided `current_scanline` scanline to the framebuffer texture.
-    /// This function is called once per frame to render the current scanline to the screen using
-    /// the scanline shader pipeline.
+    /// This function is called once per scanline to render the current scanline to the
+    /// framebuffer texture using the scanline shader pipeline.
+    ///
+    /// The scanline is not rendered right away. Instead, the scanlines are batched and rendered
+    /// at once with a single draw call and submission, once the last scanline of the frame is
+    /// reached. The values which change from line to line are taken from the per-scanline buffers
+    /// of [crate::ppu::information_for_shader::BuffersForRendering], so each scanline is still
+    /// rendered with its own scroll position, palettes, etc. The buffers holding the tile data
+    /// and tilemaps on the other hand are shared by all scanlines, so the pending scanlines are
+    /// rendered before these are updated mid-frame. This splits the frame into a few chunks at
+    /// most, since games usually only change them during VBlank.
     pub fn render_scanline(
         &mut self,
         rust_boy_ppu: &mut PPU,
         memory_bus: &mut MemoryBus,
         current_scanline: u8,
     ) {
-        // Create a view of the offscreen texture.
-        let framebuffer_view = self
-            .framebuffer_texture
-            .create_view(&wgpu::TextureViewDescriptor::default());
-
-        // Create command encoder
-        let mut encoder = self
-            .device
-            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
-                label: Some("Render Scanline Encoder"),
-            });
-
-        // Begin a render pass that writes to the framebuffer texture ("offscreen texture")
+        // If a new frame was started before the last one was complete (e.g. because the LCD was
+        // turned off and on again), the pending scanlines of the last frame are rendered first
+        if self
+            .pending_scanlines
+            .is_some_and(|(_, last_scanline)| current_scanline <= last_scanline)
         {
-            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
-                label: Some("Offscreen Render Pass"),
-                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                    view: &framebuffer_view,
-                    resolve_target: None,
-                    // Use LoadOp::Load to preserve previously rendered scanlines
-                    ops: wgpu::Operations {
-                        load: wgpu::LoadOp::Load,
-                        store: wgpu::StoreOp::Store,
-                    },
-                })],
-                depth_stencil_attachment: None,
-                occlusion_query_set: None,
-                timestamp_writes: None,
-            });
-
-            render_pass.set_pipeline(&self.scanline_buffer_pipeline);
-            render_pass.set_bind_group(0, &self.scanline_buffer_bind_group, &[]);
-            render_pass.set_vertex_buffer(0, self.scanline_buffer_pipeline_vertex_buffer.slice(..));
+            self.render_pending_scanlines(rust_boy_ppu);
+        }
 
-            // Set the scissor rect to only update the current scanline.
-            render_pass.set_scissor_rect(0, current_scanline as u32, ORIGINAL_SCREEN_WIDTH, 1);
+        // Check which of the buffers that are shared by all scanlines need to be updated. The
+        // background tilemap needs to be updated if the tilemap currently in use changed or if we
+        // switched the tilemap we are using since the last scanline, the same goes for the window
+        // tilemap and the background and window tile data
+        let background_tile_map_changed = PPU::current_background_tile_map_changed(memory_bus)
+            | memory_bus.memory_changed.background_tile_map_flag_changed;
+        let window_tile_map_changed = PPU::current_window_tile_map_changed(memory_bus)
+            | memory_bus.memory_changed.window_tile_map_flag_changed;
+        let bg_and_wd_tile_data_changed = PPU::current_bg_and_wd_tile_data_changed(memory_bus)
+            | memory_bus.memory_changed.tile_data_flag_changed;
+        let object_tile_data_changed = memory_bus.memory_changed.tile_data_block_0_1_changed;
 
-            render_pass.draw(0..self.scanline_buffer_pipeline_num_vertices, 0..1);
+        // The pending scanlines have to be rendered with the old contents of these buffers, so
+        // they are rendered before the new contents are written
+        if background_tile_map_changed
+            || window_tile_map_changed
+            || bg_and_wd_tile_data_changed
+            || object_tile_data_changed
+        {
+            self.render_pending_scanlines(rust_boy_ppu);
         }
 
-        // Update the background tilemap if the tilemap currently in use changed or if we switched
-        // the tilemap we are using since the last scanline
-        if PPU::current_background_tile_map_changed(memory_bus)
-            | memory_bus.memory_changed.background_tile_map_flag_changed
-        {
+        if background_tile_map_changed {
             // trace!("Updating tilemap");
             // trace!(
             //     "Current Scrolling: x: {} y: {}",
@@ -381,7 +918,7 @@ impl<'a> State<'a> {
             // );
             // trace!(
             //     "New Tilemap (in use) \n {} \n \n",
-            //     tile_map_to_string(rust_boy_gpu.get_background_tile_map())
+            //     tile_map_to_string(&PPU::get_background_tile_map(memory_bus))
             // );
 
             // Update tilemap and tile atlas (e.g., VRAM changes)
@@ -394,11 +931,7 @@ impl<'a> State<'a> {
             );
         }
 
-        // Update the background tilemap if the tilemap currently in use changed or if we switched
-        // the tilemap we are using since the last scanline
-        if PPU::current_window_tile_map_changed(memory_bus)
-            | memory_bus.memory_changed.window_tile_map_flag_changed
-        {
+        if window_tile_map_changed {
             // Update tilemap and tile atlas (e.g., VRAM changes)
             let new_tilemap_data = rust_boy_ppu.buffers_for_rendering.window_tile_map;
             let tilemap = TilemapUniform::from_array(&new_tilemap_data);
@@ -409,25 +942,23 @@ impl<'a> State<'a> {
             );
         }
 
-        // Update the tile data if the tile data currently in use changed or if we switched
-        // the tilemap we are using since the last scanline
-        if PPU::current_bg_and_wd_tile_data_changed(memory_bus)
-            | memory_bus.memory_changed.tile_data_flag_changed
-        {
+        if bg_and_wd_tile_data_changed {
             // DEBUG
             // trace!("Updating tile data");
-            // let tile_data_as_tiles = rust_boy_gpu.get_background_and_window_tile_data_debug();
-            // trace!("Tile data: \n {}", tile_data_to_string(&tile_data_as_tiles));
+            // trace!(
+            //     "Tile data: \n {}",
+            //     tile_data_to_string(&PPU::get_background_and_window_tile_data(memory_bus))
+            // );
             // trace!(
             //     "Tile data Block 0 and 1: \n {}",
             //     tile_data_to_string(
-            //         &rust_boy_gpu.get_background_and_window_tile_data_block_0_and_1_debug()
+            //         &PPU::get_background_and_window_tile_data_block_0_and_1(memory_bus)
             //     )
             // );
             // trace!(
             //     "Tile data Block 2 and 1: \n {}",
             //     tile_data_to_string(
-            //         &rust_boy_gpu.get_background_and_window_tile_data_block_2_and_1_debug()
+            //         &PPU::get_background_and_window_tile_data_block_2_and_1(memory_bus)
             //     )
             // );
 
@@ -440,87 +971,208 @@ impl<'a> State<'a> {
             );
         }
 
-        // Update the background and window viewport position if either of them changed since the last scanline
-        if memory_bus
-            .memory_changed
-            .background_viewport_position_changed
-            || memory_bus.memory_changed.window_viewport_position_changed
-        {
-            let updated_bg_and_wd_viewport_position = rust_boy_ppu
-                .buffers_for_rendering
-                .bg_and_wd_viewport_position;
+        // Update the object tile data buffer if it changed since the last scanline
+        if object_tile_data_changed {
+            let new_object_tile_data = rust_boy_ppu.buffers_for_rendering.object_tile_data;
             self.queue.write_buffer(
-                &self.bg_and_wd_viewport_buffer,
+                &self.object_tile_data_buffer,
                 0,
-                bytemuck::cast_slice(&[updated_bg_and_wd_viewport_position]),
+                bytemuck::cast_slice(&[TileData::from_array(new_object_tile_data)]),
             );
         }
 
-        // Update the palette buffer if the palettes have changed
-        let updated_palettes = rust_boy_ppu.buffers_for_rendering.palettes;
-        self.queue.write_buffer(
-            &self.palette_buffer,
-            0,
-            bytemuck::cast_slice(&[updated_palettes]),
-        );
+        // Reset the changed flags so on the next scanline only buffers are updated which need to be
+        memory_bus.memory_changed = ChangesToPropagateToShader::new_false();
+
+        // Add the current scanline to the pending ones and render them, if the frame is complete
+        self.pending_scanlines = Some(match self.pending_scanlines {
+            Some((first_scanline, _)) => (first_scanline, current_scanline),
+            None => (current_scanline, current_scanline),
+        });
+        if current_scanline as u32 == ORIGINAL_SCREEN_HEIGHT - 1 {
+            self.render_pending_scanlines(rust_boy_ppu);
+        }
+    }
+
+    /// Renders the scanlines which were batched by [State::render_scanline] so far, if there are
+    /// any. The entries of the pending scanlines are uploaded to the per-scanline uniform buffers,
+    /// after which all of them are drawn at once. A scissor rect restricts the draw call to the
+    /// pending scanlines, and the shader looks up the entries of the scanline a pixel lies in.
+    fn render_pending_scanlines(&mut self, rust_boy_ppu: &PPU) {
+        let Some((first_scanline, last_scanline)) = self.pending_scanlines.take() else {
+            return;
+        };
+        let pending_range = first_scanline as usize..=last_scanline as usize;
+        let buffers_for_rendering = &rust_boy_ppu.buffers_for_rendering;
 
-        // Update the current scanline and object size uniform buffer
-        let updated_current_scanline_lcd_control_and_window_internal_line_info = rust_boy_ppu
-            .buffers_for_rendering
-            .rendering_line_lcd_control_and_window_internal_line_info;
-        // DEBUG
-        log::trace!(
-            "Updated rendering_line_lcd_control_and_window_internal_line_info: {:?}",
-            updated_current_scanline_lcd_control_and_window_internal_line_info
+        // Update the entries of the pending scanlines in the per-scanline uniform buffers. Note
+        // that the palettes are buffered when exiting Transfer mode (3), so mid-frame palette
+        // changes (e.g. fades done by cycling BGP) take effect on the scanline after the write.
+        write_per_scanline_entries(
+            &self.queue,
+            &self.bg_and_wd_viewport_buffer,
+            &buffers_for_rendering.bg_and_wd_viewport_position_per_scanline,
+            pending_range.clone(),
         );
-        self.queue.write_buffer(
+        write_per_scanline_entries(
+            &self.queue,
+            &self.palette_buffer,
+            &buffers_for_rendering.palettes_per_scanline,
+            pending_range.clone(),
+        );
+        write_per_scanline_entries(
+            &self.queue,
             &self.rendering_line_lcd_control_and_window_internal_line_info_buffer,
-            0,
-            bytemuck::cast_slice(&[
-                updated_current_scanline_lcd_control_and_window_internal_line_info,
-            ]),
+            &buffers_for_rendering
+                .rendering_line_lcd_control_and_window_internal_line_info_per_scanline,
+            pending_range.clone(),
+        );
+        write_per_scanline_entries(
+            &self.queue,
+            &self.objects_in_scanline_buffer,
+            &buffers_for_rendering.objects_in_scanline_per_scanline,
+            pending_range,
         );
 
-        // Update the object tile data buffer if it changed since the last scanline
-        if memory_bus.memory_changed.tile_data_block_0_1_changed {
-            let new_object_tile_data = rust_boy_ppu.buffers_for_rendering.object_tile_data;
-            self.queue.write_buffer(
-                &self.object_tile_data_buffer,
+        // Create a view of the offscreen texture.
+        let framebuffer_view = self
+            .framebuffer_texture
+            .create_view(&wgpu::TextureViewDescriptor::default());
+
+        // Create command encoder
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Render Scanline Encoder"),
+            });
+
+        // Begin a render pass that writes to the framebuffer texture ("offscreen texture")
+        {
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Offscreen Render Pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: &framebuffer_view,
+                    resolve_target: None,
+                    // Use LoadOp::Load to preserve previously rendered scanlines
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Load,
+                        store: wgpu::StoreOp::Store,
+                    },
+                })],
+                depth_stencil_attachment: None,
+                occlusion_query_set: None,
+                timestamp_writes: None,
+            });
+
+            render_pass.set_pipeline(&self.scanline_buffer_pipeline);
+            render_pass.set_bind_group(0, &self.scanline_buffer_bind_group, &[]);
+            render_pass.set_vertex_buffer(0, self.scanline_buffer_pipeline_vertex_buffer.slice(..));
+
+            // Set the scissor rect to only update the pending scanlines.
+            render_pass.set_scissor_rect(
                 0,
-                bytemuck::cast_slice(&[TileData::from_array(new_object_tile_data)]),
+                first_scanline as u32,
+                ORIGINAL_SCREEN_WIDTH,
+                (last_scanline - first_scanline) as u32 + 1,
             );
+
+            render_pass.draw(0..self.scanline_buffer_pipeline_num_vertices, 0..1);
         }
 
-        // Update the objects in scanline buffer
-        let mut objects_in_scanline = rust_boy_ppu
-            .buffers_for_rendering
-            .objects_in_scanline_buffer;
-        // Sort objects in scanline by their x coordinate, see https://gbdev.io/pandocs/OAM.html#drawing-priority
-        objects_in_scanline.sort_by(|v, w| custom_ordering(v[1], w[1]));
-        let new_objects_in_scanline = ObjectsInScanline {
-            objects: objects_in_scanline,
-        };
-        self.queue.write_buffer(
-            &self.objects_in_scanline_buffer,
-            0,
-            bytemuck::cast_slice(&[new_objects_in_scanline]),
+        // Submit the commands to the GPU
+        // Submit will accept anything that implements IntoIter
+        self.queue.submit(std::iter::once(encoder.finish()));
+    }
+
+    /// Write the provided RGBA framebuffer of size [ORIGINAL_SCREEN_WIDTH] x [ORIGINAL_SCREEN_HEIGHT]
+    /// to the framebuffer texture. This is used instead of [State::render_scanline], if the frames
+    /// are rendered by the pixel FIFO of the PPU instead of the scanline shader.
+    pub fn write_framebuffer(&mut self, framebuffer: &[u8]) {
+        self.queue.write_texture(
+            wgpu::TexelCopyTextureInfo {
+                texture: &self.framebuffer_texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+                aspect: wgpu::TextureAspect::All,
+            },
+            framebuffer,
+            wgpu::TexelCopyBufferLayout {
+                offset: 0,
+                bytes_per_row: Some(4 * ORIGINAL_SCREEN_WIDTH),
+                rows_per_image: Some(ORIGINAL_SCREEN_HEIGHT),
+            },
+            wgpu::Extent3d {
+                width: ORIGINAL_SCREEN_WIDTH,
+                height: ORIGINAL_SCREEN_HEIGHT,
+                depth_or_array_layers: 1,
+            },
         );
-        // DEBUG
-        #[cfg(debug_assertions)]
-        {
-            // let objects_tile_data = rust_boy_gpu.get_object_tile_data();
-            // let objects_tile_data = TileData::from_array(objects_tile_data);
-            // trace!(
-            //     "{:?}",
-            //     objects_tile_data.tiles[objects_in_scanline[0][3] as usize]
-            // );
-        }
+    }
 
-        // Reset the changed flags so on the next scanline only buffers are updated which need to be
-        memory_bus.memory_changed = ChangesToPropagateToShader::new_false();
+    /// Reads the framebuffer texture back from the GPU and returns it as an RGBA framebuffer of
+    /// size [ORIGINAL_SCREEN_WIDTH] x [ORIGINAL_SCREEN_HEIGHT]. Blocks until the GPU finished
+    /// rendering and copying the frame, so this should only be used sparingly (e.g. for
+    /// recordings). Not available on the web, where the GPU can not be waited for.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn read_framebuffer(&self) -> Vec<u8> {
+        let unpadded_bytes_per_row = 4 * ORIGINAL_SCREEN_WIDTH;
+        // The rows of a copy from a texture to a buffer need to be aligned
+        let padded_bytes_per_row =
+            unpadded_bytes_per_row.next_multiple_of(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT);
+        let readback_buffer = self.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Framebuffer Readback Buffer"),
+            size: (padded_bytes_per_row * ORIGINAL_SCREEN_HEIGHT) as u64,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
 
-        // Submit the compute commands to the GPU
-        // Submit will accept anything that implements IntoIter
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Framebuffer Readback Encoder"),
+            });
+        encoder.copy_texture_to_buffer(
+            self.framebuffer_texture.as_image_copy(),
+            wgpu::TexelCopyBufferInfo {
+                buffer: &readback_buffer,
+                layout: wgpu::TexelCopyBufferLayout {
+                    offset: 0,
+                    bytes_per_row: Some(padded_bytes_per_row),
+                    rows_per_image: Some(ORIGINAL_SCREEN_HEIGHT),
+                },
+            },
+            wgpu::Extent3d {
+                width: ORIGINAL_SCREEN_WIDTH,
+                height: ORIGINAL_SCREEN_HEIGHT,
+                depth_or_array_layers: 1,
+            },
+        );
         self.queue.submit(std::iter::once(encoder.finish()));
+
+        let buffer_slice = readback_buffer.slice(..);
+        buffer_slice.map_async(wgpu::MapMode::Read, |_| {});
+        let _ = self.device.poll(wgpu::Maintain::Wait);
+
+        let framebuffer = buffer_slice
+            .get_mapped_range()
+            .chunks_exact(padded_bytes_per_row as usize)
+            .flat_map(|row| &row[..unpadded_bytes_per_row as usize])
+            .copied()
+            .collect();
+        readback_buffer.unmap();
+        framebuffer
     }
 }
+
+/// Writes the entries of the provided scanlines of a per-scanline buffer of
+/// [crate::ppu::information_for_shader::BuffersForRendering] to the corresponding uniform buffer
+/// of the scanline shader.
+fn write_per_scanline_entries<T: bytemuck::Pod>(
+    queue: &wgpu::Queue,
+    buffer: &wgpu::Buffer,
+    entries: &[T],
+    scanlines: std::ops::RangeInclusive<usize>,
+) {
+    let offset = (*scanlines.start() * size_of::<T>()) as u64;
+    queue.write_buffer(buffer, offset, bytemuck::cast_slice(&entries[scanlines]));
+}
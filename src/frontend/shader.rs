@@ -1,3 +1,7 @@
+use crate::frontend::text_overlay::{TEXT_OVERLAY_HEIGHT, TEXT_OVERLAY_WIDTH};
+use crate::ppu::information_for_shader::{
+    BgAndWdViewportPosition, PackedObjectsInScanline, Palettes, RenderingLinePositionAndObjectSize,
+};
 use crate::{ORIGINAL_SCREEN_HEIGHT, ORIGINAL_SCREEN_WIDTH};
 use bytemuck::cast;
 use wgpu::util::DeviceExt;
@@ -87,14 +91,6 @@ pub(super) struct TilemapUniform {
     pub(super) tiles: [PackedTilemapData; 256], // 32x32 grid
 }
 
-/// Stores the objects that are in the current scanline in a format that can be passed to the
-/// scanline shader.
-#[repr(C)]
-#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
-pub(super) struct ObjectsInScanline {
-    pub(super) objects: [[u32; 4]; 10],
-}
-
 impl TileData {
     /// Safely converts an input array of u8s of length 4096 to a TileData struct by using
     /// [bytemuck::cast].
@@ -124,49 +120,77 @@ impl TilemapUniform {
     }
 }
 
-/// Represents the position of the viewport of the background in the tilemap. Is a list of 4 elements
-/// just for alignment, we only use the first 2.
+/// Represents the current screensize of the window of the emulator. The first two entries are the
+/// width and height of the screen in pixels. The last two entries are the offset of the top left
+/// corner of the screen within the window, which is only non-zero if the LCD overlay draws a
+/// bezel around the screen, see [setup_lcd_overlay_pipeline].
 #[repr(C)]
 #[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
-pub struct BgAndWdViewportPosition {
-    pub pos: [u32; 4],
+pub struct CurrentScreensize {
+    pub size: [u32; 4],
 }
 
-/// Represents the current rendering line and the object size flag. Is a list of 4 elements just for alignment, we only use
-/// the first and second entry. They are the current scanline and the object size flag (0 for 8x8, 1 for 8x16).
-#[repr(C)]
-#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
-pub struct RenderingLinePositionAndObjectSize {
-    pub pos: [u32; 4],
+/// Represents the possible modes of upscaling the framebuffer texture (160x144 pixels) to the size
+/// of the window. Each mode corresponds to a fragment shader in `render_to_screen.wgsl`. The
+/// modes are cycled through by pressing `U`.
+///
+/// - `Nearest`: Each pixel of the window takes the color of the closest Game Boy pixel. Pixels
+///   stay perfectly sharp, but if the window size is not an integer multiple of the original
+///   resolution, some Game Boy pixels are drawn one window pixel wider than others, which is
+///   visible as uneven pixels, especially while scrolling. This is the default.
+/// - `SharpBilinear`: The framebuffer is (virtually) scaled up by the largest integer factor using
+///   nearest filtering first, after which the remaining non-integer factor is applied using
+///   bilinear filtering. All Game Boy pixels have the same size and their edges are only blended
+///   over a single window pixel, which is barely noticeable on high-DPI displays.
+/// - `Scale2x`: Each Game Boy pixel is split into 2x2 pixels, whose colors are chosen based on
+///   the neighboring pixels to smooth diagonal edges (also known as EPX). The result is then
+///   scaled using nearest filtering. This rounds off the edges of sprites and text, but also
+///   changes the look of the original art and can distort thin details like single pixel lines.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub(crate) enum UpscalingMode {
+    #[default]
+    Nearest,
+    SharpBilinear,
+    Scale2x,
 }
 
-/// Represents the current screensize of the window of the emulator. Is a list of 4 elements just for
-/// alignment purposes. We only use the first two entries for the width and height of the screen in
-/// pixels.
-#[repr(C)]
-#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
-pub struct CurrentScreensize {
-    pub size: [u32; 4],
-}
+impl UpscalingMode {
+    /// Returns the upscaling mode after this one, wrapping around after the last one.
+    pub(crate) fn next(self) -> UpscalingMode {
+        match self {
+            UpscalingMode::Nearest => UpscalingMode::SharpBilinear,
+            UpscalingMode::SharpBilinear => UpscalingMode::Scale2x,
+            UpscalingMode::Scale2x => UpscalingMode::Nearest,
+        }
+    }
 
-/// Represents the palettes used for the background, window and objects. Is a list of 4 elements just
-/// for alignment purposes. The last entry is not used. The first entry is the background and
-/// window palette that corresponds to register 0xFF47. The second entry is the object
-/// palette 0 that corresponds to register 0xFF48. The third entry is the object palette 1 that
-/// corresponds to register 0xFF49. See https://gbdev.io/pandocs/Palettes.html#lcd-monochrome-palettes
-/// for more information.
-#[repr(C)]
-#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
-pub struct Palettes {
-    pub values: [u32; 4],
+    /// Returns the entry point of the fragment shader in `render_to_screen.wgsl` implementing this
+    /// upscaling mode.
+    fn fragment_shader_entry_point(self) -> &'static str {
+        match self {
+            UpscalingMode::Nearest => "fs_main",
+            UpscalingMode::SharpBilinear => "fs_sharp_bilinear",
+            UpscalingMode::Scale2x => "fs_scale2x",
+        }
+    }
+
+    /// Returns the filter the framebuffer texture is sampled with in this upscaling mode. Only
+    /// sharp bilinear needs the sampler to interpolate between the pixels.
+    fn sampler_filter(self) -> wgpu::FilterMode {
+        match self {
+            UpscalingMode::SharpBilinear => wgpu::FilterMode::Linear,
+            UpscalingMode::Nearest | UpscalingMode::Scale2x => wgpu::FilterMode::Nearest,
+        }
+    }
 }
 
 /// Sets up the render shader pipeline.
 /// This pipeline is used to render the framebuffer texture to the screen. It is called in the
 /// VBlank period of the RustBoy.
 /// It uses very simple vertex and fragment shaders. The vertex shader simply creates two triangles
-/// which form a rectangle of the desired size. The fragment shader simply takes the color of the
-/// pixel from the framebuffer texture and writes it to the screen.
+/// which form a rectangle of the desired size. The fragment shader takes the color of the
+/// pixel from the framebuffer texture and writes it to the screen. Which fragment shader is used
+/// depends on the provided [UpscalingMode].
 ///
 /// The return values are as follows:
 /// - `wgpu::RenderPipeline` The render pipeline.
@@ -180,6 +204,7 @@ pub fn setup_render_shader_pipeline(
     device: &Device,
     config: &SurfaceConfiguration,
     framebuffer_texture: &wgpu::Texture,
+    upscaling_mode: UpscalingMode,
 ) -> (
     wgpu::RenderPipeline,
     wgpu::Buffer,
@@ -192,8 +217,9 @@ pub fn setup_render_shader_pipeline(
         label: Some("Framebuffer Sampler"),
         address_mode_u: wgpu::AddressMode::ClampToEdge,
         address_mode_v: wgpu::AddressMode::ClampToEdge,
-        mag_filter: wgpu::FilterMode::Nearest, // Critical for crisp pixels
-        min_filter: wgpu::FilterMode::Nearest,
+        // Nearest filtering is critical for crisp pixels, except for sharp bilinear upscaling
+        mag_filter: upscaling_mode.sampler_filter(),
+        min_filter: upscaling_mode.sampler_filter(),
         ..Default::default()
     });
 
@@ -292,7 +318,7 @@ pub fn setup_render_shader_pipeline(
         },
         fragment: Some(wgpu::FragmentState {
             module: &shader,
-            entry_point: Some("fs_main"),
+            entry_point: Some(upscaling_mode.fragment_shader_entry_point()),
             targets: &[Some(wgpu::ColorTargetState {
                 format: config.format,
                 blend: Some(wgpu::BlendState::REPLACE),
@@ -336,6 +362,302 @@ pub fn setup_render_shader_pipeline(
     )
 }
 
+/// Sets up the LCD overlay pipeline.
+/// This pipeline draws the optional LCD overlay on top of the framebuffer texture rendered to the
+/// screen by the render pipeline (see [setup_render_shader_pipeline]). The overlay consists of a
+/// bezel around the Game Boy screen, resembling the frame around the LCD of the DMG, a subtle grid
+/// between the pixels of the LCD and the shadow the bezel casts onto the LCD. It is drawn using a
+/// rectangle covering the whole window, whose fragment shader (`lcd_overlay.wgsl`) is blended on
+/// top of the framebuffer.
+///
+/// The return values are as follows:
+/// - `wgpu::RenderPipeline` The LCD overlay pipeline.
+/// - `wgpu::Buffer` The screen rect buffer.
+/// - `wgpu::BindGroup` The bind group.
+///
+/// For their details, see the documentation of the fields of [crate::frontend::State] struct.
+pub fn setup_lcd_overlay_pipeline(
+    device: &Device,
+    config: &SurfaceConfiguration,
+) -> (wgpu::RenderPipeline, wgpu::Buffer, wgpu::BindGroup) {
+    // Holds the rectangle the Game Boy screen is drawn to. See the [lcd_overlay_screen_rect_buffer]
+    // field of the [crate::frontend::State] struct for more details.
+    let initial_screen_rect = CurrentScreensize {
+        size: [ORIGINAL_SCREEN_WIDTH, ORIGINAL_SCREEN_HEIGHT, 0, 0],
+    };
+    let screen_rect_buffer: wgpu::Buffer =
+        device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("LCD Overlay Screen Rect Buffer"),
+            contents: bytemuck::cast_slice(&[initial_screen_rect]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+    let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+        label: Some("LCD Overlay Bind Group Layout"),
+        entries: &[
+            // Screen Rect Buffer (binding 0)
+            wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            },
+        ],
+    });
+
+    let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: Some("LCD Overlay Bind Group"),
+        layout: &bind_group_layout,
+        entries: &[wgpu::BindGroupEntry {
+            binding: 0,
+            resource: screen_rect_buffer.as_entire_binding(),
+        }],
+    });
+
+    let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+        label: Some("LCD Overlay Shader"),
+        source: wgpu::ShaderSource::Wgsl(include_str!("shaders/lcd_overlay.wgsl").into()),
+    });
+
+    let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+        label: Some("LCD Overlay Pipeline Layout"),
+        bind_group_layouts: &[&bind_group_layout],
+        push_constant_ranges: &[],
+    });
+
+    let lcd_overlay_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some("LCD Overlay Pipeline"),
+        layout: Some(&pipeline_layout),
+        vertex: wgpu::VertexState {
+            module: &shader,
+            entry_point: Some("vs_main"),
+            buffers: &[Vertex::desc()],
+            compilation_options: wgpu::PipelineCompilationOptions::default(),
+        },
+        fragment: Some(wgpu::FragmentState {
+            module: &shader,
+            entry_point: Some("fs_main"),
+            targets: &[Some(wgpu::ColorTargetState {
+                format: config.format,
+                // The overlay darkens the framebuffer below it according to its opacity
+                blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                write_mask: wgpu::ColorWrites::ALL,
+            })],
+            compilation_options: wgpu::PipelineCompilationOptions::default(),
+        }),
+        primitive: wgpu::PrimitiveState {
+            topology: wgpu::PrimitiveTopology::TriangleList,
+            strip_index_format: None,
+            front_face: wgpu::FrontFace::Ccw,
+            cull_mode: Some(wgpu::Face::Back),
+            polygon_mode: wgpu::PolygonMode::Fill,
+            unclipped_depth: false,
+            conservative: false,
+        },
+        depth_stencil: None,
+        multisample: wgpu::MultisampleState {
+            count: 1,
+            mask: !0,
+            alpha_to_coverage_enabled: false,
+        },
+        multiview: None,
+        cache: None,
+    });
+
+    (lcd_overlay_pipeline, screen_rect_buffer, bind_group)
+}
+
+/// Sets up the text overlay pipeline.
+/// This pipeline draws the text overlay (e.g. notifications, see
+/// [TextOverlay](crate::frontend::text_overlay::TextOverlay)) on top of the Game Boy screen,
+/// after the framebuffer texture and the LCD overlay were drawn. The text is drawn on the CPU and
+/// uploaded to the text overlay texture, which is stretched over the Game Boy screen using a
+/// rectangle covering the whole window, whose fragment shader (`text_overlay.wgsl`) is blended on
+/// top of the screen.
+///
+/// The return values are as follows:
+/// - `wgpu::RenderPipeline` The text overlay pipeline.
+/// - `wgpu::Texture` The text overlay texture.
+/// - `wgpu::Buffer` The screen rect buffer.
+/// - `wgpu::BindGroup` The bind group.
+///
+/// For their details, see the documentation of the fields of [crate::frontend::State] struct.
+pub fn setup_text_overlay_pipeline(
+    device: &Device,
+    config: &SurfaceConfiguration,
+) -> (
+    wgpu::RenderPipeline,
+    wgpu::Texture,
+    wgpu::Buffer,
+    wgpu::BindGroup,
+) {
+    let text_overlay_texture = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("Text Overlay Texture"),
+        size: wgpu::Extent3d {
+            width: TEXT_OVERLAY_WIDTH,
+            height: TEXT_OVERLAY_HEIGHT,
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: wgpu::TextureFormat::Rgba8Unorm,
+        usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+        view_formats: &[],
+    });
+
+    // Nearest filtering keeps the edges of the glyphs crisp
+    let text_overlay_sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+        label: Some("Text Overlay Sampler"),
+        address_mode_u: wgpu::AddressMode::ClampToEdge,
+        address_mode_v: wgpu::AddressMode::ClampToEdge,
+        mag_filter: wgpu::FilterMode::Nearest,
+        min_filter: wgpu::FilterMode::Nearest,
+        ..Default::default()
+    });
+
+    // Holds the rectangle the Game Boy screen is drawn to. See the [text_overlay_screen_rect_buffer]
+    // field of the [crate::frontend::State] struct for more details.
+    let initial_screen_rect = CurrentScreensize {
+        size: [ORIGINAL_SCREEN_WIDTH, ORIGINAL_SCREEN_HEIGHT, 0, 0],
+    };
+    let screen_rect_buffer: wgpu::Buffer =
+        device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Text Overlay Screen Rect Buffer"),
+            contents: bytemuck::cast_slice(&[initial_screen_rect]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+    let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+        label: Some("Text Overlay Bind Group Layout"),
+        entries: &[
+            // Text Overlay Texture (binding 0)
+            wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Texture {
+                    sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    view_dimension: wgpu::TextureViewDimension::D2,
+                    multisampled: false,
+                },
+                count: None,
+            },
+            // Text Overlay Sampler (binding 1)
+            wgpu::BindGroupLayoutEntry {
+                binding: 1,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                count: None,
+            },
+            // Screen Rect Buffer (binding 2)
+            wgpu::BindGroupLayoutEntry {
+                binding: 2,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            },
+        ],
+    });
+
+    let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: Some("Text Overlay Bind Group"),
+        layout: &bind_group_layout,
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::TextureView(
+                    &text_overlay_texture.create_view(&wgpu::TextureViewDescriptor::default()),
+                ),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: wgpu::BindingResource::Sampler(&text_overlay_sampler),
+            },
+            wgpu::BindGroupEntry {
+                binding: 2,
+                resource: screen_rect_buffer.as_entire_binding(),
+            },
+        ],
+    });
+
+    let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+        label: Some("Text Overlay Shader"),
+        source: wgpu::ShaderSource::Wgsl(include_str!("shaders/text_overlay.wgsl").into()),
+    });
+
+    let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+        label: Some("Text Overlay Pipeline Layout"),
+        bind_group_layouts: &[&bind_group_layout],
+        push_constant_ranges: &[],
+    });
+
+    let text_overlay_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some("Text Overlay Pipeline"),
+        layout: Some(&pipeline_layout),
+        vertex: wgpu::VertexState {
+            module: &shader,
+            entry_point: Some("vs_main"),
+            buffers: &[Vertex::desc()],
+            compilation_options: wgpu::PipelineCompilationOptions::default(),
+        },
+        fragment: Some(wgpu::FragmentState {
+            module: &shader,
+            entry_point: Some("fs_main"),
+            targets: &[Some(wgpu::ColorTargetState {
+                format: config.format,
+                // Only the text and the boxes behind it cover the screen below
+                blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                write_mask: wgpu::ColorWrites::ALL,
+            })],
+            compilation_options: wgpu::PipelineCompilationOptions::default(),
+        }),
+        primitive: wgpu::PrimitiveState {
+            topology: wgpu::PrimitiveTopology::TriangleList,
+            strip_index_format: None,
+            front_face: wgpu::FrontFace::Ccw,
+            cull_mode: Some(wgpu::Face::Back),
+            polygon_mode: wgpu::PolygonMode::Fill,
+            unclipped_depth: false,
+            conservative: false,
+        },
+        depth_stencil: None,
+        multisample: wgpu::MultisampleState {
+            count: 1,
+            mask: !0,
+            alpha_to_coverage_enabled: false,
+        },
+        multiview: None,
+        cache: None,
+    });
+
+    (
+        text_overlay_pipeline,
+        text_overlay_texture,
+        screen_rect_buffer,
+        bind_group,
+    )
+}
+
+/// Creates a uniform buffer holding one entry of type `T` per line of the screen, see the
+/// per-scanline buffers of [BuffersForRendering](crate::ppu::information_for_shader::BuffersForRendering).
+/// The buffer is initialized with zeroes.
+fn create_per_scanline_uniform_buffer<T>(device: &Device, label: &str) -> wgpu::Buffer {
+    device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some(label),
+        size: (size_of::<T>() * ORIGINAL_SCREEN_HEIGHT as usize) as u64,
+        usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    })
+}
+
 /// Sets up the scanline shader pipeline.
 /// This pipeline is used to render scanline by scanline to the buffer texture. It is called in the
 /// after each HBlank period of the RustBoy, that is, when a scanline is finished.
@@ -408,13 +730,10 @@ pub fn setup_scanline_shader_pipeline(
 
     // Sets the positions from where the background and the window are drawn.
     // For more details see the [bg_and_wd_viewport_buffer] field of the [crate::frontend::State] struct.
-    let initial_bg_and_wd_viewport_position = BgAndWdViewportPosition { pos: [0, 0, 0, 0] };
-    let bg_and_wd_viewport_buffer: wgpu::Buffer =
-        device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-            label: Some("Background Viewport Buffer"),
-            contents: bytemuck::cast_slice(&[initial_bg_and_wd_viewport_position]),
-            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
-        });
+    let bg_and_wd_viewport_buffer = create_per_scanline_uniform_buffer::<BgAndWdViewportPosition>(
+        device,
+        "Background Viewport Buffer",
+    );
 
     // Setup framebuffer texture.
     // For more details see the [framebuffer_texture] field of the [crate::frontend::State] struct.
@@ -429,35 +748,27 @@ pub fn setup_scanline_shader_pipeline(
         sample_count: 1,
         dimension: wgpu::TextureDimension::D2,
         format: wgpu::TextureFormat::Rgba8Unorm,
-        usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+        // COPY_DST is needed to be able to upload frames rendered by the pixel FIFO and COPY_SRC
+        // to be able to read frames back for recordings
+        usage: wgpu::TextureUsages::RENDER_ATTACHMENT
+            | wgpu::TextureUsages::TEXTURE_BINDING
+            | wgpu::TextureUsages::COPY_DST
+            | wgpu::TextureUsages::COPY_SRC,
         view_formats: &[],
     });
 
     // Buffer to hold the current line to be rendered, the lcd control register and some
     // window internal line counter information.
     // For more details see the [rendering_line_lcd_control_and_window_internal_line_info_buffer] field of the [crate::frontend::State] struct.
-    let initial_rendering_line_lcd_control_and_window_internal_line_info =
-        RenderingLinePositionAndObjectSize { pos: [0, 0, 0, 0] };
-    let rendering_line_lcd_control_and_window_internal_line_info_buffer: wgpu::Buffer = device
-        .create_buffer_init(&wgpu::util::BufferInitDescriptor {
-            label: Some("Rendering Line and Object Size Buffer"),
-            contents: bytemuck::cast_slice(&[
-                initial_rendering_line_lcd_control_and_window_internal_line_info,
-            ]),
-            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
-        });
+    let rendering_line_lcd_control_and_window_internal_line_info_buffer =
+        create_per_scanline_uniform_buffer::<RenderingLinePositionAndObjectSize>(
+            device,
+            "Rendering Line and Object Size Buffer",
+        );
 
     // This holds the palettes used for the background, window and objects.
     // For more details see the [palette_buffer] field of the [crate::frontend::State] struct.
-    let initial_palette = Palettes {
-        values: [0, 0, 0, 0],
-    };
-    let palette_buffer: wgpu::Buffer =
-        device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-            label: Some("Palette Buffer"),
-            contents: bytemuck::cast_slice(&[initial_palette]),
-            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
-        });
+    let palette_buffer = create_per_scanline_uniform_buffer::<Palettes>(device, "Palette Buffer");
 
     // This holds the object tile data.
     // For more details see the [object_tile_data_buffer] field of the [crate::frontend::State] struct.
@@ -472,15 +783,10 @@ pub fn setup_scanline_shader_pipeline(
 
     // Represents the objects that are visible in the current scanline.
     // For more details see the [objects_in_scanline_buffer] field of the [crate::frontend::State] struct.
-    let initial_objects_in_scanline = ObjectsInScanline {
-        objects: [[0; 4]; 10],
-    };
-    let objects_in_scanline_buffer: wgpu::Buffer =
-        device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-            label: Some("Tilemap Buffer"),
-            contents: bytemuck::cast_slice(&[initial_objects_in_scanline]),
-            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
-        });
+    let objects_in_scanline_buffer = create_per_scanline_uniform_buffer::<PackedObjectsInScanline>(
+        device,
+        "Objects in Scanline Buffer",
+    );
 
     // Create the bind group layout
     let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
@@ -0,0 +1,65 @@
+// Vertex shader
+struct VertexInput {
+    @location(0) position: vec3<f32>,
+    @location(1) color: vec3<f32>,
+};
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) color: vec3<f32>,
+};
+
+@vertex
+fn vs_main(
+    model: VertexInput,
+) -> VertexOutput {
+    var out: VertexOutput;
+    out.color = model.color;
+    out.clip_position = vec4<f32>(model.position, 1.0);
+    return out;
+}
+
+// The color of the bezel around the screen, resembling the dark gray frame around the LCD of the DMG.
+const BEZEL_COLOR: vec4<f32> = vec4<f32>(0.30, 0.30, 0.36, 1.0);
+// The opacity of the gaps between the pixels of the LCD.
+const GRID_OPACITY: f32 = 0.12;
+// The opacity of the shadow the bezel casts onto the top and left edge of the LCD.
+const SHADOW_OPACITY: f32 = 0.35;
+// The width of the shadow relative to the width of the bezel.
+const SHADOW_WIDTH: f32 = 0.5;
+
+// The rectangle the Game Boy screen is drawn to in pixels of the window. The first two components are its width and
+// height and the last two the offset of its top left corner, i.e. the width of the bezel.
+@group(0) @binding(0) var<uniform> screen_rect: vec4<u32>;
+
+// Fragment shader drawing the LCD overlay on top of the upscaled framebuffer. Outside the screen rectangle, the bezel
+// is drawn. Inside it, the gaps between the pixels of the LCD and the shadow of the bezel are drawn by darkening the
+// framebuffer using alpha blending.
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let size = vec2<f32>(f32(screen_rect.x), f32(screen_rect.y));
+    let offset = vec2<f32>(f32(screen_rect.z), f32(screen_rect.w));
+    let position = in.clip_position.xy - offset;
+
+    if any(position < vec2<f32>(0.0)) || any(position >= size) {
+        return BEZEL_COLOR;
+    }
+
+    // The gaps are drawn at the right and bottom edge of each Game Boy pixel. They are a fifth of a Game Boy pixel wide
+    // (rounded down to whole window pixels), so they are left out at small scales, where they would only cause uneven
+    // patterns.
+    let scale = size / vec2<f32>(160.0, 144.0);
+    let position_within_pixel = fract(position / scale) * scale;
+    let gap_width = floor(scale / 5.0);
+    var opacity = 0.0;
+    if any(position_within_pixel >= scale - gap_width) {
+        opacity = GRID_OPACITY;
+    }
+
+    // The shadow fades out linearly from the top and left edge of the screen
+    let shadow_width = max(offset * SHADOW_WIDTH, vec2<f32>(1.0));
+    let distance_to_edge = min(position.x / shadow_width.x, position.y / shadow_width.y);
+    opacity = max(opacity, SHADOW_OPACITY * (1.0 - clamp(distance_to_edge, 0.0, 1.0)));
+
+    return vec4<f32>(0.0, 0.0, 0.0, opacity);
+}
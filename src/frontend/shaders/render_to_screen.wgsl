@@ -25,18 +25,24 @@ fn vs_main(
 @group(0) @binding(0) var frameBufferTexture: texture_2d<f32>;
 // Sampler for the frame buffer
 @group(0) @binding(1) var frameBufferSampler: sampler;
-// The current screensize in pixels (x,y) are the first two components of the vector. The last two are unused and just
-// serve as padding.
+// The current screensize in pixels (x,y) are the first two components of the vector. The last two are the offset of the
+// top left corner of the screen within the window, which is only non-zero if the LCD overlay (lcd_overlay.wgsl) draws a
+// bezel around the screen.
 @group(0) @binding(2) var<uniform> current_screensize: vec4<u32>;
 
+// Returns the position of the current pixel relative to the top left corner of the screen in pixels of the window.
+fn position_in_screen(clip_position: vec4<f32>) -> vec2<f32> {
+    return clip_position.xy - vec2<f32>(f32(current_screensize.z), f32(current_screensize.w));
+}
+
 // Fragment shader
 @fragment
 fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
     // Convert the uniform screen size to f32.
     let screensize = vec2<f32>(f32(current_screensize.x), f32(current_screensize.y));
 
-    // Use the vertex's clip_position directly as pixel coordinates.
-    let pixel_coord = in.clip_position.xy;
+    // Use the vertex's clip_position (relative to the screen) as pixel coordinates.
+    let pixel_coord = position_in_screen(in.clip_position);
 
     // Determine how many screen pixels correspond to one Game Boy pixel.
     let scale = screensize / vec2<f32>(160.0, 144.0);
@@ -51,4 +57,73 @@ fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
     let uv = (clamped_pixel + vec2<f32>(0.5)) / vec2<f32>(160.0, 144.0);
 
     return textureSample(frameBufferTexture, frameBufferSampler, uv);
-}
\ No newline at end of file
+}
+// Fragment shader for sharp bilinear upscaling. The framebuffer is (virtually) scaled up by the largest integer factor
+// using nearest filtering first, after which the remaining non-integer factor is applied using bilinear filtering. This
+// is achieved by moving the sampling position towards the center of the texel, except for the window pixels right at
+// the edge between two texels, which are then interpolated by the (linear) sampler.
+@fragment
+fn fs_sharp_bilinear(in: VertexOutput) -> @location(0) vec4<f32> {
+    let screensize = vec2<f32>(f32(current_screensize.x), f32(current_screensize.y));
+    let scale = screensize / vec2<f32>(160.0, 144.0);
+
+    // The position of the current pixel in texels, e.g. (0.5, 0.5) is the center of the top left Game Boy pixel
+    let texel = position_in_screen(in.clip_position) / scale;
+    let texel_floored = floor(texel);
+    let position_within_texel = texel - texel_floored;
+
+    // The largest integer factor the framebuffer can be scaled up by. Within the inner region of the texel, the
+    // sampling position is moved to the center of the texel, so only the outer 1 / integer_scale of the texel is
+    // interpolated.
+    let integer_scale = max(floor(scale), vec2<f32>(1.0));
+    let inner_region = vec2<f32>(0.5) - vec2<f32>(0.5) / integer_scale;
+    let distance_to_center = position_within_texel - vec2<f32>(0.5);
+    let adjusted_position_within_texel =
+        (distance_to_center - clamp(distance_to_center, -inner_region, inner_region)) * integer_scale + vec2<f32>(0.5);
+
+    let uv = (texel_floored + adjusted_position_within_texel) / vec2<f32>(160.0, 144.0);
+
+    return textureSample(frameBufferTexture, frameBufferSampler, uv);
+}
+
+// Fragment shader for Scale2x (also known as EPX) upscaling. Each Game Boy pixel is split into 2x2 pixels. Each of
+// these takes the color of a neighboring pixel, if the two neighbors adjacent to it have the same color (and the
+// other two do not), which smooths diagonal edges. The result is then scaled using nearest filtering.
+@fragment
+fn fs_scale2x(in: VertexOutput) -> @location(0) vec4<f32> {
+    let screensize = vec2<f32>(f32(current_screensize.x), f32(current_screensize.y));
+    let scale = screensize / vec2<f32>(160.0, 144.0);
+
+    // The position of the current pixel in texels and the Game Boy pixel it lies in
+    let texel = position_in_screen(in.clip_position) / scale;
+    let pixel = vec2<i32>(clamp(floor(texel), vec2<f32>(0.0), vec2<f32>(159.0, 143.0)));
+    // Which of the 2x2 pixels the current pixel lies in
+    let is_right = fract(texel.x) >= 0.5;
+    let is_bottom = fract(texel.y) >= 0.5;
+
+    let center = load_framebuffer_pixel(pixel);
+    let above = load_framebuffer_pixel(pixel + vec2<i32>(0, -1));
+    let right = load_framebuffer_pixel(pixel + vec2<i32>(1, 0));
+    let left = load_framebuffer_pixel(pixel + vec2<i32>(-1, 0));
+    let below = load_framebuffer_pixel(pixel + vec2<i32>(0, 1));
+
+    // The two neighbors adjacent to the current one of the 2x2 pixels (vertical and horizontal) and the two neighbors
+    // opposite of it
+    let vertical_neighbor = select(above, below, is_bottom);
+    let horizontal_neighbor = select(left, right, is_right);
+    let opposite_vertical_neighbor = select(below, above, is_bottom);
+    let opposite_horizontal_neighbor = select(right, left, is_right);
+
+    if all(vertical_neighbor == horizontal_neighbor)
+        && any(vertical_neighbor != opposite_horizontal_neighbor)
+        && any(horizontal_neighbor != opposite_vertical_neighbor) {
+        return vertical_neighbor;
+    }
+    return center;
+}
+
+// Returns the color of the provided pixel of the framebuffer. Pixels outside the framebuffer are clamped to its edges.
+fn load_framebuffer_pixel(pixel: vec2<i32>) -> vec4<f32> {
+    let clamped_pixel = clamp(pixel, vec2<i32>(0), vec2<i32>(159, 143));
+    return textureLoad(frameBufferTexture, clamped_pixel, 0);
+}
@@ -39,6 +39,19 @@ struct ObjectsInScanline {
     objects: array<vec4<u32>, 10>,
 }
 
+// Struct to hold the objects/sprites of a scanline as they are passed to the shader. Each object is packed into a
+// single u32, with its four bytes (y coordinate, x coordinate, tile index and attributes) from the lowest to the
+// highest byte. The last two u32s are unused. See unpack_objects_in_scanline.
+struct PackedObjectsInScanline {
+    objects: array<vec4<u32>, 3>,
+}
+
+// Struct to hold the lcd monochrome palettes and the Super Game Boy colors, see the palettes uniform below.
+struct Palettes {
+    values: vec4<u32>,
+    sgb_colors: vec4<u32>,
+}
+
 const BG_AND_WD_TILE_SIZE = vec2<i32>(8, 8);
 
 const COLOR_ZERO: vec4<f32> = vec4<f32>(0.836, 0.956, 0.726, 1.0);  // White
@@ -49,6 +62,11 @@ const COLOR_TRANSPARENT: vec4<f32> = vec4<f32>(0.0, 0.0, 0.0, 0.0); // This colo
                                                                     // signals that this object pixel should be transparent
                                                                     // and covered by the background / window
 
+// The bit of the LCD control register entry which is set, if the cartridge is run in CGB mode. In CGB mode, LCDC bit 0
+// does not disable the background and window, but is their master priority instead. If it is cleared, objects are
+// always drawn over the background and window, see https://gbdev.io/pandocs/LCDC.html#lcdc0--bg-and-window-enablepriority
+const CGB_MODE_BIT: u32 = 0x100;
+
 const BG_OR_WD_TILE: u32 = 0;
 const OBJECT_TILE_WITH_PALETTE_ZERO: u32 = 1;
 const OBJECT_TILE_WITH_PALETTE_ONE: u32 = 2;
@@ -64,10 +82,11 @@ struct PixelInObjectReturnValue {
 // Each tile is 8x8 pixels, with a total of 16 tiles per row/column, so the atlas is 128 x 128 pixels in total.
 // It is encoded in Rgba8UnormSrgb format.
 @group(0) @binding(0) var<uniform> bg_and_window_tile_data: TileDataPacked;
-// The first entry is the current rendering line, the second the LCD control register, the third the a bool indicating
+// The first entry is the current rendering line, the second the LCD control register (with CGB_MODE_BIT set in CGB
+// mode), the third the a bool indicating
 // whether the window is being rendered this scanline and the last, the current line of the window tilemap that would be
-// used, if the window is rendered.
-@group(0) @binding(1) var<uniform> current_line_lcd_control_register_and_window_internal_line_info: vec4<u32>;
+// used, if the window is rendered. There is one entry per scanline, like for all uniforms which end in _per_scanline.
+@group(0) @binding(1) var<uniform> line_lcd_control_register_and_window_internal_line_info_per_scanline: array<vec4<u32>, 144>;
 // Tilemap is a 32x32 array of u32s, the same size as the grid of tiles that is loaded in the Rust Boy.
 // Each u32 is a tile index, which is used to look up the tile in the tile atlas. The tilemap is in row major,
 // so the first 32 u32s are the first row of tiles, the next 32 u32s are the second row of tiles, and so on.
@@ -77,32 +96,48 @@ struct PixelInObjectReturnValue {
 // That is it is a vector with values between 0 and 255, since the tilemap is 256x256 pixels.
 // We use the first two entries of the vector to store the x and y coordinates of the background viewport position.
 // The last two entries are the x and y coordinates of the window viewport position.
-@group(0) @binding(4) var<uniform> bg_and_wd_viewport_position: vec4<u32>;
+@group(0) @binding(4) var<uniform> bg_and_wd_viewport_position_per_scanline: array<vec4<u32>, 144>;
 // The lcd monochrome palettes are just the registers FF47, FF48, FF49 as specified in the Pandocs
 // (https://gbdev.io/pandocs/Palettes.html). The first entry in the vec is the background and window palette (FF47), the second
-// entry is the object palette 0 (FF48) and the third entry is the object palette 1 (FF49). The fourth entry is empty.
-@group(0) @binding(5) var<uniform> palettes: vec4<u32>;
+// entry is the object palette 0 (FF48) and the third entry is the object palette 1 (FF49). The fourth entry is 1, if
+// the Super Game Boy colors should be used instead of COLOR_ZERO to COLOR_THREE. These are stored in sgb_colors, one
+// color per shade encoded as 0x00RRGGBB.
+@group(0) @binding(5) var<uniform> palettes_per_scanline: array<Palettes, 144>;
 
 // The sprite tile atlas is a 2D texture containing all the tiles used for the objects/sprites.
 @group(0) @binding(6) var<uniform> object_tile_data: TileDataPacked;
 // The objects in the current scnaline are the objects that are visible in the current line of the screen.
+// The objects are stored in a packed format, see PackedObjectsInScanline.
+@group(0) @binding(7) var<uniform> objects_in_scanline_per_scanline: array<PackedObjectsInScanline, 144>;
+
+// The entries of the uniforms above for the scanline the current pixel lies in. These are set at the beginning of
+// fs_main, since all scanlines (of a frame) are drawn at once.
+var<private> current_line_lcd_control_register_and_window_internal_line_info: vec4<u32>;
+var<private> bg_and_wd_viewport_position: vec4<u32>;
+var<private> palettes: Palettes;
 // The objects are stored in an array of 10 elements, each element is a vec4<u32>.
 // If there are less than 10 objects, the rest of the array is filled with 0s.
-@group(0) @binding(7) var<uniform> objects_in_scanline: ObjectsInScanline;
+var<private> objects_in_scanline: ObjectsInScanline;
 
 @fragment
 fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    // Retrieve the "position" of "the current pixel". The render pass only covers the scanlines to be drawn using a
+    // scissor rect, so the y coordinate is the (rendering) line the pixel lies in. The x coordinate is the index of the
+    // pixel within the line, iterating between 0 and 159.
+    let x: u32 = u32(in.clip_position.x);
+    let y: u32 = u32(in.clip_position.y);
+
+    current_line_lcd_control_register_and_window_internal_line_info =
+        line_lcd_control_register_and_window_internal_line_info_per_scanline[y];
+    bg_and_wd_viewport_position = bg_and_wd_viewport_position_per_scanline[y];
+    palettes = palettes_per_scanline[y];
+    objects_in_scanline = unpack_objects_in_scanline(objects_in_scanline_per_scanline[y]);
+
     // The tilemap is a 32x32 grid of tiles, each tile is 8x8 pixels. That is 256x256 pixels. The following variable
     // represents the position of the top left pixel of the visible screen within the tilemap. That is it is a vector
     // with values between 0 and 255.
     let viewport_position_in_pixels = vec2<i32>(i32(bg_and_wd_viewport_position.x), i32(bg_and_wd_viewport_position.y));
 
-    // Retrieve the "position" of "the current pixel". That is, per workgroup, the y coordinate is fixed to the current
-    // (rendering) line. The x coordinate on the other hand, is the local invocation id, which is an index iterating
-    // between 0 and 159 Thus, each workgroup will render a line/row of 160 pixels.
-    let x: u32 = u32(in.clip_position.x);
-    let y: u32 = current_line_lcd_control_register_and_window_internal_line_info.x;
-
     var color: vec4<f32>;
     var pixel_in_object: bool = false;
 
@@ -115,20 +150,36 @@ fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
     }
 
     // If the pixel is not in an object, we take the color from the background/window. Except if it is disabled via the
-    // LCD control register. Then we just take white (COLOR_ZERO).
+    // LCD control register. Then we just take white (COLOR_ZERO). In CGB mode, the background/window is never disabled.
     if (!pixel_in_object) {
         // Check if the background/window is enabled
-        if (current_line_lcd_control_register_and_window_internal_line_info.y & 0x01) != 0 {
+        if (current_line_lcd_control_register_and_window_internal_line_info.y & (0x01 | CGB_MODE_BIT)) != 0 {
             color = get_color_for_bg_or_wd_pixel(x, y, viewport_position_in_pixels);
         } else {
             // Background and window are disabled, so we take white as the color
-            color = COLOR_ZERO;
+            color = convert_shade_to_rgba8_color(0u);
         }
     }
 
     return color;
 }
 
+/// This function unpacks the objects of a scanline, such that each object is a vec4<u32> with the y coordinate, the x
+/// coordinate, the tile index and the attributes of the object as its entries.
+fn unpack_objects_in_scanline(packed_objects: PackedObjectsInScanline) -> ObjectsInScanline {
+    var unpacked_objects: ObjectsInScanline;
+    for (var i = 0u; i < 10u; i = i + 1u) {
+        let packed_object = packed_objects.objects[i / 4u][i % 4u];
+        unpacked_objects.objects[i] = vec4<u32>(
+            packed_object & 0xFFu,
+            (packed_object >> 8u) & 0xFFu,
+            (packed_object >> 16u) & 0xFFu,
+            packed_object >> 24u,
+        );
+    }
+    return unpacked_objects;
+}
+
 /// This function checks if the current pixel is in an object. If it is, it returns the color of the object and a boolean
 /// set to true to indicate that the pixel is in an object. Otherwise it returns the COLOR_TRANSPARENT and a boolean set to
 /// false to indicate that the pixel is not in an object.
@@ -160,7 +211,8 @@ fn is_pixel_in_object(x: u32, y: u32, viewport_position_in_pixels: vec2<i32>) ->
                 // If the color id is not transparent, we have found the object that covers the pixel
                 pixel_in_object = true;
                 // We need to check if the priority bit is set, if so the background/window pixel might 'dominate' this one.
-                // In order for the background/window to be able to do that the BG enable bit must be set
+                // In order for the background/window to be able to do that the BG enable bit must be set. In CGB mode,
+                // this bit is the master priority, which makes objects be drawn over the background/window if cleared
                 if ((object.w & 0x80) != 0) && ((current_line_lcd_control_register_and_window_internal_line_info.y & 0x01) != 0) {
                     // The priority bit is set we need to check the color id of the background/window at this pixel
                     let bg_or_wd_color_id = get_color_id_for_bg_or_wd_pixel(x, y, viewport_position_in_pixels);
@@ -219,7 +271,10 @@ fn get_color_id_for_bg_or_wd_pixel(x: u32, y: u32, viewport_position_in_pixels:
         pixel_coords = vec2<f32>(f32(x + 7 - bg_and_wd_viewport_position.z), f32(current_line_lcd_control_register_and_window_internal_line_info.w));
     }
 
-    // Calculate the index (vector of x and y indeces) of the tile the pixel is in
+    // Calculate the index (vector of x and y indeces) of the tile the pixel is in. For the background, pixel_coords
+    // can exceed the 256x256 pixels of the tilemap (e.g. SCX = 252 and x = 4 results in 256), in which case the
+    // tilemap wraps around on both axes. This is done by taking the tile index modulo the 32 tiles per row and column,
+    // so a tile straddling the edge of the tilemap continues with the first tile of the row or column
     let tile_index_in_tilemap = (vec2<i32>(pixel_coords / vec2<f32>(BG_AND_WD_TILE_SIZE))) % vec2<i32>(32, 32);
     // Calculate the flattened index
     let tilemap_flat_index = tile_index_in_tilemap.x + tile_index_in_tilemap.y * 32;
@@ -246,7 +301,8 @@ fn get_color_id_for_bg_or_wd_pixel(x: u32, y: u32, viewport_position_in_pixels:
         }
     }
 
-    // Calculate the coordinates of the pixel within the tile
+    // Calculate the coordinates of the pixel within the tile. Since 256 is a multiple of the tile size, this does not
+    // need to take the wrap around of the tilemap into account
     let pixel_index = vec2<i32>(pixel_coords) % BG_AND_WD_TILE_SIZE;
 
     let type_of_tile = BG_OR_WD_TILE;
@@ -254,6 +310,9 @@ fn get_color_id_for_bg_or_wd_pixel(x: u32, y: u32, viewport_position_in_pixels:
     return get_color_id_from_tile_data_buffers(tile_index_in_atlas, vec2<u32>(pixel_index), type_of_tile);
 }
 
+/// Returns the color of the provided object at the provided pixel. Each object selects the object palette it is
+/// colored with using bit 4 of its attributes (object.w), see https://gbdev.io/pandocs/OAM.html#byte-3--attributesflags.
+/// Color id 0 is transparent for objects, regardless of the palette, which is already handled in is_pixel_in_object.
 fn get_color_for_object_pixel(object: vec4<u32>, pixel_coords: vec2<u32>) -> vec4<f32> {
     var type_of_tile: u32;
     if (object.w & 0x10) != 0 {
@@ -350,18 +409,20 @@ fn convert_color_id_to_rgba8_color(color_id: u32, type_of_tile: u32) -> vec4<f32
     var palette: u32;
     if type_of_tile == BG_OR_WD_TILE {
         // Background and window palette
-        palette = palettes.x;
+        palette = palettes.values.x;
     } else if type_of_tile == OBJECT_TILE_WITH_PALETTE_ZERO {
         // Object palette 0
-        palette = palettes.y;
+        palette = palettes.values.y;
     } else {
         // Object palette 1
-        palette = palettes.z;
+        palette = palettes.values.z;
     }
 
     var color_value: u32;
     // The color value is a 2-bit value, representing one of the four possible colors.
-    // It is determined by the color_id and what color the palette assigns this color_id.
+    // It is determined by the color_id and what color the palette assigns this color_id. Note that color_id 0 is only
+    // transparent for objects (which is handled in is_pixel_in_object). For the background and window, it is mapped
+    // through BGP like any other color_id, which games rely on to fade to black by cycling BGP.
     switch (color_id) {
         case 0u: { color_value = palette & 0x03; break; }
         case 1u: { color_value = (palette & 0x0C) >> 2u; break; }
@@ -369,6 +430,21 @@ fn convert_color_id_to_rgba8_color(color_id: u32, type_of_tile: u32) -> vec4<f32
         default: { color_value = (palette & 0xC0) >> 6u; break; }
     }
 
+    return convert_shade_to_rgba8_color(color_value);
+}
+
+fn convert_shade_to_rgba8_color(color_value: u32) -> vec4<f32> {
+    // If the Super Game Boy palettes are active, the shade is mapped to the respective Super Game Boy color
+    if palettes.values.w != 0u {
+        let sgb_color = palettes.sgb_colors[color_value];
+        return vec4<f32>(
+            f32((sgb_color >> 16u) & 0xFFu) / 255.0,
+            f32((sgb_color >> 8u) & 0xFFu) / 255.0,
+            f32(sgb_color & 0xFFu) / 255.0,
+            1.0,
+        );
+    }
+
     // The color id is a 2-bit value, where each bit represents a color
     // 0 = white, 1 = light green, 2 = dark green, 3 = very dark green/black
     switch (color_value) {
@@ -0,0 +1,48 @@
+// Vertex shader
+struct VertexInput {
+    @location(0) position: vec3<f32>,
+    @location(1) color: vec3<f32>,
+};
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) color: vec3<f32>,
+};
+
+@vertex
+fn vs_main(
+    model: VertexInput,
+) -> VertexOutput {
+    var out: VertexOutput;
+    out.color = model.color;
+    out.clip_position = vec4<f32>(model.position, 1.0);
+    return out;
+}
+
+// The text overlay holds the text drawn on top of the Game Boy screen (e.g. notifications). It is transparent wherever no
+// text is drawn and has twice the resolution of the Game Boy screen, i.e. 320x288 pixels.
+@group(0) @binding(0) var text_overlay_texture: texture_2d<f32>;
+// Sampler for the text overlay
+@group(0) @binding(1) var text_overlay_sampler: sampler;
+// The rectangle the Game Boy screen is drawn to in pixels of the window. The first two components are its width and
+// height and the last two the offset of its top left corner, which is only non-zero if the LCD overlay
+// (lcd_overlay.wgsl) draws a bezel around the screen.
+@group(0) @binding(2) var<uniform> screen_rect: vec4<u32>;
+
+// Fragment shader drawing the text overlay stretched over the Game Boy screen. It is blended on top of the upscaled
+// framebuffer using alpha blending, so only the text and the boxes behind it are visible.
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let size = vec2<f32>(f32(screen_rect.x), f32(screen_rect.y));
+    let offset = vec2<f32>(f32(screen_rect.z), f32(screen_rect.w));
+    let position = in.clip_position.xy - offset;
+
+    // The texture is sampled before checking whether the pixel is within the screen, since sampling is only allowed in
+    // uniform control flow
+    let color = textureSample(text_overlay_texture, text_overlay_sampler, position / size);
+
+    if any(position < vec2<f32>(0.0)) || any(position >= size) {
+        return vec4<f32>(0.0);
+    }
+    return color;
+}
@@ -0,0 +1,110 @@
+//! This module contains the text overlay, which is drawn on top of the Game Boy screen to show
+//! the text drawn using [State::draw_text](super::State::draw_text), e.g. the notifications of
+//! [crate::notifications]. The text is drawn on the CPU into an RGBA image
+//! using the bitmap font of [super::font], which is then uploaded to a texture and blended on top
+//! of the upscaled framebuffer, see [super::shader::setup_text_overlay_pipeline].
+
+use super::font::{GLYPH_SIZE, glyph};
+use crate::{ORIGINAL_SCREEN_HEIGHT, ORIGINAL_SCREEN_WIDTH};
+
+/// The factor the resolution of the text overlay is larger than the resolution of the Game Boy
+/// screen. With a factor of 2, a glyph takes up 4 x 4 Game Boy pixels, so the text is small
+/// enough to not cover too much of the game, while still being readable.
+const TEXT_OVERLAY_SCALE: u32 = 2;
+/// The width of the text overlay in pixels.
+pub(crate) const TEXT_OVERLAY_WIDTH: u32 = ORIGINAL_SCREEN_WIDTH * TEXT_OVERLAY_SCALE;
+/// The height of the text overlay in pixels.
+pub(crate) const TEXT_OVERLAY_HEIGHT: u32 = ORIGINAL_SCREEN_HEIGHT * TEXT_OVERLAY_SCALE;
+
+/// The image drawn on top of the Game Boy screen, see the module documentation. It is
+/// [TEXT_OVERLAY_WIDTH] x [TEXT_OVERLAY_HEIGHT] pixels large and fully transparent wherever
+/// nothing is drawn.
+pub(crate) struct TextOverlay {
+    /// The RGBA pixels of the overlay, row by row from top to bottom. The colors are not
+    /// premultiplied by their alpha.
+    pixels: Vec<u8>,
+    /// Whether nothing was drawn since the overlay was last cleared, in which case it does not
+    /// have to be drawn on top of the screen at all.
+    is_empty: bool,
+}
+
+impl TextOverlay {
+    /// Creates a new, fully transparent text overlay.
+    pub(crate) fn new() -> TextOverlay {
+        TextOverlay {
+            pixels: vec![0; (4 * TEXT_OVERLAY_WIDTH * TEXT_OVERLAY_HEIGHT) as usize],
+            is_empty: true,
+        }
+    }
+
+    /// Returns the RGBA pixels of the overlay, see [TextOverlay::pixels].
+    pub(crate) fn pixels(&self) -> &[u8] {
+        &self.pixels
+    }
+
+    /// Returns true if nothing was drawn since the overlay was last cleared.
+    pub(crate) fn is_empty(&self) -> bool {
+        self.is_empty
+    }
+
+    /// Makes the whole overlay transparent again. Does nothing if the overlay is empty anyway.
+    pub(crate) fn clear(&mut self) {
+        if !self.is_empty {
+            self.pixels.fill(0);
+            self.is_empty = true;
+        }
+    }
+
+    /// Draws the provided text in a single line with the provided color (RGBA), where `x` and `y`
+    /// are the position of the top left corner of the first character in pixels of the overlay.
+    /// Each pixel of a glyph is drawn as a square of `scale` x `scale` pixels, so a character
+    /// takes up [GLYPH_SIZE] * `scale` pixels in both directions. Only the pixels of the glyphs
+    /// are drawn, the space between them is left as it is.
+    pub(crate) fn draw_text(&mut self, text: &str, x: u32, y: u32, scale: u32, color: [u8; 4]) {
+        let glyph_size = GLYPH_SIZE.saturating_mul(scale);
+        for (index, character) in text.chars().enumerate() {
+            let glyph_x = x.saturating_add((index as u32).saturating_mul(glyph_size));
+            if glyph_x >= TEXT_OVERLAY_WIDTH {
+                break;
+            }
+            for (row, bits) in glyph(character).iter().enumerate() {
+                for column in 0..GLYPH_SIZE {
+                    if bits & (0x80 >> column) != 0 {
+                        self.fill_rect(
+                            glyph_x.saturating_add(column.saturating_mul(scale)),
+                            y.saturating_add((row as u32).saturating_mul(scale)),
+                            scale,
+                            scale,
+                            color,
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    /// Fills the provided rectangle with the provided color (RGBA). The parts of the rectangle
+    /// outside the overlay are ignored, so text and boxes are cut off at its edges.
+    pub(crate) fn fill_rect(&mut self, x: u32, y: u32, width: u32, height: u32, color: [u8; 4]) {
+        self.is_empty = false;
+        for pixel_y in y..y.saturating_add(height).min(TEXT_OVERLAY_HEIGHT) {
+            for pixel_x in x..x.saturating_add(width).min(TEXT_OVERLAY_WIDTH) {
+                self.set_pixel(pixel_x, pixel_y, color);
+            }
+        }
+    }
+
+    /// Sets the pixel at the provided position, which has to be within the overlay, to the
+    /// provided color (RGBA).
+    fn set_pixel(&mut self, x: u32, y: u32, color: [u8; 4]) {
+        let index = (4 * (y * TEXT_OVERLAY_WIDTH + x)) as usize;
+        self.pixels[index..index + 4].copy_from_slice(&color);
+    }
+}
+
+/// Returns the provided color (RGBA) with its alpha scaled by the provided opacity (between 0.0
+/// and 1.0), which is used to fade out notifications.
+pub(crate) fn with_opacity(color: [u8; 4], opacity: f32) -> [u8; 4] {
+    let [red, green, blue, alpha] = color;
+    [red, green, blue, (alpha as f32 * opacity).round() as u8]
+}
@@ -4,14 +4,28 @@
 //! While a [Joypad] struct is defined in this module, it actually only serves as a namespace for
 //! the static methods that handle joypad functionality. The actual data is held in the [MemoryBus]
 //! struct.
+//!
+//! How opposing directions (Left + Right or Up + Down) which are held at the same time are
+//! reported to the game can be configured using [SocdMode].
+//!
+//! Buttons can be set to auto-fire (turbo), in which case they are repeatedly pressed and released
+//! while held, see [AutoFire].
 
+use crate::interrupts::{Interrupt, InterruptFlagRegister};
 use crate::memory_bus::JOYPAD_REGISTER;
 use crate::memory_bus::is_bit_set;
+#[cfg(feature = "frontend")]
+use crate::ppu::PPU;
+#[cfg(feature = "frontend")]
+use crate::save_state::NUMBER_OF_SAVE_STATE_SLOTS;
 use crate::{MemoryBus, RustBoy};
-use winit::keyboard::{KeyCode, PhysicalKey};
+#[cfg(feature = "frontend")]
+use winit::keyboard::{KeyCode, ModifiersState, PhysicalKey};
 
 const SELECT_DIRECTION_BUTTON_BIT: u8 = 4;
 const SELECT_ACTION_BUTTON_BIT: u8 = 5;
+/// The default number of frames a button with auto-fire stays pressed and then released.
+pub const DEFAULT_AUTO_FIRE_FRAMES: u8 = 2;
 
 /// Struct to interact with the GameBoy joypad. The joypad state is represented by a single register
 /// in the real GameBoy. The register has two flags which can be selected, depending on which
@@ -31,7 +45,7 @@ pub struct Joypad {}
 /// Struct to represent the state of the buttons on the joypad. Can either represent the action
 /// or directional buttons. Not that, rather unconventionally, true indicates that a button is NOT
 /// pressed and false indicates that a button IS pressed.
-#[derive(Debug)]
+#[derive(Clone, Copy, Debug)]
 pub(crate) struct ButtonState {
     start_or_down: bool,
     select_or_up: bool,
@@ -39,30 +53,268 @@ pub(crate) struct ButtonState {
     a_or_right: bool,
 }
 
+/// Enum to represent how simultaneous opposing cardinal directions (SOCD), that is, Left + Right
+/// or Up + Down held at the same time, are reported to the game. On the original Game Boy, the
+/// d-pad makes this impossible, but the joypad register can report both directions as pressed,
+/// which some games use for glitches. With a keyboard, this happens easily by accident, though.
+///
+/// - `AllowBoth`: Both directions are reported as pressed, like the hardware would. Is the default.
+/// - `LastPressed`: Only the direction which was pressed last is reported as pressed.
+/// - `Neutral`: Neither direction is reported as pressed.
+#[cfg_attr(target_arch = "wasm32", wasm_bindgen::prelude::wasm_bindgen)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SocdMode {
+    /// Both opposing directions are reported as pressed.
+    #[default]
+    AllowBoth,
+    /// Only the direction pressed last is reported as pressed.
+    LastPressed,
+    /// Neither of the opposing directions is reported as pressed.
+    Neutral,
+}
+
+impl std::str::FromStr for SocdMode {
+    type Err = String;
+
+    /// Parses the mode from its name, that is, `allow`, `last` or `neutral`.
+    fn from_str(name: &str) -> Result<SocdMode, String> {
+        match name.to_lowercase().as_str() {
+            "allow" => Ok(SocdMode::AllowBoth),
+            "last" => Ok(SocdMode::LastPressed),
+            "neutral" => Ok(SocdMode::Neutral),
+            _ => Err(format!(
+                "Invalid SOCD mode '{name}', expected 'allow', 'last' or 'neutral'"
+            )),
+        }
+    }
+}
+
+/// Struct to keep track of what is needed to resolve simultaneous opposing directions, see
+/// [SocdMode].
+///
+/// - `mode`: The configured [SocdMode].
+/// - `left_pressed_last`: Whether Left was pressed after Right (the last time either was pressed).
+/// - `up_pressed_last`: Whether Up was pressed after Down (the last time either was pressed).
+#[derive(Debug, Default)]
+pub(crate) struct SocdState {
+    pub(crate) mode: SocdMode,
+    left_pressed_last: bool,
+    up_pressed_last: bool,
+}
+
+/// Struct to keep track of the auto-fire (turbo) of the buttons. While a button with auto-fire is
+/// held, it is alternately reported as pressed and released to the game, each for
+/// `frames_per_toggle` frames, which is useful for shooters and grinding. The frames are counted
+/// in emulated frames, so the rate the game sees does not change when fast-forwarding.
+///
+/// - `enabled_buttons`: The buttons auto-fire is enabled for. Is toggled for the held buttons by
+///   pressing `X`, see [RustBoy::toggle_auto_fire_of_held_buttons]. Auto-fire is off for all
+///   buttons by default.
+/// - `held_buttons`: The buttons which are held by the player, regardless of whether they are
+///   currently reported as pressed.
+/// - `frames_per_toggle`: The number of frames a button with auto-fire stays pressed and then
+///   released.
+/// - `frames_since_toggle`: The number of frames since the buttons with auto-fire were last
+///   toggled.
+#[derive(Clone, Debug)]
+pub(crate) struct AutoFire {
+    enabled_buttons: JoypadState,
+    held_buttons: JoypadState,
+    pub(crate) frames_per_toggle: u8,
+    frames_since_toggle: u8,
+}
+
+impl Default for AutoFire {
+    fn default() -> Self {
+        AutoFire {
+            enabled_buttons: JoypadState::default(),
+            held_buttons: JoypadState::default(),
+            frames_per_toggle: DEFAULT_AUTO_FIRE_FRAMES,
+            frames_since_toggle: 0,
+        }
+    }
+}
+
+impl AutoFire {
+    /// Returns a copy of the auto-fire settings without the held buttons, e.g. to carry them over
+    /// to a RustBoy whose buttons are all released.
+    pub(crate) fn without_held_buttons(&self) -> AutoFire {
+        AutoFire {
+            held_buttons: JoypadState::default(),
+            frames_since_toggle: 0,
+            ..self.clone()
+        }
+    }
+}
+
+/// Struct to represent the state of all eight buttons of the joypad at once, e.g. to play back
+/// recorded input, see [RustBoy::set_joypad_state]. Unlike [ButtonState], true indicates that a
+/// button IS pressed. The default has no button pressed.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct JoypadState {
+    /// Whether the A button is pressed.
+    pub a: bool,
+    /// Whether the B button is pressed.
+    pub b: bool,
+    /// Whether the Start button is pressed.
+    pub start: bool,
+    /// Whether the Select button is pressed.
+    pub select: bool,
+    /// Whether Up on the directional pad is pressed.
+    pub up: bool,
+    /// Whether Down on the directional pad is pressed.
+    pub down: bool,
+    /// Whether Left on the directional pad is pressed.
+    pub left: bool,
+    /// Whether Right on the directional pad is pressed.
+    pub right: bool,
+}
+
+impl JoypadState {
+    /// Returns a mutable reference to the state of the provided button.
+    fn button_mut(&mut self, button: Button) -> &mut bool {
+        match button {
+            Button::A => &mut self.a,
+            Button::B => &mut self.b,
+            Button::Start => &mut self.start,
+            Button::Select => &mut self.select,
+            Button::Up => &mut self.up,
+            Button::Down => &mut self.down,
+            Button::Left => &mut self.left,
+            Button::Right => &mut self.right,
+        }
+    }
+
+    /// Returns true if the provided button is pressed.
+    fn is_pressed(mut self, button: Button) -> bool {
+        *self.button_mut(button)
+    }
+}
+
 /// Enum to represent the buttons on the joypad. The enum is used to identify which button is
 /// pressed.
-#[derive(Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum Button {
+    /// The A button.
     A,
+    /// The B button.
     B,
+    /// The Start button.
     Start,
+    /// The Select button.
     Select,
+    /// Up on the directional pad.
     Up,
+    /// Down on the directional pad.
     Down,
+    /// Left on the directional pad.
     Left,
+    /// Right on the directional pad.
     Right,
 }
 
+impl Button {
+    /// All eight buttons of the joypad.
+    const ALL: [Button; 8] = [
+        Button::A,
+        Button::B,
+        Button::Start,
+        Button::Select,
+        Button::Up,
+        Button::Down,
+        Button::Left,
+        Button::Right,
+    ];
+}
+
 impl RustBoy {
     /// Handles a button press event by calling the [Joypad::handle_button_press] method.
+    ///
+    /// Presses of a button which is already held (e.g. repeated key events) are ignored, so they
+    /// do not interfere with the auto-fire of the button, see [AutoFire].
     pub fn handle_button_press(&mut self, button: Button) {
+        let held = self.memory_bus.auto_fire.held_buttons.button_mut(button);
+        if *held {
+            return;
+        }
+        *held = true;
         Joypad::handle_button_press(&mut self.memory_bus, button);
     }
 
     /// Handles a button release event by calling the [Joypad::handle_button_release] method.
     pub fn handle_button_release(&mut self, button: Button) {
+        *self.memory_bus.auto_fire.held_buttons.button_mut(button) = false;
         Joypad::handle_button_release(&mut self.memory_bus, button);
     }
+
+    /// Sets the pressed/released state of all eight buttons at once, e.g. to play back recorded
+    /// (TAS) input or to drive the input of automated tests. Each button whose state changes is
+    /// passed to [RustBoy::handle_button_press] or [RustBoy::handle_button_release], like a key
+    /// event would be, so the joypad interrupt is requested for each newly pressed button.
+    ///
+    /// The provided state overrides the live keyboard input until the next key event, so it
+    /// should be set before each frame the input is supposed to apply to.
+    pub fn set_joypad_state(&mut self, buttons: JoypadState) {
+        let buttons = [
+            (Button::A, buttons.a),
+            (Button::B, buttons.b),
+            (Button::Start, buttons.start),
+            (Button::Select, buttons.select),
+            (Button::Up, buttons.up),
+            (Button::Down, buttons.down),
+            (Button::Left, buttons.left),
+            (Button::Right, buttons.right),
+        ];
+        for (button, pressed) in buttons {
+            // The held state is compared, since a button with auto-fire is not always reported as
+            // pressed while held
+            match (
+                pressed,
+                self.memory_bus.auto_fire.held_buttons.is_pressed(button),
+            ) {
+                (true, false) => self.handle_button_press(button),
+                (false, true) => self.handle_button_release(button),
+                _ => {}
+            }
+        }
+    }
+
+    /// Sets how simultaneous opposing directions are reported to the game, see [SocdMode].
+    pub fn set_socd_mode(&mut self, mode: SocdMode) {
+        self.memory_bus.socd_state.mode = mode;
+    }
+
+    /// Sets the number of frames a button with auto-fire stays pressed and then released, see
+    /// [AutoFire]. A value of 0 is treated as 1.
+    pub fn set_auto_fire_frames(&mut self, frames: u8) {
+        self.memory_bus.auto_fire.frames_per_toggle = frames.max(1);
+    }
+
+    /// Toggles auto-fire for each of the currently held buttons, see [AutoFire]. A button whose
+    /// auto-fire is turned off while it is held is reported as pressed again.
+    pub fn toggle_auto_fire_of_held_buttons(&mut self) {
+        let held_buttons = self.memory_bus.auto_fire.held_buttons;
+        let mut any_button_held = false;
+        for button in Button::ALL {
+            if !held_buttons.is_pressed(button) {
+                continue;
+            }
+            any_button_held = true;
+            let enabled = self.memory_bus.auto_fire.enabled_buttons.button_mut(button);
+            *enabled = !*enabled;
+            self.notifications.post(format!(
+                "Auto-fire of {button:?}: {}",
+                if *enabled { "on" } else { "off" }
+            ));
+            if !*enabled && !Joypad::is_button_pressed(&self.memory_bus, &button) {
+                Joypad::handle_button_press(&mut self.memory_bus, button);
+            }
+        }
+        if !any_button_held {
+            self.notifications
+                .post("Hold buttons to toggle their auto-fire");
+        }
+    }
 }
 
 impl Joypad {
@@ -79,25 +331,119 @@ impl Joypad {
             (true, true) => {
                 value
                     | (memory_bus.action_button_state.as_u8()
-                        & memory_bus.direction_button_state.as_u8())
+                        & Joypad::get_direction_buttons(memory_bus))
             }
             (true, false) => value | memory_bus.action_button_state.as_u8(),
-            (false, true) => value | memory_bus.direction_button_state.as_u8(),
+            (false, true) => value | Joypad::get_direction_buttons(memory_bus),
             (false, false) => value | 0x0F,
         }
     }
 
+    /// Returns the lower nibble of the joypad register for the direction buttons, with
+    /// simultaneous opposing directions resolved according to the [SocdMode].
+    fn get_direction_buttons(memory_bus: &MemoryBus) -> u8 {
+        let mut direction_button_state = memory_bus.direction_button_state;
+        let socd_state = &memory_bus.socd_state;
+        // Note that false indicates that a button is pressed
+        if !direction_button_state.b_or_left && !direction_button_state.a_or_right {
+            match socd_state.mode {
+                SocdMode::AllowBoth => {}
+                SocdMode::LastPressed => {
+                    direction_button_state.b_or_left = !socd_state.left_pressed_last;
+                    direction_button_state.a_or_right = socd_state.left_pressed_last;
+                }
+                SocdMode::Neutral => {
+                    direction_button_state.b_or_left = true;
+                    direction_button_state.a_or_right = true;
+                }
+            }
+        }
+        if !direction_button_state.select_or_up && !direction_button_state.start_or_down {
+            match socd_state.mode {
+                SocdMode::AllowBoth => {}
+                SocdMode::LastPressed => {
+                    direction_button_state.select_or_up = !socd_state.up_pressed_last;
+                    direction_button_state.start_or_down = socd_state.up_pressed_last;
+                }
+                SocdMode::Neutral => {
+                    direction_button_state.select_or_up = true;
+                    direction_button_state.start_or_down = true;
+                }
+            }
+        }
+        direction_button_state.as_u8()
+    }
+
     /// Writes to the joypad register.
     ///
     /// Since bits 7,6 and the lower nibble are all not writable,
     /// only bits 5 and 4 of value will actually be considered.
+    ///
+    /// The write is also passed on to [crate::sgb::handle_joypad_write], since Super Game Boy
+    /// aware games send command packets via pulses on these bits.
     pub fn write_joypad_register(memory_bus: &mut MemoryBus, value: u8) {
         let value = value & 0b0011_0000;
         memory_bus.memory[JOYPAD_REGISTER as usize] = value;
+        crate::sgb::handle_joypad_write(memory_bus, value);
+    }
+
+    /// Returns true if the provided button is currently pressed (regardless of [SocdMode]).
+    fn is_button_pressed(memory_bus: &MemoryBus, button: &Button) -> bool {
+        // Note that false indicates that a button is pressed
+        !match button {
+            Button::A => memory_bus.action_button_state.a_or_right,
+            Button::B => memory_bus.action_button_state.b_or_left,
+            Button::Start => memory_bus.action_button_state.start_or_down,
+            Button::Select => memory_bus.action_button_state.select_or_up,
+            Button::Up => memory_bus.direction_button_state.select_or_up,
+            Button::Down => memory_bus.direction_button_state.start_or_down,
+            Button::Left => memory_bus.direction_button_state.b_or_left,
+            Button::Right => memory_bus.direction_button_state.a_or_right,
+        }
+    }
+
+    /// Toggles the held buttons with auto-fire between pressed and released once every
+    /// `frames_per_toggle` frames, see [AutoFire]. Is called once per frame. Pressing a button
+    /// requests the joypad interrupt like pressing its key does.
+    pub(crate) fn handle_auto_fire(memory_bus: &mut MemoryBus) {
+        let auto_fire = &mut memory_bus.auto_fire;
+        auto_fire.frames_since_toggle += 1;
+        if auto_fire.frames_since_toggle < auto_fire.frames_per_toggle {
+            return;
+        }
+        auto_fire.frames_since_toggle = 0;
+
+        let (enabled_buttons, held_buttons) = (auto_fire.enabled_buttons, auto_fire.held_buttons);
+        for button in Button::ALL {
+            if !enabled_buttons.is_pressed(button) || !held_buttons.is_pressed(button) {
+                continue;
+            }
+            if Joypad::is_button_pressed(memory_bus, &button) {
+                Joypad::handle_button_release(memory_bus, button);
+            } else {
+                Joypad::handle_button_press(memory_bus, button);
+            }
+        }
     }
 
     /// Handles the button press event by setting the corresponding button state to false (pressed).
+    ///
+    /// If the button was not pressed before, the joypad interrupt is requested, since one of the
+    /// input lines of the joypad register goes from high to low. For simplicity, this is done
+    /// regardless of which buttons are currently selected in the joypad register.
     pub(crate) fn handle_button_press(memory_bus: &mut MemoryBus, button: Button) {
+        if !Joypad::is_button_pressed(memory_bus, &button) {
+            InterruptFlagRegister::set_flag(memory_bus, Interrupt::Joypad, true);
+        }
+
+        // Keep track of which of the opposing directions was pressed last, see [SocdMode]
+        match button {
+            Button::Left => memory_bus.socd_state.left_pressed_last = true,
+            Button::Right => memory_bus.socd_state.left_pressed_last = false,
+            Button::Up => memory_bus.socd_state.up_pressed_last = true,
+            Button::Down => memory_bus.socd_state.up_pressed_last = false,
+            _ => {}
+        }
         match button {
             Button::A => memory_bus.action_button_state.a_or_right = false,
             Button::B => memory_bus.action_button_state.b_or_left = false,
@@ -157,7 +503,25 @@ impl ButtonState {
 }
 
 /// Handles the key pressed event by calling the [RustBoy::handle_button_press] method.
-pub fn handle_key_pressed_event(rust_boy: &mut RustBoy, key: &PhysicalKey, paused: &mut bool) {
+///
+/// The number keys 0 - 9 load the save state of the corresponding slot. If shift is held
+/// (see `modifiers`), the state is saved to the slot instead, see [crate::save_state].
+#[cfg(feature = "frontend")]
+pub fn handle_key_pressed_event(
+    rust_boy: &mut RustBoy,
+    key: &PhysicalKey,
+    paused: &mut bool,
+    modifiers: ModifiersState,
+) {
+    if let Some(slot) = save_state_slot_of_key(key) {
+        if modifiers.shift_key() {
+            rust_boy.save_state_to_slot(slot);
+        } else {
+            rust_boy.load_state_from_slot(slot);
+        }
+        return;
+    }
+
     match key {
         PhysicalKey::Code(KeyCode::ArrowLeft) => {
             rust_boy.handle_button_press(Button::Left);
@@ -183,19 +547,78 @@ pub fn handle_key_pressed_event(rust_boy: &mut RustBoy, key: &PhysicalKey, pause
         PhysicalKey::Code(KeyCode::Space) => {
             rust_boy.handle_button_press(Button::Select);
         }
-        PhysicalKey::Code(KeyCode::KeyP) => {
-            *paused = !*paused;
-            if *paused {
-                log::info!("Paused");
+        #[cfg(not(target_arch = "wasm32"))]
+        PhysicalKey::Code(KeyCode::KeyR) => {
+            rust_boy
+                .frame_recorder
+                .toggle_recording(&mut rust_boy.notifications);
+        }
+        PhysicalKey::Code(KeyCode::KeyT) => {
+            PPU::log_tile_debug_dump(&rust_boy.memory_bus);
+        }
+        PhysicalKey::Code(KeyCode::KeyD) => {
+            rust_boy.log_register_dump();
+        }
+        #[cfg(all(debug_assertions, not(target_arch = "wasm32")))]
+        PhysicalKey::Code(KeyCode::KeyV) => {
+            rust_boy.dump_vram_and_oam();
+        }
+        PhysicalKey::Code(KeyCode::KeyX) => {
+            rust_boy.toggle_auto_fire_of_held_buttons();
+        }
+        PhysicalKey::Code(KeyCode::Comma) if *paused => {
+            rust_boy.step_instruction_and_log();
+        }
+        PhysicalKey::Code(KeyCode::KeyN) if *paused => {
+            // The emulation is resumed until the call returned, which pauses it again like a
+            // breakpoint. Any other instruction is just stepped
+            if rust_boy.start_step_over() {
+                log::info!("Stepping over the call at {:#06X}", rust_boy.cpu.pc);
+                *paused = false;
             } else {
-                log::info!("Unpaused");
+                rust_boy.step_instruction_and_log();
             }
         }
+        PhysicalKey::Code(KeyCode::KeyP) => {
+            // Pausing or resuming by hand ends a step over, which would otherwise pause the
+            // emulation unexpectedly later on
+            rust_boy.breakpoints.cancel_step_over();
+            *paused = !*paused;
+            rust_boy
+                .notifications
+                .post(if *paused { "Paused" } else { "Unpaused" });
+        }
         _ => {}
     }
 }
 
+/// Returns the save state slot the provided key is bound to, if any. The slots 0 - 9 are bound
+/// to the number keys 0 - 9 above the letters.
+#[cfg(feature = "frontend")]
+fn save_state_slot_of_key(key: &PhysicalKey) -> Option<u8> {
+    const SLOT_KEYS: [KeyCode; NUMBER_OF_SAVE_STATE_SLOTS as usize] = [
+        KeyCode::Digit0,
+        KeyCode::Digit1,
+        KeyCode::Digit2,
+        KeyCode::Digit3,
+        KeyCode::Digit4,
+        KeyCode::Digit5,
+        KeyCode::Digit6,
+        KeyCode::Digit7,
+        KeyCode::Digit8,
+        KeyCode::Digit9,
+    ];
+    match key {
+        PhysicalKey::Code(key_code) => SLOT_KEYS
+            .iter()
+            .position(|slot_key| slot_key == key_code)
+            .map(|slot| slot as u8),
+        PhysicalKey::Unidentified(_) => None,
+    }
+}
+
 /// Handles the key released event by calling the [RustBoy::handle_button_release] method.
+#[cfg(feature = "frontend")]
 pub fn handle_key_released_event(rust_boy: &mut RustBoy, key: &PhysicalKey) {
     match key {
         PhysicalKey::Code(KeyCode::ArrowLeft) => {
@@ -21,6 +21,10 @@ const LCD_STAT_INTERRUPT_BIT: u8 = 1;
 const TIMER_INTERRUPT_BIT: u8 = 2;
 const SERIAL_INTERRUPT_BIT: u8 = 3;
 const JOYPAD_INTERRUPT_BIT: u8 = 4;
+/// The bits of the interrupt flag register (IF 0xFF0F) which are used, that is, one bit for each
+/// [Interrupt]. The upper 3 bits are unused and always read as 1, see
+/// [InterruptFlagRegister::read_interrupt_flag_register].
+const INTERRUPT_FLAG_BITS: u8 = 0b0001_1111;
 
 /// The interrupt enable register (IE 0xFFFF) is a 8-bit register that controls the enabling of interrupts.
 /// The individual bits of the register correspond to different interrupts. If these and the IME
@@ -53,7 +57,7 @@ pub struct InterruptEnableRegister {}
 /// - Bit 2: Timer interrupt
 /// - Bit 3: Serial interrupt
 /// - Bit 4: Joypad interrupt
-/// The other bits are unused.
+/// The other bits are unused. They are not stored and always read as 1.
 pub struct InterruptFlagRegister {}
 
 #[derive(Debug, Clone, Copy)]
@@ -123,6 +127,10 @@ impl CPU {
                 // Clear the interrupt flags
                 InterruptFlagRegister::set_flag(memory_bus, interrupt, false);
                 self.ime = false;
+                // An EI executed right before the dispatch (while IME was already set) must not
+                // set IME again after the first instruction of the handler, since then a second
+                // pending interrupt would be serviced even if the handler starts with DI
+                self.ime_to_be_set = false;
 
                 return true;
             }
@@ -150,14 +158,23 @@ impl InterruptEnableRegister {
 }
 
 impl InterruptFlagRegister {
-    /// Returns the value of the interrupt flag register (IF 0xFF0F).
+    /// Returns the value of the interrupt flag register (IF 0xFF0F). Only the bits of the
+    /// interrupts are returned, see [INTERRUPT_FLAG_BITS], so the unused upper 3 bits are 0.
     pub fn get_interrupt_flag_register(memory_bus: &MemoryBus) -> u8 {
-        memory_bus.memory[INTERRUPT_FLAG_REGISTER as usize]
+        memory_bus.memory[INTERRUPT_FLAG_REGISTER as usize] & INTERRUPT_FLAG_BITS
+    }
+
+    /// Returns the value of the interrupt flag register (IF 0xFF0F) as it is read by the CPU.
+    /// That is, the unused upper 3 bits always read as 1. Is also used for the logs, so they
+    /// show the same value as other emulators.
+    pub fn read_interrupt_flag_register(memory_bus: &MemoryBus) -> u8 {
+        InterruptFlagRegister::get_interrupt_flag_register(memory_bus) | !INTERRUPT_FLAG_BITS
     }
 
-    /// Sets the value of the interrupt flag register (IF 0xFF0F).
+    /// Sets the value of the interrupt flag register (IF 0xFF0F). Only the bits of the interrupts
+    /// are stored, see [INTERRUPT_FLAG_BITS].
     pub fn set_interrupt_flag_register(memory_bus: &mut MemoryBus, value: u8) {
-        memory_bus.memory[INTERRUPT_FLAG_REGISTER as usize] = value;
+        memory_bus.memory[INTERRUPT_FLAG_REGISTER as usize] = value & INTERRUPT_FLAG_BITS;
     }
 
     /// Returns the value of the provided [Interrupt] from the interrupt flag register.
@@ -213,3 +230,28 @@ impl Interrupt {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::RustBoy;
+    use crate::debugging::DebugInfo;
+
+    #[test]
+    fn upper_bits_of_the_interrupt_flag_register_read_as_1() {
+        let mut rust_boy = RustBoy::new_after_boot(DebugInfo::default());
+        let memory_bus = &mut rust_boy.memory_bus;
+        assert_eq!(memory_bus.read_byte(INTERRUPT_FLAG_REGISTER), 0xE1);
+        for (value, expected_value) in [(0x00, 0xE0), (0xFF, 0xFF), (0xA4, 0xE4)] {
+            memory_bus.write_byte(INTERRUPT_FLAG_REGISTER, value);
+            assert_eq!(
+                memory_bus.read_byte(INTERRUPT_FLAG_REGISTER),
+                expected_value
+            );
+            assert_eq!(
+                InterruptFlagRegister::get_interrupt_flag_register(memory_bus),
+                value & INTERRUPT_FLAG_BITS
+            );
+        }
+    }
+}
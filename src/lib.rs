@@ -8,63 +8,133 @@
 #![warn(clippy::all)] // Enable all Clippy lints
 //! This crate provides the methods used to run a Rust Boy emulator written in Rust. It can be run both natively and on the web using WebAssembly.
 //!
+//! The window and the GPU rendering are part of the `frontend` feature, which is enabled by
+//! default. Without it, only the emulation core is built, which can be driven using
+//! [RustBoy::from_rom] and [RustBoy::run_frame] and renders using the pixel FIFO of the PPU.
+//!
 //! For an in depth explication of the original Game Boy, which this emulates, please refer to [Pan Docs](https://gbdev.io/pandocs/).
 
+mod apu;
+mod breakpoints;
 mod cpu;
 mod debugging;
+mod error;
+#[cfg(feature = "frontend")]
+mod frame_recorder;
+#[cfg(feature = "frontend")]
 mod frontend;
 mod input;
 mod interrupts;
 mod memory_bus;
+mod notifications;
 mod ppu;
+#[cfg(all(feature = "frontend", not(target_arch = "wasm32")))]
+mod rom_watcher;
+#[cfg(feature = "frontend")]
+mod run_options;
+#[cfg(feature = "frontend")]
+mod save_file;
+mod save_state;
+mod serial;
+mod sgb;
 mod timer;
+#[cfg(all(feature = "frontend", target_arch = "wasm32"))]
+mod web;
 
+#[cfg(feature = "frontend")]
+use std::time::Duration;
 #[cfg(target_arch = "wasm32")]
 use wasm_bindgen::prelude::*;
+#[cfg(feature = "frontend")]
 use wasm_timer::Instant;
 
+use breakpoints::Breakpoints;
 use cpu::registers::CPURegisters;
 use debugging::DebugInfo;
 #[cfg(debug_assertions)]
 use debugging::setup_debugging_logs_files;
+#[cfg(debug_assertions)]
+use debugging::setup_json_trace_file;
+#[cfg(all(feature = "frontend", not(target_arch = "wasm32")))]
+use frame_recorder::FrameRecorder;
+#[cfg(feature = "frontend")]
 use frontend::State;
+#[cfg(feature = "frontend")]
 use input::{handle_key_pressed_event, handle_key_released_event};
-use ppu::RenderTask;
-use timer::TimerInfo;
+use notifications::Notifications;
+#[cfg(all(feature = "frontend", not(target_arch = "wasm32")))]
+use rom_watcher::RomWatcher;
+#[cfg(feature = "frontend")]
+use save_file::SaveFile;
+use serial::link_cable::LinkCable;
+use sgb::SgbPacket;
 
+#[cfg(feature = "frontend")]
 use winit::dpi::LogicalSize;
+#[cfg(feature = "frontend")]
 use winit::event_loop::EventLoopWindowTarget;
+#[cfg(feature = "frontend")]
 use winit::{
     dpi::PhysicalSize,
     event::*,
     event_loop::EventLoop,
-    keyboard::{KeyCode, PhysicalKey},
+    keyboard::{KeyCode, ModifiersState, PhysicalKey},
     window::WindowBuilder,
 };
 // Export main parts of the RustBoy
 pub use cpu::CPU;
-pub use input::Joypad;
+pub use cpu::InstructionHook;
+pub use cpu::instructions::Instruction;
+pub use error::RustBoyError;
+#[cfg(feature = "frontend")]
+pub use frontend::PresentMode;
+pub use input::{Button, DEFAULT_AUTO_FIRE_FRAMES, Joypad, JoypadState, SocdMode};
 pub use memory_bus::MemoryBus;
+pub use memory_bus::cartridge_header::CartridgeHeader;
 pub use ppu::PPU;
+pub use ppu::RenderTask;
+pub use ppu::tile_handling::{tile_data_to_string, tile_map_to_string};
+#[cfg(feature = "frontend")]
+pub use run_options::RunOptions;
 
+#[cfg(feature = "frontend")]
 const TARGET_FPS: f64 = 60.0;
+#[cfg(feature = "frontend")]
 const TARGET_FRAME_DURATION_IN_SECS: f64 = 1.0 / TARGET_FPS;
-pub(crate) const ORIGINAL_SCREEN_WIDTH: u32 = 160;
-pub(crate) const ORIGINAL_SCREEN_HEIGHT: u32 = 144;
+/// The speed relative to the original Game Boy the emulator runs at while `S` is held, e.g. to
+/// watch fast animations. The frames are shown for longer, instead of being skipped.
+#[cfg(feature = "frontend")]
+const SLOW_MOTION_SPEED: f64 = 0.1;
+/// The width of the screen of the Game Boy in pixels.
+pub const ORIGINAL_SCREEN_WIDTH: u32 = 160;
+/// The height of the screen of the Game Boy in pixels, that is, the number of visible scanlines.
+pub const ORIGINAL_SCREEN_HEIGHT: u32 = 144;
+/// The size of an RGBA framebuffer of the screen in bytes, see [RustBoy::framebuffer].
+pub const FRAMEBUFFER_SIZE: usize = (4 * ORIGINAL_SCREEN_WIDTH * ORIGINAL_SCREEN_HEIGHT) as usize;
+#[cfg(feature = "frontend")]
 const M_CYCLES_PER_SECOND: u32 = 1_048_576;
 const MEMORY_SIZE: usize = 65536;
 
 /// Struct to represent the Rust Boy.
 /// It splits up into 3 main parts: The [CPU](CPU), the [Memory Bus](MemoryBus), and the [PPU](PPU) (Pixel Processing Unit).
-/// The fourth field is the [TimerInfo](TimerInfo) struct, which keeps track of the timer and divider registers.
+/// The [Breakpoints](breakpoints::Breakpoints) pause the emulation at specific addresses and the
+/// notifications are shown on top of the screen after an action.
+/// With the `frontend` feature, the [SaveFile](SaveFile) field is the file the battery backed
+/// external RAM is persisted to, if any, and the last field is the
+/// [FrameRecorder](frame_recorder::FrameRecorder), which records clips of the screen (except on
+/// the web).
 ///
 /// For an in depth explication of the original Game Boy, which this emulates, please refer to [Pan Docs](https://gbdev.io/pandocs/).
 pub struct RustBoy {
     cpu: CPU,
     memory_bus: MemoryBus,
     ppu: PPU,
-    // TODO: Move this into memory bus?
-    timer_info: TimerInfo,
+    breakpoints: Breakpoints,
+    notifications: Notifications,
+    #[cfg(feature = "frontend")]
+    save_file: Option<SaveFile>,
+    #[cfg(all(feature = "frontend", not(target_arch = "wasm32")))]
+    frame_recorder: FrameRecorder,
 }
 
 impl RustBoy {
@@ -79,8 +149,13 @@ impl RustBoy {
         RustBoy {
             memory_bus: MemoryBus::new_before_boot(&debugging_flags),
             ppu: PPU::new_empty(),
-            timer_info: TimerInfo::new(),
+            breakpoints: Breakpoints::default(),
+            notifications: Notifications::default(),
             cpu: CPU::new_before_boot_rom(debugging_flags),
+            #[cfg(feature = "frontend")]
+            save_file: None,
+            #[cfg(all(feature = "frontend", not(target_arch = "wasm32")))]
+            frame_recorder: FrameRecorder::new(TARGET_FPS as u32),
         }
     }
 
@@ -98,44 +173,326 @@ impl RustBoy {
         rust_boy.memory_bus.being_initialized = false;
         rust_boy
     }
+
+    /// Creates a new instance of the RustBoy struct in the state after the boot rom has been
+    /// executed, loads the provided ROM and enables the pixel FIFO of the PPU. All debugging flags
+    /// are disabled.
+    ///
+    /// This is the entry point for using the emulation core without the window and GPU rendering
+    /// of the `frontend` feature. The frames are rendered by the pixel FIFO and can be retrieved
+    /// using [RustBoy::framebuffer] after each call to [RustBoy::run_frame]. Frontends which draw
+    /// line by line can instead drive the emulator using [RustBoy::tick] and retrieve each line
+    /// using [RustBoy::scanline].
+    ///
+    /// Returns an error, if the ROM cannot be loaded, see [MemoryBus::load_program].
+    pub fn from_rom(rom_data: &[u8]) -> Result<RustBoy, RustBoyError> {
+        let mut rust_boy = setup_rust_boy(DebugInfo::default(), None, rom_data)?;
+        rust_boy.ppu.enable_pixel_fifo();
+        Ok(rust_boy)
+    }
+
+    /// Runs the emulator until the next frame is complete. That is, until the PPU enters VBlank.
+    /// The emulator is not paced, so this returns as soon as the frame is emulated. While the LCD
+    /// is off, the PPU does not enter VBlank, so this returns after the time of a whole frame
+    /// ([DOTS_PER_FRAME](ppu::DOTS_PER_FRAME) dots) instead, with a blank screen.
+    ///
+    /// Returns early, if a breakpoint is hit, see [RustBoy::add_breakpoint].
+    ///
+    /// Returns an error, if the ROM makes the CPU execute an invalid instruction.
+    pub fn run_frame(&mut self) -> Result<(), RustBoyError> {
+        let end_of_frame_in_dots = self.ppu.rendering_info.total_dots + ppu::DOTS_PER_FRAME as u128;
+        while self.tick()? != RenderTask::RenderFrame {
+            if self.breakpoints.was_hit()
+                || self.ppu.rendering_info.total_dots >= end_of_frame_in_dots
+            {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    /// Runs the emulator for the provided number of frames using [RustBoy::run_frame] and returns
+    /// a copy of the framebuffer after each frame, oldest first. The frames are rendered in
+    /// software by the pixel FIFO, which is enabled if it is not used yet, so neither a window nor
+    /// a GPU is needed. The emulation does not depend on the wall-clock time, so the same ROM and
+    /// input always result in the same frames, e.g. for golden image or animation tests. While the
+    /// LCD is off, the frames are blank, see [RustBoy::run_frame].
+    ///
+    /// Each frame takes [FRAMEBUFFER_SIZE] bytes, which are only allocated once the frame is run.
+    /// To process the frames one by one without keeping all of them, use
+    /// [RustBoy::run_frames_with] instead.
+    ///
+    /// Returns early with the frames completed so far, if a breakpoint is hit, see
+    /// [RustBoy::add_breakpoint].
+    ///
+    /// Returns an error, if the ROM makes the CPU execute an invalid instruction.
+    pub fn run_frames(
+        &mut self,
+        number_of_frames: u32,
+    ) -> Result<Vec<[u8; FRAMEBUFFER_SIZE]>, RustBoyError> {
+        let mut frames = Vec::new();
+        self.run_frames_with(number_of_frames, |frame| frames.push(*frame))?;
+        Ok(frames)
+    }
+
+    /// Runs the emulator for the provided number of frames like [RustBoy::run_frames], but calls
+    /// the provided callback with the framebuffer after each frame instead of collecting them.
+    /// So the memory use does not grow with the number of frames.
+    ///
+    /// Returns early without calling the callback for the incomplete frame, if a breakpoint is
+    /// hit, see [RustBoy::add_breakpoint].
+    ///
+    /// Returns an error, if the ROM makes the CPU execute an invalid instruction.
+    pub fn run_frames_with(
+        &mut self,
+        number_of_frames: u32,
+        mut on_frame: impl FnMut(&[u8; FRAMEBUFFER_SIZE]),
+    ) -> Result<(), RustBoyError> {
+        if self.ppu.pixel_fifo.is_none() {
+            self.ppu.enable_pixel_fifo();
+        }
+        for _ in 0..number_of_frames {
+            self.run_frame()?;
+            if self.breakpoints.was_hit() {
+                break;
+            }
+            let framebuffer = self
+                .framebuffer()
+                .expect("The pixel FIFO was enabled above")
+                .try_into()
+                .expect("The framebuffer of the pixel FIFO should have the size of the screen");
+            on_frame(framebuffer);
+        }
+        Ok(())
+    }
+
+    /// Executes a single instruction and advances the timer and the PPU by the m-cycles it took.
+    /// While the CPU is halted, a single m-cycle passes instead. The emulator is not paced, so
+    /// this returns as soon as the instruction is emulated.
+    ///
+    /// Returns without executing the instruction, if a breakpoint is hit, see
+    /// [RustBoy::add_breakpoint]. Calling this again then executes it.
+    ///
+    /// Returns an error, if the ROM makes the CPU execute an invalid instruction.
+    pub fn step(&mut self) -> Result<(), RustBoyError> {
+        self.tick()?;
+        Ok(())
+    }
+
+    /// Executes a single instruction like [RustBoy::step] and returns what the PPU completed
+    /// meanwhile, see [RenderTask]. This lets a frontend drive the emulation and present the
+    /// lines or frames itself, e.g. using [RustBoy::scanline] on [RenderTask::WriteLineToBuffer]
+    /// and [RustBoy::framebuffer] on [RenderTask::RenderFrame]. The emulator is not paced, so
+    /// the frontend has to present about 60 frames per second to run at the original speed.
+    ///
+    /// Returns [RenderTask::None] without executing the instruction, if a breakpoint is hit, see
+    /// [RustBoy::add_breakpoint].
+    ///
+    /// Returns an error, if the ROM makes the CPU execute an invalid instruction.
+    pub fn tick(&mut self) -> Result<RenderTask, RustBoyError> {
+        handle_no_rendering_task(self)
+    }
+
+    /// Resets the RustBoy to the state after the boot ROM, like turning the Game Boy off and on
+    /// again. That is, the CPU, the PPU, the timer and the memory (including the hardware
+    /// registers, see [CPU::initialize_hardware_registers]) start over, while the loaded ROM and
+    /// the battery backed external RAM are kept. The options of the RustBoy carry over as well,
+    /// see [RustBoy::take_options_of].
+    ///
+    /// This gives a known starting state, e.g. to play back recorded input, see
+    /// [RustBoy::set_joypad_state]. In the window, the RustBoy is reset by pressing `Backspace`.
+    pub fn reset(&mut self) {
+        let mut new_rust_boy = self.new_with_same_debugging_flags();
+        if self.memory_bus.cartridge_header.is_some() {
+            new_rust_boy.memory_bus.ram_bank_count_override =
+                self.memory_bus.ram_bank_count_override;
+            new_rust_boy.memory_bus.game_tweaks = self.memory_bus.game_tweaks;
+            new_rust_boy
+                .memory_bus
+                .load_program(&self.memory_bus.rom_data())
+                .expect("The ROM was loaded before, so it can be loaded again");
+            if let Some(battery_backed_ram) = self.memory_bus.get_battery_backed_ram() {
+                new_rust_boy
+                    .memory_bus
+                    .load_battery_backed_ram(battery_backed_ram);
+            }
+            // Unsaved changes of the battery backed external RAM still have to be persisted
+            new_rust_boy.memory_bus.external_ram_written = self.memory_bus.external_ram_written;
+        }
+        new_rust_boy.take_options_of(self);
+        *self = new_rust_boy;
+        self.notifications.post("Reset the RustBoy");
+    }
+
+    /// Returns the parsed header of the loaded cartridge, e.g. to check whether the global
+    /// checksum is valid, see [CartridgeHeader::global_checksum_is_valid]. Returns None, if no
+    /// ROM is loaded.
+    pub fn cartridge_header(&self) -> Option<&CartridgeHeader> {
+        self.memory_bus.cartridge_header.as_ref()
+    }
+
+    /// Returns the RGBA framebuffer of size 160 x 144 rendered by the pixel FIFO. Returns None, if
+    /// the pixel FIFO is not used for rendering, see [PPU::enable_pixel_fifo].
+    pub fn framebuffer(&self) -> Option<&[u8]> {
+        self.ppu
+            .pixel_fifo
+            .as_ref()
+            .map(|pixel_fifo| &pixel_fifo.framebuffer[..])
+    }
+
+    /// Returns the RGBA pixels of the provided scanline (0 - 143) of the
+    /// [framebuffer](RustBoy::framebuffer), that is, 160 pixels of 4 bytes each. Returns None, if
+    /// the pixel FIFO is not used for rendering or the scanline is not visible.
+    ///
+    /// A scanline is complete once [RustBoy::tick] returned [RenderTask::WriteLineToBuffer] for
+    /// it. The last scanline (143) is complete once [RenderTask::RenderFrame] is returned instead.
+    pub fn scanline(&self, scanline: u8) -> Option<&[u8]> {
+        if scanline as u32 >= ORIGINAL_SCREEN_HEIGHT {
+            return None;
+        }
+        let bytes_per_scanline = 4 * ORIGINAL_SCREEN_WIDTH as usize;
+        let start = scanline as usize * bytes_per_scanline;
+        self.framebuffer()
+            .map(|framebuffer| &framebuffer[start..start + bytes_per_scanline])
+    }
+
+    /// Registers the provided callback, which is invoked with the program counter (PC) and the
+    /// decoded [Instruction] each time before the CPU executes an instruction. Replaces the
+    /// previously registered callback, if any. This can be used to build opcode histograms or to
+    /// find out which code of a game runs hottest.
+    ///
+    /// If no callback is registered, the only overhead is a single check per instruction.
+    pub fn set_instruction_hook(&mut self, hook: impl FnMut(u16, Instruction) + 'static) {
+        self.cpu.instruction_hook = Some(Box::new(hook));
+    }
+
+    /// Removes the callback registered with [RustBoy::set_instruction_hook], if any.
+    pub fn clear_instruction_hook(&mut self) {
+        self.cpu.instruction_hook = None;
+    }
+
+    /// Starts recording which of the 512 opcodes (256 base opcodes and 256 opcodes prefixed with
+    /// 0xCB) the CPU executes, which can be used to check whether a test suite exercises the
+    /// whole instruction set. The result can be retrieved using [RustBoy::opcode_coverage_report].
+    /// Does nothing, if the recording is already running.
+    pub fn enable_opcode_coverage(&mut self) {
+        self.cpu.opcode_coverage.get_or_insert_default();
+    }
+
+    /// Returns a report listing how many opcodes were executed since
+    /// [RustBoy::enable_opcode_coverage] was called, which ones were never executed and which
+    /// ones the decoder does not support. Returns None, if the recording is not running.
+    pub fn opcode_coverage_report(&self) -> Option<String> {
+        self.cpu
+            .opcode_coverage
+            .as_ref()
+            .map(|opcode_coverage| opcode_coverage.report())
+    }
+
+    /// Returns the last Super Game Boy command packets the game sent via the joypad register,
+    /// oldest first. See [sgb::SgbPacketReceiver] for more information.
+    pub fn get_sgb_packets(&self) -> impl Iterator<Item = &SgbPacket> {
+        self.memory_bus.sgb_packet_receiver.packets().iter()
+    }
+
+    /// Adds the provided Game Genie (`ABC-DEF-GHI` or `ABC-DEF`) or GameShark (`ABCDEFGH`) code to
+    /// the active cheats. Returns false, if the code is not valid.
+    /// See [memory_bus::cheats] for more information.
+    pub fn add_cheat(&mut self, code: &str) -> bool {
+        self.memory_bus.cheats.add(code)
+    }
+
+    /// Removes the provided code from the active cheats. Returns false, if the code was not active.
+    pub fn remove_cheat(&mut self, code: &str) -> bool {
+        self.memory_bus.cheats.remove(code)
+    }
+
+    /// Adds the cheat codes listed in the provided file, one code per line. Empty lines and lines
+    /// starting with `#` are ignored. Invalid codes are skipped with a warning.
+    pub fn load_cheats_from_file(&mut self, path: &str) -> std::io::Result<()> {
+        for line in std::fs::read_to_string(path)?.lines() {
+            let code = line.trim();
+            if code.is_empty() || code.starts_with('#') {
+                continue;
+            }
+            if self.add_cheat(code) {
+                log::info!("Added cheat {code}");
+            } else {
+                log::warn!("Skipped invalid cheat {code}");
+            }
+        }
+        Ok(())
+    }
+
+    /// Listens on the provided TCP port until another RustBoy instance connects (see
+    /// [RustBoy::connect_link_cable]) and uses it as the link partner of the serial port from then
+    /// on. Blocks until the link partner connected. See [serial::link_cable] for more information.
+    pub fn host_link_cable(&mut self, port: u16) -> std::io::Result<()> {
+        self.memory_bus.serial.link_cable = Some(LinkCable::host(port)?);
+        Ok(())
+    }
+
+    /// Connects to the RustBoy instance listening at the provided address (e.g.
+    /// `127.0.0.1:5555`, see [RustBoy::host_link_cable]) and uses it as the link partner of the
+    /// serial port from then on. See [serial::link_cable] for more information.
+    pub fn connect_link_cable(&mut self, address: &str) -> std::io::Result<()> {
+        self.memory_bus.serial.link_cable = Some(LinkCable::connect(address)?);
+        Ok(())
+    }
 }
 
 /// Run the emulator.
 /// This function is the entry point for the emulator. The parameters are as follows:
-/// - `headless`: If true, the emulator runs in headless mode. That is, without opening a window
-/// and therefore not showing the graphics
-/// - `game_boy_doctor_mode`, `file_logs`, `binjgb_mode`, `timing_mode`, `print_serial_output_to_terminal`:
-/// See [debugging::DebugInfo] for more information.
+/// - `options`: The options the emulator is started with, e.g. whether it runs headless and which
+///   debugging options are enabled, see [RunOptions].
 /// - `rom_data`: The ROM data to be loaded into the emulator.
+#[cfg(feature = "frontend")]
 #[cfg_attr(target_arch = "wasm32", wasm_bindgen)]
-pub async fn run(
-    headless: bool,
-    game_boy_doctor_mode: bool,
-    file_logs: bool,
-    binjgb_mode: bool,
-    timing_mode: bool,
-    print_serial_output_to_terminal: bool,
-    rom_data: &[u8],
-) {
-    // Initialize logger according to the target architecture
-    cfg_if::cfg_if! {
-        if #[cfg(target_arch = "wasm32")] {
-            std::panic::set_hook(Box::new(console_error_panic_hook::hook));
-            console_log::init_with_level(log::Level::Info).expect("Logger should be able to initialize");
-        } else {
-            env_logger::init();
-        }
-    }
-    log::info!("Logger initialized");
+pub async fn run(options: RunOptions, rom_data: &[u8]) {
+    let RunOptions {
+        headless,
+        game_boy_doctor_mode,
+        file_logs,
+        json_trace,
+        binjgb_mode,
+        timing_mode,
+        print_serial_output_to_terminal,
+        pixel_fifo_mode,
+        save_file_path,
+        autosave_interval_in_secs,
+        cheats_file_path,
+        recording_fps,
+        benchmark_frames,
+        breakpoint,
+        socd_mode,
+        auto_fire_frames,
+        opcode_coverage,
+        frame_skip,
+        present_mode,
+        cycle_budget,
+        max_catch_up_frames,
+        dump_after_instructions,
+        watched_rom_path,
+        link_host_port,
+        link_connect_address,
+        ram_bank_count_override,
+        log_level,
+        log_modules,
+        stdout_frame_interval,
+        dots_per_m_cycle,
+    } = options;
+    initialize_logger(log_level, log_modules);
 
     // TODO: Write initializer function to make this more compact
     let debugging_flags = DebugInfo {
         file_handle_doctor_logs: None,
         file_handle_extensive_logs: None,
+        file_handle_json_trace: None,
         log_file_index: 0,
         current_number_of_lines_in_log_file: 0,
         doctor: game_boy_doctor_mode,
         file_logs,
+        json_trace,
         binjgb_mode,
         timing_mode,
         start_time: if timing_mode {
@@ -146,14 +503,110 @@ pub async fn run(
         sb_to_terminal: print_serial_output_to_terminal,
     };
 
-    let mut rust_boy = setup_rust_boy(debugging_flags, rom_data);
+    let mut rust_boy = match setup_rust_boy(debugging_flags, ram_bank_count_override, rom_data) {
+        Ok(rust_boy) => rust_boy,
+        Err(error) => {
+            log::error!("Failed to load the ROM: {error}");
+            return;
+        }
+    };
+    if pixel_fifo_mode {
+        log::info!("Rendering using the pixel FIFO");
+        rust_boy.ppu.enable_pixel_fifo();
+    }
+    if dots_per_m_cycle != ppu::DOTS_PER_M_CYCLE {
+        log::warn!(
+            "The PPU advances {dots_per_m_cycle} dots per m-cycle instead of {}, so the timing is not accurate",
+            ppu::DOTS_PER_M_CYCLE
+        );
+        rust_boy.ppu.dots_per_m_cycle = dots_per_m_cycle;
+    }
+    if let Some(save_file_path) = save_file_path {
+        rust_boy.save_file = Some(SaveFile::new(
+            save_file_path.into(),
+            autosave_interval_in_secs,
+        ));
+        rust_boy.load_save_file();
+    }
+    if let Some(cheats_file_path) = cheats_file_path
+        && let Err(error) = rust_boy.load_cheats_from_file(&cheats_file_path)
+    {
+        log::warn!("Failed to load cheats from {cheats_file_path}: {error}");
+    }
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        rust_boy.frame_recorder = FrameRecorder::new(recording_fps);
+    }
+    #[cfg(target_arch = "wasm32")]
+    let _ = recording_fps;
+    rust_boy.set_socd_mode(socd_mode);
+    rust_boy.set_auto_fire_frames(auto_fire_frames);
+    if let Some(address) = breakpoint {
+        log::info!("Breaking at {address:#06X}");
+        rust_boy.add_breakpoint(address);
+    }
+    if opcode_coverage {
+        rust_boy.enable_opcode_coverage();
+    }
+    if let Some(port) = link_host_port {
+        log::info!("Waiting for the link partner to connect on port {port}");
+        if let Err(error) = rust_boy.host_link_cable(port) {
+            log::warn!("Failed to link on port {port}: {error}");
+        }
+    } else if let Some(address) = link_connect_address {
+        log::info!("Connecting to the link partner at {address}");
+        if let Err(error) = rust_boy.connect_link_cable(&address) {
+            log::warn!("Failed to link to {address}: {error}");
+        }
+    }
+
+    if let Some(number_of_frames) = benchmark_frames {
+        log::info!("Running in benchmark mode for {number_of_frames} frames");
+        run_benchmark(&mut rust_boy, number_of_frames);
+        print_opcode_coverage_report(&rust_boy);
+        return;
+    }
+
+    if let Some(frame_interval) = stdout_frame_interval {
+        log::info!("Writing one of every {frame_interval} frames to stdout");
+        run_with_frames_to_stdout(&mut rust_boy, frame_interval);
+        print_opcode_coverage_report(&rust_boy);
+        return;
+    }
+
+    if let Some(number_of_instructions) = dump_after_instructions {
+        log::info!("Dumping the state after {number_of_instructions} instructions");
+        run_and_dump_state(&mut rust_boy, number_of_instructions);
+        print_opcode_coverage_report(&rust_boy);
+        return;
+    }
 
     #[cfg(debug_assertions)]
     if headless {
         log::info!("Running in headless mode");
         run_headless(&mut rust_boy);
+        print_opcode_coverage_report(&rust_boy);
     }
 
+    #[cfg(not(target_arch = "wasm32"))]
+    let mut rom_watcher = watched_rom_path.and_then(|path| match RomWatcher::new(&path) {
+        Ok(rom_watcher) => {
+            log::info!("Watching {path} for changes");
+            Some(rom_watcher)
+        }
+        Err(error) => {
+            log::warn!("Failed to watch {path} for changes: {error}");
+            None
+        }
+    });
+    #[cfg(target_arch = "wasm32")]
+    let _ = watched_rom_path;
+
+    // On the web, the RustBoy is shared with the functions exported to JavaScript, e.g. to save
+    // states, since the event loop never returns
+    #[cfg(target_arch = "wasm32")]
+    let rust_boy = web::share_rust_boy(rust_boy);
+
     let event_loop = EventLoop::new().unwrap();
     let window = WindowBuilder::new()
         .with_inner_size(LogicalSize::new(
@@ -179,7 +632,17 @@ pub async fn run(
             .expect("Failed to append canvas");
     }
 
-    let mut state = State::new(&window).await;
+    // On the web, the renderer is rebuilt in the background if the device is lost, which requires
+    // the window to live for the rest of the program, see [State::rebuild]. Since the event loop
+    // never returns there, leaking the window does not leak more than once
+    #[cfg(target_arch = "wasm32")]
+    let window: &'static winit::window::Window = Box::leak(Box::new(window));
+    #[cfg(not(target_arch = "wasm32"))]
+    let window = &window;
+    let mut state = State::new(window, present_mode).await;
+    if frame_skip > 0 {
+        state.set_frame_skip(frame_skip);
+    }
     let mut surface_configured = false;
 
     // Variable to keep track of the current [gpu::RenderTask] to be executed
@@ -192,20 +655,48 @@ pub async fn run(
     let mut running_frame_counter = 0;
     let mut time_of_last_fps_calculation = Instant::now();
 
-    // Variable to track if emulator is paused
-    let mut paused = false;
+    // Variable to track if emulator is paused. With a breakpoint, the emulator starts paused, so
+    // the execution up to the breakpoint can be stepped through as well
+    let mut paused = breakpoint.is_some();
+    if paused {
+        log::info!("Starting paused, press P to run up to the breakpoint");
+    }
+    // Variable to spread the emulation of each frame over multiple redraws, if a budget is set
+    let mut cycle_budget = cycle_budget.map(CycleBudget::new);
+    if let Some(cycle_budget) = &cycle_budget {
+        log::info!(
+            "Running at most {} m-cycles per redraw",
+            cycle_budget.m_cycles_per_redraw
+        );
+    }
+    // Variable to track the currently held modifier keys, e.g. shift to save states
+    let mut modifiers = ModifiersState::empty();
 
     event_loop
-        .run(move |event, control_flow| match event {
-            Event::WindowEvent {
-                ref event,
-                window_id,
-            } if window_id == state.window.id() => {
-                if !state.input(event) {
+        .run(move |event, control_flow| {
+            #[cfg(target_arch = "wasm32")]
+            let rust_boy = &mut *rust_boy.borrow_mut();
+            #[cfg(not(target_arch = "wasm32"))]
+            let rust_boy = &mut rust_boy;
+            match event {
+                Event::WindowEvent {
+                    ref event,
+                    window_id,
+                } if window_id == state.window.id()
+                    && !state.input(event, &mut rust_boy.notifications) =>
+                {
                     match event {
                         WindowEvent::CloseRequested => handle_close_event(control_flow),
-                        WindowEvent::KeyboardInput { .. } => {
-                            handle_keyboard_input(event, control_flow, &mut rust_boy, &mut paused)
+                        WindowEvent::KeyboardInput { .. } => handle_keyboard_input(
+                            event,
+                            control_flow,
+                            rust_boy,
+                            &mut current_rendering_task,
+                            &mut paused,
+                            modifiers,
+                        ),
+                        WindowEvent::ModifiersChanged(new_modifiers) => {
+                            modifiers = new_modifiers.state();
                         }
                         WindowEvent::Resized(physical_size) => {
                             log::info!("physical_size: {physical_size:?}");
@@ -213,54 +704,295 @@ pub async fn run(
                             state.resize(*physical_size);
                         }
                         WindowEvent::RedrawRequested => {
+                            #[cfg(not(target_arch = "wasm32"))]
+                            if let Some(rom_watcher) = &mut rom_watcher
+                                && rom_watcher.rom_changed()
+                            {
+                                reload_rom(rust_boy, rom_watcher.path());
+                                current_rendering_task = RenderTask::None;
+                            }
                             handle_redraw_requested_event(
                                 &mut state,
                                 control_flow,
-                                &mut rust_boy,
+                                rust_boy,
                                 &mut current_rendering_task,
                                 &mut last_frame_time,
                                 &mut time_of_last_fps_calculation,
                                 &mut running_frame_counter,
                                 surface_configured,
-                                paused,
+                                &mut paused,
+                                &mut cycle_budget,
+                                max_catch_up_frames,
                             );
                         }
                         _ => {}
                     }
                 }
+                // Make sure the save data is persisted before exiting
+                Event::LoopExiting => {
+                    rust_boy.write_save_file();
+                    print_opcode_coverage_report(rust_boy);
+                }
+                _ => {}
             }
-            _ => {}
         })
         .expect("Event loop should be able to run");
 }
 
+/// Initializes the logger according to the target architecture.
+///
+/// - `log_level`: The level up to which messages are logged (`off`, `error`, `warn`, `info`,
+///   `debug` or `trace`). If None, the level is taken from the `RUST_LOG` environment variable
+///   (only errors, if it is not set) or is `info` on the web.
+/// - `log_modules`: A comma separated list of `module=level` entries, which override the level of
+///   the provided modules, e.g. `ppu=trace,timer=off`. The modules are relative to the crate,
+///   that is, `ppu` refers to `rustboy::ppu` and includes its submodules. Not supported on the
+///   web, since the console logger can not filter by module.
+///
+/// Invalid levels and entries are skipped with a warning printed to stderr.
+#[cfg(feature = "frontend")]
+fn initialize_logger(log_level: Option<String>, log_modules: Option<String>) {
+    let mut warnings = Vec::new();
+    let parse_level = |level: &str| {
+        level
+            .trim()
+            .parse::<log::LevelFilter>()
+            .map_err(|_| format!("Skipped invalid log level '{level}'"))
+    };
+    let level = match log_level.as_deref().map(parse_level) {
+        Some(Ok(level)) => Some(level),
+        Some(Err(warning)) => {
+            warnings.push(warning);
+            None
+        }
+        None => None,
+    };
+    let mut module_levels = Vec::new();
+    for entry in log_modules
+        .iter()
+        .flat_map(|log_modules| log_modules.split(','))
+    {
+        match entry.split_once('=') {
+            Some((module, module_level)) => match parse_level(module_level) {
+                Ok(module_level) => module_levels.push((module.trim().to_string(), module_level)),
+                Err(warning) => warnings.push(warning),
+            },
+            None => warnings.push(format!(
+                "Skipped invalid log module entry '{entry}', expected 'module=level'"
+            )),
+        }
+    }
+
+    cfg_if::cfg_if! {
+        if #[cfg(target_arch = "wasm32")] {
+            std::panic::set_hook(Box::new(console_error_panic_hook::hook));
+            console_log::init_with_level(log::Level::Trace).expect("Logger should be able to initialize");
+            log::set_max_level(level.unwrap_or(log::LevelFilter::Info));
+            if !module_levels.is_empty() {
+                warnings.push("Filtering the log by module is not supported on the web".to_string());
+            }
+        } else {
+            let mut builder = env_logger::Builder::from_default_env();
+            if let Some(level) = level {
+                builder.filter_level(level);
+            }
+            for (module, module_level) in module_levels {
+                let module = if module == "rustboy" || module.starts_with("rustboy::") {
+                    module
+                } else {
+                    format!("rustboy::{module}")
+                };
+                builder.filter_module(&module, module_level);
+            }
+            builder.init();
+        }
+    }
+    log::info!("Logger initialized");
+    // The warnings are printed directly, since the configured levels might filter them out
+    for warning in warnings {
+        eprintln!("{warning}");
+    }
+}
+
 /// Set up the Rust Boy by initializing it with the given debugging flags and
-/// loading the specified ROM file.
-fn setup_rust_boy(mut debugging_flags: DebugInfo, rom_data: &[u8]) -> RustBoy {
+/// loading the specified ROM file. If `ram_bank_count_override` is Some, the provided number of
+/// external RAM banks is allocated instead of the number declared by the cartridge header.
+/// Returns an error, if the ROM cannot be loaded.
+fn setup_rust_boy(
+    mut debugging_flags: DebugInfo,
+    ram_bank_count_override: Option<u8>,
+    rom_data: &[u8],
+) -> Result<RustBoy, RustBoyError> {
     // Initialize the logging for debug if compiling in debug mode
     #[cfg(debug_assertions)]
     if debugging_flags.doctor || debugging_flags.file_logs {
         setup_debugging_logs_files(&mut debugging_flags);
     }
+    #[cfg(debug_assertions)]
+    if debugging_flags.json_trace {
+        setup_json_trace_file(&mut debugging_flags);
+    }
 
     // TODO: Handle header checksum (init of Registers f.H and f.C): https://gbdev.io/pandocs/Power_Up_Sequence.html#obp
     let mut rust_boy = RustBoy::new_after_boot(debugging_flags);
 
-    rust_boy.memory_bus.load_program(rom_data);
+    rust_boy.memory_bus.ram_bank_count_override = ram_bank_count_override;
+    rust_boy.memory_bus.apply_rom_database(rom_data);
+    rust_boy.memory_bus.load_program(rom_data)?;
+    if let Some(cartridge_header) = &rust_boy.memory_bus.cartridge_header {
+        log_cartridge_header(cartridge_header);
+    }
 
-    rust_boy
+    Ok(rust_boy)
+}
+
+/// Logs the summary of the provided cartridge header and warns if the global checksum does not
+/// match.
+fn log_cartridge_header(cartridge_header: &CartridgeHeader) {
+    log::info!("Cartridge: {}", cartridge_header.summary_to_string());
+    // The original Game Boy does not verify the global checksum, so the ROM is run anyway.
+    // However, a mismatch hints at a corrupt or truncated dump, which can explain crashes
+    if !cartridge_header.global_checksum_is_valid() {
+        log::warn!(
+            "The global checksum of the ROM ({:#06X}) does not match the computed one ({:#06X}). The ROM dump might be corrupt or truncated",
+            cartridge_header.global_checksum,
+            cartridge_header.computed_global_checksum
+        );
+    }
+}
+
+/// Reloads the ROM at the provided path into a fresh RustBoy, which replaces the provided one.
+/// This is used by the `--WATCH` option, see [rom_watcher].
+///
+/// The save data of the previous ROM is persisted first and the save data of the reloaded ROM is
+/// loaded again afterward. The RAM bank count override and the options of the RustBoy carry over,
+/// see [RustBoy::take_options_of]. If the ROM can not be read or loaded, the error is logged and
+/// the previous RustBoy keeps running.
+#[cfg(all(feature = "frontend", not(target_arch = "wasm32")))]
+fn reload_rom(rust_boy: &mut RustBoy, path: &std::path::Path) {
+    let rom_data = match std::fs::read(path) {
+        Ok(rom_data) => rom_data,
+        Err(error) => {
+            log::error!("Failed to reload {}: {error}", path.display());
+            return;
+        }
+    };
+
+    let mut new_rust_boy = rust_boy.new_with_same_debugging_flags();
+    new_rust_boy.memory_bus.ram_bank_count_override = rust_boy.memory_bus.ram_bank_count_override;
+    new_rust_boy.memory_bus.apply_rom_database(&rom_data);
+    if let Err(error) = new_rust_boy.memory_bus.load_program(&rom_data) {
+        log::error!("Failed to reload {}: {error}", path.display());
+        return;
+    }
+    log::info!("Reloaded {}", path.display());
+    if let Some(cartridge_header) = &new_rust_boy.memory_bus.cartridge_header {
+        log_cartridge_header(cartridge_header);
+    }
+
+    rust_boy.write_save_file();
+    new_rust_boy.take_options_of(rust_boy);
+    new_rust_boy.load_save_file();
+    *rust_boy = new_rust_boy;
+}
+
+impl RustBoy {
+    /// Creates a new RustBoy in the state after the boot ROM, with the same debugging flags as
+    /// this one. The handles of the log files are not copied, they are moved over by
+    /// [RustBoy::take_options_of].
+    fn new_with_same_debugging_flags(&self) -> RustBoy {
+        let debugging_flags = &self.cpu.debugging_flags;
+        RustBoy::new_after_boot(DebugInfo {
+            file_handle_doctor_logs: None,
+            file_handle_extensive_logs: None,
+            file_handle_json_trace: None,
+            log_file_index: 0,
+            current_number_of_lines_in_log_file: 0,
+            doctor: debugging_flags.doctor,
+            file_logs: debugging_flags.file_logs,
+            json_trace: debugging_flags.json_trace,
+            binjgb_mode: debugging_flags.binjgb_mode,
+            timing_mode: debugging_flags.timing_mode,
+            start_time: debugging_flags.start_time,
+            sb_to_terminal: debugging_flags.sb_to_terminal,
+        })
+    }
+
+    /// Moves the options of the provided previous RustBoy over to this one, which replaces it,
+    /// e.g. when reloading the ROM or resetting. These are the debugging flags (including the log
+    /// files), the save file, the cheats, the breakpoints, the notifications, the instruction
+    /// hook, the opcode coverage, the frame recorder, the link cable, the pixel FIFO, the dots per
+    /// m-cycle, the SOCD mode and the auto-fire.
+    fn take_options_of(&mut self, previous_rust_boy: &mut RustBoy) {
+        std::mem::swap(
+            &mut self.cpu.debugging_flags,
+            &mut previous_rust_boy.cpu.debugging_flags,
+        );
+        #[cfg(feature = "frontend")]
+        std::mem::swap(&mut self.save_file, &mut previous_rust_boy.save_file);
+        std::mem::swap(
+            &mut self.memory_bus.cheats,
+            &mut previous_rust_boy.memory_bus.cheats,
+        );
+        std::mem::swap(&mut self.breakpoints, &mut previous_rust_boy.breakpoints);
+        // A step over refers to the stack of the previous RustBoy, so it is not carried over
+        self.breakpoints.cancel_step_over();
+        std::mem::swap(
+            &mut self.notifications,
+            &mut previous_rust_boy.notifications,
+        );
+        std::mem::swap(
+            &mut self.cpu.instruction_hook,
+            &mut previous_rust_boy.cpu.instruction_hook,
+        );
+        std::mem::swap(
+            &mut self.cpu.opcode_coverage,
+            &mut previous_rust_boy.cpu.opcode_coverage,
+        );
+        #[cfg(all(feature = "frontend", not(target_arch = "wasm32")))]
+        std::mem::swap(
+            &mut self.frame_recorder,
+            &mut previous_rust_boy.frame_recorder,
+        );
+        std::mem::swap(
+            &mut self.memory_bus.serial.link_cable,
+            &mut previous_rust_boy.memory_bus.serial.link_cable,
+        );
+        if previous_rust_boy.ppu.pixel_fifo.is_some() {
+            self.ppu.enable_pixel_fifo();
+        }
+        self.ppu.dots_per_m_cycle = previous_rust_boy.ppu.dots_per_m_cycle;
+        self.set_socd_mode(previous_rust_boy.memory_bus.socd_state.mode);
+        self.memory_bus.auto_fire = previous_rust_boy
+            .memory_bus
+            .auto_fire
+            .without_held_buttons();
+    }
 }
 
 /// Run the emulator in headless mode. That is, without a window.
 /// This is useful for (automated) testing and debugging purposes.
-#[cfg(debug_assertions)]
+#[cfg(all(debug_assertions, feature = "frontend"))]
 fn run_headless(rust_boy: &mut RustBoy) {
     let mut current_rendering_task: RenderTask = RenderTask::None;
     let mut last_frame_time = Instant::now();
     loop {
         // Make multiple steps per redraw request until something has to be rendered
         while current_rendering_task != RenderTask::RenderFrame {
-            current_rendering_task = handle_no_rendering_task(rust_boy);
+            current_rendering_task = match handle_no_rendering_task(rust_boy) {
+                Ok(rendering_task) => rendering_task,
+                Err(error) => {
+                    log::error!("Stopping emulation: {error}");
+                    return;
+                }
+            };
+            // There is no way to resume in headless mode, so breakpoints only log the registers
+            if let Some(address) = rust_boy.take_breakpoint_hit() {
+                log::info!(
+                    "Hit breakpoint at {address:#06X}: {}",
+                    rust_boy.cpu.registers_to_string()
+                );
+            }
         }
 
         if current_rendering_task == RenderTask::RenderFrame {
@@ -276,13 +1008,198 @@ fn run_headless(rust_boy: &mut RustBoy) {
     }
 }
 
+/// Run the emulator headless and without frame pacing for the provided number of frames. That is,
+/// as fast as possible. Afterward, the total number of m-cycles executed, the elapsed wall-clock
+/// time and the achieved emulated clock speed compared to the original
+/// [M_CYCLES_PER_SECOND] are printed.
+///
+/// Since nothing is rendered to the screen, this measures the CPU, timer and PPU emulation
+/// including the pixel FIFO (if enabled), but not the scanline shader.
+#[cfg(feature = "frontend")]
+fn run_benchmark(rust_boy: &mut RustBoy, number_of_frames: u32) {
+    let cycles_at_start = rust_boy.cpu.cycle_counter;
+    let start_time = Instant::now();
+
+    for _ in 0..number_of_frames {
+        if let Err(error) = rust_boy.run_frame() {
+            log::error!("Stopping benchmark: {error}");
+            return;
+        }
+    }
+
+    let elapsed_secs = start_time.elapsed().as_secs_f64();
+    let m_cycles = rust_boy.cpu.cycle_counter - cycles_at_start;
+    let emulated_mhz = m_cycles as f64 / elapsed_secs / 1_000_000.0;
+    let original_mhz = M_CYCLES_PER_SECOND as f64 / 1_000_000.0;
+    println!("Frames: {number_of_frames}");
+    println!("M-cycles: {m_cycles}");
+    println!("Elapsed time: {elapsed_secs:.3} seconds");
+    println!(
+        "Emulated clock speed: {emulated_mhz:.3} MHz ({:.2}x the original {original_mhz:.3} MHz)",
+        emulated_mhz / original_mhz
+    );
+}
+
+/// Run the emulator headless and without frame pacing, and write every `frame_interval`-th frame
+/// to stdout as a binary PPM image, see [frame_recorder::encode_ppm]. This allows capturing the
+/// screen without a GPU, e.g. to diff screenshots in CI or to pipe a video into ffmpeg
+/// (`ffmpeg -f image2pipe -i - video.mp4`). The frames are rendered by the pixel FIFO, which is
+/// enabled for this.
+///
+/// Runs until the ROM makes the CPU execute an invalid instruction or stdout is closed, e.g.
+/// because the reading process exited. Since the frames are written to stdout, the logs should go
+/// to stderr (the default) and the `--SB` and `--TIMING` options should not be used.
+#[cfg(feature = "frontend")]
+fn run_with_frames_to_stdout(rust_boy: &mut RustBoy, frame_interval: u32) {
+    use std::io::Write;
+
+    rust_boy.ppu.enable_pixel_fifo();
+    let mut stdout = std::io::BufWriter::new(std::io::stdout().lock());
+    for frame in 0u64.. {
+        if let Err(error) = rust_boy.run_frame() {
+            log::error!("Stopping emulation: {error}");
+            return;
+        }
+        if !frame.is_multiple_of(frame_interval as u64) {
+            continue;
+        }
+        let framebuffer = rust_boy
+            .framebuffer()
+            .expect("The pixel FIFO should be enabled");
+        if let Err(error) =
+            frame_recorder::encode_ppm(&mut stdout, framebuffer).and_then(|()| stdout.flush())
+        {
+            log::info!("Stopped writing frames to stdout: {error}");
+            return;
+        }
+    }
+}
+
+/// Run the emulator headless and without frame pacing for the provided number of instructions
+/// after boot, see [RustBoy::step], and write its state to
+/// `state_after_<number_of_instructions>_instructions.txt` in the dumps directory afterward, see
+/// [RustBoy::dump_state]. This is used to find where the emulation diverges from a reference
+/// emulator, by comparing the states at a specific instruction instead of the entire doctor logs.
+///
+/// If the CPU executes an invalid instruction before, the state at that point is written.
+#[cfg(feature = "frontend")]
+fn run_and_dump_state(rust_boy: &mut RustBoy, number_of_instructions: u64) {
+    for instruction in 0..number_of_instructions {
+        if let Err(error) = rust_boy.step() {
+            log::error!("Stopping after {instruction} instructions: {error}");
+            break;
+        }
+    }
+
+    let path = std::path::Path::new(debugging::DUMPS_DIRECTORY).join(format!(
+        "state_after_{number_of_instructions}_instructions.txt"
+    ));
+    match rust_boy.dump_state(&path) {
+        Ok(()) => println!("Dumped the state to {}", path.display()),
+        Err(error) => log::error!("Failed to write {}: {error}", path.display()),
+    }
+}
+
+/// Prints the report of the executed opcodes, if they are recorded, see
+/// [RustBoy::opcode_coverage_report].
+#[cfg(feature = "frontend")]
+fn print_opcode_coverage_report(rust_boy: &RustBoy) {
+    if let Some(report) = rust_boy.opcode_coverage_report() {
+        println!("{report}");
+    }
+}
+
+/// A budget of m-cycles the emulator may run per redraw, which is an alternative to running a
+/// whole frame per redraw (see `--CYCLE-BUDGET`). Without a budget, all instructions of a frame
+/// are run in the first redraw after the previous frame was presented, after which the following
+/// redraws only wait until the frame is due. With a budget, the work is spread evenly over these
+/// redraws, which avoids long stalls right before presenting. The emulated timing is the same,
+/// since only the point in (host) time at which the instructions are run changes.
+///
+/// The budget has to be large enough for the number of redraws per frame of the host, otherwise
+/// the emulator runs slower than real time. A frame takes 17556 m-cycles, so e.g. a budget of a
+/// quarter of a frame needs at least four redraws per frame.
+///
+/// - `m_cycles_per_redraw`: The number of m-cycles which may be run per redraw.
+/// - `remaining_m_cycles`: The m-cycles which are left of the budget of the current redraw. Since
+///   the last instruction of a redraw usually exceeds the budget, this can be negative, in which
+///   case the excess is taken from the budget of the next redraw. The excess is limited to one
+///   budget, so jumps of the dots of the PPU (e.g. when a state is loaded) do not stall the
+///   emulation.
+/// - `dots_at_start_of_redraw`: The total number of dots of the PPU at the start of the current
+///   redraw, which is used to count the m-cycles that were run.
+#[cfg(feature = "frontend")]
+struct CycleBudget {
+    m_cycles_per_redraw: u32,
+    remaining_m_cycles: i64,
+    dots_at_start_of_redraw: u128,
+}
+
+#[cfg(feature = "frontend")]
+impl CycleBudget {
+    /// Creates a new budget of the provided number of m-cycles per redraw (at least 1).
+    fn new(m_cycles_per_redraw: u32) -> CycleBudget {
+        CycleBudget {
+            m_cycles_per_redraw: m_cycles_per_redraw.max(1),
+            remaining_m_cycles: 0,
+            dots_at_start_of_redraw: 0,
+        }
+    }
+
+    /// Starts the budget of a new redraw. The m-cycles run in the previous redraw are subtracted
+    /// and the budget of this redraw is added. Unused m-cycles are not saved up for later redraws
+    /// (e.g. while waiting for a frame to be due), so a redraw never runs more than one budget.
+    fn start_redraw(&mut self, ppu: &PPU) {
+        let m_cycles_per_redraw = self.m_cycles_per_redraw as i64;
+        let excess = (self.remaining_m_cycles - self.m_cycles_run(ppu)).max(-m_cycles_per_redraw);
+        self.remaining_m_cycles = (excess + m_cycles_per_redraw).min(m_cycles_per_redraw);
+        self.dots_at_start_of_redraw = ppu.rendering_info.total_dots;
+    }
+
+    /// Returns true if the m-cycles run since the start of the current redraw used up its budget.
+    fn is_used_up(&self, ppu: &PPU) -> bool {
+        self.m_cycles_run(ppu) >= self.remaining_m_cycles
+    }
+
+    /// Returns the number of m-cycles run since the start of the current redraw. They are derived
+    /// from the dots of the PPU, which advances by the same number of dots every m-cycle (even
+    /// while the LCD is off).
+    fn m_cycles_run(&self, ppu: &PPU) -> i64 {
+        let dots = ppu
+            .rendering_info
+            .total_dots
+            .saturating_sub(self.dots_at_start_of_redraw);
+        (dots / ppu.dots_per_m_cycle.max(1) as u128).min(i64::MAX as u128) as i64
+    }
+}
+
 /// Handle the redraw requested event.
 ///
 /// This function is called whenever the window requests a redraw. That is, [TARGET_FPS] times per
 /// second (if there are no dropped frames). It handles the stepping of the CPU and GPU, therefore
 /// keeping them in sync and providing a "runtime" for the entire emulator.
+///
+/// While `S` is held, the duration of each frame is stretched, so the emulator runs at
+/// [SLOW_MOTION_SPEED].
+///
+/// While the emulator is paused, pressing `.` advances exactly one frame, that is, the emulator
+/// runs until the next [RenderTask::RenderFrame] and presents it right away, regardless of the
+/// time since the last frame and the frame-skip. Afterward, the emulator stays paused.
+///
+/// If a [CycleBudget] is provided, at most the m-cycles of the budget are run per redraw, so the
+/// emulation of a frame is spread over multiple redraws instead of being run at once.
+///
+/// The frames are paced against the time the last frame was due instead of the time it was
+/// actually run. So if a frame is late, the following frames are run sooner to catch up. The
+/// emulator never falls behind by more than `max_catch_up_frames` frames though, so after a
+/// stall (e.g. while the window was dragged or the emulator was paused) the rest of the delay is
+/// discarded instead of fast-forwarding through it.
+#[cfg(feature = "frontend")]
+#[allow(clippy::too_many_arguments)]
 fn handle_redraw_requested_event(
-    state: &mut State,
+    #[cfg(not(target_arch = "wasm32"))] state: &mut State,
+    // On the web, the window lives for the rest of the program, see [State::rebuild]
+    #[cfg(target_arch = "wasm32")] state: &mut State<'static>,
     control_flow: &EventLoopWindowTarget<()>,
     rust_boy: &mut RustBoy,
     current_rendering_task: &mut RenderTask,
@@ -290,7 +1207,9 @@ fn handle_redraw_requested_event(
     time_of_last_fps_calculation: &mut Instant,
     running_frame_counter: &mut u32,
     surface_configured: bool,
-    paused: bool,
+    paused: &mut bool,
+    cycle_budget: &mut Option<CycleBudget>,
+    max_catch_up_frames: u32,
 ) {
     // This tells winit that we want another frame after this one
     state.window().request_redraw();
@@ -300,19 +1219,65 @@ fn handle_redraw_requested_event(
         return;
     }
 
-    // If the emulator is paused, we don't want to run any cycles
-    if paused {
+    // If the emulator is paused, we don't want to run any cycles, unless a single frame is
+    // advanced. Presses of `.` while the emulator is running are ignored
+    let advancing_frame = std::mem::take(&mut state.frame_advance_requested) && *paused;
+    if *paused && !advancing_frame {
+        // The last frame is presented again while notifications are shown, so they still fade
+        // out and disappear while the emulator is paused
+        if state.text_overlay_needs_redraw(&mut rust_boy.notifications)
+            && last_frame_time.elapsed().as_secs_f64() >= TARGET_FRAME_DURATION_IN_SECS
+        {
+            *last_frame_time = Instant::now();
+            present_screen(state, control_flow, rust_boy);
+        }
         return;
     }
 
-    // Make multiple steps per redraw request until something has to be rendered
+    if let Some(cycle_budget) = cycle_budget {
+        cycle_budget.start_redraw(&rust_boy.ppu);
+    }
+
+    // Make multiple steps per redraw request until something has to be rendered. With a cycle
+    // budget, the remaining steps of the frame are made in the next redraws once it is used up
     while *current_rendering_task != RenderTask::RenderFrame {
-        *current_rendering_task = handle_no_rendering_task(rust_boy);
+        if let Some(cycle_budget) = cycle_budget
+            && cycle_budget.is_used_up(&rust_boy.ppu)
+        {
+            return;
+        }
+
+        *current_rendering_task = match handle_no_rendering_task(rust_boy) {
+            Ok(rendering_task) => rendering_task,
+            Err(error) => {
+                log::error!("Stopping emulation: {error}");
+                control_flow.exit();
+                return;
+            }
+        };
+
+        if let Some(address) = rust_boy.take_breakpoint_hit() {
+            log::info!(
+                "Hit breakpoint at {address:#06X}, paused (press P to resume): {}",
+                rust_boy.cpu.registers_to_string()
+            );
+            *paused = true;
+            return;
+        }
 
         // We draw a new line to the framebuffer whenever the gpu requests a new line or when it requests a
         // new frame, since in the latter case, the last line is still missing
         if *current_rendering_task != RenderTask::None {
-            if let RenderTask::WriteLineToBuffer(current_scanline) = *current_rendering_task {
+            if let Some(pixel_fifo) = &rust_boy.ppu.pixel_fifo {
+                // The pixel FIFO already pushed the pixels to its own framebuffer, so we only
+                // need to upload it to the framebuffer texture once the frame is complete
+                if *current_rendering_task == RenderTask::RenderFrame {
+                    state.write_framebuffer(&pixel_fifo.framebuffer);
+                } else {
+                    *current_rendering_task = RenderTask::None;
+                }
+            } else if let RenderTask::WriteLineToBuffer(current_scanline) = *current_rendering_task
+            {
                 // If the current rendering task was to render a line, we need to reset it to none,
                 // since we have just written a line to the framebuffer. If it was to render a frame,
                 // it has to stay as is, since we still need to render the frame
@@ -335,10 +1300,43 @@ fn handle_redraw_requested_event(
         // should be drawn or we still wait
         let now = Instant::now();
         let elapsed = now.duration_since(*last_frame_time);
-        if elapsed.as_secs_f64() >= TARGET_FRAME_DURATION_IN_SECS {
-            *last_frame_time = Instant::now();
+        let target_frame_duration_in_secs = if state.slow_motion_held {
+            TARGET_FRAME_DURATION_IN_SECS / SLOW_MOTION_SPEED
+        } else {
+            TARGET_FRAME_DURATION_IN_SECS
+        };
+        if elapsed.as_secs_f64() >= target_frame_duration_in_secs || advancing_frame {
+            // The time of the last frame is advanced by one frame duration, so the delay of a
+            // late frame is caught up on, but only up to the maximum catch-up. An advanced frame
+            // does not count as late, since the emulator was paused
+            let target_frame_duration = Duration::from_secs_f64(target_frame_duration_in_secs);
+            *last_frame_time = if advancing_frame {
+                now
+            } else {
+                next_frame_time(
+                    *last_frame_time,
+                    now,
+                    target_frame_duration,
+                    max_catch_up_frames,
+                )
+            };
             *current_rendering_task = RenderTask::None;
 
+            // Persist the save data, if it changed and the autosave interval has passed
+            rust_boy.autosave();
+
+            // Capture the frame, if a recording is running
+            #[cfg(not(target_arch = "wasm32"))]
+            if rust_boy.frame_recorder.frame_is_due() {
+                let framebuffer = match &rust_boy.ppu.pixel_fifo {
+                    Some(pixel_fifo) => pixel_fifo.framebuffer.to_vec(),
+                    None => state.read_framebuffer(),
+                };
+                rust_boy
+                    .frame_recorder
+                    .capture_frame(&framebuffer, &mut rust_boy.notifications);
+            }
+
             // Estimate FPS
             *running_frame_counter += 1;
 
@@ -350,72 +1348,192 @@ fn handle_redraw_requested_event(
                 *time_of_last_fps_calculation = now;
             }
 
-            match state.render_screen() {
-                Ok(_) => {}
-                // Reconfigure the surface if it's lost or outdated
-                Err(wgpu::SurfaceError::Lost | wgpu::SurfaceError::Outdated) => {
-                    log::warn!("Surface is Lost or Outdated");
-                    state.resize(state.size)
-                }
-                // The system is out of memory, we should probably quit
-                Err(wgpu::SurfaceError::OutOfMemory | wgpu::SurfaceError::Other) => {
-                    log::error!("OutOfMemory");
-                    control_flow.exit();
-                }
+            // With a frame-skip, the frame is only emulated, but not presented. An advanced frame
+            // is always presented, since it is to be looked at
+            if !advancing_frame && !state.present_is_due() {
+                return;
+            }
 
-                // This happens when a frame takes too long to present
-                Err(wgpu::SurfaceError::Timeout) => {
-                    log::warn!("Surface timeout")
-                }
+            present_screen(state, control_flow, rust_boy);
+        }
+    }
+}
+
+/// Returns the time the frame after the one due at the provided `last_frame_time` is due, see
+/// [handle_redraw_requested_event]. This is one frame duration later, unless the emulator fell
+/// behind `now` by more than `max_catch_up_frames` frames, in which case the rest of the delay is
+/// discarded.
+#[cfg(feature = "frontend")]
+fn next_frame_time(
+    last_frame_time: Instant,
+    now: Instant,
+    target_frame_duration: Duration,
+    max_catch_up_frames: u32,
+) -> Instant {
+    let next_frame_time = last_frame_time + target_frame_duration;
+    let max_delay = target_frame_duration * max_catch_up_frames;
+    // The maximum delay is only subtracted from now if the result is after the next frame time,
+    // so it cannot overflow even if the maximum catch-up is longer than the uptime of the host
+    if now.duration_since(next_frame_time) > max_delay {
+        now - max_delay
+    } else {
+        next_frame_time
+    }
+}
+
+/// Presents the framebuffer texture to the screen using [State::render_screen] and handles the
+/// errors of the surface, if presenting fails.
+#[cfg(feature = "frontend")]
+fn present_screen(
+    #[cfg(not(target_arch = "wasm32"))] state: &mut State,
+    // On the web, the window lives for the rest of the program, see [State::rebuild]
+    #[cfg(target_arch = "wasm32")] state: &mut State<'static>,
+    control_flow: &EventLoopWindowTarget<()>,
+    rust_boy: &mut RustBoy,
+) {
+    // On the web, the renderer is rebuilt in the background, see [State::rebuild]
+    #[cfg(target_arch = "wasm32")]
+    if !state.poll_rebuild(&mut rust_boy.memory_bus) {
+        return;
+    }
+
+    match state.render_screen(&mut rust_boy.notifications) {
+        Ok(_) => {}
+        // Reconfigure the surface if it's lost or outdated. If this does not fix it (e.g.
+        // because the GPU was reset), the whole state is rebuilt
+        Err(wgpu::SurfaceError::Lost | wgpu::SurfaceError::Outdated) => {
+            log::warn!("Surface is Lost or Outdated, reconfiguring it");
+            state.resize(state.size);
+            if let Err(error) = state.render_screen(&mut rust_boy.notifications) {
+                log::warn!("Reconfiguring the surface did not help ({error})");
+                state.rebuild(&mut rust_boy.memory_bus);
             }
         }
+        // The system is out of memory, we should probably quit
+        Err(wgpu::SurfaceError::OutOfMemory) => {
+            log::error!("OutOfMemory");
+            control_flow.exit();
+        }
+        // Other errors are e.g. caused by a lost device, which only rebuilding fixes
+        Err(wgpu::SurfaceError::Other) => {
+            log::warn!("Surface error of unknown cause");
+            state.rebuild(&mut rust_boy.memory_bus);
+        }
+
+        // This happens when a frame takes too long to present
+        Err(wgpu::SurfaceError::Timeout) => {
+            log::warn!("Surface timeout")
+        }
     }
 }
 
 /// Handle the case in the game boy loop, where we are not requesting a redraw.
-fn handle_no_rendering_task(rust_boy: &mut RustBoy) -> RenderTask {
+///
+/// Returns an error, if the CPU executed an invalid instruction.
+fn handle_no_rendering_task(rust_boy: &mut RustBoy) -> Result<RenderTask, RustBoyError> {
+    // Stop before executing the instruction at a breakpoint. The caller then checks whether a
+    // breakpoint was hit
+    if rust_boy
+        .breakpoints
+        .should_break_at(rust_boy.cpu.pc, rust_boy.cpu.sp)
+    {
+        return Ok(RenderTask::None);
+    }
+
+    // If the next instruction accesses a register which games tend to poll tightly (e.g. LY, STAT
+    // or DIV), we advance the timer and PPU up to the m-cycle of the access before executing
+    // the instruction, so the access sees (and changes) the state at the correct point in time.
+    let m_cycles_before_access = rust_boy
+        .cpu
+        .m_cycles_before_timing_sensitive_access(&rust_boy.memory_bus)
+        .unwrap_or(0);
+    let rendering_task_before_access = advance_timer_and_ppu(rust_boy, m_cycles_before_access);
+
     // Fetch and execute next instruction with cpu_step().
     rust_boy
         .cpu
-        .cpu_step(&mut rust_boy.memory_bus, &mut rust_boy.ppu);
+        .cpu_step(&mut rust_boy.memory_bus, &rust_boy.ppu)?;
     let last_num_of_cycles = rust_boy
         .cpu
         .cycles_current_instruction
         .expect("Cycles should be set by cpu_step()");
 
-    // Increment the timer and divider register according to the number of cycles that the
-    // last instruction took
-    rust_boy.handle_timer_and_divider(last_num_of_cycles as u32);
-
-    // Convert m-cycles to dots (1 m-cycle = 4 dots)
-    let last_num_of_dots = last_num_of_cycles as u32 * 4;
-
-    // Check what has to be done for rendering and sync gpu with cpu with gpu_step()
-    let new_rendering_task = rust_boy
-        .ppu
-        .ppu_step(&mut rust_boy.memory_bus, last_num_of_dots);
+    // Advance the timer and PPU by the remaining cycles the last instruction took
+    let rendering_task_after_access =
+        advance_timer_and_ppu(rust_boy, last_num_of_cycles as u32 - m_cycles_before_access);
 
     // Reset the cycles of the current instruction
     rust_boy.cpu.cycles_current_instruction = None;
 
-    // Return the new total number of cpu cycles and possible rendering tasks
-    new_rendering_task
+    // GameShark codes and the auto-fire of the buttons are applied once per frame
+    if rendering_task_before_access == RenderTask::RenderFrame
+        || rendering_task_after_access == RenderTask::RenderFrame
+    {
+        rust_boy.memory_bus.apply_gameshark_codes();
+        Joypad::handle_auto_fire(&mut rust_boy.memory_bus);
+    }
+
+    // Each mode of the PPU lasts longer than any instruction, so at most one of the two steps
+    // returns a rendering task
+    if rendering_task_before_access != RenderTask::None {
+        Ok(rendering_task_before_access)
+    } else {
+        Ok(rendering_task_after_access)
+    }
+}
+
+/// Advances the timer and divider registers and the PPU by the provided number of m-cycles and
+/// returns the rendering task of the PPU.
+fn advance_timer_and_ppu(rust_boy: &mut RustBoy, m_cycles: u32) -> RenderTask {
+    if m_cycles == 0 {
+        return RenderTask::None;
+    }
+
+    // Increment the timer and divider register according to the number of cycles that passed
+    rust_boy.memory_bus.handle_timer(m_cycles);
+
+    // Shift the bits of a running serial transfer
+    rust_boy.memory_bus.handle_serial(m_cycles);
+
+    // Copy the bytes of a running OAM DMA transfer
+    rust_boy.memory_bus.handle_oam_dma(m_cycles);
+
+    // Advance the PPU one m-cycle (4 dots) at a time, so mode transitions and the STAT interrupts
+    // they request happen on the m-cycle they are due and not only after the whole instruction.
+    // Each mode of the PPU lasts longer than any instruction, so at most one of the steps returns
+    // a rendering task. The number of dots per m-cycle can be changed for debugging, see
+    // `dots_per_m_cycle` of the PPU
+    let mut rendering_task = RenderTask::None;
+    let dots_per_m_cycle = rust_boy.ppu.dots_per_m_cycle;
+    for _ in 0..m_cycles {
+        let rendering_task_of_step = rust_boy
+            .ppu
+            .ppu_step(&mut rust_boy.memory_bus, dots_per_m_cycle);
+        if rendering_task_of_step != RenderTask::None {
+            rendering_task = rendering_task_of_step;
+        }
+    }
+    rendering_task
 }
 
 /// Handles the close event of the window by exiting the event loop.
+#[cfg(feature = "frontend")]
 fn handle_close_event(control_flow: &EventLoopWindowTarget<()>) {
     control_flow.exit();
 }
 
 /// Handles the keyboard input events.
 ///
-/// That is, control flow inputs like ESCAPE to exit the emulator, or P to pause the emulator but
-/// also inputs for the emulator itself.
+/// That is, control flow inputs like ESCAPE to exit the emulator, BACKSPACE to reset the emulator
+/// (see [RustBoy::reset]) or P to pause the emulator but also inputs for the emulator itself.
+#[cfg(feature = "frontend")]
 fn handle_keyboard_input(
     event: &WindowEvent,
     control_flow: &EventLoopWindowTarget<()>,
     rust_boy: &mut RustBoy,
+    current_rendering_task: &mut RenderTask,
     paused: &mut bool,
+    modifiers: ModifiersState,
 ) {
     match event {
         WindowEvent::KeyboardInput {
@@ -427,6 +1545,20 @@ fn handle_keyboard_input(
                 },
             ..
         } => control_flow.exit(),
+        WindowEvent::KeyboardInput {
+            event:
+                KeyEvent {
+                    state: ElementState::Pressed,
+                    physical_key: PhysicalKey::Code(KeyCode::Backspace),
+                    repeat: false,
+                    ..
+                },
+            ..
+        } => {
+            rust_boy.reset();
+            // The rendering task belongs to the frame which was interrupted by the reset
+            *current_rendering_task = RenderTask::None;
+        }
         WindowEvent::KeyboardInput {
             event:
                 KeyEvent {
@@ -435,7 +1567,7 @@ fn handle_keyboard_input(
                     ..
                 },
             ..
-        } => handle_key_pressed_event(rust_boy, key, paused),
+        } => handle_key_pressed_event(rust_boy, key, paused, modifiers),
         WindowEvent::KeyboardInput {
             event:
                 KeyEvent {
@@ -448,3 +1580,178 @@ fn handle_keyboard_input(
         _ => {}
     }
 }
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// Returns a RustBoy in the state after the boot ROM, which runs the provided program from
+    /// 0x0100 on. The program is placed in an otherwise empty ROM without MBC, that is, the rest
+    /// of the ROM consists of NOPs.
+    pub(crate) fn rust_boy_with_program(program: &[u8]) -> RustBoy {
+        let mut rom_data = vec![0; 0x8000];
+        rom_data[0x0100..0x0100 + program.len()].copy_from_slice(program);
+        RustBoy::from_rom(&rom_data).expect("The ROM should be loadable")
+    }
+
+    #[cfg(feature = "frontend")]
+    #[test]
+    fn next_frame_time_catches_up_on_at_most_the_maximum_number_of_frames() {
+        let frame = Duration::from_millis(16);
+        let now = Instant::now();
+        let last_frame_time = now - 10 * frame;
+        assert_eq!(
+            next_frame_time(last_frame_time, now, frame, 20),
+            last_frame_time + frame
+        );
+        assert_eq!(
+            next_frame_time(last_frame_time, now, frame, 2),
+            now - 2 * frame
+        );
+        // The maximum catch-up of years must not reach back before the start of the clock
+        assert_eq!(
+            next_frame_time(last_frame_time, now, frame, u32::MAX),
+            last_frame_time + frame
+        );
+    }
+
+    #[test]
+    fn run_frame_returns_after_a_frame_while_the_lcd_is_off() {
+        // XOR A; LDH [LCDC],A; JR -2
+        let mut rust_boy = rust_boy_with_program(&[0xAF, 0xE0, 0x40, 0x18, 0xFE]);
+        // The last frame before the LCD was turned off is cleared from the screen
+        rust_boy
+            .ppu
+            .pixel_fifo
+            .as_mut()
+            .expect("The pixel FIFO is enabled by from_rom")
+            .framebuffer
+            .fill(0);
+        let frames = rust_boy.run_frames(3).expect("The ROM should run");
+        assert_eq!(frames.len(), 3);
+        // The frames start after the boot ROM, so they take at least 3 frames worth of dots
+        let total_dots = rust_boy.ppu.rendering_info.total_dots;
+        assert!(total_dots >= 3 * ppu::DOTS_PER_FRAME as u128);
+        assert!(total_dots < 4 * ppu::DOTS_PER_FRAME as u128);
+        assert!(frames.iter().all(|frame| {
+            frame
+                .chunks_exact(4)
+                .all(|color| color == ppu::pixel_fifo::SHADE_COLORS[0])
+        }));
+    }
+
+    #[test]
+    fn reset_in_the_middle_of_a_frame_starts_over() {
+        let rom_data = include_bytes!("../roms/test_roms/pocket.gb");
+        let mut rust_boy = RustBoy::from_rom(rom_data).expect("The ROM should be loadable");
+        rust_boy.run_frames(30).expect("The ROM should run");
+        for _ in 0..1000 {
+            rust_boy.step().expect("The ROM should run");
+        }
+
+        rust_boy.reset();
+        assert_eq!(rust_boy.cpu.pc, 0x0100);
+        assert_eq!(rust_boy.cpu.sp, 0xFFFE);
+        let mut fresh_rust_boy = RustBoy::from_rom(rom_data).expect("The ROM should be loadable");
+        let expected_frames = fresh_rust_boy.run_frames(60).expect("The ROM should run");
+        let frames = rust_boy.run_frames(60).expect("The ROM should run");
+        assert!(frames == expected_frames);
+    }
+
+    #[cfg(feature = "frontend")]
+    #[test]
+    fn notifications_are_kept_per_rust_boy_and_across_resets() {
+        let mut rust_boy = RustBoy::new_after_boot(DebugInfo::default());
+        let other_rust_boy = RustBoy::new_after_boot(DebugInfo::default());
+        rust_boy.notifications.post("Before the reset");
+        rust_boy.reset();
+        let messages: Vec<String> = rust_boy
+            .notifications
+            .visible()
+            .into_iter()
+            .map(|(message, _)| message)
+            .collect();
+        assert_eq!(messages, ["Before the reset", "Reset the RustBoy"]);
+        let mut other_notifications = other_rust_boy.notifications;
+        assert!(other_notifications.visible().is_empty());
+    }
+
+    #[test]
+    fn reset_keeps_the_battery_backed_ram() {
+        // MBC1+RAM+BATTERY with 8 KiB of RAM. The program enables the RAM and writes 0x42 to
+        // 0xA000: LD A,0x0A; LD [0x0000],A; LD A,0x42; LD [0xA000],A; JR -2
+        let mut rom_data = vec![0; 0x8000];
+        rom_data[0x0147] = 0x03;
+        rom_data[0x0149] = 0x02;
+        rom_data[0x0100..0x010C].copy_from_slice(&[
+            0x3E, 0x0A, 0xEA, 0x00, 0x00, 0x3E, 0x42, 0xEA, 0x00, 0xA0, 0x18, 0xFE,
+        ]);
+        let mut rust_boy = RustBoy::from_rom(&rom_data).expect("The ROM should be loadable");
+        for _ in 0..5 {
+            rust_boy.step().expect("The program should run");
+        }
+
+        rust_boy.reset();
+        assert_eq!(rust_boy.cpu.pc, 0x0100);
+        let battery_backed_ram = rust_boy
+            .memory_bus
+            .get_battery_backed_ram()
+            .expect("The cartridge has battery backed RAM");
+        assert_eq!(battery_backed_ram[0], 0x42);
+    }
+
+    /// The 64-bit FNV-1a hash of the frame dmg-acid2 shows once it is done, which was checked to
+    /// match the reference image of the ROM, apart from the shades of the palette.
+    const DMG_ACID2_FRAME_HASH: u64 = 0x7FDA_941E_BFCC_AC36;
+
+    /// Returns the 64-bit FNV-1a hash of the provided frame.
+    fn frame_hash(frame: &[u8]) -> u64 {
+        frame.iter().fold(0xCBF2_9CE4_8422_2325, |hash, &byte| {
+            (hash ^ byte as u64).wrapping_mul(0x0100_0000_01B3)
+        })
+    }
+
+    #[test]
+    fn run_frames_renders_the_dmg_acid2_reference_image() {
+        let rom_data = include_bytes!("../roms/test_roms/dmg-acid2.gb");
+        let mut rust_boy = RustBoy::from_rom(rom_data).expect("The ROM should be loadable");
+        let frames = rust_boy.run_frames(30).expect("The ROM should run");
+        assert_eq!(frames.len(), 30);
+        assert_eq!(frame_hash(&frames[29]), DMG_ACID2_FRAME_HASH);
+        assert_eq!(
+            rust_boy.framebuffer(),
+            Some(&frames[29][..]),
+            "The last frame should be the current framebuffer"
+        );
+    }
+
+    #[test]
+    fn run_frames_with_passes_the_same_frames_as_run_frames() {
+        let rom_data = include_bytes!("../roms/test_roms/pocket.gb");
+        let mut rust_boy = RustBoy::from_rom(rom_data).expect("The ROM should be loadable");
+        let expected_hashes: Vec<u64> = rust_boy
+            .run_frames(60)
+            .expect("The ROM should run")
+            .iter()
+            .map(|frame| frame_hash(frame))
+            .collect();
+
+        let mut rust_boy = RustBoy::from_rom(rom_data).expect("The ROM should be loadable");
+        let mut hashes = Vec::new();
+        rust_boy
+            .run_frames_with(60, |frame| hashes.push(frame_hash(frame)))
+            .expect("The ROM should run");
+        assert_eq!(hashes, expected_hashes);
+    }
+
+    #[test]
+    fn run_frames_returns_early_at_a_breakpoint() {
+        let rom_data = include_bytes!("../roms/test_roms/dmg-acid2.gb");
+        let mut rust_boy = RustBoy::from_rom(rom_data).expect("The ROM should be loadable");
+        rust_boy.add_breakpoint(0x0100);
+        // No memory is reserved for frames which are not run
+        let frames = rust_boy.run_frames(u32::MAX).expect("The ROM should run");
+        assert!(frames.is_empty());
+        assert_eq!(rust_boy.take_breakpoint_hit(), Some(0x0100));
+    }
+}
@@ -1,5 +1,5 @@
 use clap::Parser;
-use rustboy::run;
+use rustboy::{CartridgeHeader, DEFAULT_AUTO_FIRE_FRAMES, PresentMode, RunOptions, SocdMode, run};
 
 #[derive(Parser, Debug)]
 #[command(name = "Rust Boy")]
@@ -17,6 +17,12 @@ struct Args {
     #[arg(short, long = "LOGS", default_value_t = false)]
     file_logs: bool,
 
+    /// If present, writes a JSON-lines trace of the executed instructions (one object per
+    /// instruction) to logs/trace.jsonl, which is easier to parse by tools than the other logs.
+    /// Only available in debug builds
+    #[arg(long = "JSON-TRACE", default_value_t = false)]
+    json_trace: bool,
+
     /// If present, runs in binjgb mode, which allows for easier debugging with the binjgb emulator
     #[arg(short, long = "BINJGB", default_value_t = false)]
     binjgb_mode: bool,
@@ -30,13 +36,161 @@ struct Args {
     #[arg(short, long = "SB", default_value_t = false)]
     print_serial_output_to_terminal: bool,
 
+    /// If present, renders the frames dot by dot using the pixel FIFO instead of the scanline
+    /// shader. Slower, but supports effects from registers changing in the middle of a scanline.
+    #[arg(long = "FIFO", default_value_t = false)]
+    pixel_fifo_mode: bool,
+
+    /// Interval in seconds in which the save file is written if the save data changed. If 0, the
+    /// save file is only written on exit.
+    #[arg(long = "AUTOSAVE", value_name = "SECONDS", default_value_t = 5)]
+    autosave_interval_in_secs: u32,
+
+    /// Path of a file listing Game Genie and GameShark codes (one per line) to apply
+    #[arg(long = "CHEATS", value_name = "CHEATS_PATH")]
+    cheats_path: Option<String>,
+
+    /// Framerate of the recordings of the screen, which are started and stopped by pressing 'R'
+    #[arg(long = "RECORD-FPS", value_name = "FPS", default_value_t = 30)]
+    recording_fps: u32,
+
+    /// If present, runs the emulator headless and as fast as possible for the provided number of
+    /// frames and prints the achieved emulated clock speed afterward
+    #[arg(long = "BENCH", value_name = "FRAMES")]
+    benchmark_frames: Option<u32>,
+
+    /// If present, starts the emulator paused and pauses it again and logs the registers once the
+    /// CPU is about to execute the instruction at the provided address (e.g. 0x0150). Press 'P' to
+    /// resume.
+    #[arg(long = "BREAK", value_name = "ADDRESS", value_parser = parse_address)]
+    breakpoint: Option<u16>,
+
+    /// How Left + Right or Up + Down held at the same time are reported to the game: 'allow'
+    /// (both, like the hardware), 'last' (the one pressed last) or 'neutral' (neither)
+    #[arg(long = "SOCD", value_name = "MODE", default_value = "allow")]
+    socd_mode: SocdMode,
+
+    /// Number of frames a button with auto-fire stays pressed and then released. Auto-fire is
+    /// off by default and toggled for the held buttons by pressing 'X'
+    #[arg(
+        long = "AUTO-FIRE-FRAMES",
+        value_name = "FRAMES",
+        default_value_t = DEFAULT_AUTO_FIRE_FRAMES,
+        value_parser = clap::value_parser!(u8).range(1..)
+    )]
+    auto_fire_frames: u8,
+
+    /// If present, records which opcodes are executed and prints the ones which were never
+    /// executed on exit. Used to check how much of the instruction set a test ROM covers
+    #[arg(long = "COVERAGE", default_value_t = false)]
+    opcode_coverage: bool,
+
+    /// Number of frames which are emulated, but not shown, after each shown frame. Saves GPU time
+    /// on weak hardware. Can be cycled at runtime by pressing 'F'
+    #[arg(long = "FRAME-SKIP", value_name = "FRAMES", default_value_t = 0)]
+    frame_skip: u32,
+
+    /// How frames are presented: 'auto' (the preferred mode of the platform), 'vsync' (no
+    /// tearing), 'mailbox' (no tearing with less latency) or 'immediate' (lowest latency, may
+    /// tear). Falls back to a supported mode, if the requested one is not supported
+    #[arg(long = "PRESENT-MODE", value_name = "MODE", default_value = "auto")]
+    present_mode: PresentMode,
+
+    /// If present, runs at most the provided number of m-cycles per redraw instead of a whole
+    /// frame (17556 m-cycles) at once, spreading the work over the redraws between two frames for
+    /// smoother frame times. Does not change the emulated timing, but the emulator runs slower
+    /// than real time, if the budget is too small for the number of redraws per frame
+    #[arg(long = "CYCLE-BUDGET", value_name = "M_CYCLES")]
+    cycle_budget: Option<u32>,
+
+    /// The maximum number of frames the emulator catches up on after it fell behind real time,
+    /// e.g. after the window was dragged. Any further delay is discarded instead of fast-forwarding
+    /// through it. 0 never catches up, so every late frame slows the emulator down a bit
+    #[arg(long = "MAX-CATCH-UP", value_name = "FRAMES", default_value_t = 2)]
+    max_catch_up_frames: u32,
+
+    /// If present, runs the emulator headless for the provided number of instructions, writes the
+    /// registers and the memory to a file in the 'dumps' directory and exits afterward
+    #[arg(long = "DUMP-AFTER", value_name = "INSTRUCTIONS")]
+    dump_after_instructions: Option<u64>,
+
+    /// If present, watches the ROM file and reloads it whenever it changes, e.g. after rebuilding
+    /// a homebrew game
+    #[arg(long = "WATCH", default_value_t = false)]
+    watch: bool,
+
+    /// If present, waits for another instance to connect to the provided TCP port and uses it as
+    /// the link partner, e.g. to play link cable games with two players
+    #[arg(
+        long = "LINK-HOST",
+        value_name = "PORT",
+        conflicts_with = "link_connect_address"
+    )]
+    link_host_port: Option<u16>,
+
+    /// If present, connects to another instance listening at the provided address (e.g.
+    /// 127.0.0.1:5555, see '--LINK-HOST') and uses it as the link partner
+    #[arg(long = "LINK-CONNECT", value_name = "ADDRESS")]
+    link_connect_address: Option<String>,
+
+    /// Number of 8 KiB external RAM banks to allocate instead of the number declared by the
+    /// cartridge header, e.g. to test homebrew ROMs with more or less RAM. At most 4, since the
+    /// MBC1 can only select 4 RAM banks
+    #[arg(
+        long = "RAM-BANKS",
+        value_name = "COUNT",
+        value_parser = clap::value_parser!(u8).range(0..=4)
+    )]
+    ram_bank_count_override: Option<u8>,
+
+    /// Level up to which messages are logged: 'off', 'error', 'warn', 'info', 'debug' or 'trace'.
+    /// Defaults to the level set by the RUST_LOG environment variable
+    #[arg(long = "LOG-LEVEL", value_name = "LEVEL")]
+    log_level: Option<String>,
+
+    /// Comma separated list of 'module=level' entries overriding the log level of single modules,
+    /// e.g. 'ppu=trace,timer=off' to only trace the PPU
+    #[arg(long = "LOG-MODULE", value_name = "MODULE=LEVEL,...")]
+    log_modules: Option<String>,
+
+    /// If present, runs the emulator headless and as fast as possible and writes every n-th frame
+    /// to stdout as a binary PPM image, e.g. to diff screenshots in CI or to pipe a video into
+    /// 'ffmpeg -f image2pipe -i -'
+    #[arg(
+        long = "STDOUT-FRAMES",
+        value_name = "INTERVAL",
+        value_parser = clap::value_parser!(u32).range(1..)
+    )]
+    stdout_frame_interval: Option<u32>,
+
+    /// Debugging tool: Number of dots the PPU advances per m-cycle of the CPU. Defaults to 4, as
+    /// on the hardware. Other values slow down or speed up the PPU relative to the CPU to observe
+    /// timing-sensitive behavior and break the timing accuracy
+    #[arg(
+        long = "DOT-SCALE",
+        value_name = "DOTS",
+        default_value_t = 4,
+        value_parser = clap::value_parser!(u32).range(1..=16)
+    )]
+    dots_per_m_cycle: u32,
+
+    /// If present, prints the details of the cartridge header of the ROM and exits without
+    /// running the emulator
+    #[arg(long = "INFO", default_value_t = false)]
+    info: bool,
+
+    /// Path of the file the battery backed save data is loaded from and written to. Defaults to
+    /// the path of the ROM with the extension '.sav'
+    #[arg(long = "SAV", value_name = "SAVE_PATH")]
+    save_path: Option<String>,
+
     /// Specify the path of the ROM file to run
     #[arg(short, long = "ROM", value_name = "ROM_PATH")]
     rom_path: String,
 }
 
 /// Main function to run the emulator. Calls the [run] function from the [rustboy] crate with the
-/// arguments parsed from the command line.
+/// [RunOptions] parsed from the command line.
 fn main() {
     let args = Args::parse();
 
@@ -44,13 +198,68 @@ fn main() {
     let rom = std::fs::read(&args.rom_path)
         .expect(&format!("Should be able to read file: {}", &args.rom_path));
 
-    pollster::block_on(run(
-        args.headless,
-        args.game_boy_doctor,
-        args.file_logs,
-        args.binjgb_mode,
-        args.timing_mode,
-        args.print_serial_output_to_terminal,
-        rom.as_slice(),
-    ));
+    if args.info {
+        match CartridgeHeader::parse(&rom) {
+            Some(cartridge_header) => println!("{}", cartridge_header.info_to_string()),
+            None => {
+                eprintln!(
+                    "The ROM is too short to contain a cartridge header ({} bytes)",
+                    rom.len()
+                );
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
+
+    // The save data is persisted next to the ROM file, unless another path is provided
+    let save_file_path = args.save_path.unwrap_or_else(|| {
+        std::path::Path::new(&args.rom_path)
+            .with_extension("sav")
+            .to_string_lossy()
+            .into_owned()
+    });
+
+    let options = RunOptions {
+        headless: args.headless,
+        game_boy_doctor_mode: args.game_boy_doctor,
+        file_logs: args.file_logs,
+        json_trace: args.json_trace,
+        binjgb_mode: args.binjgb_mode,
+        timing_mode: args.timing_mode,
+        print_serial_output_to_terminal: args.print_serial_output_to_terminal,
+        pixel_fifo_mode: args.pixel_fifo_mode,
+        save_file_path: Some(save_file_path),
+        autosave_interval_in_secs: args.autosave_interval_in_secs,
+        cheats_file_path: args.cheats_path,
+        recording_fps: args.recording_fps,
+        benchmark_frames: args.benchmark_frames,
+        breakpoint: args.breakpoint,
+        socd_mode: args.socd_mode,
+        auto_fire_frames: args.auto_fire_frames,
+        opcode_coverage: args.opcode_coverage,
+        frame_skip: args.frame_skip,
+        present_mode: args.present_mode,
+        cycle_budget: args.cycle_budget,
+        max_catch_up_frames: args.max_catch_up_frames,
+        dump_after_instructions: args.dump_after_instructions,
+        watched_rom_path: args.watch.then(|| args.rom_path.clone()),
+        link_host_port: args.link_host_port,
+        link_connect_address: args.link_connect_address,
+        ram_bank_count_override: args.ram_bank_count_override,
+        log_level: args.log_level,
+        log_modules: args.log_modules,
+        stdout_frame_interval: args.stdout_frame_interval,
+        dots_per_m_cycle: args.dots_per_m_cycle,
+    };
+    pollster::block_on(run(options, rom.as_slice()));
+}
+
+/// Parses an address in hexadecimal, with or without the `0x` prefix (e.g. `0x0150` or `150`).
+fn parse_address(address: &str) -> Result<u16, String> {
+    let digits = address
+        .strip_prefix("0x")
+        .or_else(|| address.strip_prefix("0X"))
+        .unwrap_or(address);
+    u16::from_str_radix(digits, 16).map_err(|error| format!("Invalid address '{address}': {error}"))
 }
@@ -4,15 +4,34 @@
 //! The main functionality is provided by [MemoryBus::read_byte] and [MemoryBus::write_byte],
 //! which handle the reading and writing of bytes to the memory.
 
+pub(crate) mod cartridge_header;
+pub(crate) mod cheats;
+mod io_registers;
 mod mbc;
+pub(crate) mod memory_mapped_device;
+mod oam_dma;
+pub(crate) mod rom_database;
+mod work_ram;
 
+use crate::RustBoyError;
+use crate::apu::{Apu, SOUND_CHANNEL_REGISTERS_BEGIN, SOUND_ON_REGISTER, SOUND_PANNING_REGISTER};
 use crate::debugging::{DebugInfo, DebuggingFlagsWithoutFileHandles};
-use crate::input::{ButtonState, Joypad};
+use crate::input::{AutoFire, ButtonState, Joypad, SocdState};
 use crate::interrupts::{InterruptEnableRegister, InterruptFlagRegister};
 use crate::ppu::information_for_shader::ChangesToPropagateToShader;
 use crate::ppu::tile_handling::{Tile, empty_tile};
+use crate::save_state::{StateReader, StateWriter};
+use crate::serial::{SERIAL_TRANSFER_CONTROL_REGISTER, SERIAL_TRANSFER_DATA_REGISTER, Serial};
+use crate::sgb::{SgbPacketReceiver, SgbPalettes};
+use crate::timer::TimerRegisters;
 use crate::{MEMORY_SIZE, PPU};
+use cartridge_header::{CartridgeHeader, RAM_BANK_SIZE, ROM_BANK_SIZE};
+use cheats::Cheats;
+use io_registers::{INFRARED_PORT_REGISTER, IO_REGISTERS_BEGIN, IO_REGISTERS_END};
 use mbc::MBC;
+use oam_dma::OamDma;
+use rom_database::GameTweaks;
+use work_ram::{WORK_RAM_BANK_REGISTER, WorkRamBanks};
 
 const ROM_BANK_0_BEGIN: u16 = 0x0000;
 const ROM_BANK_0_END: u16 = 0x3FFF;
@@ -22,8 +41,13 @@ const ROM_BANK_1_BEGIN: u16 = 0x4000;
 const ROM_BANK_1_END: u16 = 0x7FFF;
 pub const VRAM_BEGIN: u16 = 0x8000;
 pub const VRAM_END: u16 = 0x9FFF;
+const TILE_DATA_END: u16 = 0x97FF;
 pub const RAM_BANK_BEGIN: u16 = 0xA000;
 pub const RAM_BANK_END: u16 = 0xBFFF;
+const ECHO_RAM_BEGIN: u16 = 0xE000;
+const ECHO_RAM_END: u16 = 0xFDFF;
+/// The offset between the echo RAM and the work RAM it mirrors (0xC000 - 0xDDFF).
+const ECHO_RAM_OFFSET: u16 = 0x2000;
 pub const OAM_START: u16 = 0xFE00;
 pub const OAM_END: u16 = 0xFE9F;
 const UNUSABLE_RAM_BEGIN: u16 = 0xFEA0;
@@ -45,6 +69,29 @@ pub(crate) const INTERRUPT_ENABLE_REGISTER: u16 = 0xFFFF;
 /// - `debugging_flags_without_file_handles`: Flags used for debugging purposes.
 /// - `memory_changed`: Tracks changes to memory that need to be propagated to the shader for rendering.
 /// - `tile_set`: An array of tiles representing the graphics data of the RustBoy.
+/// - `oam_dma`: The running OAM DMA transfer, if any, see [OamDma].
+/// - `cartridge_header`: The parsed header of the loaded cartridge, see [CartridgeHeader].
+/// - `external_ram_written`: A flag indicating if the external RAM was written to since it was
+///   last persisted to the save file.
+/// - `sgb_packet_receiver`: Captures the Super Game Boy command packets sent via the joypad
+///   register, see [SgbPacketReceiver].
+/// - `sgb_palettes`: The palettes set by the Super Game Boy palette commands, see [SgbPalettes].
+///   Is None, if the cartridge does not support Super Game Boy functions.
+/// - `cheats`: The active Game Genie and GameShark codes, see [Cheats].
+/// - `socd_state`: How simultaneous opposing directions are reported, see [SocdState].
+/// - `auto_fire`: Which buttons are repeatedly pressed and released while held, see [AutoFire].
+/// - `work_ram_banks`: The switchable work RAM banks of the Game Boy Color, see [WorkRamBanks].
+///   Is None, if the cartridge does not support the Game Boy Color.
+/// - `apu`: The state of the audio processing unit which is not part of its registers, see [Apu].
+/// - `serial`: The state of the serial port which is not part of its registers, see [Serial].
+/// - `ram_bank_count_override`: The number of external RAM banks to allocate instead of the
+///   number declared by the cartridge header, if Some. Has to be set before the ROM is loaded, see
+///   [MemoryBus::load_program].
+/// - `game_tweaks`: The tweaks to the emulation of the loaded game, if it is in the ROM
+///   database, see [MemoryBus::apply_rom_database]. Has to be set before the ROM is loaded.
+/// - `timer_registers`: The timer and divider registers, which are not stored in `memory` but
+///   mapped as a [MemoryMappedDevice](memory_mapped_device::MemoryMappedDevice), see
+///   [TimerRegisters].
 ///
 /// For details on memory mapping and behavior, refer to [Pan Docs - Memory Map](https://gbdev.io/pandocs/Memory_Map.html)
 /// and [Pan Docs - Hardware Registers](https://gbdev.io/pandocs/Hardware_Reg_List.html).
@@ -64,44 +111,177 @@ pub struct MemoryBus {
     // The following should be tried to get rid of
     pub(crate) tile_set: [Tile; 384],
 
-    pub(crate) dma_happened: bool,
+    pub(crate) oam_dma: Option<OamDma>,
 
     pub(crate) action_button_state: ButtonState,
     pub(crate) direction_button_state: ButtonState,
+
+    pub(crate) cartridge_header: Option<CartridgeHeader>,
+    pub(crate) external_ram_written: bool,
+
+    pub(crate) sgb_packet_receiver: SgbPacketReceiver,
+    pub(crate) sgb_palettes: Option<SgbPalettes>,
+
+    pub(crate) cheats: Cheats,
+    pub(crate) socd_state: SocdState,
+    pub(crate) auto_fire: AutoFire,
+
+    pub(crate) work_ram_banks: Option<WorkRamBanks>,
+
+    pub(crate) apu: Apu,
+    pub(crate) serial: Serial,
+
+    pub(crate) ram_bank_count_override: Option<u8>,
+    pub(crate) game_tweaks: GameTweaks,
+
+    pub(crate) timer_registers: TimerRegisters,
 }
 
 impl MemoryBus {
     /// Loads a program into the memory bus at address 0x0000.
-    pub fn load_program(&mut self, rom_data: &[u8]) {
-        let mbc_type = rom_data[0x147];
-        match mbc_type {
+    ///
+    /// The cartridge header is parsed to determine the memory bank controller (MBC), the size of
+    /// the external RAM and whether the Super Game Boy palettes should be emulated.
+    ///
+    /// If the ROM is smaller than the size declared by the cartridge header (e.g. because a
+    /// download was incomplete), a warning is logged and the ROM is padded with 0xFF, see
+    /// [pad_rom]. The size of the external RAM can be overridden, see
+    /// [MemoryBus::external_ram_size].
+    ///
+    /// Returns an error, if the ROM has no (complete) cartridge header or its memory bank
+    /// controller is not supported.
+    pub fn load_program(&mut self, rom_data: &[u8]) -> Result<(), RustBoyError> {
+        let cartridge_header =
+            CartridgeHeader::parse(rom_data).ok_or(RustBoyError::MissingCartridgeHeader {
+                rom_size: rom_data.len(),
+            })?;
+        let padded_rom_data = pad_rom(rom_data, &cartridge_header);
+        let rom_data = padded_rom_data.as_slice();
+        match cartridge_header.cartridge_type {
             0x00 => {
-                // No MBC
-                self.load(0x0000, &rom_data);
+                // No MBC, so only the first two banks can be mapped
+                self.load(0x0000, &rom_data[..ROM_BANK_1_END as usize + 1]);
+                if self.ram_bank_count_override.is_some() {
+                    log::warn!(
+                        "Ignoring the RAM bank count override, since the cartridge has no memory bank controller"
+                    );
+                }
             }
             0x01 => {
                 // MBC1
-                self.memory_bank_controller =
-                    Some(MBC::new(mbc::MBCType::MBC1, rom_data.to_vec(), 0));
+                self.memory_bank_controller = Some(MBC::new(
+                    mbc::MBCType::MBC1,
+                    rom_data.to_vec(),
+                    self.external_ram_size(0),
+                ));
             }
-            0x02 => {
-                // MBC1 + RAM
-                let ram_size = rom_data[0x149] as usize;
-                self.memory_bank_controller =
-                    Some(MBC::new(mbc::MBCType::MBC1, rom_data.to_vec(), ram_size));
+            0x02 | 0x03 => {
+                // MBC1 + RAM (+ Battery)
+                self.memory_bank_controller = Some(MBC::new(
+                    mbc::MBCType::MBC1,
+                    rom_data.to_vec(),
+                    self.external_ram_size(cartridge_header.ram_size()),
+                ));
             }
-            0x03 => {
-                // MBC1 + RAM + Battery
-                let ram_size = rom_data[0x149] as usize;
+            0x05 | 0x06 => {
+                // MBC2 (+ Battery)
+                if self.ram_bank_count_override.is_some() {
+                    log::warn!(
+                        "Ignoring the RAM bank count override, since the MBC2 has built-in RAM"
+                    );
+                }
                 self.memory_bank_controller =
-                    Some(MBC::new(mbc::MBCType::MBC1, rom_data.to_vec(), ram_size));
+                    Some(MBC::new(mbc::MBCType::MBC2, rom_data.to_vec(), 0));
             }
-            _ => {
-                panic!("The MBC type {:#02X} is not supported yet", mbc_type);
+            mbc_type => {
+                return Err(RustBoyError::UnsupportedCartridgeType(mbc_type));
+            }
+        }
+        if cartridge_header.supports_sgb() && self.game_tweaks.sgb_palettes {
+            self.sgb_palettes = Some(SgbPalettes::new());
+        }
+        if cartridge_header.supports_cgb() && self.game_tweaks.cgb_features {
+            self.work_ram_banks = Some(WorkRamBanks::new());
+        }
+        self.cartridge_header = Some(cartridge_header);
+        Ok(())
+    }
+
+    /// Returns the size of the external RAM in bytes to allocate for a cartridge, whose header
+    /// declares the provided size. If `ram_bank_count_override` is set (see the `--RAM-BANKS`
+    /// option), the overridden number of banks is used instead and logged, e.g. for homebrew ROMs
+    /// whose header does not match the RAM they actually use. Otherwise, the number of banks of
+    /// the ROM database is used, if the game is in it, see [MemoryBus::apply_rom_database].
+    fn external_ram_size(&self, declared_ram_size: usize) -> usize {
+        match self
+            .ram_bank_count_override
+            .or(self.game_tweaks.ram_bank_count)
+        {
+            Some(ram_bank_count) => {
+                let ram_size = ram_bank_count as usize * RAM_BANK_SIZE;
+                log::info!(
+                    "Overriding the external RAM size declared by the cartridge header ({} KiB) with {ram_bank_count} banks ({} KiB)",
+                    declared_ram_size / 1024,
+                    ram_size / 1024
+                );
+                ram_size
             }
+            None => declared_ram_size,
+        }
+    }
+
+    /// Looks up the provided ROM in the ROM database and uses its tweaks when the ROM is loaded
+    /// afterward, see [rom_database]. Has to be called before [MemoryBus::load_program].
+    pub(crate) fn apply_rom_database(&mut self, rom_data: &[u8]) {
+        self.game_tweaks = match rom_database::look_up(rom_data) {
+            Some((name, game_tweaks)) => {
+                log::info!("Applying the tweaks of the ROM database for {name}");
+                game_tweaks
+            }
+            None => GameTweaks::NONE,
+        };
+    }
+
+    /// Returns the data of the loaded ROM, padded as by [MemoryBus::load_program], e.g. to load it
+    /// again when resetting the RustBoy.
+    pub(crate) fn rom_data(&self) -> Vec<u8> {
+        match &self.memory_bank_controller {
+            Some(mbc) => mbc.rom().to_vec(),
+            // Without a memory bank controller, the ROM is mapped into the memory as a whole
+            None => self.memory[..=ROM_BANK_1_END as usize].to_vec(),
         }
     }
 
+    /// Returns the battery backed external RAM of the cartridge, that is, the part of the
+    /// memory that is persisted to a save file. Returns None, if the cartridge has no battery
+    /// backed external RAM.
+    pub fn get_battery_backed_ram(&self) -> Option<&[u8]> {
+        match &self.memory_bank_controller {
+            Some(mbc) if self.cartridge_has_battery() && !mbc.external_ram().is_empty() => {
+                Some(mbc.external_ram())
+            }
+            _ => None,
+        }
+    }
+
+    /// Loads the provided data (e.g. from a save file) into the battery backed external RAM
+    /// of the cartridge. Does nothing, if the cartridge has no battery backed external RAM.
+    pub fn load_battery_backed_ram(&mut self, data: &[u8]) {
+        let cartridge_has_battery = self.cartridge_has_battery();
+        match &mut self.memory_bank_controller {
+            Some(mbc) if cartridge_has_battery => mbc.load_external_ram(data),
+            _ => {}
+        }
+    }
+
+    /// Returns true if the loaded cartridge has a battery, that is, if its external RAM should
+    /// be persisted.
+    fn cartridge_has_battery(&self) -> bool {
+        self.cartridge_header
+            .as_ref()
+            .is_some_and(CartridgeHeader::has_battery)
+    }
+
     /// Reads the instruction byte from the memory at the given address. Used separately to check
     /// if the CPU is starting up.
     ///
@@ -116,28 +296,26 @@ impl MemoryBus {
         }
     }
 
-    /// Read a byte from memory at the given address.
+    /// Read a byte from memory at the given address. While an OAM DMA transfer is running, only
+    /// the high RAM can be read and all other addresses return 0xFF, see [oam_dma].
     pub(super) fn read_byte(&self, address: u16) -> u8 {
+        if self.oam_dma_blocks_access(address) {
+            return 0xFF;
+        }
+        self.read_byte_ignoring_oam_dma(address)
+    }
+
+    /// Read a byte from memory at the given address, even if an OAM DMA transfer is running. Is
+    /// used by the transfer itself.
+    fn read_byte_ignoring_oam_dma(&self, address: u16) -> u8 {
         match address {
-            ROM_BANK_0_BEGIN..=ROM_BANK_0_END => {
-                if self.starting_up {
-                    match address {
-                        BIOS_BEGIN..=BIOS_END => self.bios[address as usize],
-                        _ => self.memory[address as usize],
-                    }
-                } else if let Some(mbc) = &self.memory_bank_controller {
-                    // If a memory bank controller is present, we read from it
-                    mbc.read_byte(address)
+            ROM_BANK_0_BEGIN..=ROM_BANK_1_END => {
+                let value = self.read_rom_byte(address);
+                // Game Genie codes patch the reads from the ROM
+                if self.cheats.game_genie_codes_are_active() {
+                    self.cheats.patch_rom_read(address, value)
                 } else {
-                    self.memory[address as usize]
-                }
-            }
-            ROM_BANK_1_BEGIN..=ROM_BANK_1_END => {
-                if let Some(mbc) = &self.memory_bank_controller {
-                    // If a memory bank controller is present, we read from it
-                    mbc.read_byte(address)
-                } else {
-                    self.memory[address as usize]
+                    value
                 }
             }
 
@@ -150,21 +328,15 @@ impl MemoryBus {
                     self.memory[address as usize]
                 }
             }
+            ECHO_RAM_BEGIN..=ECHO_RAM_END => self.memory[(address - ECHO_RAM_OFFSET) as usize],
             OAM_START..=OAM_END => self.memory[address as usize],
             UNUSABLE_RAM_BEGIN..=UNUSABLE_RAM_END => {
                 // When trying to read from unusable RAM, we return 0xFF
                 0xFF
             }
 
-            // Joypad register
-            JOYPAD_REGISTER => Joypad::get_joypad_register(&self),
-
-            // GPU registers
-            0xFF40 | 0xFF41 | 0xFF42 | 0xFF43 | 0xFF44 | 0xFF45 | 0xFF47 | 0xFF48 | 0xFF49
-            | 0xFF4A | 0xFF4B => PPU::read_registers(&self, address),
-
-            // Interrupt flag register
-            0xFF0F => InterruptFlagRegister::get_interrupt_flag_register(&self),
+            // I/O registers
+            IO_REGISTERS_BEGIN..=IO_REGISTERS_END => self.read_io_register(address),
 
             // Interrupt enable register
             0xFFFF => InterruptEnableRegister::get_interrupt_enable_register(&self),
@@ -173,8 +345,59 @@ impl MemoryBus {
         }
     }
 
-    /// Write a byte to memory at the given address.
+    /// Returns the byte stored at the given address without any side effects, for use by debugging
+    /// tools like logs and memory dumps. In contrast to [MemoryBus::read_byte], the raw stored
+    /// value is returned:
+    /// - The ROM and the external RAM are read from the currently selected banks, but Game Genie
+    ///   codes are not applied and the external RAM is read even if it is disabled.
+    /// - The hardware registers are returned as stored, e.g. the joypad register without the
+    ///   button states mixed in.
+    /// - The unusable memory area (0xFEA0 - 0xFEFF) returns the stored byte instead of 0xFF.
+    pub fn peek(&self, address: u16) -> u8 {
+        match address {
+            ROM_BANK_0_BEGIN..=ROM_BANK_1_END => self.read_rom_byte(address),
+            RAM_BANK_BEGIN..=RAM_BANK_END => match &self.memory_bank_controller {
+                Some(mbc) => mbc.peek_byte(address),
+                None => self.memory[address as usize],
+            },
+            ECHO_RAM_BEGIN..=ECHO_RAM_END => self.memory[(address - ECHO_RAM_OFFSET) as usize],
+            IO_REGISTERS_BEGIN..=IO_REGISTERS_END => self
+                .read_memory_mapped_devices(address)
+                .unwrap_or(self.memory[address as usize]),
+            _ => self.memory[address as usize],
+        }
+    }
+
+    /// Read a byte from the ROM (or the BIOS while starting up) at the given address.
+    fn read_rom_byte(&self, address: u16) -> u8 {
+        match address {
+            BIOS_BEGIN..=BIOS_END if self.starting_up => self.bios[address as usize],
+            ROM_BANK_0_BEGIN..=ROM_BANK_0_END if self.starting_up => self.memory[address as usize],
+            _ => {
+                if let Some(mbc) = &self.memory_bank_controller {
+                    // If a memory bank controller is present, we read from it
+                    mbc.read_byte(address)
+                } else {
+                    self.memory[address as usize]
+                }
+            }
+        }
+    }
+
+    /// Write a byte to memory at the given address. While an OAM DMA transfer is running, only
+    /// the high RAM can be written and writes to all other addresses are ignored, see [oam_dma].
     pub(super) fn write_byte(&mut self, address: u16, value: u8) {
+        if self.oam_dma_blocks_access(address) {
+            return;
+        }
+
+        // The I/O registers owned by a device are written by the device itself
+        if (IO_REGISTERS_BEGIN..=IO_REGISTERS_END).contains(&address)
+            && self.write_memory_mapped_devices(address, value)
+        {
+            return;
+        }
+
         match address {
             // TODO: Add Memory bank controller
             ROM_BANK_0_BEGIN..=ROM_BANK_0_END => {
@@ -194,13 +417,22 @@ impl MemoryBus {
 
             VRAM_BEGIN..=VRAM_END => PPU::write_vram(self, address, value),
             RAM_BANK_BEGIN..=RAM_BANK_END => {
+                // Keep track of writes to the external RAM, so we know when it has to be
+                // persisted to the save file again. Writes while the RAM is disabled are ignored
+                // by the memory bank controller, so they do not count
                 if let Some(mbc) = &mut self.memory_bank_controller {
                     // If a memory bank controller is present, we write to it
-                    mbc.write_byte(address, value);
+                    if mbc.write_byte(address, value) {
+                        self.external_ram_written = true;
+                    }
                 } else {
                     self.memory[address as usize] = value;
+                    self.external_ram_written = true;
                 }
             }
+            ECHO_RAM_BEGIN..=ECHO_RAM_END => {
+                self.memory[(address - ECHO_RAM_OFFSET) as usize] = value;
+            }
             OAM_START..=OAM_END => self.memory[address as usize] = value,
             UNUSABLE_RAM_BEGIN..=UNUSABLE_RAM_END => {
                 // When trying to write to unusable RAM, we just do nothing
@@ -217,17 +449,19 @@ impl MemoryBus {
 
             // DMA transfer register
             0xFF46 => {
+                // The written value can be read back
+                self.memory[address as usize] = value;
                 // If the RustBoy and Memory is being initialized by the BIOS, we do not want to
                 // trigger a DMA transfer
                 if !self.being_initialized {
                     // The value written to the DMA register is the starting address of the transfer
-                    // divided by 0x100 (= 256). The transfer takes 160 cycles.
-                    self.handle_dma(value);
+                    // divided by 0x100 (= 256). The transfer takes 160 m-cycles.
+                    self.start_oam_dma(value);
                 }
             }
 
             // Serial transfer register
-            0xFF01 => {
+            SERIAL_TRANSFER_DATA_REGISTER => {
                 if self.debugging_flags_without_file_handles.timing_mode {
                     if value as char == 'P' {
                         println!(
@@ -247,12 +481,17 @@ impl MemoryBus {
                 self.memory[address as usize] = value;
             }
 
-            // Divider register
-            0xFF04 => {
-                // When a write happens to the divider register, it just resets to 0
-                self.memory[address as usize] = 0;
+            // Serial transfer control register
+            SERIAL_TRANSFER_CONTROL_REGISTER => self.write_serial_control_register(value),
+
+            // Sound channel and control registers
+            SOUND_CHANNEL_REGISTERS_BEGIN..=SOUND_PANNING_REGISTER => {
+                self.write_sound_register(address, value);
             }
 
+            // Sound on/off register
+            SOUND_ON_REGISTER => self.write_sound_on_register(value),
+
             // Interrupt flag register
             INTERRUPT_FLAG_REGISTER => {
                 InterruptFlagRegister::set_interrupt_flag_register(self, value);
@@ -263,6 +502,12 @@ impl MemoryBus {
                 InterruptEnableRegister::set_interrupt_enable_register(self, value);
             }
 
+            // Infrared communications port (CGB only)
+            INFRARED_PORT_REGISTER => self.write_infrared_port_register(value),
+
+            // Work RAM bank register (CGB only)
+            WORK_RAM_BANK_REGISTER => self.write_work_ram_bank_register(value),
+
             _ => {
                 self.memory[address as usize] = value;
             }
@@ -284,6 +529,20 @@ impl MemoryBus {
         self.read_word_little_endian(pc + 1)
     }
 
+    /// Returns true if the loaded cartridge is run in CGB mode, that is, if it supports the Game
+    /// Boy Color (see
+    /// [CartridgeHeader::supports_cgb](cartridge_header::CartridgeHeader::supports_cgb)) and the
+    /// ROM database does not disable the Game Boy Color features for it (see
+    /// [GameTweaks::cgb_features]). Is used to gate the registers which only exist on the CGB and
+    /// the behavior which differs between the DMG and the CGB, e.g. the meaning of LCDC bit 0.
+    pub(crate) fn cgb_mode(&self) -> bool {
+        self.game_tweaks.cgb_features
+            && self
+                .cartridge_header
+                .as_ref()
+                .is_some_and(|cartridge_header| cartridge_header.supports_cgb())
+    }
+
     /// Writes data immediately to the memory at the given address.
     pub(super) fn load(&mut self, address: u16, data: &[u8]) {
         for (i, &byte) in data.iter().enumerate() {
@@ -291,22 +550,58 @@ impl MemoryBus {
         }
     }
 
-    /// The DMA transfer is started by writing to the DMA register at 0xFF46. The value written
-    /// is the starting address of the transfer divided by 0x100 (= 256). The transfer takes 160
-    /// cycles.
-    ///
-    /// TODO: Possibly split the dma into 40 individual writes each taking 4 cycles
-    /// to simulate the transfer speed of the DMG.
-    pub(crate) fn handle_dma(&mut self, address: u8) {
-        if !self.debugging_flags_without_file_handles.binjgb_mode {
-            // In the binjgb emulator, the DMA transfer does not seem to increment the cycle counter
-            self.dma_happened = true;
+    /// Writes the memory, the state of the memory bank controller, the Super Game Boy palettes, the
+    /// work RAM banks of the Game Boy Color, the state of the APU and the serial port and the timer
+    /// registers to the provided save state, see [crate::save_state].
+    pub(crate) fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_bytes(&self.memory);
+        writer.write_bool(self.being_initialized);
+        writer.write_bool(self.starting_up);
+        OamDma::save_state(&self.oam_dma, writer);
+        if let Some(mbc) = &self.memory_bank_controller {
+            mbc.save_state(writer);
+        }
+        if let Some(sgb_palettes) = &self.sgb_palettes {
+            sgb_palettes.save_state(writer);
+        }
+        if let Some(work_ram_banks) = &self.work_ram_banks {
+            work_ram_banks.save_state(writer);
         }
-        let address = (address as u16) << 8;
-        for i in 0..(OAM_END + 1 - OAM_START) + 1 {
-            let value = self.read_byte(address + i);
-            self.write_byte(OAM_START + i, value);
+        self.apu.save_state(writer);
+        self.serial.save_state(writer);
+        self.timer_registers.save_state(writer);
+    }
+
+    /// Restores the memory, the state of the memory bank controller, the Super Game Boy palettes,
+    /// the work RAM banks, the state of the APU and the serial port and the timer registers
+    /// written by [MemoryBus::save_state]. Whether there is a memory bank controller and whether
+    /// the Super Game Boy palettes and work RAM banks are used depends on the cartridge, which the
+    /// save state is already checked to belong to.
+    pub(crate) fn load_state(&mut self, reader: &mut StateReader) -> Result<(), RustBoyError> {
+        reader.read_bytes_into(&mut self.memory)?;
+        self.being_initialized = reader.read_bool()?;
+        self.starting_up = reader.read_bool()?;
+        self.oam_dma = OamDma::load_state(reader)?;
+        if let Some(mbc) = &mut self.memory_bank_controller {
+            mbc.load_state(reader)?;
+            // The external RAM changed, so it has to be persisted to the save file again
+            self.external_ram_written = true;
+        }
+        if let Some(sgb_palettes) = &mut self.sgb_palettes {
+            sgb_palettes.load_state(reader)?;
+        }
+        if let Some(work_ram_banks) = &mut self.work_ram_banks {
+            work_ram_banks.load_state(reader)?;
         }
+        self.apu.load_state(reader)?;
+        self.serial.load_state(reader)?;
+        self.timer_registers.load_state(reader)?;
+
+        // The tile set is derived from the tile data in VRAM, so it is rebuilt
+        for address in (VRAM_BEGIN..=TILE_DATA_END).step_by(2) {
+            PPU::handle_tile_data_change(self, address);
+        }
+        Ok(())
     }
 
     /// Creates a new instance of the [MemoryBus] struct with the given [DebugInfo]. The memory,
@@ -328,10 +623,24 @@ impl MemoryBus {
 
             tile_set: [empty_tile(); 384],
 
-            dma_happened: false,
+            oam_dma: None,
 
             action_button_state: ButtonState::new_nothing_pressed(),
             direction_button_state: ButtonState::new_nothing_pressed(),
+
+            cartridge_header: None,
+            external_ram_written: false,
+            sgb_packet_receiver: SgbPacketReceiver::new(),
+            sgb_palettes: None,
+            cheats: Cheats::default(),
+            socd_state: SocdState::default(),
+            auto_fire: AutoFire::default(),
+            work_ram_banks: None,
+            apu: Apu::default(),
+            serial: Serial::default(),
+            ram_bank_count_override: None,
+            game_tweaks: GameTweaks::NONE,
+            timer_registers: TimerRegisters::default(),
         }
     }
 
@@ -348,16 +657,17 @@ impl MemoryBus {
             if i % 2 == 0 {
                 string.push_str("\n");
             }
+            let address = (i * 8) as u16;
             let tmp_string = format!(
                 "{:#04X} {:#04X} {:#04X} {:#04X} {:#04X} {:#04X} {:#04X} {:#04X} ",
-                self.memory[i],
-                self.memory[i + 1],
-                self.memory[i + 2],
-                self.memory[i + 3],
-                self.memory[i + 4],
-                self.memory[i + 5],
-                self.memory[i + 6],
-                self.memory[i + 7]
+                self.peek(address),
+                self.peek(address + 1),
+                self.peek(address + 2),
+                self.peek(address + 3),
+                self.peek(address + 4),
+                self.peek(address + 5),
+                self.peek(address + 6),
+                self.peek(address + 7)
             );
             string.push_str(&tmp_string);
         }
@@ -366,6 +676,30 @@ impl MemoryBus {
     }
 }
 
+/// Returns the provided ROM padded with 0xFF (the value read from unconnected ROM) to the size
+/// declared by the cartridge header, but at least to the two banks mapped to 0x0000 - 0x7FFF and
+/// to a whole number of banks. This way, all banks can be read without going out of bounds, even
+/// if the ROM is truncated. Logs a warning with the expected and actual size, if the ROM is
+/// smaller than the declared size.
+fn pad_rom(rom_data: &[u8], cartridge_header: &CartridgeHeader) -> Vec<u8> {
+    let declared_size = cartridge_header.rom_size().unwrap_or(0);
+    if rom_data.len() < declared_size {
+        log::warn!(
+            "The ROM is smaller than declared in its cartridge header (expected {} bytes, got {} bytes). It might be truncated, so the missing part is filled with 0xFF",
+            declared_size,
+            rom_data.len()
+        );
+    }
+    let padded_size = rom_data
+        .len()
+        .next_multiple_of(ROM_BANK_SIZE)
+        .max(declared_size)
+        .max(2 * ROM_BANK_SIZE);
+    let mut padded_rom_data = rom_data.to_vec();
+    padded_rom_data.resize(padded_size, 0xFF);
+    padded_rom_data
+}
+
 /// Checks if the bit at the given position is set in the given value.
 pub fn is_bit_set(value: u8, bit_position: u8) -> bool {
     (value & (1 << bit_position)) != 0
@@ -380,3 +714,30 @@ pub fn set_bit(value: u8, bit_position: u8) -> u8 {
 pub fn clear_bit(value: u8, bit_position: u8) -> u8 {
     value & !(1 << bit_position)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_writes_stored_in_the_external_ram_mark_it_as_written() {
+        // MBC1+RAM+BATTERY with 8 KiB of RAM
+        let mut rom_data = vec![0; 0x8000];
+        rom_data[0x0147] = 0x03;
+        rom_data[0x0149] = 0x02;
+        let mut memory_bus = MemoryBus::new_before_boot(&DebugInfo::default());
+        memory_bus.being_initialized = false;
+        memory_bus
+            .load_program(&rom_data)
+            .expect("The ROM should be loadable");
+
+        // The RAM is disabled, so the write is ignored
+        memory_bus.write_byte(0xA000, 0x42);
+        assert!(!memory_bus.external_ram_written);
+        memory_bus.write_byte(0x0000, 0x0A);
+        assert!(!memory_bus.external_ram_written);
+        memory_bus.write_byte(0xA000, 0x42);
+        assert!(memory_bus.external_ram_written);
+        assert_eq!(memory_bus.read_byte(0xA000), 0x42);
+    }
+}
@@ -0,0 +1,353 @@
+//! This module contains the [CartridgeHeader] struct, which is used to parse the header of a
+//! cartridge (ROM), that is, the memory area 0x0100 - 0x014F. It contains information about the
+//! cartridge like its title, the memory bank controller (MBC) used and the size of the ROM and
+//! external RAM. For more information, please refer to
+//! [Pan Docs - The Cartridge Header](https://gbdev.io/pandocs/The_Cartridge_Header.html).
+
+const TITLE_BEGIN: usize = 0x0134;
+const TITLE_END: usize = 0x0143;
+const CGB_FLAG_ADDRESS: usize = 0x0143;
+const SGB_FLAG_ADDRESS: usize = 0x0146;
+const CARTRIDGE_TYPE_ADDRESS: usize = 0x0147;
+const ROM_SIZE_ADDRESS: usize = 0x0148;
+const RAM_SIZE_ADDRESS: usize = 0x0149;
+const OLD_LICENSEE_CODE_ADDRESS: usize = 0x014B;
+const HEADER_CHECKSUM_ADDRESS: usize = 0x014D;
+const GLOBAL_CHECKSUM_ADDRESS: usize = 0x014E;
+/// The first address after the cartridge header. ROMs shorter than this have no (complete) header.
+pub(crate) const HEADER_END: usize = 0x0150;
+/// The size of a ROM bank in bytes.
+pub(crate) const ROM_BANK_SIZE: usize = 0x4000;
+/// The size of each bank of the external RAM in bytes.
+pub(crate) const RAM_BANK_SIZE: usize = 0x2000;
+
+/// Struct to represent the header of a cartridge.
+///
+/// - `title`: The title of the game in upper case ASCII.
+/// - `cgb_flag`: Indicates whether the cartridge supports (0x80) or requires (0xC0) the Game Boy Color.
+/// - `sgb_flag`: Indicates whether the cartridge supports Super Game Boy functions (0x03).
+/// - `cartridge_type`: Indicates which memory bank controller (MBC) and further hardware the cartridge has.
+/// - `rom_size_code`: Encodes the size of the ROM, which is 32 KiB * (1 << `rom_size_code`).
+/// - `ram_size_code`: Encodes the size of the external RAM, see [CartridgeHeader::ram_size].
+/// - `old_licensee_code`: The code of the publisher of the game. Is 0x33 for newer games, which
+///   use the new licensee code instead. Needed to check for Super Game Boy support.
+/// - `header_checksum`: The checksum of the header bytes 0x0134 - 0x014C stored in the header.
+/// - `computed_header_checksum`: The checksum of the header bytes 0x0134 - 0x014C as computed by
+///   the boot ROM. If it does not match `header_checksum`, the original Game Boy would lock up.
+/// - `global_checksum`: The checksum of the entire ROM stored in the header. It is not verified by
+///   the original Game Boy.
+/// - `computed_global_checksum`: The checksum of the entire ROM, that is, the sum of all bytes
+///   except the two bytes of the global checksum itself. If it does not match `global_checksum`,
+///   the ROM dump is likely corrupt or truncated.
+#[derive(Clone, Debug)]
+pub struct CartridgeHeader {
+    /// The title of the game.
+    pub title: String,
+    /// Whether the cartridge supports or requires the Game Boy Color.
+    pub cgb_flag: u8,
+    /// Whether the cartridge supports Super Game Boy functions.
+    pub sgb_flag: u8,
+    /// The memory bank controller and further hardware of the cartridge.
+    pub cartridge_type: u8,
+    /// The encoded size of the ROM.
+    pub rom_size_code: u8,
+    /// The encoded size of the external RAM.
+    pub ram_size_code: u8,
+    /// The code of the publisher of the game.
+    pub old_licensee_code: u8,
+    /// The header checksum stored in the header.
+    pub header_checksum: u8,
+    /// The header checksum as computed by the boot ROM.
+    pub computed_header_checksum: u8,
+    /// The global checksum stored in the header.
+    pub global_checksum: u16,
+    /// The global checksum computed over the entire ROM.
+    pub computed_global_checksum: u16,
+}
+
+impl CartridgeHeader {
+    /// Parses the header of the provided ROM. Returns None if the ROM is too short to contain a
+    /// (complete) header.
+    pub fn parse(rom_data: &[u8]) -> Option<CartridgeHeader> {
+        if rom_data.len() < HEADER_END {
+            return None;
+        }
+
+        // The title is padded with zeros. Newer cartridges use the last bytes of the title area
+        // for other purposes (like the CGB flag), so we only read up to the first zero and ignore
+        // non ASCII characters.
+        let title = rom_data[TITLE_BEGIN..TITLE_END]
+            .iter()
+            .take_while(|&&byte| byte != 0)
+            .filter(|byte| byte.is_ascii_graphic() || **byte == b' ')
+            .map(|&byte| byte as char)
+            .collect::<String>()
+            .trim()
+            .to_string();
+
+        // The header checksum is computed as described in
+        // https://gbdev.io/pandocs/The_Cartridge_Header.html#014d--header-checksum
+        let computed_header_checksum = rom_data[TITLE_BEGIN..HEADER_CHECKSUM_ADDRESS]
+            .iter()
+            .fold(0u8, |checksum, &byte| {
+                checksum.wrapping_sub(byte).wrapping_sub(1)
+            });
+
+        // The global checksum is computed as described in
+        // https://gbdev.io/pandocs/The_Cartridge_Header.html#014e-014f--global-checksum
+        let computed_global_checksum = rom_data
+            .iter()
+            .enumerate()
+            .filter(|(address, _)| {
+                !(GLOBAL_CHECKSUM_ADDRESS..=GLOBAL_CHECKSUM_ADDRESS + 1).contains(address)
+            })
+            .fold(0u16, |checksum, (_, &byte)| {
+                checksum.wrapping_add(byte as u16)
+            });
+
+        Some(CartridgeHeader {
+            title,
+            cgb_flag: rom_data[CGB_FLAG_ADDRESS],
+            sgb_flag: rom_data[SGB_FLAG_ADDRESS],
+            cartridge_type: rom_data[CARTRIDGE_TYPE_ADDRESS],
+            rom_size_code: rom_data[ROM_SIZE_ADDRESS],
+            ram_size_code: rom_data[RAM_SIZE_ADDRESS],
+            old_licensee_code: rom_data[OLD_LICENSEE_CODE_ADDRESS],
+            header_checksum: rom_data[HEADER_CHECKSUM_ADDRESS],
+            computed_header_checksum,
+            global_checksum: u16::from_be_bytes([
+                rom_data[GLOBAL_CHECKSUM_ADDRESS],
+                rom_data[GLOBAL_CHECKSUM_ADDRESS + 1],
+            ]),
+            computed_global_checksum,
+        })
+    }
+
+    /// Returns the name of the cartridge type, that is, the memory bank controller and further
+    /// hardware of the cartridge, see
+    /// [Pan Docs - Cartridge Type](https://gbdev.io/pandocs/The_Cartridge_Header.html#0147--cartridge-type).
+    pub fn cartridge_type_name(&self) -> &'static str {
+        match self.cartridge_type {
+            0x00 => "ROM ONLY",
+            0x01 => "MBC1",
+            0x02 => "MBC1+RAM",
+            0x03 => "MBC1+RAM+BATTERY",
+            0x05 => "MBC2",
+            0x06 => "MBC2+BATTERY",
+            0x08 => "ROM+RAM",
+            0x09 => "ROM+RAM+BATTERY",
+            0x0B => "MMM01",
+            0x0C => "MMM01+RAM",
+            0x0D => "MMM01+RAM+BATTERY",
+            0x0F => "MBC3+TIMER+BATTERY",
+            0x10 => "MBC3+TIMER+RAM+BATTERY",
+            0x11 => "MBC3",
+            0x12 => "MBC3+RAM",
+            0x13 => "MBC3+RAM+BATTERY",
+            0x19 => "MBC5",
+            0x1A => "MBC5+RAM",
+            0x1B => "MBC5+RAM+BATTERY",
+            0x1C => "MBC5+RUMBLE",
+            0x1D => "MBC5+RUMBLE+RAM",
+            0x1E => "MBC5+RUMBLE+RAM+BATTERY",
+            0x20 => "MBC6",
+            0x22 => "MBC7+SENSOR+RUMBLE+RAM+BATTERY",
+            0xFC => "POCKET CAMERA",
+            0xFD => "BANDAI TAMA5",
+            0xFE => "HuC3",
+            0xFF => "HuC1+RAM+BATTERY",
+            _ => "UNKNOWN",
+        }
+    }
+
+    /// Returns true if the memory bank controller of the cartridge is supported by the RustBoy.
+    pub fn cartridge_type_is_supported(&self) -> bool {
+        matches!(self.cartridge_type, 0x00..=0x03 | 0x05 | 0x06)
+    }
+
+    /// Returns true if the external RAM of the cartridge is battery backed, that is, if the save
+    /// data should be persisted.
+    pub fn has_battery(&self) -> bool {
+        matches!(
+            self.cartridge_type,
+            0x03 | 0x06 | 0x09 | 0x0D | 0x0F | 0x10 | 0x13 | 0x1B | 0x1E | 0x22 | 0xFF
+        )
+    }
+
+    /// Returns the number of 16 KiB ROM banks of the cartridge, see
+    /// [Pan Docs - ROM Size](https://gbdev.io/pandocs/The_Cartridge_Header.html#0148--rom-size).
+    /// Returns None, if the ROM size code is unknown.
+    pub fn rom_bank_count(&self) -> Option<usize> {
+        (self.rom_size_code <= 0x08).then(|| 2 << self.rom_size_code as usize)
+    }
+
+    /// Returns the size of the ROM in bytes declared by the cartridge header. Returns None, if the
+    /// ROM size code is unknown, see [CartridgeHeader::rom_bank_count].
+    pub fn rom_size(&self) -> Option<usize> {
+        self.rom_bank_count()
+            .map(|rom_bank_count| rom_bank_count * ROM_BANK_SIZE)
+    }
+
+    /// Returns the ROM size as a human readable string, e.g. `64 KiB (4 banks)`, or the unknown
+    /// ROM size code.
+    fn rom_size_to_string(&self) -> String {
+        match self.rom_bank_count() {
+            Some(rom_bank_count) => {
+                format!("{} KiB ({} banks)", rom_bank_count * 16, rom_bank_count)
+            }
+            None => format!("unknown (code {:#04X})", self.rom_size_code),
+        }
+    }
+
+    /// Returns the size of the external RAM in bytes, see
+    /// [Pan Docs - RAM Size](https://gbdev.io/pandocs/The_Cartridge_Header.html#0149--ram-size).
+    pub fn ram_size(&self) -> usize {
+        match self.ram_size_code {
+            0x02 => 8 * 1024,
+            0x03 => 32 * 1024,
+            0x04 => 128 * 1024,
+            0x05 => 64 * 1024,
+            _ => 0,
+        }
+    }
+
+    /// Returns true if the cartridge supports or requires the Game Boy Color.
+    pub fn supports_cgb(&self) -> bool {
+        self.cgb_flag & 0x80 != 0
+    }
+
+    /// Returns true if the cartridge supports Super Game Boy functions. For this, the SGB flag has
+    /// to be set and the old licensee code has to be 0x33.
+    pub fn supports_sgb(&self) -> bool {
+        self.sgb_flag == 0x03 && self.old_licensee_code == 0x33
+    }
+
+    /// Returns true if the header checksum stored in the header matches the computed one.
+    pub fn header_checksum_is_valid(&self) -> bool {
+        self.header_checksum == self.computed_header_checksum
+    }
+
+    /// Returns true if the global checksum stored in the header matches the computed one.
+    pub fn global_checksum_is_valid(&self) -> bool {
+        self.global_checksum == self.computed_global_checksum
+    }
+
+    /// Returns a detailed, human readable description of the cartridge header with one field per
+    /// line. Is printed by the `--INFO` option of the binary.
+    pub fn info_to_string(&self) -> String {
+        let checksum_validity = |is_valid| if is_valid { "valid" } else { "invalid" };
+        format!(
+            "Title:           {}\n\
+             Cartridge type:  {:#04X} {} ({})\n\
+             ROM size:        {}\n\
+             RAM size:        {} KiB{}\n\
+             Game Boy Color:  {}\n\
+             Super Game Boy:  {}\n\
+             Header checksum: {:#04X} ({}, computed {:#04X})\n\
+             Global checksum: {:#06X} ({}, computed {:#06X})",
+            self.title,
+            self.cartridge_type,
+            self.cartridge_type_name(),
+            if self.cartridge_type_is_supported() {
+                "supported"
+            } else {
+                "not supported"
+            },
+            self.rom_size_to_string(),
+            self.ram_size() / 1024,
+            if self.has_battery() {
+                " (battery backed)"
+            } else {
+                ""
+            },
+            match self.cgb_flag {
+                0xC0 => "required",
+                _ if self.supports_cgb() => "supported",
+                _ => "no",
+            },
+            if self.supports_sgb() {
+                "supported"
+            } else {
+                "no"
+            },
+            self.header_checksum,
+            checksum_validity(self.header_checksum_is_valid()),
+            self.computed_header_checksum,
+            self.global_checksum,
+            checksum_validity(self.global_checksum_is_valid()),
+            self.computed_global_checksum,
+        )
+    }
+
+    /// Returns a concise, human readable summary of the cartridge header.
+    pub fn summary_to_string(&self) -> String {
+        format!(
+            "Title: {:?}, Type: {:#04X} {} ({}), ROM: {}, RAM: {} KiB, CGB: {}, SGB: {}, Header checksum: {:#04X} ({}), Global checksum: {:#06X} ({})",
+            self.title,
+            self.cartridge_type,
+            self.cartridge_type_name(),
+            if self.cartridge_type_is_supported() {
+                "supported"
+            } else {
+                "not supported"
+            },
+            self.rom_size_to_string(),
+            self.ram_size() / 1024,
+            match self.cgb_flag {
+                0xC0 => "required",
+                _ if self.supports_cgb() => "supported",
+                _ => "no",
+            },
+            if self.supports_sgb() {
+                "supported"
+            } else {
+                "no"
+            },
+            self.header_checksum,
+            if self.header_checksum_is_valid() {
+                "valid"
+            } else {
+                "invalid"
+            },
+            self.global_checksum,
+            if self.global_checksum_is_valid() {
+                "valid"
+            } else {
+                "invalid"
+            },
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns a header parsed from an empty ROM with the provided ROM size code.
+    fn header_with_rom_size_code(rom_size_code: u8) -> CartridgeHeader {
+        let mut rom_data = vec![0; HEADER_END];
+        rom_data[ROM_SIZE_ADDRESS] = rom_size_code;
+        CartridgeHeader::parse(&rom_data).expect("The ROM contains a complete header")
+    }
+
+    #[test]
+    fn known_rom_size_codes_give_the_number_of_banks() {
+        let header = header_with_rom_size_code(0x00);
+        assert_eq!(header.rom_bank_count(), Some(2));
+        assert_eq!(header.rom_size(), Some(32 * 1024));
+        let header = header_with_rom_size_code(0x08);
+        assert_eq!(header.rom_bank_count(), Some(512));
+        assert_eq!(header.rom_size(), Some(8 * 1024 * 1024));
+    }
+
+    #[test]
+    fn unknown_rom_size_codes_do_not_overflow() {
+        for rom_size_code in [0x09, 0x52, 0x54, 0xFF] {
+            let header = header_with_rom_size_code(rom_size_code);
+            assert_eq!(header.rom_bank_count(), None);
+            assert_eq!(header.rom_size(), None);
+            assert!(header.summary_to_string().contains("unknown"));
+            assert!(header.info_to_string().contains("unknown"));
+        }
+    }
+}
@@ -0,0 +1,217 @@
+//! This module contains the [Cheat] enum and the [Cheats] struct, which are used to parse and apply
+//! Game Genie and GameShark cheat codes.
+//!
+//! - Game Genie codes (`ABC-DEF-GHI` or `ABC-DEF`) patch reads from the ROM. The patched value is
+//!   returned instead of the byte in the ROM, optionally only if the byte in the ROM matches a
+//!   compare value. See [MemoryBus::read_byte].
+//! - GameShark codes (`ABCDEFGH`) write a value to the RAM once per frame, overriding whatever
+//!   the game wrote there. See [MemoryBus::apply_gameshark_codes].
+
+use super::MemoryBus;
+
+/// Represents a single parsed cheat code.
+///
+/// - `GameGenie`: Replaces reads from the ROM at `address` with `value`. If `compare` is set,
+///   the read is only replaced if the byte in the ROM equals `compare`.
+/// - `GameShark`: Writes `value` to `address` once per frame. The address lies in the external
+///   RAM, the work RAM or the high RAM. The RAM bank encoded in the code is ignored.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Cheat {
+    GameGenie {
+        address: u16,
+        value: u8,
+        compare: Option<u8>,
+    },
+    GameShark {
+        address: u16,
+        value: u8,
+    },
+}
+
+impl Cheat {
+    /// Parses the provided Game Genie or GameShark code. Dashes and whitespace are ignored.
+    /// Returns None, if the code is not valid.
+    ///
+    /// The format of the codes is described in
+    /// [Game Genie - Game Boy](https://gbdev.gg8.se/wiki/articles/Gameshark_and_Game_Genie).
+    pub fn parse(code: &str) -> Option<Cheat> {
+        let digits = normalize_code(code)
+            .chars()
+            .map(|digit| digit.to_digit(16).map(|digit| digit as u8))
+            .collect::<Option<Vec<u8>>>()?;
+        match digits.len() {
+            6 | 9 => {
+                // Game Genie: The digits AB are the value, the digits FCDE (with F XORed with
+                // 0xF) the address and the digits G and I the (encoded) compare value. Digit H
+                // is not used.
+                let value = digits[0] << 4 | digits[1];
+                let address = ((digits[5] ^ 0xF) as u16) << 12
+                    | (digits[2] as u16) << 8
+                    | (digits[3] as u16) << 4
+                    | digits[4] as u16;
+                if address >= 0x8000 {
+                    // Game Genie codes can only patch the ROM
+                    return None;
+                }
+                let compare = (digits.len() == 9)
+                    .then(|| (digits[6] << 4 | digits[8]).rotate_right(2) ^ 0xBA);
+                Some(Cheat::GameGenie {
+                    address,
+                    value,
+                    compare,
+                })
+            }
+            8 => {
+                // GameShark: The digits AB are the RAM bank, CD the value and EFGH the address
+                // in little endian
+                let value = digits[2] << 4 | digits[3];
+                let address =
+                    u16::from_le_bytes([digits[4] << 4 | digits[5], digits[6] << 4 | digits[7]]);
+                if !matches!(address, 0xA000..=0xDFFF | 0xFF80..=0xFFFE) {
+                    // GameShark codes can only write to the external RAM, the work RAM and
+                    // the high RAM
+                    return None;
+                }
+                Some(Cheat::GameShark { address, value })
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Struct to keep track of the active cheats. The cheats are stored together with their
+/// normalized code (upper case, without dashes and whitespace), so they can be removed again.
+#[derive(Debug, Default)]
+pub(crate) struct Cheats {
+    game_genie_codes: Vec<(String, Cheat)>,
+    gameshark_codes: Vec<(String, Cheat)>,
+}
+
+impl Cheats {
+    /// Parses the provided code and adds it to the active cheats. Returns false, if the code
+    /// is not valid.
+    pub(crate) fn add(&mut self, code: &str) -> bool {
+        let Some(cheat) = Cheat::parse(code) else {
+            return false;
+        };
+        match cheat {
+            Cheat::GameGenie { .. } => &mut self.game_genie_codes,
+            Cheat::GameShark { .. } => &mut self.gameshark_codes,
+        }
+        .push((normalize_code(code), cheat));
+        true
+    }
+
+    /// Removes the provided code from the active cheats. Returns false, if the code was not active.
+    pub(crate) fn remove(&mut self, code: &str) -> bool {
+        let code = normalize_code(code);
+        let number_of_cheats = self.game_genie_codes.len() + self.gameshark_codes.len();
+        self.game_genie_codes
+            .retain(|(active_code, _)| *active_code != code);
+        self.gameshark_codes
+            .retain(|(active_code, _)| *active_code != code);
+        number_of_cheats != self.game_genie_codes.len() + self.gameshark_codes.len()
+    }
+
+    /// Returns the value of a read from the ROM at the provided address with the Game Genie codes
+    /// applied. `value` is the byte actually stored in the ROM.
+    pub(crate) fn patch_rom_read(&self, address: u16, value: u8) -> u8 {
+        self.game_genie_codes
+            .iter()
+            .find_map(|(_, cheat)| match *cheat {
+                Cheat::GameGenie {
+                    address: cheat_address,
+                    value: cheat_value,
+                    compare,
+                } if cheat_address == address && compare.is_none_or(|compare| compare == value) => {
+                    Some(cheat_value)
+                }
+                _ => None,
+            })
+            .unwrap_or(value)
+    }
+
+    /// Returns true if there are active Game Genie codes. Used to skip patching ROM reads in the
+    /// common case of no cheats being active.
+    pub(crate) fn game_genie_codes_are_active(&self) -> bool {
+        !self.game_genie_codes.is_empty()
+    }
+}
+
+impl MemoryBus {
+    /// Writes the values of the active GameShark codes to the RAM. Is called once per frame.
+    pub(crate) fn apply_gameshark_codes(&mut self) {
+        // The codes are copied, since writing needs mutable access to the memory bus
+        let writes = self
+            .cheats
+            .gameshark_codes
+            .iter()
+            .filter_map(|(_, cheat)| match *cheat {
+                Cheat::GameShark { address, value } => Some((address, value)),
+                Cheat::GameGenie { .. } => None,
+            })
+            .collect::<Vec<_>>();
+        for (address, value) in writes {
+            self.write_byte(address, value);
+        }
+    }
+}
+
+/// Normalizes the provided code by removing dashes and whitespace and converting it to upper case.
+fn normalize_code(code: &str) -> String {
+    code.chars()
+        .filter(|character| *character != '-' && !character.is_whitespace())
+        .collect::<String>()
+        .to_uppercase()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn gameshark_codes_are_parsed() {
+        assert_eq!(
+            Cheat::parse("01ff43cb"),
+            Some(Cheat::GameShark {
+                address: 0xCB43,
+                value: 0xFF,
+            })
+        );
+        assert_eq!(
+            Cheat::parse("01 05 A0 FF"),
+            Some(Cheat::GameShark {
+                address: 0xFFA0,
+                value: 0x05,
+            })
+        );
+    }
+
+    #[test]
+    fn gameshark_codes_outside_of_the_ram_are_rejected() {
+        // ROM
+        assert_eq!(Cheat::parse("01FF0040"), None);
+        // VRAM
+        assert_eq!(Cheat::parse("01FF0080"), None);
+        // Echo RAM, OAM and I/O registers
+        assert_eq!(Cheat::parse("01FF00E0"), None);
+        assert_eq!(Cheat::parse("01FF00FE"), None);
+        assert_eq!(Cheat::parse("01FF40FF"), None);
+        // Interrupt enable register
+        assert_eq!(Cheat::parse("01FFFFFF"), None);
+    }
+
+    #[test]
+    fn game_genie_codes_are_parsed() {
+        assert_eq!(
+            Cheat::parse("00A-17B-C49"),
+            Some(Cheat::GameGenie {
+                address: 0x4A17,
+                value: 0x00,
+                compare: Some(0xC8),
+            })
+        );
+        // Game Genie codes can not patch the RAM
+        assert_eq!(Cheat::parse("00A-170"), None);
+    }
+}
@@ -0,0 +1,194 @@
+//! This module contains the read behavior of the I/O registers (0xFF00 - 0xFF7F). Many of them
+//! have bits which are not used or which can only be written. These always read as 1 on the
+//! original Game Boy (DMG), as do the addresses which are not mapped to any register at all.
+//! For more information, please refer to
+//! [Pan Docs - Hardware Registers](https://gbdev.io/pandocs/Hardware_Reg_List.html) and the
+//! `unused_hwio` test ROM of the [Mooneye Test Suite](https://github.com/Gekkio/mooneye-test-suite).
+//!
+//! Cartridges supporting the Game Boy Color (CGB) additionally get a stub of the infrared
+//! communications port (RP), see [MemoryBus::write_infrared_port_register], and the work RAM bank
+//! register (SVBK), see [super::work_ram].
+
+use super::work_ram::WORK_RAM_BANK_REGISTER;
+use super::{INTERRUPT_FLAG_REGISTER, JOYPAD_REGISTER, MemoryBus};
+use crate::PPU;
+use crate::apu::SOUND_ON_REGISTER;
+use crate::input::Joypad;
+use crate::interrupts::InterruptFlagRegister;
+
+pub(crate) const IO_REGISTERS_BEGIN: u16 = 0xFF00;
+pub(crate) const IO_REGISTERS_END: u16 = 0xFF7F;
+pub(super) const INFRARED_PORT_REGISTER: u16 = 0xFF56;
+
+/// The bits of the infrared communications port (RP) register which can be written: The LED
+/// (bit 0) and the read enable bits (bits 6 and 7).
+const INFRARED_PORT_WRITABLE_BITS: u8 = 0b1100_0001;
+/// The bits of the infrared communications port (RP) register which always read as 1 when no
+/// signal is received: The unused bits 2 - 5 and bit 1, which reads as 0 only while a signal is
+/// received.
+const INFRARED_PORT_NO_SIGNAL_BITS: u8 = 0b0011_1110;
+
+/// The bits of each I/O register which always read as 1, indexed by the address minus
+/// [IO_REGISTERS_BEGIN]. A value of 0xFF means that the register is write-only or that the address
+/// is not mapped on the DMG.
+#[rustfmt::skip]
+const UNUSED_IO_REGISTER_BITS: [u8; (IO_REGISTERS_END - IO_REGISTERS_BEGIN + 1) as usize] = [
+    // 0xFF00 P1 (upper 2 bits unused), 0xFF01 SB, 0xFF02 SC (bits 1 - 6 unused),
+    // 0xFF03 unmapped, 0xFF04 DIV, 0xFF05 TIMA, 0xFF06 TMA, 0xFF07 TAC (upper 5 bits unused)
+    0xC0, 0x00, 0x7E, 0xFF, 0x00, 0x00, 0x00, 0xF8,
+    // 0xFF08 - 0xFF0E unmapped, 0xFF0F IF (upper 3 bits unused, set by InterruptFlagRegister)
+    0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0x00,
+    // 0xFF10 NR10 (bit 7 unused), 0xFF11 NR11 (length is write-only), 0xFF12 NR12,
+    // 0xFF13 NR13 (write-only), 0xFF14 NR14 (only the length enable bit is readable),
+    // 0xFF15 unmapped, 0xFF16 NR21 (length is write-only), 0xFF17 NR22
+    0x80, 0x3F, 0x00, 0xFF, 0xBF, 0xFF, 0x3F, 0x00,
+    // 0xFF18 NR23 (write-only), 0xFF19 NR24 (only the length enable bit is readable),
+    // 0xFF1A NR30 (lower 7 bits unused), 0xFF1B NR31 (write-only), 0xFF1C NR32 (only the
+    // volume bits are used), 0xFF1D NR33 (write-only), 0xFF1E NR34 (only the length enable bit
+    // is readable), 0xFF1F unmapped
+    0xFF, 0xBF, 0x7F, 0xFF, 0x9F, 0xFF, 0xBF, 0xFF,
+    // 0xFF20 NR41 (write-only), 0xFF21 NR42, 0xFF22 NR43, 0xFF23 NR44 (only the length enable
+    // bit is readable), 0xFF24 NR50, 0xFF25 NR51, 0xFF26 NR52 (bits 4 - 6 unused),
+    // 0xFF27 unmapped
+    0xFF, 0x00, 0x00, 0xBF, 0x00, 0x00, 0x70, 0xFF,
+    // 0xFF28 - 0xFF2F unmapped
+    0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF,
+    // 0xFF30 - 0xFF3F wave RAM
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    // 0xFF40 LCDC, 0xFF41 STAT (bit 7 unused), 0xFF42 SCY, 0xFF43 SCX, 0xFF44 LY, 0xFF45 LYC,
+    // 0xFF46 DMA (reads the last written value), 0xFF47 BGP
+    0x00, 0x80, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    // 0xFF48 OBP0, 0xFF49 OBP1, 0xFF4A WY, 0xFF4B WX, 0xFF4C - 0xFF4F unmapped (CGB only)
+    0x00, 0x00, 0x00, 0x00, 0xFF, 0xFF, 0xFF, 0xFF,
+    // 0xFF50 boot ROM mapping control (write-only), 0xFF51 - 0xFF7F unmapped (CGB only)
+    0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF,
+    0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF,
+    0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF,
+    0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF,
+    0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF,
+    0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF,
+];
+
+impl MemoryBus {
+    /// Reads the I/O register at the provided address (0xFF00 - 0xFF7F). The registers owned by
+    /// a [MemoryMappedDevice](super::memory_mapped_device::MemoryMappedDevice) are read from the
+    /// device. The registers with special read behavior (the joypad, the PPU registers, IF and
+    /// NR52) are read using their respective handlers, all others are read from the memory.
+    /// Afterward, the bits of [UNUSED_IO_REGISTER_BITS] are set.
+    pub(super) fn read_io_register(&self, address: u16) -> u8 {
+        if let Some(value) = self.read_memory_mapped_devices(address) {
+            return value | UNUSED_IO_REGISTER_BITS[(address - IO_REGISTERS_BEGIN) as usize];
+        }
+
+        let value = match address {
+            // Joypad register
+            JOYPAD_REGISTER => Joypad::get_joypad_register(self),
+
+            // GPU registers
+            0xFF40 | 0xFF41 | 0xFF42 | 0xFF43 | 0xFF44 | 0xFF45 | 0xFF47 | 0xFF48 | 0xFF49
+            | 0xFF4A | 0xFF4B => PPU::read_registers(self, address),
+
+            // Interrupt flag register
+            INTERRUPT_FLAG_REGISTER => InterruptFlagRegister::read_interrupt_flag_register(self),
+
+            // Sound on/off register, which reports the enabled channels
+            SOUND_ON_REGISTER => self.read_sound_on_register(),
+
+            // Infrared communications port, which never receives a signal
+            INFRARED_PORT_REGISTER if self.cgb_mode() => {
+                return self.memory[address as usize] | INFRARED_PORT_NO_SIGNAL_BITS;
+            }
+
+            // Work RAM bank register, which does not exist on the DMG
+            WORK_RAM_BANK_REGISTER => match self.read_work_ram_bank_register() {
+                Some(value) => return value,
+                None => self.memory[address as usize],
+            },
+
+            _ => self.memory[address as usize],
+        };
+        value | UNUSED_IO_REGISTER_BITS[(address - IO_REGISTERS_BEGIN) as usize]
+    }
+
+    /// Writes the infrared communications port (RP) register (0xFF56). Only the control bits are
+    /// stored, see [INFRARED_PORT_WRITABLE_BITS], which is enough for games polling the port (like
+    /// the Pokémon games) to see that no signal is received instead of hanging. The actual
+    /// infrared communication is not emulated. The register only exists on the Game Boy Color,
+    /// so writes are ignored if the cartridge is not run in CGB mode, see [MemoryBus::cgb_mode].
+    ///
+    /// See [Pan Docs - Infrared Communications Port](https://gbdev.io/pandocs/IR.html).
+    pub(super) fn write_infrared_port_register(&mut self, value: u8) {
+        if self.cgb_mode() {
+            self.memory[INFRARED_PORT_REGISTER as usize] = value & INFRARED_PORT_WRITABLE_BITS;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::debugging::DebugInfo;
+    use crate::ppu::object_handling::ObjectPriorityMode;
+
+    /// Returns a memory bus with the provided ROM loaded, including its tweaks of the ROM
+    /// database.
+    fn memory_bus_with_rom(rom_data: &[u8]) -> MemoryBus {
+        let mut memory_bus = MemoryBus::new_before_boot(&DebugInfo::default());
+        memory_bus.apply_rom_database(rom_data);
+        memory_bus
+            .load_program(rom_data)
+            .expect("The ROM should be loadable");
+        memory_bus.being_initialized = false;
+        memory_bus
+    }
+
+    /// Returns an empty ROM without MBC, which supports the Game Boy Color and is not in the ROM
+    /// database.
+    fn cgb_rom() -> Vec<u8> {
+        let mut rom_data = vec![0; 0x8000];
+        rom_data[0x0143] = 0x80;
+        rom_data
+    }
+
+    #[test]
+    fn infrared_port_exists_in_cgb_mode() {
+        let mut memory_bus = memory_bus_with_rom(&cgb_rom());
+        assert!(memory_bus.cgb_mode());
+        memory_bus.write_byte(INFRARED_PORT_REGISTER, 0xFF);
+        assert_eq!(
+            memory_bus.read_byte(INFRARED_PORT_REGISTER),
+            INFRARED_PORT_WRITABLE_BITS | INFRARED_PORT_NO_SIGNAL_BITS
+        );
+        memory_bus.write_byte(INFRARED_PORT_REGISTER, 0x00);
+        assert_eq!(
+            memory_bus.read_byte(INFRARED_PORT_REGISTER),
+            INFRARED_PORT_NO_SIGNAL_BITS
+        );
+        assert_eq!(
+            ObjectPriorityMode::for_cartridge(&memory_bus),
+            ObjectPriorityMode::Cgb
+        );
+    }
+
+    #[test]
+    fn rom_database_disables_the_cgb_registers_and_behavior() {
+        // Blargg's cpu_instrs supports the Game Boy Color, but its Game Boy Color features are
+        // disabled by the ROM database
+        let mut memory_bus =
+            memory_bus_with_rom(include_bytes!("../../roms/test_roms/cpu_instrs.gb"));
+        assert!(
+            memory_bus
+                .cartridge_header
+                .as_ref()
+                .is_some_and(|cartridge_header| cartridge_header.supports_cgb())
+        );
+        assert!(!memory_bus.cgb_mode());
+        memory_bus.write_byte(INFRARED_PORT_REGISTER, 0x00);
+        assert_eq!(memory_bus.read_byte(INFRARED_PORT_REGISTER), 0xFF);
+        assert_eq!(
+            ObjectPriorityMode::for_cartridge(&memory_bus),
+            ObjectPriorityMode::Dmg
+        );
+    }
+}
@@ -1,29 +1,90 @@
 mod mbc1;
+mod mbc2;
+
+use crate::RustBoyError;
+use crate::save_state::{StateReader, StateWriter};
 
 pub(super) enum MBCType {
     MBC1,
+    MBC2,
 }
 
 pub(super) enum MBC {
     MBC1(mbc1::MBC1),
+    MBC2(mbc2::MBC2),
 }
 
 impl MBC {
     pub fn new(mbc_type: MBCType, rom_data: Vec<u8>, ram_size: usize) -> Self {
         match mbc_type {
             MBCType::MBC1 => MBC::MBC1(mbc1::MBC1::new(rom_data, ram_size)),
+            // The MBC2 has built-in RAM, so the RAM size of the cartridge header is not used
+            MBCType::MBC2 => MBC::MBC2(mbc2::MBC2::new(rom_data)),
         }
     }
 
     pub fn read_byte(&self, address: u16) -> u8 {
         match self {
             MBC::MBC1(mbc) => mbc.read_byte(address),
+            MBC::MBC2(mbc) => mbc.read_byte(address),
         }
     }
 
-    pub fn write_byte(&mut self, address: u16, value: u8) {
+    /// Returns the byte at the given address like [MBC::read_byte], but reads the external RAM
+    /// even if it is disabled. See [super::MemoryBus::peek].
+    pub fn peek_byte(&self, address: u16) -> u8 {
+        match self {
+            MBC::MBC1(mbc) => mbc.peek_byte(address),
+            MBC::MBC2(mbc) => mbc.peek_byte(address),
+        }
+    }
+
+    /// Writes a byte to the memory controlled by the MBC. Returns true, if the byte was stored in
+    /// the external RAM, that is, if the RAM is enabled and the address is in 0xA000..=0xBFFF.
+    pub fn write_byte(&mut self, address: u16, value: u8) -> bool {
         match self {
             MBC::MBC1(mbc) => mbc.write_byte(address, value),
+            MBC::MBC2(mbc) => mbc.write_byte(address, value),
+        }
+    }
+
+    /// Returns the ROM of the cartridge.
+    pub fn rom(&self) -> &[u8] {
+        match self {
+            MBC::MBC1(mbc) => mbc.rom(),
+            MBC::MBC2(mbc) => mbc.rom(),
+        }
+    }
+
+    /// Returns the external RAM of the cartridge.
+    pub fn external_ram(&self) -> &[u8] {
+        match self {
+            MBC::MBC1(mbc) => mbc.external_ram(),
+            MBC::MBC2(mbc) => mbc.external_ram(),
+        }
+    }
+
+    /// Overwrites the external RAM of the cartridge with the provided data.
+    pub fn load_external_ram(&mut self, data: &[u8]) {
+        match self {
+            MBC::MBC1(mbc) => mbc.load_external_ram(data),
+            MBC::MBC2(mbc) => mbc.load_external_ram(data),
+        }
+    }
+
+    /// Writes the state of the MBC, including the external RAM, to the provided save state.
+    pub fn save_state(&self, writer: &mut StateWriter) {
+        match self {
+            MBC::MBC1(mbc) => mbc.save_state(writer),
+            MBC::MBC2(mbc) => mbc.save_state(writer),
+        }
+    }
+
+    /// Restores the state of the MBC written by [MBC::save_state].
+    pub fn load_state(&mut self, reader: &mut StateReader) -> Result<(), RustBoyError> {
+        match self {
+            MBC::MBC1(mbc) => mbc.load_state(reader),
+            MBC::MBC2(mbc) => mbc.load_state(reader),
         }
     }
 }
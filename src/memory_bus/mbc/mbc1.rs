@@ -1,3 +1,13 @@
+use crate::RustBoyError;
+use crate::save_state::{StateReader, StateWriter};
+
+/// The size of the MBC1M multi-game compilation cartridges, see [is_multicart].
+const MULTICART_ROM_SIZE: usize = 1024 * 1024;
+/// The size of each game on an MBC1M multi-game compilation cartridge, that is, 16 ROM banks.
+const MULTICART_GAME_SIZE: usize = 16 * 0x4000;
+/// The range of the Nintendo logo in the cartridge header of each game.
+const NINTENDO_LOGO_RANGE: std::ops::Range<usize> = 0x0104..0x0134;
+
 /// Struct to represent the MBC1 memory bank controller.
 /// This struct handles the memory (ram and rom) mapping for cartridges using MBC1.
 ///
@@ -5,12 +15,19 @@
 /// - `rom`: A vector of bytes representing the ROM data.
 /// - `ram`: A vector of bytes representing the RAM data.
 /// - `ram_enabled`: A boolean indicating whether reading/writing of external RAM is enabled.
-/// - `rom_bank_number`: The current ROM bank number. Is a 5-bit register (range $01-$1F) which
-/// selects the ROM bank number for the 4000-7FFF region.
-/// - `ram_bank_number`: The current RAM bank number. Is a 2-bit register (range $00-$03) which
-/// selects the RAM bank (32 KiB ram carts only), or to select the upper 2 bits (4-5) of the ROM
-/// bank number (1 MiB ROM or larger carts only).
-/// - `mode`: A 1-bit register (range $00-$01) which selects the mode of operation.
+/// - `rom_bank_number`: The 5-bit register (range $01-$1F) which selects the lower bits of the
+///   ROM bank number for the 4000-7FFF region (BANK1 in Pan Docs).
+/// - `ram_bank_number`: The 2-bit register (range $00-$03) which selects the upper bits of the
+///   ROM bank number (1 MiB ROM or larger carts only) and, if `mode` is set, the RAM bank (32 KiB
+///   ram carts only) and the ROM bank mapped to the 0000-3FFF region (BANK2 in Pan Docs).
+/// - `mode`: A 1-bit register (range $00-$01) which selects the mode of operation. If it is
+///   set, `ram_bank_number` also applies to the RAM and the 0000-3FFF region.
+/// - `multicart`: Whether the cartridge is an MBC1M multi-game compilation, see [is_multicart].
+///   On these, `ram_bank_number` selects the game instead of the upper bits of the ROM bank
+///   number, that is, it makes up bits 4-5 of the ROM bank number and only the lower 4 bits of
+///   `rom_bank_number` are used.
+///
+/// For more information, see [Pan Docs - MBC1](https://gbdev.io/pandocs/MBC1.html).
 pub struct MBC1 {
     rom: Vec<u8>,
     ram: Vec<u8>,
@@ -18,12 +35,35 @@ pub struct MBC1 {
     rom_bank_number: u8,
     ram_bank_number: u8,
     mode: bool,
+    multicart: bool,
+}
+
+/// Returns true if the provided ROM is an MBC1M multi-game compilation cartridge (e.g. the
+/// "Mortal Kombat I & II" or "Bomberman Collection" carts). These are wired differently than
+/// standard MBC1 cartridges, see [MBC1], but use the same cartridge type in their header.
+///
+/// They can only be detected heuristically: Their ROM is 1 MiB large and made up of four games of
+/// 256 KiB each, each starting with its own cartridge header. So we check whether the second game
+/// (at ROM bank 0x10) contains the Nintendo logo of the cartridge header of the first game (the
+/// menu). This is the same heuristic other emulators use. The logo is required by the boot ROM,
+/// so the chance of it appearing at that offset in a standard MBC1 cartridge is negligible.
+fn is_multicart(rom: &[u8]) -> bool {
+    if rom.len() != MULTICART_ROM_SIZE {
+        return false;
+    }
+    let second_game = &rom[MULTICART_GAME_SIZE..];
+    rom[NINTENDO_LOGO_RANGE] == second_game[NINTENDO_LOGO_RANGE]
 }
 
 impl MBC1 {
-    /// Creates a new MBC1 instance with the given ROM data and RAM size.
+    /// Creates a new MBC1 instance with the given ROM data and RAM size. Whether the cartridge
+    /// is an MBC1M multi-game compilation is detected from the ROM data, see [is_multicart].
     pub(super) fn new(rom_data: Vec<u8>, ram_size: usize) -> Self {
         let ram = vec![0; ram_size];
+        let multicart = is_multicart(&rom_data);
+        if multicart {
+            log::info!("Detected an MBC1M multi-game compilation cartridge");
+        }
         MBC1 {
             rom: rom_data,
             ram,
@@ -31,37 +71,89 @@ impl MBC1 {
             rom_bank_number: 1,
             ram_bank_number: 0,
             mode: false,
+            multicart,
         }
     }
 
+    /// Returns the number of bits of the ROM bank number which are selected by
+    /// `rom_bank_number`, which `ram_bank_number` is shifted by. Is 4 on MBC1M multi-game
+    /// compilation cartridges and 5 otherwise.
+    fn rom_bank_number_bits(&self) -> u32 {
+        if self.multicart { 4 } else { 5 }
+    }
+
+    /// Returns the offset in the ROM of the bank currently mapped to the provided region, that
+    /// is, the 0000-3FFF region if `upper_region` is false and the 4000-7FFF region otherwise.
+    ///
+    /// If the selected ROM bank is larger than the number of banks of the ROM, the bank number
+    /// wraps around, as only as many bits of the bank number are wired up as the ROM needs.
+    fn rom_bank_offset(&self, upper_region: bool) -> usize {
+        let upper_bits = if upper_region || self.mode {
+            (self.ram_bank_number as usize) << self.rom_bank_number_bits()
+        } else {
+            0
+        };
+        let lower_bits = if upper_region {
+            let mask = (1 << self.rom_bank_number_bits()) - 1;
+            (self.rom_bank_number & mask) as usize
+        } else {
+            0
+        };
+        ((upper_bits | lower_bits) * 0x4000) % self.rom.len()
+    }
+
+    /// Returns the index in the external RAM of the provided address (0xA000..=0xBFFF). The RAM
+    /// bank is only selected by `ram_bank_number` if `mode` is set and wraps around, if it is
+    /// larger than the number of banks of the RAM. Must not be called if there is no RAM.
+    fn ram_index(&self, address: u16) -> usize {
+        let bank = if self.mode {
+            self.ram_bank_number as usize
+        } else {
+            0
+        };
+        (bank * 0x2000 + (address as usize - 0xA000)) % self.ram.len()
+    }
+
     /// Read a byte from the memory controlled by the MBC1.
     ///
-    /// Panics if the address is not in the range of 0x0000..=0x7FFF or 0xA000..=0xBFFF.
+    /// The address has to be in the range of 0x0000..=0x7FFF or 0xA000..=0xBFFF, which is
+    /// asserted in debug builds.
     pub(super) fn read_byte(&self, address: u16) -> u8 {
         match address {
-            // ROM Bank 0
-            0x0000..=0x3FFF => self.rom[address as usize],
-            // ROM Bank 0x01-0x1F
-            0x4000..=0x7FFF => {
-                let bank_offset = (self.rom_bank_number as usize) * 0x4000;
-                self.rom[bank_offset + (address as usize - 0x4000)]
-            }
+            // ROM Bank 0 (or 0x20/0x40/0x60, if mode is set)
+            0x0000..=0x3FFF => self.rom[self.rom_bank_offset(false) + address as usize],
+            // ROM Bank 0x01-0x7F
+            0x4000..=0x7FFF => self.rom[self.rom_bank_offset(true) + (address as usize - 0x4000)],
             0xA000..=0xBFFF => {
                 if self.ram_enabled {
-                    let bank_offset = (self.ram_bank_number as usize) * 0x2000;
-                    self.ram[bank_offset + (address as usize - 0xA000)]
+                    self.ram[self.ram_index(address)]
                 } else {
                     0
                 }
             }
-            _ => panic!("Invalid read address in MBC: {:#X}", address),
+            _ => {
+                debug_assert!(false, "Invalid read address in MBC: {:#X}", address);
+                0xFF
+            }
         }
     }
 
-    /// Write a byte to the memory controlled by the MBC1.
+    /// Returns the byte at the given address like [MBC1::read_byte], but reads the external RAM
+    /// even if it is disabled. Returns 0xFF, if the cartridge has no external RAM.
+    pub(super) fn peek_byte(&self, address: u16) -> u8 {
+        match address {
+            0xA000..=0xBFFF if self.ram.is_empty() => 0xFF,
+            0xA000..=0xBFFF => self.ram[self.ram_index(address)],
+            _ => self.read_byte(address),
+        }
+    }
+
+    /// Write a byte to the memory controlled by the MBC1. Returns true, if the byte was stored in
+    /// the external RAM.
     ///
-    /// Panics if the address is not in the range of 0x000..=0x7FFF or 0xA000..=0xBFFF.
-    pub(super) fn write_byte(&mut self, address: u16, value: u8) {
+    /// The address has to be in the range of 0x0000..=0x7FFF or 0xA000..=0xBFFF, which is
+    /// asserted in debug builds.
+    pub(super) fn write_byte(&mut self, address: u16, value: u8) -> bool {
         match address {
             // RAM Enable/Disable. Ram is enabled if the value is 0x0A.
             0x0000..=0x1FFF => {
@@ -71,7 +163,8 @@ impl MBC1 {
                 }
             }
             // ROM Bank Number. Only the lower 5 bits are used, and bank_number 0 is considered as
-            // bank_number 1.
+            // bank_number 1. On MBC1M cartridges, only the lower 4 bits are used for banking,
+            // but bank_number 0x10 is still not considered as 1.
             0x2000..=0x3FFF => {
                 let bank_number = value & 0x1F;
                 if bank_number == 0 {
@@ -80,28 +173,62 @@ impl MBC1 {
                     self.rom_bank_number = bank_number;
                 }
             }
-            // RAM Bank Number / Upper Bits of ROM Bank Number. Depending on the mode, this sets the
-            // RAM bank number (if mode is 0) or the upper bits of the ROM bank number (if mode is 1).
+            // RAM Bank Number / Upper Bits of ROM Bank Number. Which of them this selects is
+            // decided when reading, depending on the mode, see MBC1::rom_bank_offset and
+            // MBC1::ram_index.
             0x4000..=0x5FFF => {
-                if self.mode {
-                    self.ram_bank_number = value & 0x03;
-                } else {
-                    let upper_bits = value & 0x03;
-                    self.rom_bank_number = (self.rom_bank_number & 0x1F) | (upper_bits << 5);
-                }
+                self.ram_bank_number = value & 0x03;
             }
-            // Mode Selection
+            // Mode Selection. Only the lowest bit is used.
             0x6000..=0x7FFF => {
-                self.mode = value == 1;
+                self.mode = value & 0x01 != 0;
             }
             // RAM Write
             0xA000..=0xBFFF => {
                 if self.ram_enabled {
-                    let bank_offset = (self.ram_bank_number as usize) * 0x2000;
-                    self.ram[bank_offset + (address as usize - 0xA000)] = value;
+                    let index = self.ram_index(address);
+                    self.ram[index] = value;
+                    return true;
                 }
             }
-            _ => panic!("Invalid write address in MBC: {:#X}", address),
+            _ => debug_assert!(false, "Invalid write address in MBC: {:#X}", address),
         }
+        false
+    }
+
+    /// Returns the ROM of the cartridge.
+    pub(super) fn rom(&self) -> &[u8] {
+        &self.rom
+    }
+
+    /// Returns the external RAM of the cartridge.
+    pub(super) fn external_ram(&self) -> &[u8] {
+        &self.ram
+    }
+
+    /// Overwrites the external RAM of the cartridge with the provided data. If the data is
+    /// shorter or longer than the external RAM, only the overlapping part is copied.
+    pub(super) fn load_external_ram(&mut self, data: &[u8]) {
+        let length = data.len().min(self.ram.len());
+        self.ram[..length].copy_from_slice(&data[..length]);
+    }
+
+    /// Writes the external RAM and the registers of the MBC1 to the provided save state.
+    pub(super) fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_bytes(&self.ram);
+        writer.write_bool(self.ram_enabled);
+        writer.write_u8(self.rom_bank_number);
+        writer.write_u8(self.ram_bank_number);
+        writer.write_bool(self.mode);
+    }
+
+    /// Restores the external RAM and the registers of the MBC1 written by [MBC1::save_state].
+    pub(super) fn load_state(&mut self, reader: &mut StateReader) -> Result<(), RustBoyError> {
+        reader.read_bytes_into(&mut self.ram)?;
+        self.ram_enabled = reader.read_bool()?;
+        self.rom_bank_number = reader.read_u8()?;
+        self.ram_bank_number = reader.read_u8()?;
+        self.mode = reader.read_bool()?;
+        Ok(())
     }
 }
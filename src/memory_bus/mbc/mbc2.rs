@@ -0,0 +1,200 @@
+use crate::RustBoyError;
+use crate::save_state::{StateReader, StateWriter};
+
+/// The size of the built-in RAM of the MBC2 in half bytes (nibbles).
+const RAM_SIZE: usize = 512;
+/// The bits of each byte of the built-in RAM which are actually stored. The upper 4 bits are not
+/// connected and read as 1.
+const RAM_BITS: u8 = 0x0F;
+/// The bit of the address which selects whether a write to 0x0000-0x3FFF controls the RAM enable
+/// register (if it is clear) or the ROM bank number (if it is set).
+const REGISTER_SELECT_ADDRESS_BIT: u16 = 0x0100;
+
+/// Struct to represent the MBC2 memory bank controller.
+/// This struct handles the memory (ram and rom) mapping for cartridges using MBC2.
+///
+/// The fields of this struct are:
+/// - `rom`: A vector of bytes representing the ROM data.
+/// - `ram`: The built-in RAM of 512 half bytes, of which only the lower 4 bits of each byte are
+///   used. It is mapped to A000-A1FF and echoed across the rest of the A000-BFFF region.
+/// - `ram_enabled`: A boolean indicating whether reading/writing of the RAM is enabled.
+/// - `rom_bank_number`: The current ROM bank number. Is a 4-bit register (range $01-$0F) which
+///   selects the ROM bank number for the 4000-7FFF region.
+///
+/// For more information, see [Pan Docs - MBC2](https://gbdev.io/pandocs/MBC2.html).
+pub struct MBC2 {
+    rom: Vec<u8>,
+    ram: Vec<u8>,
+    ram_enabled: bool,
+    rom_bank_number: u8,
+}
+
+impl MBC2 {
+    /// Creates a new MBC2 instance with the given ROM data. The size of the RAM is fixed, since
+    /// it is built into the MBC2.
+    pub(super) fn new(rom_data: Vec<u8>) -> Self {
+        MBC2 {
+            rom: rom_data,
+            ram: vec![0; RAM_SIZE],
+            ram_enabled: false,
+            rom_bank_number: 1,
+        }
+    }
+
+    /// Read a byte from the memory controlled by the MBC2.
+    ///
+    /// The address has to be in the range of 0x0000..=0x7FFF or 0xA000..=0xBFFF, which is
+    /// asserted in debug builds.
+    ///
+    /// If the selected ROM bank is larger than the number of banks of the ROM, the bank number
+    /// wraps around, as only as many bits of the bank number are wired up as the ROM needs.
+    pub(super) fn read_byte(&self, address: u16) -> u8 {
+        match address {
+            // ROM Bank 0
+            0x0000..=0x3FFF => self.rom[address as usize],
+            // ROM Bank 0x01-0x0F
+            0x4000..=0x7FFF => {
+                let bank_offset = ((self.rom_bank_number as usize) * 0x4000) % self.rom.len();
+                self.rom[bank_offset + (address as usize - 0x4000)]
+            }
+            0xA000..=0xBFFF => {
+                if self.ram_enabled {
+                    self.peek_byte(address)
+                } else {
+                    // Nothing drives the data bus if the RAM is disabled
+                    0xFF
+                }
+            }
+            _ => {
+                debug_assert!(false, "Invalid read address in MBC: {:#X}", address);
+                0xFF
+            }
+        }
+    }
+
+    /// Returns the byte at the given address like [MBC2::read_byte], but reads the RAM even if it
+    /// is disabled.
+    pub(super) fn peek_byte(&self, address: u16) -> u8 {
+        match address {
+            0xA000..=0xBFFF => self.ram[Self::ram_index(address)] | !RAM_BITS,
+            _ => self.read_byte(address),
+        }
+    }
+
+    /// Write a byte to the memory controlled by the MBC2. Returns true, if the byte was stored in
+    /// the built-in RAM.
+    ///
+    /// The address has to be in the range of 0x0000..=0x7FFF or 0xA000..=0xBFFF, which is
+    /// asserted in debug builds.
+    pub(super) fn write_byte(&mut self, address: u16, value: u8) -> bool {
+        match address {
+            // RAM Enable/Disable or ROM Bank Number, depending on bit 8 of the address
+            0x0000..=0x3FFF => {
+                if address & REGISTER_SELECT_ADDRESS_BIT == 0 {
+                    // Ram is enabled if the lower 4 bits of the value are 0x0A
+                    self.ram_enabled = value & 0x0F == 0x0A;
+                } else {
+                    // Only the lower 4 bits are used, and bank_number 0 is considered as
+                    // bank_number 1
+                    let bank_number = value & 0x0F;
+                    self.rom_bank_number = bank_number.max(1);
+                }
+            }
+            // The MBC2 has no registers in this region
+            0x4000..=0x7FFF => {}
+            // RAM Write, of which only the lower 4 bits are stored
+            0xA000..=0xBFFF => {
+                if self.ram_enabled {
+                    self.ram[Self::ram_index(address)] = value & RAM_BITS;
+                    return true;
+                }
+            }
+            _ => debug_assert!(false, "Invalid write address in MBC: {:#X}", address),
+        }
+        false
+    }
+
+    /// Returns the index in the RAM of the provided address (0xA000..=0xBFFF). Only the lower 9
+    /// bits of the address are used, so the RAM is echoed across the A000-BFFF region.
+    fn ram_index(address: u16) -> usize {
+        (address as usize - 0xA000) % RAM_SIZE
+    }
+
+    /// Returns the ROM of the cartridge.
+    pub(super) fn rom(&self) -> &[u8] {
+        &self.rom
+    }
+
+    /// Returns the built-in RAM of the cartridge.
+    pub(super) fn external_ram(&self) -> &[u8] {
+        &self.ram
+    }
+
+    /// Overwrites the built-in RAM of the cartridge with the provided data. If the data is
+    /// shorter or longer than the RAM, only the overlapping part is copied. Only the lower 4 bits
+    /// of each byte are stored.
+    pub(super) fn load_external_ram(&mut self, data: &[u8]) {
+        for (ram_byte, data_byte) in self.ram.iter_mut().zip(data) {
+            *ram_byte = data_byte & RAM_BITS;
+        }
+    }
+
+    /// Writes the built-in RAM and the registers of the MBC2 to the provided save state.
+    pub(super) fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_bytes(&self.ram);
+        writer.write_bool(self.ram_enabled);
+        writer.write_u8(self.rom_bank_number);
+    }
+
+    /// Restores the built-in RAM and the registers of the MBC2 written by [MBC2::save_state].
+    pub(super) fn load_state(&mut self, reader: &mut StateReader) -> Result<(), RustBoyError> {
+        reader.read_bytes_into(&mut self.ram)?;
+        self.ram_enabled = reader.read_bool()?;
+        self.rom_bank_number = reader.read_u8()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns an MBC2 with a ROM of 4 banks, each of which is filled with its bank number.
+    fn mbc2_with_four_rom_banks() -> MBC2 {
+        let rom_data = (0..4u8)
+            .flat_map(|bank| std::iter::repeat_n(bank, 0x4000))
+            .collect();
+        MBC2::new(rom_data)
+    }
+
+    #[test]
+    fn ram_stores_only_the_lower_nibble() {
+        let mut mbc2 = mbc2_with_four_rom_banks();
+        // Bit 8 of the address is clear, so this enables the RAM
+        mbc2.write_byte(0x0000, 0x0A);
+        assert!(mbc2.write_byte(0xA000, 0x5A));
+        assert_eq!(mbc2.read_byte(0xA000), 0xFA);
+        // The 512 half bytes are echoed across A000-BFFF
+        assert_eq!(mbc2.read_byte(0xA200), 0xFA);
+        assert_eq!(mbc2.read_byte(0xBE00), 0xFA);
+
+        mbc2.write_byte(0x0000, 0x00);
+        assert_eq!(mbc2.read_byte(0xA000), 0xFF);
+        assert!(!mbc2.write_byte(0xA000, 0x03));
+        assert_eq!(mbc2.peek_byte(0xA000), 0xFA);
+    }
+
+    #[test]
+    fn writes_with_address_bit_8_set_select_the_rom_bank() {
+        let mut mbc2 = mbc2_with_four_rom_banks();
+        mbc2.write_byte(0x0100, 0x02);
+        assert_eq!(mbc2.read_byte(0x4000), 2);
+        // Only the lower 4 bits are used and bank 0 is mapped to bank 1
+        mbc2.write_byte(0x2100, 0xF0);
+        assert_eq!(mbc2.read_byte(0x4000), 1);
+        // A write with bit 8 set does not enable the RAM
+        mbc2.write_byte(0x0100, 0x0A);
+        assert_eq!(mbc2.read_byte(0xA000), 0xFF);
+        assert_eq!(mbc2.read_byte(0x4000), 2);
+    }
+}
@@ -0,0 +1,53 @@
+//! This module contains the [MemoryMappedDevice] trait, which is implemented by the components
+//! of the RustBoy that own their I/O registers instead of storing them in the flat memory array
+//! of the [MemoryBus].
+//!
+//! Reads and writes of the I/O registers (0xFF00 - 0xFF7F) are first offered to the devices
+//! returned by [MemoryBus::memory_mapped_devices]. Only if none of them maps the address, the
+//! access falls back to the special handling in [MemoryBus::read_io_register] and
+//! [MemoryBus::write_byte] or the flat memory array. This way, a component can be moved out of the
+//! memory bus one at a time. So far, only the timer registers are such a device, see
+//! [TimerRegisters](crate::timer::TimerRegisters).
+
+use super::MemoryBus;
+
+/// A device which owns some of the I/O registers of the RustBoy.
+pub(crate) trait MemoryMappedDevice {
+    /// Reads the register at the provided address. Returns None, if the address is not mapped to
+    /// this device. The unused bits of the register do not have to be set, since this is done by
+    /// [MemoryBus::read_io_register] for all I/O registers.
+    fn read(&self, address: u16) -> Option<u8>;
+
+    /// Writes the register at the provided address. Returns false, if the address is not mapped
+    /// to this device, in which case nothing is written.
+    fn write(&mut self, address: u16, value: u8) -> bool;
+}
+
+impl MemoryBus {
+    /// Returns the devices which are registered on the memory bus, see [MemoryMappedDevice].
+    fn memory_mapped_devices(&self) -> [&dyn MemoryMappedDevice; 1] {
+        [&self.timer_registers]
+    }
+
+    /// Returns the devices which are registered on the memory bus mutably, in the same order as
+    /// [MemoryBus::memory_mapped_devices].
+    fn memory_mapped_devices_mut(&mut self) -> [&mut dyn MemoryMappedDevice; 1] {
+        [&mut self.timer_registers]
+    }
+
+    /// Reads the register at the provided address from the first registered device which maps
+    /// it. Returns None, if no device maps the address.
+    pub(super) fn read_memory_mapped_devices(&self, address: u16) -> Option<u8> {
+        self.memory_mapped_devices()
+            .into_iter()
+            .find_map(|device| device.read(address))
+    }
+
+    /// Writes the register at the provided address to the first registered device which maps it.
+    /// Returns false, if no device maps the address.
+    pub(super) fn write_memory_mapped_devices(&mut self, address: u16, value: u8) -> bool {
+        self.memory_mapped_devices_mut()
+            .into_iter()
+            .any(|device| device.write(address, value))
+    }
+}
@@ -0,0 +1,157 @@
+//! This module contains the [OamDma] struct, which emulates the OAM DMA transfer. Writing to the
+//! DMA register (0xFF46) copies the 160 bytes starting at the written value times 0x100 to the
+//! object attribute memory (OAM), one byte per m-cycle. For more information, please refer to
+//! [Pan Docs - OAM DMA Transfer](https://gbdev.io/pandocs/OAM_DMA_Transfer.html).
+//!
+//! While the transfer is running, the DMA occupies the memory bus, so the CPU can only access the
+//! high RAM (HRAM 0xFF80 - 0xFFFE). Reads of any other address return 0xFF and writes are
+//! ignored, see [MemoryBus::oam_dma_blocks_access]. This is why games run the routine which starts
+//! the transfer and waits for it to complete from HRAM.
+
+use super::{ECHO_RAM_BEGIN, ECHO_RAM_OFFSET, MemoryBus, OAM_END, OAM_START};
+use crate::RustBoyError;
+use crate::save_state::{StateReader, StateWriter};
+
+/// The first address of the high RAM, which the CPU can still access during the transfer.
+const HIGH_RAM_BEGIN: u16 = 0xFF80;
+/// The last address of the high RAM.
+const HIGH_RAM_END: u16 = 0xFFFE;
+/// The number of bytes copied by each transfer, which is the size of the OAM. One byte is copied
+/// per m-cycle.
+const TRANSFER_LENGTH: u16 = OAM_END - OAM_START + 1;
+
+/// Struct to represent a running OAM DMA transfer.
+///
+/// - `source`: The address the transfer copies from, which is the value written to the DMA
+///   register times 0x100.
+/// - `bytes_copied`: The number of bytes which were already copied to the OAM.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct OamDma {
+    source: u16,
+    bytes_copied: u16,
+}
+
+impl OamDma {
+    /// Writes the state of the provided transfer to the provided save state. Is None, if no
+    /// transfer is running.
+    pub(crate) fn save_state(oam_dma: &Option<OamDma>, writer: &mut StateWriter) {
+        writer.write_bool(oam_dma.is_some());
+        if let Some(oam_dma) = oam_dma {
+            writer.write_u16(oam_dma.source);
+            writer.write_u16(oam_dma.bytes_copied);
+        }
+    }
+
+    /// Restores the state of the transfer written by [OamDma::save_state].
+    pub(crate) fn load_state(reader: &mut StateReader) -> Result<Option<OamDma>, RustBoyError> {
+        if !reader.read_bool()? {
+            return Ok(None);
+        }
+        Ok(Some(OamDma {
+            source: reader.read_u16()? & 0xFF00,
+            bytes_copied: reader.read_u16()?.min(TRANSFER_LENGTH),
+        }))
+    }
+}
+
+impl MemoryBus {
+    /// Starts a transfer from the provided value of the DMA register times 0x100. A transfer which
+    /// is still running is restarted from the new source.
+    pub(super) fn start_oam_dma(&mut self, value: u8) {
+        self.oam_dma = Some(OamDma {
+            source: (value as u16) << 8,
+            bytes_copied: 0,
+        });
+    }
+
+    /// Advances a running transfer by the provided number of m-cycles, copying one byte to the
+    /// OAM per m-cycle. Once all bytes are copied, the CPU can access the whole memory again.
+    pub(crate) fn handle_oam_dma(&mut self, m_cycles: u32) {
+        let Some(mut oam_dma) = self.oam_dma else {
+            return;
+        };
+        for _ in 0..m_cycles {
+            if oam_dma.bytes_copied == TRANSFER_LENGTH {
+                break;
+            }
+            let mut source = oam_dma.source + oam_dma.bytes_copied;
+            // From 0xE000 on, the DMA reads the mirrored work RAM like the echo RAM. This
+            // includes 0xFE00 - 0xFFFF, since the DMA can not read the OAM and the I/O registers
+            if source >= ECHO_RAM_BEGIN {
+                source -= ECHO_RAM_OFFSET;
+            }
+            self.memory[(OAM_START + oam_dma.bytes_copied) as usize] =
+                self.read_byte_ignoring_oam_dma(source);
+            oam_dma.bytes_copied += 1;
+        }
+        self.oam_dma = (oam_dma.bytes_copied < TRANSFER_LENGTH).then_some(oam_dma);
+    }
+
+    /// Returns true if the CPU can not access the provided address, because an OAM DMA transfer is
+    /// running. Only the high RAM can be accessed during the transfer.
+    pub(super) fn oam_dma_blocks_access(&self, address: u16) -> bool {
+        self.oam_dma.is_some() && !(HIGH_RAM_BEGIN..=HIGH_RAM_END).contains(&address)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::debugging::DebugInfo;
+
+    const DMA_REGISTER_ADDRESS: u16 = 0xFF46;
+    const WORK_RAM_BEGIN: u16 = 0xC000;
+
+    /// Returns a memory bus whose work RAM from 0xC000 on holds the bytes 0, 1, 2, ...
+    fn memory_bus_with_work_ram_pattern() -> MemoryBus {
+        let mut memory_bus = MemoryBus::new_before_boot(&DebugInfo::default());
+        memory_bus.being_initialized = false;
+        for offset in 0..TRANSFER_LENGTH {
+            memory_bus.write_byte(WORK_RAM_BEGIN + offset, offset as u8);
+        }
+        memory_bus
+    }
+
+    #[test]
+    fn transfer_copies_one_byte_per_m_cycle() {
+        let mut memory_bus = memory_bus_with_work_ram_pattern();
+        memory_bus.write_byte(DMA_REGISTER_ADDRESS, (WORK_RAM_BEGIN >> 8) as u8);
+        memory_bus.handle_oam_dma(10);
+        assert_eq!(memory_bus.memory[(OAM_START + 9) as usize], 9);
+        assert_eq!(memory_bus.memory[(OAM_START + 10) as usize], 0);
+
+        memory_bus.handle_oam_dma(TRANSFER_LENGTH as u32 - 10);
+        assert!(memory_bus.oam_dma.is_none());
+        for offset in 0..TRANSFER_LENGTH {
+            assert_eq!(memory_bus.read_byte(OAM_START + offset), offset as u8);
+        }
+    }
+
+    #[test]
+    fn cpu_can_only_access_high_ram_during_the_transfer() {
+        let mut memory_bus = memory_bus_with_work_ram_pattern();
+        memory_bus.write_byte(DMA_REGISTER_ADDRESS, (WORK_RAM_BEGIN >> 8) as u8);
+        memory_bus.handle_oam_dma(1);
+
+        assert_eq!(memory_bus.read_byte(WORK_RAM_BEGIN + 1), 0xFF);
+        memory_bus.write_byte(WORK_RAM_BEGIN + 1, 0x42);
+        for address in [HIGH_RAM_BEGIN, HIGH_RAM_END] {
+            memory_bus.write_byte(address, 0x42);
+            assert_eq!(memory_bus.read_byte(address), 0x42);
+        }
+
+        memory_bus.handle_oam_dma(TRANSFER_LENGTH as u32);
+        assert_eq!(memory_bus.read_byte(WORK_RAM_BEGIN + 1), 1);
+        assert_eq!(memory_bus.read_byte(OAM_START + 1), 1);
+    }
+
+    #[test]
+    fn transfer_from_echo_ram_reads_the_work_ram() {
+        let mut memory_bus = memory_bus_with_work_ram_pattern();
+        memory_bus.write_byte(DMA_REGISTER_ADDRESS, (ECHO_RAM_BEGIN >> 8) as u8);
+        memory_bus.handle_oam_dma(TRANSFER_LENGTH as u32);
+        for offset in 0..TRANSFER_LENGTH {
+            assert_eq!(memory_bus.read_byte(OAM_START + offset), offset as u8);
+        }
+    }
+}
@@ -0,0 +1,238 @@
+//! This module contains the built-in database of known ROMs, which tweaks the emulation for
+//! specific games, so they work without the user having to configure anything. The ROMs are
+//! identified by the CRC32 of their data (as listed in the No-Intro DAT files), since the
+//! cartridge header does not distinguish between revisions and is wrong for some games.
+//!
+//! To add a game, add a [KnownRom] to [KNOWN_ROMS] with the tweaks it needs. Tweaks which are
+//! not needed are left at their default, see [GameTweaks::NONE].
+
+/// The tweaks to the emulation applied to a known ROM. The defaults (see [GameTweaks::NONE])
+/// emulate the cartridge as declared by its header.
+///
+/// - `ram_bank_count`: The number of external RAM banks to allocate instead of the number
+///   declared by the cartridge header, if Some. The `--RAM-BANKS` option takes precedence.
+/// - `sgb_palettes`: Whether the Super Game Boy palettes are emulated, if the cartridge supports
+///   Super Game Boy functions. If false, the game is shown in the plain DMG palette instead.
+/// - `cgb_features`: Whether the Game Boy Color features (e.g. the switchable work RAM banks) are
+///   emulated, if the cartridge supports the Game Boy Color. If false, the game runs like on the
+///   DMG, which it supports as well.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct GameTweaks {
+    pub(crate) ram_bank_count: Option<u8>,
+    pub(crate) sgb_palettes: bool,
+    pub(crate) cgb_features: bool,
+}
+
+impl GameTweaks {
+    /// The tweaks of a ROM which is not in the database, that is, none.
+    pub(crate) const NONE: GameTweaks = GameTweaks {
+        ram_bank_count: None,
+        sgb_palettes: true,
+        cgb_features: true,
+    };
+}
+
+impl Default for GameTweaks {
+    fn default() -> Self {
+        GameTweaks::NONE
+    }
+}
+
+/// An entry of the database.
+///
+/// - `crc32`: The CRC32 of the ROM data, see [crc32].
+/// - `name`: The name of the game, which is logged when the tweaks are applied.
+/// - `tweaks`: The tweaks applied to the ROM.
+struct KnownRom {
+    crc32: u32,
+    name: &'static str,
+    tweaks: GameTweaks,
+}
+
+/// The ROMs which need tweaks to the emulation.
+const KNOWN_ROMS: &[KnownRom] = &[
+    // The CGB flag is only set, so the test also runs on the Game Boy Color. It tests the
+    // instructions of the DMG, so there is no need for the Game Boy Color features
+    KnownRom {
+        crc32: 0xB074_356D,
+        name: "Blargg's cpu_instrs",
+        tweaks: GameTweaks {
+            cgb_features: false,
+            ..GameTweaks::NONE
+        },
+    },
+    // The single tests of cpu_instrs, which are run by the CI, have the CGB flag set as well
+    KnownRom {
+        crc32: 0xF698_A9CE,
+        name: "Blargg's cpu_instrs (01-special)",
+        tweaks: GameTweaks {
+            cgb_features: false,
+            ..GameTweaks::NONE
+        },
+    },
+    KnownRom {
+        crc32: 0x1E9B_789B,
+        name: "Blargg's cpu_instrs (02-interrupts)",
+        tweaks: GameTweaks {
+            cgb_features: false,
+            ..GameTweaks::NONE
+        },
+    },
+    KnownRom {
+        crc32: 0x1391_53F1,
+        name: "Blargg's cpu_instrs (03-op sp,hl)",
+        tweaks: GameTweaks {
+            cgb_features: false,
+            ..GameTweaks::NONE
+        },
+    },
+    KnownRom {
+        crc32: 0x121D_37F1,
+        name: "Blargg's cpu_instrs (04-op r,imm)",
+        tweaks: GameTweaks {
+            cgb_features: false,
+            ..GameTweaks::NONE
+        },
+    },
+    KnownRom {
+        crc32: 0x004F_C12B,
+        name: "Blargg's cpu_instrs (05-op rp)",
+        tweaks: GameTweaks {
+            cgb_features: false,
+            ..GameTweaks::NONE
+        },
+    },
+    KnownRom {
+        crc32: 0x12E6_5772,
+        name: "Blargg's cpu_instrs (06-ld r,r)",
+        tweaks: GameTweaks {
+            cgb_features: false,
+            ..GameTweaks::NONE
+        },
+    },
+    KnownRom {
+        crc32: 0x8E89_8AEB,
+        name: "Blargg's cpu_instrs (07-jr,jp,call,ret,rst)",
+        tweaks: GameTweaks {
+            cgb_features: false,
+            ..GameTweaks::NONE
+        },
+    },
+    KnownRom {
+        crc32: 0xC704_3A26,
+        name: "Blargg's cpu_instrs (08-misc instrs)",
+        tweaks: GameTweaks {
+            cgb_features: false,
+            ..GameTweaks::NONE
+        },
+    },
+    KnownRom {
+        crc32: 0x0AB9_DB2F,
+        name: "Blargg's cpu_instrs (09-op r,r)",
+        tweaks: GameTweaks {
+            cgb_features: false,
+            ..GameTweaks::NONE
+        },
+    },
+    KnownRom {
+        crc32: 0x1202_7F22,
+        name: "Blargg's cpu_instrs (10-bit ops)",
+        tweaks: GameTweaks {
+            cgb_features: false,
+            ..GameTweaks::NONE
+        },
+    },
+    KnownRom {
+        crc32: 0xD9B2_EF4C,
+        name: "Blargg's cpu_instrs (11-op a,(hl))",
+        tweaks: GameTweaks {
+            cgb_features: false,
+            ..GameTweaks::NONE
+        },
+    },
+];
+
+/// Returns the name and the tweaks of the provided ROM, if it is in the database.
+pub(crate) fn look_up(rom_data: &[u8]) -> Option<(&'static str, GameTweaks)> {
+    let crc32 = crc32(rom_data);
+    KNOWN_ROMS
+        .iter()
+        .find(|known_rom| known_rom.crc32 == crc32)
+        .map(|known_rom| (known_rom.name, known_rom.tweaks))
+}
+
+/// The lookup table of the CRC32 for each value of a byte, computed at compile time.
+const CRC32_TABLE: [u32; 256] = crc32_table();
+
+/// The reversed polynomial of the CRC32 (as used by zip and No-Intro).
+const CRC32_POLYNOMIAL: u32 = 0xEDB8_8320;
+
+/// Computes the lookup table of the CRC32 for each value of a byte.
+const fn crc32_table() -> [u32; 256] {
+    let mut table = [0; 256];
+    let mut byte = 0;
+    while byte < 256 {
+        let mut crc = byte as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ CRC32_POLYNOMIAL
+            } else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+        table[byte] = crc;
+        byte += 1;
+    }
+    table
+}
+
+/// Returns the CRC32 of the provided data.
+fn crc32(data: &[u8]) -> u32 {
+    !data.iter().fold(u32::MAX, |crc, &byte| {
+        CRC32_TABLE[((crc ^ byte as u32) & 0xFF) as usize] ^ (crc >> 8)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn crc32_matches_the_check_value() {
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+    }
+
+    #[test]
+    fn bundled_cpu_instrs_roms_are_found() {
+        let roms: [&[u8]; 12] = [
+            include_bytes!("../../roms/test_roms/cpu_instrs.gb"),
+            include_bytes!("../../roms/test_roms/blarggs/cpu_instrs/01-special.gb"),
+            include_bytes!("../../roms/test_roms/blarggs/cpu_instrs/02-interrupts.gb"),
+            include_bytes!("../../roms/test_roms/blarggs/cpu_instrs/03-op sp,hl.gb"),
+            include_bytes!("../../roms/test_roms/blarggs/cpu_instrs/04-op r,imm.gb"),
+            include_bytes!("../../roms/test_roms/blarggs/cpu_instrs/05-op rp.gb"),
+            include_bytes!("../../roms/test_roms/blarggs/cpu_instrs/06-ld r,r.gb"),
+            include_bytes!("../../roms/test_roms/blarggs/cpu_instrs/07-jr,jp,call,ret,rst.gb"),
+            include_bytes!("../../roms/test_roms/blarggs/cpu_instrs/08-misc instrs.gb"),
+            include_bytes!("../../roms/test_roms/blarggs/cpu_instrs/09-op r,r.gb"),
+            include_bytes!("../../roms/test_roms/blarggs/cpu_instrs/10-bit ops.gb"),
+            include_bytes!("../../roms/test_roms/blarggs/cpu_instrs/11-op a,(hl).gb"),
+        ];
+        for rom_data in roms {
+            let (name, tweaks) = look_up(rom_data).expect("The ROM should be in the database");
+            assert!(name.starts_with("Blargg's cpu_instrs"));
+            assert!(!tweaks.cgb_features);
+        }
+    }
+
+    #[test]
+    fn unknown_roms_are_not_found() {
+        assert_eq!(
+            look_up(include_bytes!("../../roms/test_roms/dmg-acid2.gb")),
+            None
+        );
+        assert_eq!(look_up(&[0; 0x8000]), None);
+    }
+}
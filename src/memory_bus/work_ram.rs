@@ -0,0 +1,150 @@
+//! This module contains the [WorkRamBanks] struct, which implements the work RAM (WRAM) banking
+//! of the Game Boy Color (CGB). The CGB has 8 WRAM banks of 4 KiB each. Bank 0 is fixed at
+//! 0xC000 - 0xCFFF, while one of the banks 1 - 7 is mapped to 0xD000 - 0xDFFF, which is selected
+//! using the SVBK register (0xFF70). Writing 0 to SVBK selects bank 1 as well. For more information,
+//! please refer to [Pan Docs - SVBK](https://gbdev.io/pandocs/CGB_Registers.html#ff70--svbk-cgb-mode-only-wram-bank).
+//!
+//! The currently selected bank is kept in the main memory of the [MemoryBus], so reads and writes
+//! (including those to the echo RAM) work the same as on the DMG. Only when another bank is
+//! selected, the banks are swapped.
+
+use super::MemoryBus;
+use crate::RustBoyError;
+use crate::save_state::{StateReader, StateWriter};
+
+pub(super) const WORK_RAM_BANK_REGISTER: u16 = 0xFF70;
+const SWITCHABLE_WORK_RAM_BEGIN: usize = 0xD000;
+const WORK_RAM_BANK_SIZE: usize = 0x1000;
+/// The number of switchable WRAM banks (1 - 7).
+const NUMBER_OF_SWITCHABLE_BANKS: usize = 7;
+/// The bits of SVBK which select the bank. The other bits always read as 1.
+const WORK_RAM_BANK_BITS: u8 = 0b0000_0111;
+
+/// Struct to store the switchable WRAM banks of the CGB. The bank at index `i` is bank `i + 1`.
+/// The entry of the currently selected bank is stale, since that bank is kept in the main memory.
+#[derive(Debug)]
+pub(crate) struct WorkRamBanks {
+    banks: Box<[[u8; WORK_RAM_BANK_SIZE]; NUMBER_OF_SWITCHABLE_BANKS]>,
+}
+
+impl WorkRamBanks {
+    /// Creates a new instance of WorkRamBanks with all banks set to 0.
+    pub(crate) fn new() -> WorkRamBanks {
+        WorkRamBanks {
+            banks: Box::new([[0; WORK_RAM_BANK_SIZE]; NUMBER_OF_SWITCHABLE_BANKS]),
+        }
+    }
+
+    /// Writes the banks to the provided save state, see [crate::save_state]. The selected bank is
+    /// part of the main memory and SVBK, which are saved by [MemoryBus::save_state].
+    pub(crate) fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_bytes(self.banks.as_flattened());
+    }
+
+    /// Restores the banks written by [WorkRamBanks::save_state].
+    pub(crate) fn load_state(&mut self, reader: &mut StateReader) -> Result<(), RustBoyError> {
+        reader.read_bytes_into(self.banks.as_flattened_mut())
+    }
+}
+
+/// Returns the bank selected by the provided value of SVBK. A value of 0 selects bank 1.
+fn selected_bank(register_value: u8) -> usize {
+    ((register_value & WORK_RAM_BANK_BITS) as usize).max(1)
+}
+
+impl MemoryBus {
+    /// Reads the SVBK register (0xFF70). Returns None, if the cartridge does not support the CGB,
+    /// in which case the register does not exist.
+    pub(super) fn read_work_ram_bank_register(&self) -> Option<u8> {
+        self.work_ram_banks.as_ref()?;
+        Some(self.memory[WORK_RAM_BANK_REGISTER as usize] | !WORK_RAM_BANK_BITS)
+    }
+
+    /// Writes the SVBK register (0xFF70) and maps the selected bank to 0xD000 - 0xDFFF. Writes
+    /// are ignored, if the cartridge does not support the CGB.
+    pub(super) fn write_work_ram_bank_register(&mut self, value: u8) {
+        let Some(work_ram_banks) = &mut self.work_ram_banks else {
+            return;
+        };
+        let previous_bank = selected_bank(self.memory[WORK_RAM_BANK_REGISTER as usize]);
+        let new_bank = selected_bank(value);
+        self.memory[WORK_RAM_BANK_REGISTER as usize] = value & WORK_RAM_BANK_BITS;
+        if previous_bank != new_bank {
+            let mapped_bank = &mut self.memory
+                [SWITCHABLE_WORK_RAM_BEGIN..SWITCHABLE_WORK_RAM_BEGIN + WORK_RAM_BANK_SIZE];
+            work_ram_banks.banks[previous_bank - 1].copy_from_slice(mapped_bank);
+            mapped_bank.copy_from_slice(&work_ram_banks.banks[new_bank - 1]);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::debugging::DebugInfo;
+
+    /// Returns a memory bus with an empty ROM without MBC loaded, which supports the Game Boy
+    /// Color, if `cgb` is true.
+    fn memory_bus_with_rom(cgb: bool) -> MemoryBus {
+        let mut rom_data = vec![0; 0x8000];
+        if cgb {
+            rom_data[0x0143] = 0x80;
+        }
+        let mut memory_bus = MemoryBus::new_before_boot(&DebugInfo::default());
+        memory_bus
+            .load_program(&rom_data)
+            .expect("The ROM should be loadable");
+        memory_bus.being_initialized = false;
+        memory_bus
+    }
+
+    #[test]
+    fn each_bank_keeps_its_own_values() {
+        let mut memory_bus = memory_bus_with_rom(true);
+        memory_bus.write_byte(0xC000, 0xC0);
+        for bank in 1..=7 {
+            memory_bus.write_byte(WORK_RAM_BANK_REGISTER, bank);
+            memory_bus.write_byte(0xD000, 0xD0 + bank);
+        }
+        for bank in (1..=7).rev() {
+            memory_bus.write_byte(WORK_RAM_BANK_REGISTER, bank);
+            assert_eq!(memory_bus.read_byte(0xD000), 0xD0 + bank);
+            // Bank 0 is fixed
+            assert_eq!(memory_bus.read_byte(0xC000), 0xC0);
+        }
+    }
+
+    #[test]
+    fn bank_zero_selects_bank_one() {
+        let mut memory_bus = memory_bus_with_rom(true);
+        memory_bus.write_byte(WORK_RAM_BANK_REGISTER, 1);
+        memory_bus.write_byte(0xD000, 0x11);
+        memory_bus.write_byte(WORK_RAM_BANK_REGISTER, 2);
+        memory_bus.write_byte(0xD000, 0x22);
+        memory_bus.write_byte(WORK_RAM_BANK_REGISTER, 0);
+        assert_eq!(memory_bus.read_byte(0xD000), 0x11);
+        // The unused bits read as 1
+        assert_eq!(memory_bus.read_byte(WORK_RAM_BANK_REGISTER), 0xF8);
+    }
+
+    #[test]
+    fn echo_ram_follows_the_selected_bank() {
+        let mut memory_bus = memory_bus_with_rom(true);
+        memory_bus.write_byte(WORK_RAM_BANK_REGISTER, 2);
+        memory_bus.write_byte(0xD123, 0x22);
+        memory_bus.write_byte(WORK_RAM_BANK_REGISTER, 3);
+        memory_bus.write_byte(0xF123, 0x33);
+        assert_eq!(memory_bus.read_byte(0xD123), 0x33);
+        memory_bus.write_byte(WORK_RAM_BANK_REGISTER, 2);
+        assert_eq!(memory_bus.read_byte(0xF123), 0x22);
+    }
+
+    #[test]
+    fn there_is_no_banking_without_cgb_support() {
+        let mut memory_bus = memory_bus_with_rom(false);
+        memory_bus.write_byte(0xD000, 0x11);
+        memory_bus.write_byte(WORK_RAM_BANK_REGISTER, 2);
+        assert_eq!(memory_bus.read_byte(0xD000), 0x11);
+        assert_eq!(memory_bus.read_byte(WORK_RAM_BANK_REGISTER), 0xFF);
+    }
+}
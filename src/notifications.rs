@@ -0,0 +1,134 @@
+//! This module contains the [Notifications] struct, which holds the notifications of a RustBoy.
+//! These are short messages shown on top of the Game Boy screen for a moment after an action, e.g.
+//! "Saved state to slot 2" after pressing `Shift + 2`.
+//!
+//! Each RustBoy has its own notifications, to which it and the frontend post using
+//! [Notifications::post]. The frontend draws the ones which are still visible with every
+//! presented frame, see [TextOverlay](crate::frontend::text_overlay::TextOverlay). Every
+//! notification is also logged, so it is not lost if there is no window (e.g. in headless mode).
+//! Without the `frontend` feature, notifications are only logged.
+
+#[cfg(feature = "frontend")]
+use std::collections::VecDeque;
+#[cfg(feature = "frontend")]
+use std::time::Duration;
+
+#[cfg(feature = "frontend")]
+use wasm_timer::Instant;
+
+/// The time a notification is shown at full opacity, after which it starts to fade out.
+#[cfg(feature = "frontend")]
+const NOTIFICATION_DURATION: Duration = Duration::from_millis(2000);
+/// The time a notification takes to fade out after [NOTIFICATION_DURATION].
+#[cfg(feature = "frontend")]
+const NOTIFICATION_FADE_OUT_DURATION: Duration = Duration::from_millis(500);
+/// The maximum number of notifications shown at once. If more are posted, the oldest ones are
+/// dropped.
+#[cfg(feature = "frontend")]
+const MAX_NOTIFICATIONS: usize = 4;
+
+/// Struct to represent the notifications posted to a RustBoy.
+///
+/// - `queue`: The notifications which were posted, ordered from oldest to newest. Expired
+///   notifications are removed when the visible notifications are requested, see
+///   [Notifications::visible].
+#[derive(Default)]
+pub(crate) struct Notifications {
+    #[cfg(feature = "frontend")]
+    queue: VecDeque<Notification>,
+}
+
+/// A message posted using [Notifications::post].
+///
+/// - `message`: The message to show.
+/// - `posted_at`: The time the message was posted, which determines when it fades out.
+#[cfg(feature = "frontend")]
+struct Notification {
+    message: String,
+    posted_at: Instant,
+}
+
+impl Notifications {
+    /// Posts the provided message, which is then shown on top of the Game Boy screen for a moment
+    /// and logged (at info level).
+    pub(crate) fn post(&mut self, message: impl Into<String>) {
+        let message = message.into();
+        log::info!("{message}");
+        #[cfg(feature = "frontend")]
+        {
+            if self.queue.len() == MAX_NOTIFICATIONS {
+                self.queue.pop_front();
+            }
+            self.queue.push_back(Notification {
+                message,
+                posted_at: Instant::now(),
+            });
+        }
+    }
+
+    /// Returns the messages of the notifications which are currently visible, ordered from oldest
+    /// to newest, together with their opacity (between 0.0 and 1.0). Notifications which have
+    /// faded out completely are removed.
+    #[cfg(feature = "frontend")]
+    pub(crate) fn visible(&mut self) -> Vec<(String, f32)> {
+        self.queue.retain(|notification| {
+            notification.posted_at.elapsed()
+                < NOTIFICATION_DURATION + NOTIFICATION_FADE_OUT_DURATION
+        });
+        self.queue
+            .iter()
+            .map(|notification| {
+                let fade_out_progress = notification
+                    .posted_at
+                    .elapsed()
+                    .saturating_sub(NOTIFICATION_DURATION)
+                    .as_secs_f32()
+                    / NOTIFICATION_FADE_OUT_DURATION.as_secs_f32();
+                (
+                    notification.message.clone(),
+                    (1.0 - fade_out_progress).clamp(0.0, 1.0),
+                )
+            })
+            .collect()
+    }
+}
+
+#[cfg(all(test, feature = "frontend"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_the_newest_notifications_are_kept() {
+        let mut notifications = Notifications::default();
+        for i in 0..MAX_NOTIFICATIONS + 2 {
+            notifications.post(format!("Notification {i}"));
+        }
+        let messages: Vec<String> = notifications
+            .visible()
+            .into_iter()
+            .map(|(message, opacity)| {
+                assert_eq!(opacity, 1.0);
+                message
+            })
+            .collect();
+        let expected: Vec<String> = (2..MAX_NOTIFICATIONS + 2)
+            .map(|i| format!("Notification {i}"))
+            .collect();
+        assert_eq!(messages, expected);
+    }
+
+    #[test]
+    fn expired_notifications_are_removed() {
+        let mut notifications = Notifications::default();
+        notifications.post("Expired");
+        notifications.queue[0].posted_at -= NOTIFICATION_DURATION + NOTIFICATION_FADE_OUT_DURATION;
+        notifications.post("Fading out");
+        notifications.queue[1].posted_at -=
+            NOTIFICATION_DURATION + NOTIFICATION_FADE_OUT_DURATION / 2;
+        let visible = notifications.visible();
+        assert_eq!(visible.len(), 1);
+        assert_eq!(visible[0].0, "Fading out");
+        assert!(visible[0].1 > 0.0 && visible[0].1 <= 0.5);
+        assert_eq!(notifications.queue.len(), 1);
+    }
+}
@@ -8,14 +8,17 @@
 
 pub(crate) mod information_for_shader;
 pub(crate) mod object_handling;
+pub(crate) mod pixel_fifo;
 pub mod registers;
 pub(crate) mod tile_handling;
 
-use crate::MemoryBus;
 use crate::interrupts::{Interrupt, InterruptFlagRegister};
 use crate::memory_bus::is_bit_set;
 use crate::ppu::registers::LCDCRegister;
+use crate::save_state::{StateReader, StateWriter};
+use crate::{MemoryBus, RustBoyError};
 use information_for_shader::BuffersForRendering;
+use pixel_fifo::PixelFifo;
 use registers::PPURegisters;
 
 const TILE_DATA_BLOCK_0_START: usize = 0x8000;
@@ -35,6 +38,15 @@ const DOTS_IN_OAM_SCAN: u32 = 80;
 /// The number of dots (PPU cycles) in the VBlank Mode.
 pub(crate) const DOTS_IN_VBLANK: u32 = 4560;
 
+/// The number of dots (PPU cycles) of a whole frame, that is, of 154 scanlines of 456 dots.
+pub(crate) const DOTS_PER_FRAME: u32 = 70224;
+
+/// The number of dots during the last line of VBlank (153) after which LY already reads 0.
+const DOTS_WITH_LY_153: u32 = 4;
+
+/// The number of dots the PPU advances per m-cycle of the CPU on the hardware.
+pub(crate) const DOTS_PER_M_CYCLE: u32 = 4;
+
 /// The PPU mode the PPU is in when the LCD is turned off.
 pub(crate) const PPU_MODE_WHILE_LCD_TURNED_OFF: RenderingMode = RenderingMode::HBlank0;
 
@@ -43,6 +55,11 @@ pub(crate) const PPU_MODE_WHILE_LCD_TURNED_OFF: RenderingMode = RenderingMode::H
 /// - `rendering_info`: Contains information about the current rendering state of the PPU, such as
 ///     the number of dots (cycles) elapsed and flags for window rendering.
 /// - `buffers_for_rendering`: Buffers used for the shaders, including tile and object data.
+/// - `pixel_fifo`: The dot-accurate pixel FIFO renderer, if it is used instead of the scanline
+///   shader. See [PixelFifo].
+/// - `dots_per_m_cycle`: The number of dots the PPU advances per m-cycle of the CPU. Is
+///   [DOTS_PER_M_CYCLE], unless changed for debugging (see the `--DOT-SCALE` option), which
+///   breaks the timing accuracy on purpose.
 ///
 /// The PPU in the RustBoy has a video RAM (VRAM) of 8KB (0x8000 - 0x9FFF), which contains:
 /// - A tile set with 384 tiles, stored as a 2D array of 8x8 tile pixel values for easier access.
@@ -54,6 +71,8 @@ pub(crate) const PPU_MODE_WHILE_LCD_TURNED_OFF: RenderingMode = RenderingMode::H
 pub struct PPU {
     pub(crate) rendering_info: RenderingInfo,
     pub(crate) buffers_for_rendering: BuffersForRendering,
+    pub(crate) pixel_fifo: Option<PixelFifo>,
+    pub(crate) dots_per_m_cycle: u32,
 }
 
 /// Struct to collect the information about the current rendering state of the PPU.
@@ -101,11 +120,20 @@ pub(crate) enum RenderingMode {
     Transfer3,
 }
 
-/// Represents the possible tasks of the PPU.
+/// Represents the possible tasks of the PPU, which tell the frontend what to render after a step.
+///
+/// - `None`: Nothing has to be rendered.
+/// - `WriteLineToBuffer`: The scanline with the provided number (0 - 142) was completed and can
+///   be written to the framebuffer.
+/// - `RenderFrame`: The last scanline (143) was completed and the PPU entered VBlank, so the
+///   frame can be presented.
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum RenderTask {
+    /// Nothing has to be rendered.
     None,
+    /// The scanline with the provided number was completed.
     WriteLineToBuffer(u8),
+    /// The frame was completed.
     RenderFrame,
 }
 
@@ -126,10 +154,17 @@ impl PPU {
             if self.rendering_info.lcd_was_turned_off == false {
                 // If the LCD is not enabled, there is no rendering task and we can reset the PPU
                 // to its initial state. We only do this once when the LCD is turned off.
+                // No interrupts are requested while the LCD is off, in particular no VBlank
+                // interrupt, since the PPU does not advance to VBlank. A VBlank interrupt that was
+                // requested before the LCD was turned off stays pending, as on the real hardware.
                 self.rendering_info.dots_clock = 0;
                 self.rendering_info.dots_for_transfer = 0;
                 PPURegisters::set_ppu_mode(memory_bus, PPU_MODE_WHILE_LCD_TURNED_OFF);
                 PPURegisters::set_scanline(memory_bus, 0);
+                // The screen is blank while the LCD is off
+                if let Some(pixel_fifo) = &mut self.pixel_fifo {
+                    pixel_fifo.clear_framebuffer();
+                }
                 self.rendering_info.lcd_was_turned_off = true;
             }
             RenderTask::None
@@ -141,9 +176,19 @@ impl PPU {
                 // https://www.reddit.com/r/EmuDev/comments/1cykjdr/gameboy_ppu_timing_question/
                 // To make sure this irregularity is handled, we set the first_scanline_after_lcd_was_turned_on
                 // flag.
+                // The first line is still as long as any other line (80 + 172 + 204 dots with the
+                // scanline shader), so the first VBlank starts 144 * 456 = 65664 dots after the
+                // LCD was turned on and the first whole frame takes the usual 70224 dots.
+                // Since the PPU does not actually enter HBlank mode here (it rather performs an
+                // OAM scan which reports mode 0), no mode 0 STAT interrupt is requested. The
+                // mode 2 STAT interrupt is not requested for line 0 either, see
+                // https://github.com/LIJI32/SameBoy/blob/master/Core/display.c
                 // TODO: Possibly handle that first frame after turning on the LCD is not actually
                 // sent to the screen, but rather just a blank screen.
-                PPURegisters::set_ppu_mode(memory_bus, RenderingMode::HBlank0);
+                PPURegisters::reset_ppu_mode(memory_bus, RenderingMode::HBlank0);
+                // The LY=LYC comparison is evaluated immediately when the LCD is turned on, so
+                // if LYC is 0, the coincidence flag is set and possibly a STAT interrupt requested
+                PPURegisters::set_scanline(memory_bus, 0);
                 self.rendering_info.first_scanline_after_lcd_was_turned_on = true;
                 self.rendering_info.lcd_was_turned_off = false;
             }
@@ -155,6 +200,11 @@ impl PPU {
                         // lasts [DOTS_IN_OAM_SCAN] dots and then enters Transfer mode.
                         if self.rendering_info.dots_clock >= DOTS_IN_OAM_SCAN {
                             self.rendering_info.dots_clock -= DOTS_IN_OAM_SCAN;
+                            self.fetch_objects_in_scanline_to_rendering_buffer(
+                                memory_bus,
+                                PPURegisters::get_scanline_internal(memory_bus),
+                            );
+                            self.start_pixel_fifo_scanline(memory_bus);
                             PPURegisters::set_ppu_mode(memory_bus, RenderingMode::Transfer3);
                             // We can now set the first_scanline_after_lcd_was_turned_on flag to
                             // false, since after this we are in Transfer mode and then regular
@@ -206,24 +256,24 @@ impl PPU {
                 RenderingMode::VBlank1 => {
                     if self.rendering_info.dots_clock >= DOTS_IN_VBLANK / 10 {
                         self.rendering_info.dots_clock -= DOTS_IN_VBLANK / 10;
-                        PPURegisters::set_scanline(
-                            memory_bus,
-                            PPURegisters::get_scanline_internal(memory_bus) + 1,
-                        );
-                        if PPURegisters::get_scanline_internal(memory_bus) == 154 {
+                        let current_scanline = PPURegisters::get_scanline_internal(memory_bus);
+                        if current_scanline != 0 {
+                            // Setting the scanline also compares it against LYC, so the
+                            // LY=LYC interrupt can be requested during VBlank as well
+                            PPURegisters::set_scanline(memory_bus, current_scanline + 1);
+                        } else {
+                            // LY is already 0 during the last line of VBlank (153), see below. So
+                            // it is not set again, which would request the LY=LYC interrupt for
+                            // LYC=0 a second time.
                             // On exiting VBlank, we update (reset) the window internal line counter
-                            self.rendering_info.update_window_internal_line_counter(
-                                memory_bus,
-                                // The current scanline is guaranteed to be 154 by the if condition
-                                154,
-                            );
+                            // by passing the scanline after the last line of VBlank
+                            self.rendering_info
+                                .update_window_internal_line_counter(memory_bus, 154, None);
                             // We also need to reset the wy_condition_was_triggered_this_frame and
                             // window_is_rendered_this_scanline flags for the next frame
                             self.rendering_info.wy_condition_was_met_this_frame = false;
                             self.rendering_info.window_is_rendered_this_scanline = false;
 
-                            PPURegisters::set_scanline(memory_bus, 0);
-
                             // Since we are now entering OAMScan2, we want to check whether
                             // the WY condition is met
                             self.rendering_info.check_wy_condition(
@@ -234,6 +284,15 @@ impl PPU {
                             PPURegisters::set_ppu_mode(memory_bus, RenderingMode::OAMScan2);
                         }
                     }
+                    // During the last line of VBlank, LY only reads 153 for the first m-cycle and
+                    // then already reads 0 for the rest of the line. So an LY=LYC interrupt for
+                    // LYC=0 is requested (almost) a whole line before the next frame starts, see
+                    // https://gbdev.io/pandocs/STAT.html#ff44--ly-lcd-y-coordinate-read-only
+                    if PPURegisters::get_scanline_internal(memory_bus) == 153
+                        && self.rendering_info.dots_clock >= DOTS_WITH_LY_153
+                    {
+                        PPURegisters::set_scanline(memory_bus, 0);
+                    }
                 }
                 RenderingMode::OAMScan2 => {
                     if self.rendering_info.dots_clock >= DOTS_IN_OAM_SCAN {
@@ -242,24 +301,48 @@ impl PPU {
                             memory_bus,
                             PPURegisters::get_scanline_internal(memory_bus),
                         );
+                        self.start_pixel_fifo_scanline(memory_bus);
 
                         PPURegisters::set_ppu_mode(memory_bus, RenderingMode::Transfer3);
                     }
                 }
                 RenderingMode::Transfer3 => {
-                    // TODO: Implement possible delay in this Mode if background scrolling or sprite fetching happened
-                    if self.rendering_info.dots_clock >= DOTS_IN_TRANSFER {
-                        self.rendering_info.dots_clock -= DOTS_IN_TRANSFER;
-                        self.rendering_info.dots_for_transfer = DOTS_IN_TRANSFER;
+                    let dots_in_transfer = match &mut self.pixel_fifo {
+                        // The pixel FIFO pushes the pixels dot by dot, so the length of the
+                        // Transfer mode depends on the scrolling, the window and the objects on
+                        // this scanline
+                        Some(pixel_fifo) => {
+                            pixel_fifo.step(memory_bus, self.rendering_info.dots_clock)
+                        }
+                        // TODO: Implement possible delay in this Mode if background scrolling or sprite fetching happened
+                        None => (self.rendering_info.dots_clock >= DOTS_IN_TRANSFER)
+                            .then_some(DOTS_IN_TRANSFER),
+                    };
+                    if let Some(dots_in_transfer) = dots_in_transfer {
+                        self.rendering_info.dots_clock -= dots_in_transfer;
+                        self.rendering_info.dots_for_transfer = dots_in_transfer;
                         let current_scanline = PPURegisters::get_scanline_internal(memory_bus);
                         // On exiting Transfer mode, before buffering the information for
                         // the next scanline, we update the window internal line counter
-                        self.rendering_info
-                            .update_window_internal_line_counter(memory_bus, current_scanline);
-                        self.fetch_rendering_information_to_rendering_buffer(
+                        // If the pixel FIFO is used, it knows whether the window was actually
+                        // reached on this scanline, even if it was toggled mid-scanline
+                        let window_was_fetched = self
+                            .pixel_fifo
+                            .as_ref()
+                            .map(PixelFifo::window_was_fetched_this_scanline);
+                        self.rendering_info.update_window_internal_line_counter(
                             memory_bus,
                             current_scanline,
+                            window_was_fetched,
                         );
+                        // The pixel FIFO already rendered the scanline, so we only need to buffer
+                        // the information for the scanline shader if it is used
+                        if self.pixel_fifo.is_none() {
+                            self.fetch_rendering_information_to_rendering_buffer(
+                                memory_bus,
+                                current_scanline,
+                            );
+                        }
 
                         PPURegisters::set_ppu_mode(memory_bus, RenderingMode::HBlank0);
                     }
@@ -291,11 +374,79 @@ impl PPU {
         }
     }
 
+    /// Prepares the pixel FIFO (if it is used) for rendering the current scanline. Is called
+    /// when entering Transfer mode (3).
+    fn start_pixel_fifo_scanline(&mut self, memory_bus: &MemoryBus) {
+        if let Some(pixel_fifo) = &mut self.pixel_fifo {
+            pixel_fifo.start_scanline(
+                memory_bus,
+                PPURegisters::get_scanline_internal(memory_bus),
+                &self.buffers_for_rendering.objects_in_scanline_buffer,
+                // The window internal line counter is only incremented when exiting Transfer
+                // mode, so it currently holds the window line to be rendered next
+                self.rendering_info.window_internal_line_counter,
+                self.rendering_info.wy_condition_was_met_this_frame,
+            );
+        }
+    }
+
+    /// Writes the rendering state, the buffers for the shaders and the state of the pixel FIFO
+    /// (if it is enabled) to the provided save state, see [crate::save_state].
+    pub(crate) fn save_state(&self, writer: &mut StateWriter) {
+        let rendering_info = &self.rendering_info;
+        writer.write_u32(rendering_info.dots_clock);
+        writer.write_u128(rendering_info.total_dots);
+        writer.write_u32(rendering_info.dots_for_transfer);
+        writer.write_bool(rendering_info.lcd_was_turned_off);
+        writer.write_bool(rendering_info.first_scanline_after_lcd_was_turned_on);
+        writer.write_u8(rendering_info.window_internal_line_counter);
+        writer.write_bool(rendering_info.wy_condition_was_met_this_frame);
+        writer.write_bool(rendering_info.window_is_rendered_this_scanline);
+        self.buffers_for_rendering.save_state(writer);
+        writer.write_bool(self.pixel_fifo.is_some());
+        if let Some(pixel_fifo) = &self.pixel_fifo {
+            pixel_fifo.save_state(writer);
+        }
+    }
+
+    /// Restores the rendering state, the buffers for the shaders and the state of the pixel FIFO
+    /// written by [PPU::save_state]. Whether the pixel FIFO is enabled is not changed, so the
+    /// state of the pixel FIFO is only restored, if it is enabled on both sides.
+    pub(crate) fn load_state(&mut self, reader: &mut StateReader) -> Result<(), RustBoyError> {
+        let rendering_info = &mut self.rendering_info;
+        rendering_info.dots_clock = reader.read_u32()?;
+        rendering_info.total_dots = reader.read_u128()?;
+        rendering_info.dots_for_transfer = reader.read_u32()?;
+        rendering_info.lcd_was_turned_off = reader.read_bool()?;
+        rendering_info.first_scanline_after_lcd_was_turned_on = reader.read_bool()?;
+        rendering_info.window_internal_line_counter = reader.read_u8()?;
+        rendering_info.wy_condition_was_met_this_frame = reader.read_bool()?;
+        rendering_info.window_is_rendered_this_scanline = reader.read_bool()?;
+        self.buffers_for_rendering.load_state(reader)?;
+        if reader.read_bool()? {
+            // The state is read in any case, so the reader ends up behind it
+            let mut pixel_fifo = PixelFifo::new();
+            pixel_fifo.load_state(reader)?;
+            if self.pixel_fifo.is_some() {
+                self.pixel_fifo = Some(pixel_fifo);
+            }
+        }
+        Ok(())
+    }
+
+    /// Switches the PPU to the dot-accurate pixel FIFO renderer instead of the scanline shader.
+    /// The rendered frames are then available in the framebuffer of the [PixelFifo].
+    pub fn enable_pixel_fifo(&mut self) {
+        self.pixel_fifo = Some(PixelFifo::new());
+    }
+
     /// Returns a new PPU instance set to the initial state of the PPU.
     pub fn new_empty() -> Self {
         Self {
             rendering_info: RenderingInfo::new_initial_state(),
             buffers_for_rendering: BuffersForRendering::new_empty(),
+            pixel_fifo: None,
+            dots_per_m_cycle: DOTS_PER_M_CYCLE,
         }
     }
 }
@@ -350,10 +501,21 @@ impl RenderingInfo {
     /// Updates the window internal line counter.
     /// This is used to determine how many lines have been rendered where the window was part of the
     /// line.
+    ///
+    /// The counter is only incremented on scanlines where the window is actually rendered. If the
+    /// window is hidden for some scanlines mid-frame (by clearing LCDC bit 5 or moving WX off-screen),
+    /// the counter keeps its value, so the window resumes with the next line of the window tilemap
+    /// when it reappears, see
+    /// [Pan Docs - Window](https://gbdev.io/pandocs/Scrolling.html#window).
+    ///
+    /// `window_was_fetched` is provided by the pixel FIFO (if it is used) and indicates whether the
+    /// window was reached on the current scanline. Otherwise, this is determined using the state of
+    /// the registers at the end of Transfer mode (3).
     fn update_window_internal_line_counter(
         &mut self,
         memory_bus: &MemoryBus,
         current_scanline: u8,
+        window_was_fetched: Option<bool>,
     ) {
         if current_scanline > 143 {
             // If the current scanline is greater than 143, we are in VBlank mode and the window
@@ -362,10 +524,12 @@ impl RenderingInfo {
         } else {
             // We are about to exit Transfer mode and we need to check, if the window will be
             // rendered on the current scanline.
-            if self.wy_condition_was_met_this_frame
-                && PPURegisters::get_window_x_position(memory_bus) < 167
-                && is_bit_set(PPURegisters::get_lcd_control(memory_bus), 5)
-            {
+            let window_is_rendered = window_was_fetched.unwrap_or_else(|| {
+                self.wy_condition_was_met_this_frame
+                    && PPURegisters::get_window_x_position(memory_bus) < 167
+                    && is_bit_set(PPURegisters::get_lcd_control(memory_bus), 5)
+            });
+            if window_is_rendered {
                 // The window will be rendered, if the wy condition was met this frame, the x position
                 // of the window is not out of bounds, and the window flag in the lcd control register
                 // is set
@@ -390,3 +554,377 @@ impl RenderingInfo {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::RustBoy;
+    use crate::debugging::DebugInfo;
+
+    const LCDC_REGISTER_ADDRESS: u16 = 0xFF40;
+    const LCD_STATUS_REGISTER_ADDRESS: u16 = 0xFF41;
+    const SCANLINE_COMPARE_REGISTER_ADDRESS: u16 = 0xFF45;
+    const BACKGROUND_PALETTE_REGISTER_ADDRESS: u16 = 0xFF47;
+    const WINDOW_Y_POSITION_REGISTER_ADDRESS: u16 = 0xFF4A;
+    const WINDOW_X_POSITION_REGISTER_ADDRESS: u16 = 0xFF4B;
+    /// The value of LCDC after the boot ROM, with the LCD turned on.
+    const LCD_CONTROL_AFTER_BOOT: u8 = 0x91;
+    /// The bits of STAT which select the mode 0, mode 1 and mode 2 STAT interrupt.
+    const MODE_INT_SELECTS: u8 = 0b111 << 3;
+    /// The bit of STAT which selects the mode 0 STAT interrupt.
+    const MODE_0_INT_SELECT: u8 = 1 << 3;
+    /// The bit of STAT which selects the mode 2 STAT interrupt.
+    const MODE_2_INT_SELECT: u8 = 1 << 5;
+    /// The bit of STAT which selects the LY=LYC interrupt.
+    const LYC_INT_SELECT: u8 = 1 << 6;
+    /// The bit of STAT which is set while LY=LYC.
+    const LYC_LY_COINCIDENCE_FLAG: u8 = 1 << 2;
+
+    /// Returns a RustBoy in the state after the boot ROM, whose PPU is stepped directly by the
+    /// tests. The provided value is written to STAT to select the sources of the STAT interrupt
+    /// and all interrupt flags are cleared.
+    fn rust_boy_with_lcd_status(lcd_status: u8) -> RustBoy {
+        let mut rust_boy = RustBoy::new_after_boot(DebugInfo::default());
+        rust_boy
+            .memory_bus
+            .write_byte(LCD_STATUS_REGISTER_ADDRESS, lcd_status);
+        InterruptFlagRegister::set_interrupt_flag_register(&mut rust_boy.memory_bus, 0);
+        rust_boy
+    }
+
+    /// Steps the PPU of the provided RustBoy by a single m-cycle.
+    fn step_m_cycle(rust_boy: &mut RustBoy) -> RenderTask {
+        rust_boy
+            .ppu
+            .ppu_step(&mut rust_boy.memory_bus, DOTS_PER_M_CYCLE)
+    }
+
+    /// Steps the PPU of the provided RustBoy until LY changes.
+    fn run_until_next_scanline(rust_boy: &mut RustBoy) {
+        let scanline = PPURegisters::get_scanline_internal(&rust_boy.memory_bus);
+        while PPURegisters::get_scanline_internal(&rust_boy.memory_bus) == scanline {
+            step_m_cycle(rust_boy);
+        }
+    }
+
+    /// Steps the PPU of the provided RustBoy until it is in the OAM scan of line 0, that is, at
+    /// the start of a frame. Line 0 after the LCD was turned on does not count, since it starts
+    /// without an OAM scan.
+    fn run_until_start_of_frame(rust_boy: &mut RustBoy) {
+        while PPURegisters::get_ppu_mode(&rust_boy.memory_bus) != RenderingMode::OAMScan2
+            || PPURegisters::get_scanline_internal(&rust_boy.memory_bus) != 0
+        {
+            step_m_cycle(rust_boy);
+        }
+    }
+
+    /// Turns the LCD of the provided RustBoy off and on again and clears all interrupt flags.
+    fn turn_lcd_off_and_on(rust_boy: &mut RustBoy) {
+        rust_boy
+            .memory_bus
+            .write_byte(LCDC_REGISTER_ADDRESS, LCD_CONTROL_AFTER_BOOT & 0x7F);
+        step_m_cycle(rust_boy);
+        InterruptFlagRegister::set_interrupt_flag_register(&mut rust_boy.memory_bus, 0);
+        rust_boy
+            .memory_bus
+            .write_byte(LCDC_REGISTER_ADDRESS, LCD_CONTROL_AFTER_BOOT);
+    }
+
+    /// Steps the PPU of the provided RustBoy until the provided interrupt is requested and returns
+    /// the number of dots this took. The interrupt flag is cleared afterward.
+    fn dots_until_interrupt(rust_boy: &mut RustBoy, interrupt: Interrupt) -> u32 {
+        let mut dots = 0;
+        while !InterruptFlagRegister::get_flag(&rust_boy.memory_bus, interrupt) {
+            step_m_cycle(rust_boy);
+            dots += DOTS_PER_M_CYCLE;
+        }
+        InterruptFlagRegister::set_flag(&mut rust_boy.memory_bus, interrupt, false);
+        dots
+    }
+
+    /// Returns whether the STAT interrupt is requested in the provided RustBoy.
+    fn stat_interrupt_is_requested(rust_boy: &RustBoy) -> bool {
+        InterruptFlagRegister::get_flag(&rust_boy.memory_bus, Interrupt::LcdStat)
+    }
+
+    #[test]
+    fn ly_lyc_interrupt_is_requested_during_vblank() {
+        let mut rust_boy = rust_boy_with_lcd_status(LYC_INT_SELECT);
+        rust_boy
+            .memory_bus
+            .write_byte(SCANLINE_COMPARE_REGISTER_ADDRESS, 144);
+        while PPURegisters::get_scanline_internal(&rust_boy.memory_bus) != 144 {
+            assert!(!stat_interrupt_is_requested(&rust_boy));
+            step_m_cycle(&mut rust_boy);
+        }
+        assert_eq!(
+            PPURegisters::get_ppu_mode(&rust_boy.memory_bus),
+            RenderingMode::VBlank1
+        );
+        assert!(stat_interrupt_is_requested(&rust_boy));
+        assert_ne!(
+            rust_boy.memory_bus.read_byte(LCD_STATUS_REGISTER_ADDRESS) & LYC_LY_COINCIDENCE_FLAG,
+            0
+        );
+    }
+
+    #[test]
+    fn ly_lyc_interrupt_is_requested_when_the_lcd_is_turned_on() {
+        let mut rust_boy = rust_boy_with_lcd_status(LYC_INT_SELECT);
+        rust_boy
+            .memory_bus
+            .write_byte(LCDC_REGISTER_ADDRESS, LCD_CONTROL_AFTER_BOOT & 0x7F);
+        step_m_cycle(&mut rust_boy);
+        // LY is 0 while the LCD is turned off, but no interrupt is requested until it is turned on
+        rust_boy
+            .memory_bus
+            .write_byte(SCANLINE_COMPARE_REGISTER_ADDRESS, 0);
+        step_m_cycle(&mut rust_boy);
+        assert!(!stat_interrupt_is_requested(&rust_boy));
+
+        rust_boy
+            .memory_bus
+            .write_byte(LCDC_REGISTER_ADDRESS, LCD_CONTROL_AFTER_BOOT);
+        step_m_cycle(&mut rust_boy);
+        assert!(stat_interrupt_is_requested(&rust_boy));
+        assert_ne!(
+            rust_boy.memory_bus.read_byte(LCD_STATUS_REGISTER_ADDRESS) & LYC_LY_COINCIDENCE_FLAG,
+            0
+        );
+    }
+
+    #[test]
+    fn ly_reads_0_for_most_of_the_last_vblank_line() {
+        let mut rust_boy = rust_boy_with_lcd_status(LYC_INT_SELECT);
+        rust_boy
+            .memory_bus
+            .write_byte(SCANLINE_COMPARE_REGISTER_ADDRESS, 0);
+        while PPURegisters::get_scanline_internal(&rust_boy.memory_bus) != 153 {
+            step_m_cycle(&mut rust_boy);
+        }
+        InterruptFlagRegister::set_interrupt_flag_register(&mut rust_boy.memory_bus, 0);
+
+        // LY reads 153 for a single m-cycle and then 0 for the rest of the line, which requests
+        // the LY=LYC interrupt for LYC=0 while still in VBlank
+        step_m_cycle(&mut rust_boy);
+        assert_eq!(PPURegisters::get_scanline(&rust_boy.memory_bus), 0);
+        assert_eq!(
+            PPURegisters::get_ppu_mode(&rust_boy.memory_bus),
+            RenderingMode::VBlank1
+        );
+        assert!(stat_interrupt_is_requested(&rust_boy));
+
+        // The interrupt is not requested a second time when the next frame starts
+        InterruptFlagRegister::set_interrupt_flag_register(&mut rust_boy.memory_bus, 0);
+        let mut m_cycles_until_next_frame = 0;
+        while PPURegisters::get_ppu_mode(&rust_boy.memory_bus) == RenderingMode::VBlank1 {
+            step_m_cycle(&mut rust_boy);
+            m_cycles_until_next_frame += 1;
+        }
+        assert_eq!(PPURegisters::get_scanline(&rust_boy.memory_bus), 0);
+        assert!(!stat_interrupt_is_requested(&rust_boy));
+        // The rest of the line takes 456 dots, minus those of the m-cycle with LY=153
+        assert_eq!(m_cycles_until_next_frame, 456 / 4 - 1);
+    }
+
+    #[test]
+    fn mode_2_interrupt_is_also_requested_on_entering_vblank() {
+        let mut rust_boy = rust_boy_with_lcd_status(MODE_2_INT_SELECT);
+        run_until_start_of_frame(&mut rust_boy);
+        InterruptFlagRegister::set_interrupt_flag_register(&mut rust_boy.memory_bus, 0);
+
+        // Once at the start of each visible line and once more at the start of line 144, that is,
+        // 145 times per frame. The request for line 0 of this frame was cleared above
+        let mut requested_on_scanlines = Vec::new();
+        for _ in 0..154 * 456 / 4 {
+            step_m_cycle(&mut rust_boy);
+            if stat_interrupt_is_requested(&rust_boy) {
+                requested_on_scanlines
+                    .push(PPURegisters::get_scanline_internal(&rust_boy.memory_bus));
+                InterruptFlagRegister::set_interrupt_flag_register(&mut rust_boy.memory_bus, 0);
+            }
+        }
+        assert_eq!(
+            requested_on_scanlines,
+            (1..=144).chain([0]).collect::<Vec<u8>>()
+        );
+    }
+
+    #[test]
+    fn first_frame_after_turning_the_lcd_on_has_the_usual_length() {
+        let mut rust_boy = rust_boy_with_lcd_status(0);
+        turn_lcd_off_and_on(&mut rust_boy);
+        assert_eq!(
+            dots_until_interrupt(&mut rust_boy, Interrupt::VBlank),
+            144 * 456
+        );
+        assert_eq!(
+            dots_until_interrupt(&mut rust_boy, Interrupt::VBlank),
+            154 * 456
+        );
+    }
+
+    #[test]
+    fn turning_the_lcd_on_does_not_request_a_mode_0_interrupt() {
+        let mut rust_boy = rust_boy_with_lcd_status(MODE_0_INT_SELECT);
+        turn_lcd_off_and_on(&mut rust_boy);
+        // The first mode 0 interrupt is requested at the end of the transfer of line 0, which
+        // takes 80 + 172 dots
+        assert_eq!(
+            dots_until_interrupt(&mut rust_boy, Interrupt::LcdStat),
+            80 + 172
+        );
+
+        // Line 0 starts without an OAM scan, so the first mode 2 interrupt is the one of line 1
+        let mut rust_boy = rust_boy_with_lcd_status(MODE_2_INT_SELECT);
+        turn_lcd_off_and_on(&mut rust_boy);
+        dots_until_interrupt(&mut rust_boy, Interrupt::LcdStat);
+        assert_eq!(PPURegisters::get_scanline_internal(&rust_boy.memory_bus), 1);
+    }
+
+    #[test]
+    fn no_interrupts_are_requested_while_the_lcd_is_turned_off() {
+        let mut rust_boy = rust_boy_with_lcd_status(MODE_INT_SELECTS | LYC_INT_SELECT);
+        // LY is 0 while the LCD is turned off, which matches LYC
+        rust_boy
+            .memory_bus
+            .write_byte(SCANLINE_COMPARE_REGISTER_ADDRESS, 0);
+        run_until_next_scanline(&mut rust_boy);
+        InterruptFlagRegister::set_interrupt_flag_register(&mut rust_boy.memory_bus, 0);
+
+        rust_boy
+            .memory_bus
+            .write_byte(LCDC_REGISTER_ADDRESS, LCD_CONTROL_AFTER_BOOT & 0x7F);
+        // A few frames worth of m-cycles
+        for _ in 0..3 * 154 * 114 {
+            assert_eq!(step_m_cycle(&mut rust_boy), RenderTask::None);
+        }
+        assert!(!InterruptFlagRegister::get_flag(
+            &rust_boy.memory_bus,
+            Interrupt::VBlank
+        ));
+        assert!(!stat_interrupt_is_requested(&rust_boy));
+        assert_eq!(PPURegisters::get_scanline_internal(&rust_boy.memory_bus), 0);
+    }
+
+    #[test]
+    fn window_internal_line_counter_stays_the_same_while_the_window_is_hidden() {
+        for use_pixel_fifo in [false, true] {
+            let mut rust_boy = rust_boy_with_lcd_status(0);
+            if use_pixel_fifo {
+                rust_boy.ppu.enable_pixel_fifo();
+            }
+            rust_boy
+                .memory_bus
+                .write_byte(WINDOW_Y_POSITION_REGISTER_ADDRESS, 0);
+            run_until_start_of_frame(&mut rust_boy);
+
+            let mut expected_window_line = 0;
+            for scanline in 0..144 {
+                // The window is hidden by clearing LCDC bit 5 for some scanlines and by moving it
+                // off-screen for others
+                let window_enabled = !(48..64).contains(&scanline);
+                let window_on_screen = !(96..112).contains(&scanline);
+                let lcd_control = if window_enabled {
+                    LCD_CONTROL_AFTER_BOOT | (1 << 5)
+                } else {
+                    LCD_CONTROL_AFTER_BOOT
+                };
+                let window_x_position = if window_on_screen { 7 } else { 200 };
+                rust_boy
+                    .memory_bus
+                    .write_byte(LCDC_REGISTER_ADDRESS, lcd_control);
+                rust_boy
+                    .memory_bus
+                    .write_byte(WINDOW_X_POSITION_REGISTER_ADDRESS, window_x_position);
+
+                run_until_next_scanline(&mut rust_boy);
+                if window_enabled && window_on_screen {
+                    expected_window_line += 1;
+                }
+                assert_eq!(
+                    rust_boy.ppu.rendering_info.window_internal_line_counter,
+                    expected_window_line
+                );
+            }
+            assert_eq!(expected_window_line, 112);
+        }
+    }
+
+    #[test]
+    fn palette_writes_take_effect_on_the_scanline_they_are_written_on() {
+        for use_pixel_fifo in [false, true] {
+            let mut rust_boy = rust_boy_with_lcd_status(0);
+            if use_pixel_fifo {
+                rust_boy.ppu.enable_pixel_fifo();
+            }
+            run_until_start_of_frame(&mut rust_boy);
+
+            // Fade the screen to black over the frame, like games do by writing BGP repeatedly.
+            // The VRAM is empty, so the whole background has color 0, whose shade is given by the
+            // lowest two bits of BGP
+            let background_palette_of_scanline = |scanline: usize| (scanline / 36) as u8;
+            for scanline in 0..144 {
+                rust_boy.memory_bus.write_byte(
+                    BACKGROUND_PALETTE_REGISTER_ADDRESS,
+                    background_palette_of_scanline(scanline),
+                );
+                run_until_next_scanline(&mut rust_boy);
+            }
+
+            for scanline in 0..144 {
+                let background_palette = background_palette_of_scanline(scanline);
+                if use_pixel_fifo {
+                    // All scanlines with the same palette have the same shade and the shade
+                    // changes on the scanline the palette is changed on
+                    let first_scanline_with_palette = background_palette as usize * 36;
+                    assert_eq!(
+                        rust_boy.scanline(scanline as u8),
+                        rust_boy.scanline(first_scanline_with_palette as u8)
+                    );
+                    if scanline == first_scanline_with_palette && scanline > 0 {
+                        assert_ne!(
+                            rust_boy.scanline(scanline as u8),
+                            rust_boy.scanline(scanline as u8 - 1)
+                        );
+                    }
+                } else {
+                    assert_eq!(
+                        rust_boy.ppu.buffers_for_rendering.palettes_per_scanline[scanline].values
+                            [0],
+                        background_palette as u32
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn save_state_in_the_middle_of_a_scanline_restores_the_pixel_fifo() {
+        let rom_data = include_bytes!("../roms/test_roms/pocket.gb");
+        let mut rust_boy = RustBoy::from_rom(rom_data).expect("The ROM should be loadable");
+        rust_boy.run_frames(200).expect("The ROM should run");
+        // Stop in the middle of Transfer mode (3) of a scanline in the middle of the screen, while
+        // the pixel FIFO is pushing pixels
+        let mut steps_in_transfer = 0;
+        while steps_in_transfer < 2 {
+            rust_boy.step().expect("The ROM should run");
+            if PPURegisters::get_ppu_mode(&rust_boy.memory_bus) == RenderingMode::Transfer3
+                && PPURegisters::get_scanline_internal(&rust_boy.memory_bus) >= 72
+            {
+                steps_in_transfer += 1;
+            } else {
+                steps_in_transfer = 0;
+            }
+        }
+        let state = rust_boy.save_state_to_bytes();
+        let expected_frames = rust_boy.run_frames(2).expect("The ROM should run");
+
+        let mut loaded_rust_boy = RustBoy::from_rom(rom_data).expect("The ROM should be loadable");
+        loaded_rust_boy
+            .load_state_from_bytes(&state)
+            .expect("The state should be loadable");
+        let frames = loaded_rust_boy.run_frames(2).expect("The ROM should run");
+        assert!(frames == expected_frames);
+    }
+}
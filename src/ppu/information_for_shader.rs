@@ -1,9 +1,79 @@
 use super::PPU;
-use crate::MemoryBus;
-use crate::frontend::shader::{
-    BgAndWdViewportPosition, Palettes, RenderingLinePositionAndObjectSize,
-};
+use crate::ppu::object_handling::{ObjectPriorityMode, custom_ordering};
 use crate::ppu::registers::PPURegisters;
+use crate::save_state::{StateReader, StateWriter};
+use crate::sgb::SgbPalettes;
+use crate::{MemoryBus, ORIGINAL_SCREEN_HEIGHT, RustBoyError};
+
+/// The bit of the LCD control register entry of [RenderingLinePositionAndObjectSize] which is set
+/// in CGB mode, see [MemoryBus::cgb_mode].
+const CGB_MODE_BIT: u32 = 1 << 8;
+
+/// The number of entries of the per-scanline buffers of [BuffersForRendering], one for each
+/// line of the screen.
+const NUMBER_OF_SCANLINES: usize = ORIGINAL_SCREEN_HEIGHT as usize;
+
+/// Represents the position of the viewport of the background in the tilemap. Is a list of 4 elements
+/// just for alignment, we only use the first 2.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct BgAndWdViewportPosition {
+    pub pos: [u32; 4],
+}
+
+/// Represents the current rendering line and the object size flag. Is a list of 4 elements just for alignment, we only use
+/// the first and second entry. They are the current scanline and the object size flag (0 for 8x8, 1 for 8x16).
+///
+/// The second entry holds the whole LCD control register in its lower 8 bits. Bit 8
+/// ([CGB_MODE_BIT]) is set, if the cartridge is run in CGB mode, in which LCDC bit 0 is the
+/// background and window master priority instead of their enable flag, see
+/// [crate::ppu::registers::LCDCRegister::get_background_and_window_enable_flag].
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct RenderingLinePositionAndObjectSize {
+    pub pos: [u32; 4],
+}
+
+/// Represents the palettes used for the background, window and objects. The first entry of
+/// `values` is the background and window palette that corresponds to register 0xFF47. The second
+/// entry is the object palette 0 that corresponds to register 0xFF48. The third entry is the
+/// object palette 1 that corresponds to register 0xFF49. See
+/// https://gbdev.io/pandocs/Palettes.html#lcd-monochrome-palettes for more information.
+///
+/// The fourth entry of `values` is 1, if the Super Game Boy colors in `sgb_colors` should be used
+/// instead of the default colors of the RustBoy and 0 otherwise. The entries of `sgb_colors` are
+/// the colors the four shades are mapped to, encoded as 0x00RRGGBB.
+/// See [crate::sgb::SgbPalettes] for more information.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct Palettes {
+    pub values: [u32; 4],
+    pub sgb_colors: [u32; 4],
+}
+
+/// Represents the objects in a scanline in the packed format they are passed to the shader in.
+/// Each object is packed into a single u32, with its four bytes (y coordinate, x coordinate, tile
+/// index and attributes) from the lowest to the highest byte. Since there are at most 10 objects
+/// per scanline, the last two u32s are unused and only there for alignment.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct PackedObjectsInScanline {
+    pub objects: [[u32; 4]; 3],
+}
+
+impl PackedObjectsInScanline {
+    /// Packs the provided objects, which are given as one u32 per byte of the object, as
+    /// returned by [PPU::get_objects_for_current_scanline].
+    fn from_objects(objects: &[[u32; 4]; 10]) -> Self {
+        let mut packed_objects = [0; 12];
+        for (packed_object, object) in packed_objects.iter_mut().zip(objects) {
+            *packed_object = u32::from_le_bytes(object.map(|byte| byte as u8));
+        }
+        PackedObjectsInScanline {
+            objects: bytemuck::cast(packed_objects),
+        }
+    }
+}
 
 /// Struct to keep track of the resources that are fetched during transfer (and OAMScan) mode which are then
 /// sent to the shader.
@@ -12,22 +82,33 @@ use crate::ppu::registers::PPURegisters;
 /// - `background_tile_map`: The tile map for the background.
 /// - `window_tile_map`: The tile map for the window.
 /// - `bg_and_wd_tile_data`: The tile data for the background and window.
-/// - `bg_and_wd_viewport_position`: The viewport position for the background and window.
-/// - `palettes`: The palettes for the background and window.
-/// - `rendering_line_lcd_control_and_window_internal_line_info`: The LCD control register and window
-/// internal line info.
+/// - `bg_and_wd_viewport_position_per_scanline`: The viewport position for the background and
+///   window.
+/// - `palettes_per_scanline`: The palettes for the background, window and objects.
+/// - `rendering_line_lcd_control_and_window_internal_line_info_per_scanline`: The LCD control
+///   register and window internal line info.
 /// - `object_tile_data`: The tile data for the objects.
+/// - `objects_in_scanline_per_scanline`: The objects in the scanline, sorted by their drawing
+///   priority, see [ObjectPriorityMode].
 /// - `objects_in_scanline_buffer`: The objects in the current scanline buffer.
+///
+/// The buffers ending in `_per_scanline` have one entry per line of the screen, which is written
+/// when exiting transfer mode on the respective line. This way, all scanlines of a frame can be
+/// rendered at once, while still using the scroll position, palettes, LCD control register and
+/// objects of the respective scanline. Only the tile data and tilemaps are shared between all
+/// scanlines, see [crate::frontend::State::render_scanline].
 pub struct BuffersForRendering {
     // Transfer mode buffers:
     pub(crate) background_tile_map: [u8; 1024],
     pub(crate) window_tile_map: [u8; 1024],
     pub(crate) bg_and_wd_tile_data: [u8; 4096],
-    pub(crate) bg_and_wd_viewport_position: BgAndWdViewportPosition,
-    pub(crate) palettes: Palettes,
-    pub(crate) rendering_line_lcd_control_and_window_internal_line_info:
-        RenderingLinePositionAndObjectSize,
+    pub(crate) bg_and_wd_viewport_position_per_scanline:
+        [BgAndWdViewportPosition; NUMBER_OF_SCANLINES],
+    pub(crate) palettes_per_scanline: [Palettes; NUMBER_OF_SCANLINES],
+    pub(crate) rendering_line_lcd_control_and_window_internal_line_info_per_scanline:
+        [RenderingLinePositionAndObjectSize; NUMBER_OF_SCANLINES],
     pub(crate) object_tile_data: [u8; 4096],
+    pub(crate) objects_in_scanline_per_scanline: [PackedObjectsInScanline; NUMBER_OF_SCANLINES],
     // OAMScan mode buffer:
     pub(crate) objects_in_scanline_buffer: [[u32; 4]; 10],
 }
@@ -39,14 +120,57 @@ impl BuffersForRendering {
             background_tile_map: [0; 1024],
             window_tile_map: [0; 1024],
             bg_and_wd_tile_data: [0; 4096],
-            bg_and_wd_viewport_position: BgAndWdViewportPosition { pos: [0; 4] },
-            palettes: Palettes { values: [0; 4] },
-            rendering_line_lcd_control_and_window_internal_line_info:
-                RenderingLinePositionAndObjectSize { pos: [0; 4] },
+            bg_and_wd_viewport_position_per_scanline: [BgAndWdViewportPosition { pos: [0; 4] };
+                NUMBER_OF_SCANLINES],
+            palettes_per_scanline: [Palettes {
+                values: [0; 4],
+                sgb_colors: [0; 4],
+            }; NUMBER_OF_SCANLINES],
+            rendering_line_lcd_control_and_window_internal_line_info_per_scanline:
+                [RenderingLinePositionAndObjectSize { pos: [0; 4] }; NUMBER_OF_SCANLINES],
             object_tile_data: [0; 4096],
+            objects_in_scanline_per_scanline: [PackedObjectsInScanline {
+                objects: [[0; 4]; 3],
+            }; NUMBER_OF_SCANLINES],
             objects_in_scanline_buffer: [[0; 4]; 10],
         }
     }
+
+    /// Writes the buffers to the provided save state.
+    pub(crate) fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_bytes(&self.background_tile_map);
+        writer.write_bytes(&self.window_tile_map);
+        writer.write_bytes(&self.bg_and_wd_tile_data);
+        writer.write_bytes(bytemuck::cast_slice(
+            &self.bg_and_wd_viewport_position_per_scanline,
+        ));
+        writer.write_bytes(bytemuck::cast_slice(&self.palettes_per_scanline));
+        writer.write_bytes(bytemuck::cast_slice(
+            &self.rendering_line_lcd_control_and_window_internal_line_info_per_scanline,
+        ));
+        writer.write_bytes(&self.object_tile_data);
+        writer.write_bytes(bytemuck::cast_slice(&self.objects_in_scanline_per_scanline));
+        writer.write_bytes(bytemuck::bytes_of(&self.objects_in_scanline_buffer));
+    }
+
+    /// Restores the buffers written by [BuffersForRendering::save_state].
+    pub(crate) fn load_state(&mut self, reader: &mut StateReader) -> Result<(), RustBoyError> {
+        reader.read_bytes_into(&mut self.background_tile_map)?;
+        reader.read_bytes_into(&mut self.window_tile_map)?;
+        reader.read_bytes_into(&mut self.bg_and_wd_tile_data)?;
+        reader.read_bytes_into(bytemuck::cast_slice_mut(
+            &mut self.bg_and_wd_viewport_position_per_scanline,
+        ))?;
+        reader.read_bytes_into(bytemuck::cast_slice_mut(&mut self.palettes_per_scanline))?;
+        reader.read_bytes_into(bytemuck::cast_slice_mut(
+            &mut self.rendering_line_lcd_control_and_window_internal_line_info_per_scanline,
+        ))?;
+        reader.read_bytes_into(&mut self.object_tile_data)?;
+        reader.read_bytes_into(bytemuck::cast_slice_mut(
+            &mut self.objects_in_scanline_per_scanline,
+        ))?;
+        reader.read_bytes_into(bytemuck::bytes_of_mut(&mut self.objects_in_scanline_buffer))
+    }
 }
 
 impl PPU {
@@ -55,12 +179,15 @@ impl PPU {
     /// RustBoy fetches it in mode 3 (Transfer) and we only actually render it in mode 0 (HBlank).
     /// So, to avoid reading already changed data for rendering, we buffer the "old state".
     ///
-    /// Hence, this function is called once for every scanline when exiting mode 3 (Transfer).
+    /// Hence, this function is called once for every scanline when exiting mode 3 (Transfer). The
+    /// information which can change from line to line is written to the entry of the current
+    /// scanline of the respective per-scanline buffer.
     pub(super) fn fetch_rendering_information_to_rendering_buffer(
         &mut self,
         memory_bus: &MemoryBus,
         current_scanline: u8,
     ) {
+        let line = current_scanline as usize;
         self.buffers_for_rendering.background_tile_map = PPU::get_background_tile_map(memory_bus);
 
         self.buffers_for_rendering.window_tile_map = PPU::get_window_tile_map(memory_bus);
@@ -68,7 +195,8 @@ impl PPU {
         self.buffers_for_rendering.bg_and_wd_tile_data =
             PPU::get_background_and_window_tile_data(memory_bus);
 
-        self.buffers_for_rendering.bg_and_wd_viewport_position = BgAndWdViewportPosition {
+        self.buffers_for_rendering
+            .bg_and_wd_viewport_position_per_scanline[line] = BgAndWdViewportPosition {
             pos: [
                 PPURegisters::get_bg_scroll_x(memory_bus) as u32,
                 PPURegisters::get_bg_scroll_y(memory_bus) as u32,
@@ -77,23 +205,35 @@ impl PPU {
             ],
         };
 
-        self.buffers_for_rendering.palettes = Palettes {
+        let sgb_colors = memory_bus
+            .sgb_palettes
+            .as_ref()
+            .and_then(SgbPalettes::playfield_colors);
+        self.buffers_for_rendering.palettes_per_scanline[line] = Palettes {
             values: [
                 PPURegisters::get_background_palette(memory_bus) as u32,
                 PPURegisters::get_object_palette_zero(memory_bus) as u32,
                 PPURegisters::get_object_palette_one(memory_bus) as u32,
-                0,
+                sgb_colors.is_some() as u32,
             ],
+            sgb_colors: sgb_colors
+                .unwrap_or_default()
+                .map(|[red, green, blue, _]| u32::from_be_bytes([0, red, green, blue])),
         };
 
         self.buffers_for_rendering.object_tile_data = PPU::get_object_tile_data(memory_bus);
 
         self.buffers_for_rendering
-            .rendering_line_lcd_control_and_window_internal_line_info =
+            .rendering_line_lcd_control_and_window_internal_line_info_per_scanline[line] =
             RenderingLinePositionAndObjectSize {
                 pos: [
                     current_scanline as u32,
-                    PPURegisters::get_lcd_control(memory_bus) as u32,
+                    PPURegisters::get_lcd_control(memory_bus) as u32
+                        | if memory_bus.cgb_mode() {
+                            CGB_MODE_BIT
+                        } else {
+                            0
+                        },
                     // We pass the info necessary for the window internal line counter
                     self.rendering_info.window_is_rendered_this_scanline as u32,
                     // By the documentation of the [window_internal_line_counter](super::RenderingInfo)
@@ -106,6 +246,17 @@ impl PPU {
                     } as u32,
                 ],
             };
+
+        // In DMG mode, sort objects in scanline by their x coordinate, see
+        // https://gbdev.io/pandocs/OAM.html#drawing-priority. Since the sort is stable, objects
+        // with the same x coordinate stay in OAM order. In CGB mode, the OAM order is kept as is.
+        let mut objects_in_scanline = self.buffers_for_rendering.objects_in_scanline_buffer;
+        if ObjectPriorityMode::for_cartridge(memory_bus) == ObjectPriorityMode::Dmg {
+            objects_in_scanline.sort_by(|v, w| custom_ordering(v[1], w[1]));
+        }
+        self.buffers_for_rendering.objects_in_scanline_per_scanline[line] =
+            PackedObjectsInScanline::from_objects(&objects_in_scanline);
+
         // DEBUG
         log::trace!(
             "Window rendered this scanline: {}, Current LCD control: {:<8b}, Current Scanline: {:<3}, Window position: {:<3}/{:<3}",
@@ -164,6 +315,7 @@ pub struct ChangesToPropagateToShader {
 
 impl ChangesToPropagateToShader {
     /// Returns a new instance of MemoryChanged with everything set to false.
+    #[cfg(feature = "frontend")]
     pub(crate) fn new_false() -> Self {
         Self {
             tile_data_flag_changed: false,
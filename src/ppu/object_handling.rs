@@ -47,6 +47,32 @@ impl Object {
     }
 }
 
+/// The rule used to decide which object is drawn on top if multiple (non-transparent) objects
+/// overlap, see [Pan Docs - Drawing priority](https://gbdev.io/pandocs/OAM.html#drawing-priority).
+///
+/// - `Dmg`: The object with the smaller x position is drawn on top. If the x positions are equal,
+///   the object with the earlier OAM entry is drawn on top.
+/// - `Cgb`: The object with the earlier OAM entry is drawn on top, regardless of the x positions.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub(crate) enum ObjectPriorityMode {
+    #[default]
+    Dmg,
+    Cgb,
+}
+
+impl ObjectPriorityMode {
+    /// Returns the priority mode to be used for the loaded cartridge. If the cartridge is run in
+    /// CGB mode (see [MemoryBus::cgb_mode]), the CGB rule is used. Otherwise (or if no cartridge
+    /// is loaded), the DMG rule is used.
+    pub(crate) fn for_cartridge(memory_bus: &MemoryBus) -> ObjectPriorityMode {
+        if memory_bus.cgb_mode() {
+            ObjectPriorityMode::Cgb
+        } else {
+            ObjectPriorityMode::Dmg
+        }
+    }
+}
+
 impl PPU {
     /// Returns the objects that are currently on the scanline.
     ///
@@ -72,21 +98,26 @@ impl PPU {
 
         let mut objects: [[u32; 4]; 10] = Default::default();
         let mut count = 0;
-        // Adjust for y_position = 0 being 16 pixels above the top of the screen
-        let adjusted_scanline = scanline + 16;
+        // Adjust for y_position = 0 being 16 pixels above the top of the screen. The comparisons
+        // are done using u16s, since the bottom of objects with a y position of 241 or more would
+        // overflow a u8 and wrap around to the top of the screen
+        let adjusted_scanline = scanline as u16 + 16;
+        // Set object height according to the flag in the LCD control register
+        let object_height = if LCDCRegister::get_sprite_size_flag(memory_bus) {
+            16
+        } else {
+            8
+        };
 
         for i in 0..oam_as_objects.len() {
             let object = oam_as_objects[i];
-            // Set object height according to the flag in the LCD control register
-            let object_height = if LCDCRegister::get_sprite_size_flag(memory_bus) {
-                16
-            } else {
-                8
-            };
-            // We have to adjust for y_position = 0 being 16 pixels above the top of the screen
-            if object.y_position <= adjusted_scanline
-                && object.y_position + object_height > adjusted_scanline
-            {
+            // An object covers the scanlines y_position - 16 up to (excluding)
+            // y_position - 16 + object_height. So objects with a y position of 0 (or 8 for 8x8
+            // objects) are hidden above the screen and objects with a y position of 160 or more
+            // are hidden below the screen, see
+            // [Pan Docs - OAM](https://gbdev.io/pandocs/OAM.html#byte-0--y-position).
+            let object_top = object.y_position as u16;
+            if object_top <= adjusted_scanline && object_top + object_height > adjusted_scanline {
                 objects[count] = object.to_bytes();
                 count += 1;
                 if count == 10 {
@@ -113,3 +144,59 @@ pub fn custom_ordering(a: u32, b: u32) -> std::cmp::Ordering {
         a.cmp(&b)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::debugging::DebugInfo;
+
+    /// Returns the number of objects selected for the provided scanline, if OAM contains objects
+    /// at the provided y positions. If `tall_objects` is true, 8x16 objects are used.
+    fn number_of_objects_on_scanline(
+        y_positions: &[u8],
+        tall_objects: bool,
+        scanline: u8,
+    ) -> usize {
+        let mut memory_bus = MemoryBus::new_before_boot(&DebugInfo::default());
+        memory_bus.memory[0xFF40] = if tall_objects { 0x86 } else { 0x82 };
+        for (index, y_position) in y_positions.iter().enumerate() {
+            memory_bus.memory[OAM_START as usize + index * 4] = *y_position;
+            memory_bus.memory[OAM_START as usize + index * 4 + 1] = 8;
+        }
+        PPU::new_empty()
+            .get_objects_for_current_scanline(&memory_bus, scanline)
+            .iter()
+            .filter(|object| **object != [0; 4])
+            .count()
+    }
+
+    #[test]
+    fn object_at_y_16_starts_on_the_first_scanline() {
+        assert_eq!(number_of_objects_on_scanline(&[16], false, 0), 1);
+        assert_eq!(number_of_objects_on_scanline(&[16], false, 7), 1);
+        assert_eq!(number_of_objects_on_scanline(&[16], false, 8), 0);
+        assert_eq!(number_of_objects_on_scanline(&[16], true, 15), 1);
+        assert_eq!(number_of_objects_on_scanline(&[16], true, 16), 0);
+    }
+
+    #[test]
+    fn objects_above_and_below_the_screen_are_hidden() {
+        for scanline in 0..144 {
+            assert_eq!(
+                number_of_objects_on_scanline(&[0, 160, 241, 255], true, scanline),
+                0
+            );
+            // 8x8 objects at y = 8 end right above the screen, 8x16 objects cover 8 scanlines
+            assert_eq!(number_of_objects_on_scanline(&[8], false, scanline), 0);
+            assert_eq!(
+                number_of_objects_on_scanline(&[8], true, scanline),
+                (scanline < 8) as usize
+            );
+        }
+    }
+
+    #[test]
+    fn at_most_10_objects_are_selected_per_scanline() {
+        assert_eq!(number_of_objects_on_scanline(&[16; 40], false, 0), 10);
+    }
+}
@@ -0,0 +1,899 @@
+//! This module contains an alternative, dot-accurate implementation of the pixel pipeline of the
+//! PPU. Instead of rendering an entire scanline at once using the scanline shader, the [PixelFifo]
+//! emulates the background/window fetcher and the pixel FIFOs of the original Game Boy and pushes
+//! one pixel per dot to a framebuffer.
+//!
+//! Since the registers are read while the pixels are being fetched and pushed, changes to them
+//! during mode 3 (Transfer), e.g. to the palettes or the scroll registers, take effect in the
+//! middle of a scanline, as they do on the original hardware.
+//!
+//! See [Pan Docs - Pixel FIFO](https://gbdev.io/pandocs/pixel_fifo.html) for details.
+
+use std::collections::VecDeque;
+
+use super::object_handling::{Object, ObjectPriorityMode};
+use super::registers::{LCDCRegister, PPURegisters};
+use super::{
+    TILE_DATA_BLOCK_0_START, TILE_DATA_BLOCK_2_START, TILEMAP_ONE_START, TILEMAP_ZERO_START,
+};
+use crate::memory_bus::is_bit_set;
+use crate::save_state::{StateReader, StateWriter};
+use crate::sgb::SgbPalettes;
+use crate::{MemoryBus, ORIGINAL_SCREEN_HEIGHT, ORIGINAL_SCREEN_WIDTH, RustBoyError};
+
+/// The RGBA values of the four shades the Game Boy can display. These are the same colors the
+/// scanline shader uses (COLOR_ZERO to COLOR_THREE).
+pub(crate) const SHADE_COLORS: [[u8; 4]; 4] = [
+    [213, 244, 185, 255],
+    [69, 134, 43, 255],
+    [0, 30, 0, 255],
+    [10, 30, 15, 255],
+];
+
+/// The number of dots the first fetch of each scanline takes. The tile fetched there is
+/// discarded on the original hardware, so we just wait instead.
+const DOTS_OF_INITIAL_FETCH: u32 = 6;
+/// The number of dots the fetcher is stalled while an object is being fetched.
+const DOTS_PER_OBJECT_FETCH: u32 = 6;
+/// The number of dots each of the steps of the fetcher (except for pushing) takes.
+const DOTS_PER_FETCHER_STEP: u32 = 2;
+
+/// The steps the background/window fetcher cycles through. Each step except for [FetcherStep::Push]
+/// takes [DOTS_PER_FETCHER_STEP] dots. Pushing is retried every dot until the background FIFO is
+/// empty.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum FetcherStep {
+    GetTile,
+    GetTileDataLow,
+    GetTileDataHigh,
+    Push,
+}
+
+/// A pixel in the object FIFO.
+///
+/// - `color`: The color index (0 - 3) of the pixel. 0 means transparent.
+/// - `palette_one`: Whether the object uses the object palette 1 (OBP1) instead of 0 (OBP0).
+/// - `background_priority`: Whether the background and window colors 1 - 3 are drawn over this
+///   pixel.
+/// - `oam_position`: The position of the object this pixel belongs to among the objects on the
+///   current scanline, which are in OAM order. Used to resolve the drawing priority in
+///   [ObjectPriorityMode::Cgb].
+#[derive(Clone, Copy, Debug, Default)]
+struct ObjectPixel {
+    color: u8,
+    palette_one: bool,
+    background_priority: bool,
+    oam_position: u8,
+}
+
+/// Struct to represent the pixel FIFO renderer of the PPU.
+///
+/// The fields are as follows:
+/// - `background_fifo`: The color indices of the background/window pixels waiting to be pushed to the LCD.
+/// - `object_fifo`: The object pixels waiting to be mixed with the background/window pixels.
+/// - `fetcher_step`: The step the background/window fetcher is currently in.
+/// - `fetcher_dots`: The number of dots the fetcher has already spent in its current step.
+/// - `fetcher_x`: The x coordinate (in tiles) of the next tile to be fetched. For the window this is
+///   relative to the left edge of the window.
+/// - `fetched_tile_index`, `fetched_tile_data_low`, `fetched_tile_data_high`: The data fetched by the fetcher
+///   for the tile it is currently working on.
+/// - `fetching_window`: Whether the fetcher switched to fetching window tiles on the current scanline.
+/// - `pixels_to_discard`: The number of pixels still to be discarded at the beginning of the scanline
+///   due to the fine background scrolling (SCX mod 8).
+/// - `lcd_x`: The x coordinate of the next pixel to be pushed to the LCD.
+/// - `stall_dots`: The number of dots for which the pixel pipeline is stalled, e.g. due to fetching an object.
+/// - `objects`: The objects on the current scanline which have not been fetched yet, in OAM order.
+/// - `object_priority_mode`: The rule used to decide which of two overlapping objects is drawn on
+///   top, see [ObjectPriorityMode].
+/// - `scanline`: The scanline currently being rendered.
+/// - `window_line`: The line of the window tilemap used, if the window is rendered on this scanline.
+/// - `wy_condition_was_met_this_frame`: Whether the window y position was equal to the scanline at
+///   some point in the current frame, see [super::RenderingInfo].
+/// - `dots_in_transfer`: The number of dots spent in Transfer mode (3) on the current scanline so far.
+/// - `framebuffer`: The RGBA framebuffer of size [ORIGINAL_SCREEN_WIDTH] x [ORIGINAL_SCREEN_HEIGHT]
+///   the pixels are pushed to.
+pub(crate) struct PixelFifo {
+    background_fifo: VecDeque<u8>,
+    object_fifo: VecDeque<ObjectPixel>,
+    fetcher_step: FetcherStep,
+    fetcher_dots: u32,
+    fetcher_x: u8,
+    fetched_tile_index: u8,
+    fetched_tile_data_low: u8,
+    fetched_tile_data_high: u8,
+    fetching_window: bool,
+    pixels_to_discard: u8,
+    lcd_x: u8,
+    stall_dots: u32,
+    objects: [Option<Object>; 10],
+    object_priority_mode: ObjectPriorityMode,
+    scanline: u8,
+    window_line: u8,
+    wy_condition_was_met_this_frame: bool,
+    dots_in_transfer: u32,
+    pub(crate) framebuffer: Box<[u8]>,
+}
+
+impl PixelFifo {
+    /// Returns a new pixel FIFO with empty FIFOs and a framebuffer filled with the lightest shade.
+    pub(crate) fn new() -> Self {
+        Self {
+            background_fifo: VecDeque::with_capacity(8),
+            object_fifo: VecDeque::with_capacity(8),
+            fetcher_step: FetcherStep::GetTile,
+            fetcher_dots: 0,
+            fetcher_x: 0,
+            fetched_tile_index: 0,
+            fetched_tile_data_low: 0,
+            fetched_tile_data_high: 0,
+            fetching_window: false,
+            pixels_to_discard: 0,
+            lcd_x: 0,
+            stall_dots: 0,
+            objects: [None; 10],
+            object_priority_mode: ObjectPriorityMode::Dmg,
+            scanline: 0,
+            window_line: 0,
+            wy_condition_was_met_this_frame: false,
+            dots_in_transfer: 0,
+            framebuffer: SHADE_COLORS[0]
+                .repeat((ORIGINAL_SCREEN_WIDTH * ORIGINAL_SCREEN_HEIGHT) as usize)
+                .into_boxed_slice(),
+        }
+    }
+
+    /// Fills the framebuffer with the lightest shade, like the screen while the LCD is off.
+    pub(crate) fn clear_framebuffer(&mut self) {
+        for color in self.framebuffer.chunks_exact_mut(4) {
+            color.copy_from_slice(&SHADE_COLORS[0]);
+        }
+    }
+
+    /// Resets the FIFOs and the fetcher to prepare rendering the provided scanline. Is called when
+    /// entering Transfer mode (3).
+    ///
+    /// The objects are the ones found during OAMScan mode (2) in the format of
+    /// [PPU::get_objects_for_current_scanline](super::PPU::get_objects_for_current_scanline).
+    pub(super) fn start_scanline(
+        &mut self,
+        memory_bus: &MemoryBus,
+        scanline: u8,
+        objects_in_scanline: &[[u32; 4]; 10],
+        window_line: u8,
+        wy_condition_was_met_this_frame: bool,
+    ) {
+        self.background_fifo.clear();
+        self.object_fifo.clear();
+        self.fetcher_step = FetcherStep::GetTile;
+        self.fetcher_dots = 0;
+        self.fetcher_x = 0;
+        self.fetching_window = false;
+        self.pixels_to_discard = PPURegisters::get_bg_scroll_x(memory_bus) % 8;
+        self.lcd_x = 0;
+        self.stall_dots = DOTS_OF_INITIAL_FETCH;
+        self.scanline = scanline;
+        self.window_line = window_line;
+        self.wy_condition_was_met_this_frame = wy_condition_was_met_this_frame;
+        self.dots_in_transfer = 0;
+        self.object_priority_mode = ObjectPriorityMode::for_cartridge(memory_bus);
+
+        for (object, entry) in self.objects.iter_mut().zip(objects_in_scanline.iter()) {
+            // Unused entries are filled with zeros. Since an object with y position 0 is never
+            // visible, we can safely ignore these.
+            *object = if entry[0] == 0 {
+                None
+            } else {
+                Some(Object {
+                    y_position: entry[0] as u8,
+                    x_position: entry[1] as u8,
+                    tile_index: entry[2] as u8,
+                    attributes: entry[3] as u8,
+                })
+            };
+        }
+    }
+
+    /// Steps the pixel FIFO until the provided number of dots has been spent in Transfer mode (3)
+    /// on the current scanline or until all pixels of the scanline have been pushed to the LCD.
+    ///
+    /// Returns the total number of dots Transfer mode (3) took on this scanline, if the scanline is
+    /// finished. Otherwise, returns None.
+    pub(super) fn step(&mut self, memory_bus: &MemoryBus, dots_in_transfer: u32) -> Option<u32> {
+        while self.dots_in_transfer < dots_in_transfer {
+            self.step_dot(memory_bus);
+            self.dots_in_transfer += 1;
+            if self.lcd_x as u32 == ORIGINAL_SCREEN_WIDTH {
+                return Some(self.dots_in_transfer);
+            }
+        }
+        None
+    }
+
+    /// Returns true if the fetcher switched to fetching window tiles on the current scanline. Is used
+    /// to determine whether the window internal line counter is incremented when exiting Transfer
+    /// mode (3), since the window might have been enabled or disabled mid-scanline.
+    pub(super) fn window_was_fetched_this_scanline(&self) -> bool {
+        self.fetching_window
+    }
+
+    /// Advances the pixel pipeline by a single dot.
+    fn step_dot(&mut self, memory_bus: &MemoryBus) {
+        if self.stall_dots > 0 {
+            self.stall_dots -= 1;
+            return;
+        }
+
+        if !self.fetching_window && self.window_is_reached(memory_bus) {
+            // When the window is reached, the background FIFO is cleared and the fetcher restarts
+            // fetching tiles from the window tilemap
+            self.fetching_window = true;
+            self.background_fifo.clear();
+            self.fetcher_x = 0;
+            self.fetcher_step = FetcherStep::GetTile;
+            self.fetcher_dots = 0;
+        }
+
+        if LCDCRegister::get_object_enable_flag(memory_bus) {
+            // Objects are fetched once the pixel at their left edge is reached (or at the start of
+            // the scanline, if they are partially cut off on the left side of the screen)
+            let lcd_x = self.lcd_x;
+            if let Some((oam_position, object)) = self
+                .objects
+                .iter_mut()
+                .enumerate()
+                .find(
+                    |(_, object)| matches!(object, Some(object) if object.x_position <= lcd_x + 8),
+                )
+                .and_then(|(oam_position, object)| Some((oam_position as u8, object.take()?)))
+            {
+                self.fetch_object(memory_bus, object, oam_position);
+                self.stall_dots = DOTS_PER_OBJECT_FETCH;
+                return;
+            }
+        }
+
+        self.step_fetcher(memory_bus);
+        self.push_pixel_to_lcd(memory_bus);
+    }
+
+    /// Returns true if the window starts at the current x position of the LCD, see
+    /// [Pan Docs - Window](https://gbdev.io/pandocs/Scrolling.html#window).
+    fn window_is_reached(&self, memory_bus: &MemoryBus) -> bool {
+        let window_x_position = PPURegisters::get_window_x_position(memory_bus);
+        LCDCRegister::get_window_enable_flag(memory_bus)
+            && self.wy_condition_was_met_this_frame
+            && window_x_position < 167
+            && self.lcd_x as u16 + 7 >= window_x_position as u16
+    }
+
+    /// Advances the background/window fetcher by a single dot.
+    fn step_fetcher(&mut self, memory_bus: &MemoryBus) {
+        if self.fetcher_step == FetcherStep::Push {
+            // The fetched pixels can only be pushed once the background FIFO is empty
+            if self.background_fifo.is_empty() {
+                for bit in (0..8).rev() {
+                    let lower_bit = (self.fetched_tile_data_low >> bit) & 1;
+                    let upper_bit = (self.fetched_tile_data_high >> bit) & 1;
+                    self.background_fifo.push_back((upper_bit << 1) | lower_bit);
+                }
+                self.fetcher_x = self.fetcher_x.wrapping_add(1);
+                self.fetcher_step = FetcherStep::GetTile;
+            }
+            return;
+        }
+
+        self.fetcher_dots += 1;
+        if self.fetcher_dots < DOTS_PER_FETCHER_STEP {
+            return;
+        }
+        self.fetcher_dots = 0;
+
+        match self.fetcher_step {
+            FetcherStep::GetTile => {
+                self.fetched_tile_index = self.fetch_tile_index(memory_bus);
+                self.fetcher_step = FetcherStep::GetTileDataLow;
+            }
+            FetcherStep::GetTileDataLow => {
+                self.fetched_tile_data_low = memory_bus.memory[self.tile_row_address(memory_bus)];
+                self.fetcher_step = FetcherStep::GetTileDataHigh;
+            }
+            FetcherStep::GetTileDataHigh => {
+                self.fetched_tile_data_high =
+                    memory_bus.memory[self.tile_row_address(memory_bus) + 1];
+                self.fetcher_step = FetcherStep::Push;
+            }
+            FetcherStep::Push => {}
+        }
+    }
+
+    /// Returns the index of the tile the fetcher is currently fetching from the background or
+    /// window tilemap.
+    fn fetch_tile_index(&self, memory_bus: &MemoryBus) -> u8 {
+        let (use_tile_map_one, tile_x, tile_y) = if self.fetching_window {
+            (
+                LCDCRegister::get_window_tile_map_flag(memory_bus),
+                self.fetcher_x as usize,
+                self.window_line as usize / 8,
+            )
+        } else {
+            let scroll_x = PPURegisters::get_bg_scroll_x(memory_bus);
+            let scroll_y = PPURegisters::get_bg_scroll_y(memory_bus);
+            (
+                LCDCRegister::get_background_tile_map_flag(memory_bus),
+                (scroll_x / 8) as usize + self.fetcher_x as usize,
+                self.scanline.wrapping_add(scroll_y) as usize / 8,
+            )
+        };
+        let tile_map_start = if use_tile_map_one {
+            TILEMAP_ONE_START
+        } else {
+            TILEMAP_ZERO_START
+        };
+        memory_bus.memory[tile_map_start + (tile_y % 32) * 32 + (tile_x % 32)]
+    }
+
+    /// Returns the address of the first byte of the row of the fetched tile which is needed for the
+    /// current scanline. Switches the addressing mode automatically, according to LCDC bit 4
+    /// (background_and_window_tile_data).
+    fn tile_row_address(&self, memory_bus: &MemoryBus) -> usize {
+        let row = if self.fetching_window {
+            self.window_line % 8
+        } else {
+            self.scanline
+                .wrapping_add(PPURegisters::get_bg_scroll_y(memory_bus))
+                % 8
+        } as usize;
+        let tile_address = if LCDCRegister::get_background_and_window_tile_data_flag(memory_bus) {
+            TILE_DATA_BLOCK_0_START + self.fetched_tile_index as usize * 16
+        } else {
+            (TILE_DATA_BLOCK_2_START as isize + (self.fetched_tile_index as i8) as isize * 16)
+                as usize
+        };
+        tile_address + row * 2
+    }
+
+    /// Fetches the row of the provided object on the current scanline and mixes it into the object
+    /// FIFO. `oam_position` is the position of the object among the objects on the current
+    /// scanline.
+    ///
+    /// In [ObjectPriorityMode::Dmg], pixels already in the object FIFO which are not transparent
+    /// take precedence, since they belong to objects with a smaller x position or an earlier OAM
+    /// entry. In [ObjectPriorityMode::Cgb], they are replaced if the fetched object has an earlier
+    /// OAM entry. See
+    /// [Pan Docs - Drawing priority](https://gbdev.io/pandocs/OAM.html#drawing-priority).
+    fn fetch_object(&mut self, memory_bus: &MemoryBus, object: Object, oam_position: u8) {
+        let object_height = if LCDCRegister::get_sprite_size_flag(memory_bus) {
+            16
+        } else {
+            8
+        };
+        let x_flip = is_bit_set(object.attributes, 5);
+        let y_flip = is_bit_set(object.attributes, 6);
+
+        // The row is masked to the current object height, since the objects of this scanline were
+        // selected during OAMScan mode (2), possibly with the taller height, if LCDC.2 was
+        // cleared since then
+        let mut row =
+            (self.scanline + 16).wrapping_sub(object.y_position) as usize & (object_height - 1);
+        if y_flip {
+            row ^= object_height - 1;
+        }
+        // In 8x16 mode, the lowest bit of the tile index is ignored
+        let tile_index = if object_height == 16 {
+            object.tile_index & 0xFE
+        } else {
+            object.tile_index
+        };
+        let address = TILE_DATA_BLOCK_0_START + tile_index as usize * 16 + row * 2;
+        let tile_data_low = memory_bus.memory[address];
+        let tile_data_high = memory_bus.memory[address + 1];
+
+        while self.object_fifo.len() < 8 {
+            self.object_fifo.push_back(ObjectPixel::default());
+        }
+
+        // If the object is partially cut off on the left side of the screen, the first few pixels
+        // of the object are skipped
+        let pixels_to_skip = (self.lcd_x + 8 - object.x_position) as usize;
+        for column in pixels_to_skip..8 {
+            let bit = if x_flip { column } else { 7 - column };
+            let lower_bit = (tile_data_low >> bit) & 1;
+            let upper_bit = (tile_data_high >> bit) & 1;
+            let color = (upper_bit << 1) | lower_bit;
+            let fifo_pixel = &mut self.object_fifo[column - pixels_to_skip];
+            let replaces_fifo_pixel = match self.object_priority_mode {
+                ObjectPriorityMode::Dmg => fifo_pixel.color == 0,
+                ObjectPriorityMode::Cgb => {
+                    fifo_pixel.color == 0 || (color != 0 && oam_position < fifo_pixel.oam_position)
+                }
+            };
+            if replaces_fifo_pixel {
+                *fifo_pixel = ObjectPixel {
+                    color,
+                    palette_one: is_bit_set(object.attributes, 4),
+                    background_priority: is_bit_set(object.attributes, 7),
+                    oam_position,
+                };
+            }
+        }
+    }
+
+    /// Pops a pixel from the background FIFO (and the object FIFO), mixes them and writes the
+    /// resulting color to the framebuffer. Does nothing if the background FIFO is empty.
+    fn push_pixel_to_lcd(&mut self, memory_bus: &MemoryBus) {
+        let Some(background_color) = self.background_fifo.pop_front() else {
+            return;
+        };
+        if self.pixels_to_discard > 0 {
+            self.pixels_to_discard -= 1;
+            return;
+        }
+        let object_pixel = self.object_fifo.pop_front().unwrap_or_default();
+
+        // On the DMG, the background and window become blank (white) if they are disabled (LCDC
+        // bit 0) and the background palette is not applied. On the CGB, they are always drawn and
+        // the bit is their master priority instead: If it is cleared, objects are drawn over them
+        // regardless of the priority bits of the objects, see
+        // [Pan Docs - LCDC](https://gbdev.io/pandocs/LCDC.html#lcdc0--bg-and-window-enablepriority)
+        let lcdc_bit_0 = LCDCRegister::get_background_and_window_enable_flag(memory_bus);
+        let background_is_enabled = lcdc_bit_0 || memory_bus.cgb_mode();
+        let background_color = if background_is_enabled {
+            background_color
+        } else {
+            0
+        };
+        let background_has_priority =
+            lcdc_bit_0 && object_pixel.background_priority && background_color != 0;
+
+        let shade = if object_pixel.color != 0
+            && LCDCRegister::get_object_enable_flag(memory_bus)
+            && !background_has_priority
+        {
+            let palette = if object_pixel.palette_one {
+                PPURegisters::get_object_palette_one(memory_bus)
+            } else {
+                PPURegisters::get_object_palette_zero(memory_bus)
+            };
+            apply_palette(palette, object_pixel.color)
+        } else if background_is_enabled {
+            // Color 0 of the background and window is not transparent, but mapped through the
+            // background palette like any other color
+            apply_palette(
+                PPURegisters::get_background_palette(memory_bus),
+                background_color,
+            )
+        } else {
+            0
+        };
+
+        // If the Super Game Boy palettes are active, the shade is mapped to the respective color
+        let color = match memory_bus
+            .sgb_palettes
+            .as_ref()
+            .and_then(SgbPalettes::playfield_colors)
+        {
+            Some(sgb_colors) => sgb_colors[shade as usize],
+            None => SHADE_COLORS[shade as usize],
+        };
+
+        let framebuffer_index =
+            (self.scanline as usize * ORIGINAL_SCREEN_WIDTH as usize + self.lcd_x as usize) * 4;
+        self.framebuffer[framebuffer_index..framebuffer_index + 4].copy_from_slice(&color);
+        self.lcd_x += 1;
+    }
+
+    /// Writes the state of the FIFOs, the fetcher and the framebuffer to the provided save state,
+    /// see [crate::save_state]. States can be saved in the middle of a scanline (e.g. when a
+    /// breakpoint is hit), so the state of the scanline being rendered is saved as well.
+    pub(super) fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_u8(self.background_fifo.len() as u8);
+        for color in &self.background_fifo {
+            writer.write_u8(*color);
+        }
+        writer.write_u8(self.object_fifo.len() as u8);
+        for pixel in &self.object_fifo {
+            writer.write_u8(pixel.color);
+            writer.write_bool(pixel.palette_one);
+            writer.write_bool(pixel.background_priority);
+            writer.write_u8(pixel.oam_position);
+        }
+        writer.write_u8(self.fetcher_step as u8);
+        writer.write_u32(self.fetcher_dots);
+        writer.write_u8(self.fetcher_x);
+        writer.write_u8(self.fetched_tile_index);
+        writer.write_u8(self.fetched_tile_data_low);
+        writer.write_u8(self.fetched_tile_data_high);
+        writer.write_bool(self.fetching_window);
+        writer.write_u8(self.pixels_to_discard);
+        writer.write_u8(self.lcd_x);
+        writer.write_u32(self.stall_dots);
+        for object in &self.objects {
+            writer.write_bool(object.is_some());
+            let object = object.unwrap_or_default();
+            writer.write_u8(object.y_position);
+            writer.write_u8(object.x_position);
+            writer.write_u8(object.tile_index);
+            writer.write_u8(object.attributes);
+        }
+        writer.write_bool(self.object_priority_mode == ObjectPriorityMode::Cgb);
+        writer.write_u8(self.scanline);
+        writer.write_u8(self.window_line);
+        writer.write_bool(self.wy_condition_was_met_this_frame);
+        writer.write_u32(self.dots_in_transfer);
+        writer.write_bytes(&self.framebuffer);
+    }
+
+    /// Restores the state written by [PixelFifo::save_state].
+    pub(super) fn load_state(&mut self, reader: &mut StateReader) -> Result<(), RustBoyError> {
+        let background_fifo_length = reader.read_u8()?;
+        self.background_fifo.clear();
+        for _ in 0..background_fifo_length {
+            self.background_fifo.push_back(reader.read_u8()?);
+        }
+        let object_fifo_length = reader.read_u8()?;
+        self.object_fifo.clear();
+        for _ in 0..object_fifo_length {
+            self.object_fifo.push_back(ObjectPixel {
+                color: reader.read_u8()?,
+                palette_one: reader.read_bool()?,
+                background_priority: reader.read_bool()?,
+                oam_position: reader.read_u8()?,
+            });
+        }
+        self.fetcher_step = match reader.read_u8()? {
+            0 => FetcherStep::GetTile,
+            1 => FetcherStep::GetTileDataLow,
+            2 => FetcherStep::GetTileDataHigh,
+            3 => FetcherStep::Push,
+            _ => {
+                return Err(RustBoyError::InvalidSaveState(
+                    "the step of the pixel fetcher is invalid",
+                ));
+            }
+        };
+        self.fetcher_dots = reader.read_u32()?;
+        self.fetcher_x = reader.read_u8()?;
+        self.fetched_tile_index = reader.read_u8()?;
+        self.fetched_tile_data_low = reader.read_u8()?;
+        self.fetched_tile_data_high = reader.read_u8()?;
+        self.fetching_window = reader.read_bool()?;
+        self.pixels_to_discard = reader.read_u8()?;
+        self.lcd_x = reader.read_u8()?;
+        self.stall_dots = reader.read_u32()?;
+        for object in &mut self.objects {
+            let is_some = reader.read_bool()?;
+            let read_object = Object {
+                y_position: reader.read_u8()?,
+                x_position: reader.read_u8()?,
+                tile_index: reader.read_u8()?,
+                attributes: reader.read_u8()?,
+            };
+            *object = is_some.then_some(read_object);
+        }
+        self.object_priority_mode = if reader.read_bool()? {
+            ObjectPriorityMode::Cgb
+        } else {
+            ObjectPriorityMode::Dmg
+        };
+        self.scanline = reader.read_u8()?;
+        self.window_line = reader.read_u8()?;
+        self.wy_condition_was_met_this_frame = reader.read_bool()?;
+        self.dots_in_transfer = reader.read_u32()?;
+        reader.read_bytes_into(&mut self.framebuffer)
+    }
+}
+
+/// Returns the shade (0 - 3) the provided palette maps the color index (0 - 3) to, see
+/// [Pan Docs - Palettes](https://gbdev.io/pandocs/Palettes.html).
+fn apply_palette(palette: u8, color: u8) -> u8 {
+    (palette >> (color * 2)) & 0b11
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::RustBoy;
+    use crate::debugging::DebugInfo;
+    use crate::tests::rust_boy_with_program;
+
+    /// The value of LCDC after the boot ROM, with the LCD, the background and 8x8 objects turned
+    /// on, but without objects being enabled.
+    const LCD_CONTROL_AFTER_BOOT: u8 = 0x91;
+    /// The bit of LCDC which enables the objects.
+    const OBJECT_ENABLE: u8 = 1 << 1;
+    /// The bit of LCDC which selects 8x16 objects.
+    const OBJECT_SIZE: u8 = 1 << 2;
+    /// The bit of the object attributes which selects OBP1 instead of OBP0.
+    const OBJECT_PALETTE_ONE: u8 = 1 << 4;
+    /// The bit of the object attributes which flips the object horizontally.
+    const X_FLIP: u8 = 1 << 5;
+    /// The bit of the object attributes which flips the object vertically.
+    const Y_FLIP: u8 = 1 << 6;
+    /// The palette of the background and OBP0, which maps each color to the shade of the same
+    /// value.
+    const IDENTITY_PALETTE: u8 = 0b11_10_01_00;
+
+    /// Returns a tile whose rows are all the provided pair of bytes, that is, the low and high
+    /// bits of the colors of the row.
+    fn tile_with_rows(row: (u8, u8)) -> [u8; 16] {
+        [row.0, row.1].repeat(8).try_into().unwrap()
+    }
+
+    /// Renders a frame with the pixel FIFO after writing the provided tiles to the tile data at
+    /// 0x8000 and calling the provided function to set up the rest of the memory. The VRAM is empty
+    /// otherwise, so the background only consists of tile 0 and color 0. The background and both
+    /// object palettes use the [IDENTITY_PALETTE], unless the provided function changes them.
+    fn render(
+        lcd_control: u8,
+        tiles: &[(u8, [u8; 16])],
+        set_up: impl FnOnce(&mut MemoryBus),
+    ) -> RustBoy {
+        // JR -2, so the CPU keeps looping at 0x0100
+        let mut rust_boy = rust_boy_with_program(&[0x18, 0xFE]);
+        for (tile_index, tile) in tiles {
+            let start = TILE_DATA_BLOCK_0_START + *tile_index as usize * 16;
+            rust_boy.memory_bus.memory[start..start + 16].copy_from_slice(tile);
+        }
+        rust_boy.memory_bus.write_byte(0xFF40, lcd_control);
+        rust_boy.memory_bus.write_byte(0xFF47, IDENTITY_PALETTE);
+        rust_boy.memory_bus.write_byte(0xFF48, IDENTITY_PALETTE);
+        rust_boy.memory_bus.write_byte(0xFF49, IDENTITY_PALETTE);
+        set_up(&mut rust_boy.memory_bus);
+        // The first frame might have started before the VRAM was written
+        rust_boy.run_frames(2).expect("The ROM should run");
+        rust_boy
+    }
+
+    /// Renders a frame like [render] with the provided objects written to OAM. OBP1 uses the
+    /// provided palette.
+    fn render_objects(
+        lcd_control: u8,
+        object_palette_one: u8,
+        tiles: &[(u8, [u8; 16])],
+        objects: &[Object],
+    ) -> RustBoy {
+        render(lcd_control, tiles, |memory_bus| {
+            for (index, object) in objects.iter().enumerate() {
+                memory_bus.memory[0xFE00 + index * 4..0xFE00 + index * 4 + 4].copy_from_slice(&[
+                    object.y_position,
+                    object.x_position,
+                    object.tile_index,
+                    object.attributes,
+                ]);
+            }
+            memory_bus.write_byte(0xFF49, object_palette_one);
+        })
+    }
+
+    /// Returns the shade of the pixel at the provided position of the last frame rendered by the
+    /// provided RustBoy.
+    fn shade_at(rust_boy: &RustBoy, x: usize, y: usize) -> usize {
+        let start = (y * ORIGINAL_SCREEN_WIDTH as usize + x) * 4;
+        let framebuffer = rust_boy
+            .framebuffer()
+            .expect("The pixel FIFO should be used");
+        SHADE_COLORS
+            .iter()
+            .position(|color| color[..] == framebuffer[start..start + 4])
+            .expect("The pixel should have one of the shades")
+    }
+
+    /// Fetches the object at the top left of the screen on scanline 12 with 8x8 objects, as if it
+    /// was selected with 8x16 objects during OAMScan mode (2). Returns the colors pushed to the
+    /// object FIFO. Only row 3 of tile 0 has color 1, all other rows have color 2.
+    fn fetch_object_after_height_change(attributes: u8) -> Vec<u8> {
+        let mut memory_bus = MemoryBus::new_before_boot(&DebugInfo::default());
+        // LCD on with 8x8 objects
+        memory_bus.memory[0xFF40] = 0x80;
+        for row in 0..16 {
+            let (low, high) = if row == 3 { (0xFF, 0x00) } else { (0x00, 0xFF) };
+            memory_bus.memory[TILE_DATA_BLOCK_0_START + row * 2] = low;
+            memory_bus.memory[TILE_DATA_BLOCK_0_START + row * 2 + 1] = high;
+        }
+        let mut pixel_fifo = PixelFifo::new();
+        pixel_fifo.scanline = 12;
+        let object = Object {
+            y_position: 16,
+            x_position: 8,
+            tile_index: 0,
+            attributes,
+        };
+        pixel_fifo.fetch_object(&memory_bus, object, 0);
+        pixel_fifo
+            .object_fifo
+            .iter()
+            .map(|pixel| pixel.color)
+            .collect()
+    }
+
+    #[test]
+    fn object_row_is_masked_to_the_current_object_height() {
+        // Row 12 of an 8x16 object is row 4 of an 8x8 object
+        assert_eq!(fetch_object_after_height_change(0x00), vec![2; 8]);
+        // Flipped vertically, row 4 becomes row 3
+        assert_eq!(fetch_object_after_height_change(0x40), vec![1; 8]);
+    }
+
+    #[test]
+    fn objects_use_the_object_palette_selected_by_their_attributes() {
+        // Maps color 0 to shade 3, which must not be visible, since color 0 is transparent. Color
+        // 1 is mapped to shade 2 and color 3 to shade 3
+        let object_palette_one = 0b11_00_10_11;
+        let rust_boy = render_objects(
+            LCD_CONTROL_AFTER_BOOT | OBJECT_ENABLE,
+            object_palette_one,
+            &[
+                (1, tile_with_rows((0xFF, 0x00))),
+                // Color 0 on the left half and color 3 on the right half
+                (2, tile_with_rows((0x0F, 0x0F))),
+            ],
+            &[
+                Object {
+                    y_position: 16,
+                    x_position: 8,
+                    tile_index: 1,
+                    attributes: 0,
+                },
+                Object {
+                    y_position: 16,
+                    x_position: 24,
+                    tile_index: 1,
+                    attributes: OBJECT_PALETTE_ONE,
+                },
+                Object {
+                    y_position: 16,
+                    x_position: 40,
+                    tile_index: 2,
+                    attributes: OBJECT_PALETTE_ONE,
+                },
+            ],
+        );
+        for y in 0..8 {
+            for x in 0..8 {
+                assert_eq!(shade_at(&rust_boy, x, y), 1);
+                assert_eq!(shade_at(&rust_boy, 16 + x, y), 2);
+                let expected_shade = if x < 4 { 0 } else { 3 };
+                assert_eq!(shade_at(&rust_boy, 32 + x, y), expected_shade);
+            }
+            // Next to the objects, the background is visible
+            assert_eq!(shade_at(&rust_boy, 8, y), 0);
+        }
+    }
+
+    #[test]
+    fn tall_objects_ignore_the_lowest_bit_of_the_tile_index() {
+        let rust_boy = render_objects(
+            LCD_CONTROL_AFTER_BOOT | OBJECT_ENABLE | OBJECT_SIZE,
+            IDENTITY_PALETTE,
+            &[
+                // Tile 2 has color 1 and tile 3 has color 2
+                (2, tile_with_rows((0xFF, 0x00))),
+                (3, tile_with_rows((0x00, 0xFF))),
+            ],
+            &[
+                Object {
+                    y_position: 16,
+                    x_position: 8,
+                    tile_index: 3,
+                    attributes: 0,
+                },
+                Object {
+                    y_position: 16,
+                    x_position: 24,
+                    tile_index: 3,
+                    attributes: Y_FLIP,
+                },
+            ],
+        );
+        for y in 0..16 {
+            // The top half is tile 2 and the bottom half is tile 3, which is swapped by flipping
+            let (top_shade, bottom_shade) = (1, 2);
+            let (expected_shade, expected_flipped_shade) = if y < 8 {
+                (top_shade, bottom_shade)
+            } else {
+                (bottom_shade, top_shade)
+            };
+            assert_eq!(shade_at(&rust_boy, 0, y), expected_shade);
+            assert_eq!(shade_at(&rust_boy, 16, y), expected_flipped_shade);
+        }
+        assert_eq!(shade_at(&rust_boy, 0, 16), 0);
+    }
+
+    #[test]
+    fn background_palette_remaps_color_0() {
+        // All tiles of the background are tile 0, which only consists of color 0
+        let rust_boy = render(LCD_CONTROL_AFTER_BOOT, &[], |memory_bus| {
+            memory_bus.write_byte(0xFF47, 0x03);
+        });
+        for y in 0..ORIGINAL_SCREEN_HEIGHT as usize {
+            for x in 0..ORIGINAL_SCREEN_WIDTH as usize {
+                assert_eq!(shade_at(&rust_boy, x, y), 3);
+            }
+        }
+    }
+
+    #[test]
+    fn background_wraps_around_horizontally() {
+        let rust_boy = render(
+            LCD_CONTROL_AFTER_BOOT,
+            &[
+                // Color 0 on the left half and color 3 on the right half
+                (1, tile_with_rows((0x0F, 0x0F))),
+                (2, tile_with_rows((0x00, 0xFF))),
+            ],
+            |memory_bus| {
+                memory_bus.memory[TILEMAP_ZERO_START + 31] = 1;
+                memory_bus.memory[TILEMAP_ZERO_START] = 2;
+                memory_bus.write_byte(0xFF43, 252);
+            },
+        );
+        for y in 0..8 {
+            // The right half of column 31, followed by column 0 and column 1
+            for x in 0..4 {
+                assert_eq!(shade_at(&rust_boy, x, y), 3);
+            }
+            for x in 4..12 {
+                assert_eq!(shade_at(&rust_boy, x, y), 2);
+            }
+            assert_eq!(shade_at(&rust_boy, 12, y), 0);
+        }
+    }
+
+    #[test]
+    fn background_wraps_around_vertically() {
+        // Color 1 in the upper half and color 3 in the lower half
+        let mut upper_and_lower_half = tile_with_rows((0xFF, 0x00));
+        upper_and_lower_half[8..].fill(0xFF);
+        let rust_boy = render(
+            LCD_CONTROL_AFTER_BOOT,
+            &[(1, upper_and_lower_half), (2, tile_with_rows((0x00, 0xFF)))],
+            |memory_bus| {
+                memory_bus.memory[TILEMAP_ZERO_START + 31 * 32] = 1;
+                memory_bus.memory[TILEMAP_ZERO_START] = 2;
+                memory_bus.write_byte(0xFF42, 252);
+            },
+        );
+        for x in 0..8 {
+            // The lower half of row 31, followed by row 0 and row 1
+            for y in 0..4 {
+                assert_eq!(shade_at(&rust_boy, x, y), 3);
+            }
+            for y in 4..12 {
+                assert_eq!(shade_at(&rust_boy, x, y), 2);
+            }
+            assert_eq!(shade_at(&rust_boy, x, 12), 0);
+        }
+    }
+
+    #[test]
+    fn objects_are_flipped_by_their_attributes() {
+        // An arrow pointing to the bottom left: Row r has color 1 in its first r + 1 pixels
+        let mut arrow = [0; 16];
+        for row in 0..8 {
+            arrow[row * 2] = 0xFF << (7 - row);
+        }
+        let rust_boy = render_objects(
+            LCD_CONTROL_AFTER_BOOT | OBJECT_ENABLE,
+            IDENTITY_PALETTE,
+            &[(1, arrow)],
+            &[0, X_FLIP, Y_FLIP]
+                .iter()
+                .enumerate()
+                .map(|(index, attributes)| Object {
+                    y_position: 16,
+                    x_position: 8 + 16 * index as u8,
+                    tile_index: 1,
+                    attributes: *attributes,
+                })
+                .collect::<Vec<_>>(),
+        );
+        for y in 0..8 {
+            for x in 0..8 {
+                let shade = |is_drawn: bool| if is_drawn { 1 } else { 0 };
+                assert_eq!(shade_at(&rust_boy, x, y), shade(x <= y));
+                assert_eq!(shade_at(&rust_boy, 16 + x, y), shade(7 - x <= y));
+                assert_eq!(shade_at(&rust_boy, 32 + x, y), shade(x <= 7 - y));
+            }
+        }
+    }
+}
@@ -19,9 +19,12 @@ const WINDOW_X_POSITION_REGISTER_ADDRESS: usize = 0xFF4B;
 // Positions of the bits in the LCD Control register
 const LCD_ENABLE_BIT_POSITION: usize = 7;
 const WINDOW_TILE_MAP_BIT_POSITION: usize = 6;
+const WINDOW_ENABLE_BIT_POSITION: usize = 5;
 const BG_AND_WINDOW_TILE_DATA_BIT_POSITION: usize = 4;
 const BG_TILE_MAP_BIT_POSITION: usize = 3;
 const OBJ_SIZE_BIT_POSITION: usize = 2;
+const OBJ_ENABLE_BIT_POSITION: usize = 1;
+const BG_AND_WINDOW_ENABLE_BIT_POSITION: usize = 0;
 
 // Positions of the bits in the LCD Status register
 const LYC_LY_COINCIDENCE_FLAG_BIT_POSITION: usize = 2;
@@ -102,10 +105,15 @@ impl PPU {
             0xFF49 => PPURegisters::get_object_palette_one(memory_bus),
             0xFF4A => PPURegisters::get_window_y_position(memory_bus),
             0xFF4B => PPURegisters::get_window_x_position(memory_bus),
-            _ => panic!(
-                "Reading from invalid PPU register address: {:#04X}",
-                address
-            ),
+            // The memory bus only forwards the addresses of the PPU registers
+            _ => {
+                debug_assert!(
+                    false,
+                    "Reading from invalid PPU register address: {:#04X}",
+                    address
+                );
+                0xFF
+            }
         }
     }
 
@@ -127,7 +135,12 @@ impl PPU {
             0xFF49 => PPURegisters::set_object_palette_one(memory_bus, value),
             0xFF4A => PPURegisters::set_window_y_position(memory_bus, value),
             0xFF4B => PPURegisters::set_window_x_position(memory_bus, value),
-            _ => panic!("Writing to invalid PPU register address: {:#04X}", address),
+            // The memory bus only forwards the addresses of the PPU registers
+            _ => debug_assert!(
+                false,
+                "Writing to invalid PPU register address: {:#04X}",
+                address
+            ),
         }
     }
 }
@@ -206,7 +219,8 @@ impl PPURegisters {
     /// Set the current scanline register to the provided value.
     ///
     /// Possibly sets the lyc ly coincidence flag in the LCD status register, if the current scanline
-    /// is equal to the LY Compare register.
+    /// is equal to the LY Compare register. This happens on every scanline change, including the
+    /// VBlank lines 144 - 153.
     pub(super) fn set_scanline(memory_bus: &mut MemoryBus, value: u8) {
         memory_bus.memory[SCANLINE_REGISTER_ADDRESS] = value;
         LCDStatusRegister::set_lyc_ly_coincidence_flag(
@@ -229,12 +243,25 @@ impl PPURegisters {
         );
     }
 
+    /// Set the GPU/PPU Mode to the provided value without requesting any interrupt.
+    ///
+    /// This is used when the LCD is turned on. The PPU then starts line 0 in HBlank mode, which is
+    /// not actually entered, so no mode 0 STAT interrupt is requested, see [PPU::ppu_step].
+    pub(crate) fn reset_ppu_mode(memory_bus: &mut MemoryBus, mode: RenderingMode) {
+        LCDStatusRegister::set_ppu_mode(memory_bus, mode);
+    }
+
     /// Set the GPU/PPU Mode to the provided value.
     ///
     /// Possibly sets an interrupt flag in the interrupt flag register depending on the mode and the
-    /// interrupt select flags in the LCD status register.
+    /// interrupt select flags in the LCD status register. While the LCD is turned off, no
+    /// interrupts are requested, since the mode is only reset and not actually entered. Entering
+    /// VBlank requests the STAT interrupt if the mode 1 or the mode 2 interrupt is selected.
     pub(crate) fn set_ppu_mode(memory_bus: &mut MemoryBus, mode: RenderingMode) {
         LCDStatusRegister::set_ppu_mode(memory_bus, mode);
+        if !LCDCRegister::get_display_on_flag(memory_bus) {
+            return;
+        }
         match mode {
             RenderingMode::HBlank0 => {
                 if LCDStatusRegister::get_mode_0_int_select(memory_bus) {
@@ -242,7 +269,14 @@ impl PPURegisters {
                 }
             }
             RenderingMode::VBlank1 => {
-                if LCDStatusRegister::get_mode_1_int_select(memory_bus) {
+                // On the DMG, the mode 2 interrupt is requested at the start of line 144 as well,
+                // since the PPU starts an OAM scan there, before it realizes that VBlank begins.
+                // So with only the mode 2 interrupt selected, it is requested once per visible
+                // line and once more on entering VBlank, see
+                // [Pan Docs - STAT modes](https://gbdev.io/pandocs/STAT.html#stat-modes)
+                if LCDStatusRegister::get_mode_1_int_select(memory_bus)
+                    || LCDStatusRegister::get_mode_2_int_select(memory_bus)
+                {
                     InterruptFlagRegister::set_flag(memory_bus, Interrupt::LcdStat, true);
                 }
             }
@@ -322,17 +356,15 @@ impl PPURegisters {
 
     /// Get the LCD Status register.
     ///
-    /// If the LCD is turned off, we return VBlank mode (0b01) as the current mode (lower two
-    /// bits of the LCD status register), because the CPU might read this register before the
-    /// GPU has a chance to update it.
+    /// If the LCD is turned off, we return [PPU_MODE_WHILE_LCD_TURNED_OFF] as the current mode
+    /// (lower two bits of the LCD status register), because the CPU might read this register
+    /// before the PPU has a chance to update it.
     pub fn get_lcd_status(memory_bus: &MemoryBus) -> u8 {
-        let before_lcd_enable = memory_bus.memory[LCD_STATUS_REGISTER_ADDRESS];
+        let lcd_status = memory_bus.memory[LCD_STATUS_REGISTER_ADDRESS];
         if LCDCRegister::get_display_on_flag(memory_bus) {
-            // If the LCD is turned off, we return VBlank mode (0b01) as the current mode (lower two
-            // bits of the LCD status register)
-            before_lcd_enable & (0b1111_1100 | PPU_MODE_WHILE_LCD_TURNED_OFF.as_u8())
+            lcd_status
         } else {
-            before_lcd_enable
+            (lcd_status & 0b1111_1100) | PPU_MODE_WHILE_LCD_TURNED_OFF.as_u8()
         }
     }
 
@@ -441,6 +473,33 @@ impl LCDCRegister {
             LCD_ENABLE_BIT_POSITION as u8,
         )
     }
+
+    /// Returns the state of the window enable flag.
+    pub fn get_window_enable_flag(memory_bus: &MemoryBus) -> bool {
+        is_bit_set(
+            memory_bus.memory[LCDC_REGISTER_ADDRESS],
+            WINDOW_ENABLE_BIT_POSITION as u8,
+        )
+    }
+
+    /// Returns the state of the object/sprite enable flag.
+    pub fn get_object_enable_flag(memory_bus: &MemoryBus) -> bool {
+        is_bit_set(
+            memory_bus.memory[LCDC_REGISTER_ADDRESS],
+            OBJ_ENABLE_BIT_POSITION as u8,
+        )
+    }
+
+    /// Returns the state of the background and window enable flag. In CGB mode (see
+    /// [MemoryBus::cgb_mode]), this bit does not disable the background and window, but is their
+    /// master priority instead: If it is cleared, objects are always drawn over them, see
+    /// [Pan Docs - LCDC](https://gbdev.io/pandocs/LCDC.html#cgb-mode-bg-and-window-master-priority).
+    pub fn get_background_and_window_enable_flag(memory_bus: &MemoryBus) -> bool {
+        is_bit_set(
+            memory_bus.memory[LCDC_REGISTER_ADDRESS],
+            BG_AND_WINDOW_ENABLE_BIT_POSITION as u8,
+        )
+    }
 }
 
 impl LCDStatusRegister {
@@ -500,10 +559,13 @@ impl LCDStatusRegister {
                 LYC_LY_COINCIDENCE_FLAG_BIT_POSITION as u8,
             )
         };
-        if value {
-            if LCDStatusRegister::get_lyc_int_select(memory_bus) {
-                InterruptFlagRegister::set_flag(memory_bus, Interrupt::LcdStat, true);
-            }
+        // While the LCD is turned off, no STAT interrupts are requested. Instead, the comparison
+        // is evaluated again when the LCD is turned on
+        if value
+            && LCDStatusRegister::get_lyc_int_select(memory_bus)
+            && LCDCRegister::get_display_on_flag(memory_bus)
+        {
+            InterruptFlagRegister::set_flag(memory_bus, Interrupt::LcdStat, true);
         }
     }
 
@@ -521,3 +583,46 @@ impl LCDStatusRegister {
         register
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::RustBoy;
+    use crate::debugging::DebugInfo;
+
+    /// The PPU registers which can be read and written freely, that is, SCY, SCX, LYC, BGP,
+    /// OBP0, OBP1, WY and WX.
+    const READ_WRITE_REGISTER_ADDRESSES: [u16; 8] = [
+        0xFF42, 0xFF43, 0xFF45, 0xFF47, 0xFF48, 0xFF49, 0xFF4A, 0xFF4B,
+    ];
+    const LCD_STATUS_REGISTER_ADDRESS: u16 = 0xFF41;
+    /// The bits of STAT which hold the PPU mode and the LY=LYC flag and are read-only.
+    const READ_ONLY_LCD_STATUS_BITS: u8 = 0b0000_0111;
+
+    #[test]
+    fn ppu_registers_are_read_and_written_through_the_memory_bus() {
+        let mut rust_boy = RustBoy::new_after_boot(DebugInfo::default());
+        for address in READ_WRITE_REGISTER_ADDRESSES {
+            for value in [0x00, 0xA5, 0xFF] {
+                rust_boy.memory_bus.write_byte(address, value);
+                assert_eq!(rust_boy.memory_bus.read_byte(address), value);
+            }
+        }
+    }
+
+    #[test]
+    fn writes_to_stat_keep_the_read_only_bits() {
+        let mut rust_boy = RustBoy::new_after_boot(DebugInfo::default());
+        for value in [0x00, 0xFF] {
+            let read_only_bits = rust_boy.memory_bus.read_byte(LCD_STATUS_REGISTER_ADDRESS)
+                & READ_ONLY_LCD_STATUS_BITS;
+            rust_boy
+                .memory_bus
+                .write_byte(LCD_STATUS_REGISTER_ADDRESS, value);
+            // Bit 7 is unused and reads as 1
+            assert_eq!(
+                rust_boy.memory_bus.read_byte(LCD_STATUS_REGISTER_ADDRESS),
+                0x80 | value & !READ_ONLY_LCD_STATUS_BITS | read_only_bits
+            );
+        }
+    }
+}
@@ -219,3 +219,95 @@ pub type Tile = [[TilePixelValue; 8]; 8];
 pub fn empty_tile() -> Tile {
     [[TilePixelValue::Zero; 8]; 8]
 }
+
+/// Characters used to print the four shades of tile pixels in [tile_data_to_string], from
+/// lightest ([TilePixelValue::Zero]) to darkest ([TilePixelValue::Three]).
+const TILE_PIXEL_CHARACTERS: [char; 4] = ['.', '-', '+', '#'];
+/// The number of tiles printed next to each other in a row by [tile_data_to_string].
+const TILES_PER_ROW_IN_STRING: usize = 16;
+
+/// Returns the provided tilemap (32x32 tile indices) as a grid of hexadecimal tile indices. Each
+/// line of the string corresponds to one row of tiles of the tilemap. Is used for debugging.
+pub fn tile_map_to_string(tile_map: &[u8; 1024]) -> String {
+    tile_map
+        .chunks_exact(32)
+        .map(|row| {
+            row.iter()
+                .map(|tile_index| format!("{tile_index:02X}"))
+                .collect::<Vec<_>>()
+                .join(" ")
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Returns the provided tile data (16 bytes per tile, as stored in VRAM) decoded as ASCII art,
+/// with [TILES_PER_ROW_IN_STRING] tiles per row. Each block of tiles is preceded by the index of
+/// its first tile (relative to the start of the provided tile data) and the pixels are printed
+/// using [TILE_PIXEL_CHARACTERS]. Is used for debugging.
+pub fn tile_data_to_string(tile_data: &[u8]) -> String {
+    let tiles = tile_data
+        .chunks_exact(16)
+        .map(decode_tile)
+        .collect::<Vec<Tile>>();
+    let mut string = String::new();
+    for (block_index, tiles_in_row) in tiles.chunks(TILES_PER_ROW_IN_STRING).enumerate() {
+        string.push_str(&format!(
+            "Tiles {:#04X}:\n",
+            block_index * TILES_PER_ROW_IN_STRING
+        ));
+        for row_index in 0..8 {
+            let row = tiles_in_row
+                .iter()
+                .map(|tile| {
+                    tile[row_index]
+                        .iter()
+                        .map(|pixel| TILE_PIXEL_CHARACTERS[*pixel as usize])
+                        .collect::<String>()
+                })
+                .collect::<Vec<_>>()
+                .join(" ");
+            string.push_str(&row);
+            string.push('\n');
+        }
+    }
+    string
+}
+
+/// Decodes a single tile from the 16 bytes it is stored in, in VRAM. See
+/// [PPU::handle_tile_data_change] for the encoding.
+fn decode_tile(bytes: &[u8]) -> Tile {
+    let mut tile = empty_tile();
+    for (row, row_bytes) in tile.iter_mut().zip(bytes.chunks_exact(2)) {
+        for (pixel_index, pixel) in row.iter_mut().enumerate() {
+            let mask = 1 << (7 - pixel_index);
+            *pixel = TilePixelValue::from_bits(row_bytes[0] & mask, row_bytes[1] & mask);
+        }
+    }
+    tile
+}
+
+impl PPU {
+    /// Logs the tilemaps and the tile data currently used for the background and window, as well
+    /// as the tile data used for objects, using [tile_map_to_string] and [tile_data_to_string].
+    /// Is triggered by pressing `T` and is used for debugging.
+    #[cfg(feature = "frontend")]
+    pub(crate) fn log_tile_debug_dump(memory_bus: &MemoryBus) {
+        log::info!(
+            "Background tilemap:\n{}",
+            tile_map_to_string(&PPU::get_background_tile_map(memory_bus))
+        );
+        log::info!(
+            "Window tilemap:\n{}",
+            tile_map_to_string(&PPU::get_window_tile_map(memory_bus))
+        );
+        log::info!(
+            "Background and window tile data:\n{}",
+            tile_data_to_string(&PPU::get_background_and_window_tile_data(memory_bus))
+        );
+        log::info!(
+            "Object tile data:\n{}",
+            tile_data_to_string(&PPU::get_object_tile_data(memory_bus))
+        );
+    }
+}
@@ -0,0 +1,83 @@
+//! This module contains the [RomWatcher] struct, which watches the ROM file for changes, so it can
+//! be reloaded automatically (see the `--WATCH` option). This speeds up the build-test loop when
+//! developing homebrew games, since the emulator does not have to be restarted after each build.
+//!
+//! The directory of the ROM is watched instead of the file itself, since many tools (e.g. linkers)
+//! replace the file instead of writing to it, after which a watch on the file itself would be
+//! lost. Since a file is usually written in several steps, the ROM is only reloaded once no
+//! change happened for [RELOAD_DELAY], so a half written ROM is not loaded.
+
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{Receiver, channel};
+use std::time::Duration;
+
+use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use wasm_timer::Instant;
+
+/// The time to wait after the last change of the ROM file before it is reloaded.
+const RELOAD_DELAY: Duration = Duration::from_millis(200);
+
+/// Struct to watch the ROM file for changes.
+///
+/// - `path`: The path of the watched ROM file.
+/// - `_watcher`: The watcher of the directory of the ROM file, which sends the file system events
+///   to `events`. It stops watching once dropped, so it has to be kept.
+/// - `events`: The receiving end of the file system events of the watcher.
+/// - `time_of_last_change`: The point in time the ROM file last changed, if it did not get
+///   reloaded since then.
+pub(crate) struct RomWatcher {
+    path: PathBuf,
+    _watcher: RecommendedWatcher,
+    events: Receiver<notify::Result<Event>>,
+    time_of_last_change: Option<Instant>,
+}
+
+impl RomWatcher {
+    /// Starts watching the ROM file at the provided path. Returns an error, if the directory of
+    /// the file can not be watched.
+    pub(crate) fn new(path: &str) -> notify::Result<RomWatcher> {
+        // The events report absolute paths, so the path is made absolute to compare them
+        let path = std::path::absolute(path)?;
+        let directory = path.parent().unwrap_or(Path::new("/")).to_path_buf();
+        let (sender, events) = channel();
+        let mut watcher = notify::recommended_watcher(sender)?;
+        watcher.watch(&directory, RecursiveMode::NonRecursive)?;
+        Ok(RomWatcher {
+            path,
+            _watcher: watcher,
+            events,
+            time_of_last_change: None,
+        })
+    }
+
+    /// Returns the path of the watched ROM file.
+    pub(crate) fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns true if the ROM file changed and should be reloaded, that is, if no further change
+    /// happened for [RELOAD_DELAY]. Does not block, so it can be called on every frame.
+    pub(crate) fn rom_changed(&mut self) -> bool {
+        while let Ok(event) = self.events.try_recv() {
+            match event {
+                // Accesses are ignored, since reading the ROM to reload it is one as well
+                Ok(event)
+                    if matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_))
+                        && event.paths.contains(&self.path) =>
+                {
+                    self.time_of_last_change = Some(Instant::now());
+                }
+                Ok(_) => {}
+                Err(error) => log::warn!("Failed to watch the ROM file: {error}"),
+            }
+        }
+
+        match self.time_of_last_change {
+            Some(time_of_last_change) if time_of_last_change.elapsed() >= RELOAD_DELAY => {
+                self.time_of_last_change = None;
+                true
+            }
+            _ => false,
+        }
+    }
+}
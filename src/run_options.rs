@@ -0,0 +1,165 @@
+//! This module contains the [RunOptions] struct, which holds the options the emulator is started
+//! with by [run](crate::run). Natively, they are parsed from the command line. On the web, they
+//! are created from JavaScript using `new RunOptions()` and adjusted by setting their fields.
+
+#[cfg(target_arch = "wasm32")]
+use wasm_bindgen::prelude::*;
+
+use crate::frontend::PresentMode;
+use crate::input::{DEFAULT_AUTO_FIRE_FRAMES, SocdMode};
+use crate::ppu::DOTS_PER_M_CYCLE;
+
+/// Struct to represent the options the emulator is started with, see [run](crate::run). The
+/// defaults (see [RunOptions::new]) run the emulator in a window without any debugging options,
+/// saving, cheats or link partner.
+#[cfg_attr(target_arch = "wasm32", wasm_bindgen(getter_with_clone))]
+#[derive(Clone, Debug)]
+pub struct RunOptions {
+    /// If true, the emulator runs in headless mode. That is, without opening a window and
+    /// therefore not showing the graphics.
+    pub headless: bool,
+    /// See [DebugInfo](crate::debugging::DebugInfo) for more information.
+    pub game_boy_doctor_mode: bool,
+    /// See [DebugInfo](crate::debugging::DebugInfo) for more information.
+    pub file_logs: bool,
+    /// See [DebugInfo](crate::debugging::DebugInfo) for more information.
+    pub json_trace: bool,
+    /// See [DebugInfo](crate::debugging::DebugInfo) for more information.
+    pub binjgb_mode: bool,
+    /// See [DebugInfo](crate::debugging::DebugInfo) for more information.
+    pub timing_mode: bool,
+    /// See [DebugInfo](crate::debugging::DebugInfo) for more information.
+    pub print_serial_output_to_terminal: bool,
+    /// If true, the frames are rendered dot by dot by the pixel FIFO of the PPU instead of
+    /// scanline by scanline by the scanline shader. This is slower, but allows effects which rely
+    /// on registers being changed in the middle of a scanline.
+    pub pixel_fifo_mode: bool,
+    /// The path of the `.sav` file the battery backed external RAM of the cartridge is loaded
+    /// from and persisted to. If None, the save data is not persisted.
+    pub save_file_path: Option<String>,
+    /// The interval in seconds in which the save file is written, if the external RAM changed
+    /// since the last save. If 0, the save file is only written on exit.
+    pub autosave_interval_in_secs: u32,
+    /// The path of a file listing Game Genie and GameShark codes to apply, see
+    /// [RustBoy::load_cheats_from_file](crate::RustBoy::load_cheats_from_file). If None, no
+    /// cheats are applied.
+    pub cheats_file_path: Option<String>,
+    /// The framerate of the recordings of the screen, which are toggled by pressing `R`. See
+    /// [frame_recorder](crate::frame_recorder) for more information. Ignored on the web, which
+    /// does not support recording.
+    pub recording_fps: u32,
+    /// If Some, the emulator runs in benchmark mode for the provided number of frames and exits
+    /// afterward, see [run_benchmark](crate::run_benchmark).
+    pub benchmark_frames: Option<u32>,
+    /// If Some, the emulator starts paused and is paused again once the CPU is about to execute
+    /// the instruction at the provided address and the registers are logged, see
+    /// [breakpoints](crate::breakpoints).
+    pub breakpoint: Option<u16>,
+    /// How simultaneous opposing directions (e.g. Left + Right) are reported to the game, see
+    /// [SocdMode].
+    pub socd_mode: SocdMode,
+    /// The number of frames a button with auto-fire stays pressed and then released. Auto-fire
+    /// is toggled for the held buttons by pressing `X`, see
+    /// [RustBoy::toggle_auto_fire_of_held_buttons](crate::RustBoy::toggle_auto_fire_of_held_buttons).
+    pub auto_fire_frames: u8,
+    /// If true, the executed opcodes are recorded and a report of the opcodes which were never
+    /// executed is printed on exit, see
+    /// [RustBoy::enable_opcode_coverage](crate::RustBoy::enable_opcode_coverage).
+    pub opcode_coverage: bool,
+    /// The number of frames which are not presented to the screen after each presented frame, to
+    /// save GPU time on weak hardware. The emulation itself is not affected. Can be cycled at
+    /// runtime by pressing `F`.
+    pub frame_skip: u32,
+    /// The present mode of the surface, which trades input latency against tearing, see
+    /// [PresentMode].
+    pub present_mode: PresentMode,
+    /// If Some, the emulator runs at most the provided number of m-cycles per redraw instead of a
+    /// whole frame at once, see [CycleBudget](crate::CycleBudget).
+    pub cycle_budget: Option<u32>,
+    /// The maximum number of frames the emulator catches up on after it fell behind real time.
+    /// Any further delay (e.g. while the window was dragged) is discarded, see
+    /// [handle_redraw_requested_event](crate::handle_redraw_requested_event).
+    pub max_catch_up_frames: u32,
+    /// If Some, the emulator runs headless for the provided number of instructions, writes its
+    /// state to a file and exits afterward, see [run_and_dump_state](crate::run_and_dump_state).
+    pub dump_after_instructions: Option<u64>,
+    /// If Some, the ROM file at the provided path is watched and reloaded into a fresh RustBoy
+    /// whenever it changes, see [rom_watcher](crate::rom_watcher). Is ignored on the web, since
+    /// there is no file system to watch.
+    pub watched_rom_path: Option<String>,
+    /// If Some, the emulator waits for another RustBoy instance to connect to the provided port
+    /// and uses it as the link partner of the serial port, see
+    /// [RustBoy::host_link_cable](crate::RustBoy::host_link_cable). Not supported on the web,
+    /// since there are no TCP sockets.
+    pub link_host_port: Option<u16>,
+    /// If Some and `link_host_port` is None, the emulator connects to another RustBoy instance at
+    /// the provided address and uses it as the link partner of the serial port, see
+    /// [RustBoy::connect_link_cable](crate::RustBoy::connect_link_cable). Not supported on the
+    /// web, since there are no TCP sockets.
+    pub link_connect_address: Option<String>,
+    /// If Some, the provided number of 8 KiB external RAM banks is allocated instead of the number
+    /// declared by the cartridge header, e.g. for homebrew ROMs whose header does not match the
+    /// RAM they use. Only the first 4 banks are addressable, since the MBC1 can only select 4 RAM
+    /// banks.
+    pub ram_bank_count_override: Option<u8>,
+    /// The level up to which messages are logged, see
+    /// [initialize_logger](crate::initialize_logger).
+    pub log_level: Option<String>,
+    /// The log levels of single modules, see [initialize_logger](crate::initialize_logger).
+    pub log_modules: Option<String>,
+    /// If Some, the emulator runs headless and writes every n-th frame to stdout, see
+    /// [run_with_frames_to_stdout](crate::run_with_frames_to_stdout).
+    pub stdout_frame_interval: Option<u32>,
+    /// The number of dots the PPU advances per m-cycle of the CPU, which is 4 on the hardware.
+    /// Other values slow down or speed up the PPU relative to the CPU, which breaks the timing
+    /// accuracy, but helps to observe and bisect timing-sensitive behavior.
+    pub dots_per_m_cycle: u32,
+}
+
+#[cfg_attr(target_arch = "wasm32", wasm_bindgen)]
+impl RunOptions {
+    /// Returns the default options, which run the emulator in a window without any debugging
+    /// options, saving, cheats or link partner. The save file is written every 5 seconds once a
+    /// path is set, recordings use 30 FPS and the emulator catches up on at most 2 frames.
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen(constructor))]
+    pub fn new() -> RunOptions {
+        RunOptions {
+            headless: false,
+            game_boy_doctor_mode: false,
+            file_logs: false,
+            json_trace: false,
+            binjgb_mode: false,
+            timing_mode: false,
+            print_serial_output_to_terminal: false,
+            pixel_fifo_mode: false,
+            save_file_path: None,
+            autosave_interval_in_secs: 5,
+            cheats_file_path: None,
+            recording_fps: 30,
+            benchmark_frames: None,
+            breakpoint: None,
+            socd_mode: SocdMode::default(),
+            auto_fire_frames: DEFAULT_AUTO_FIRE_FRAMES,
+            opcode_coverage: false,
+            frame_skip: 0,
+            present_mode: PresentMode::default(),
+            cycle_budget: None,
+            max_catch_up_frames: 2,
+            dump_after_instructions: None,
+            watched_rom_path: None,
+            link_host_port: None,
+            link_connect_address: None,
+            ram_bank_count_override: None,
+            log_level: None,
+            log_modules: None,
+            stdout_frame_interval: None,
+            dots_per_m_cycle: DOTS_PER_M_CYCLE,
+        }
+    }
+}
+
+impl Default for RunOptions {
+    fn default() -> RunOptions {
+        RunOptions::new()
+    }
+}
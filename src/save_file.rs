@@ -0,0 +1,108 @@
+//! This module contains the [SaveFile] struct and its methods, which are used to persist the
+//! battery backed external RAM of the cartridge, that is, the save data of a game, to a `.sav`
+//! file. For more information on the external RAM, please refer to
+//! [Pan Docs - Memory Bank Controllers](https://gbdev.io/pandocs/MBCs.html).
+//!
+//! The save file is loaded when the emulator starts, written when the emulator exits and,
+//! additionally, written periodically if the external RAM changed, so no progress is lost if the
+//! emulator crashes or is killed.
+
+use std::path::PathBuf;
+use std::time::Duration;
+
+use wasm_timer::Instant;
+
+use crate::RustBoy;
+
+/// Struct to keep track of the save file the battery backed external RAM is persisted to.
+///
+/// - `path`: The path of the save file.
+/// - `autosave_interval`: The interval in which the external RAM is written to the save file,
+///   if it changed since the last save. If it is zero, the save file is only written on exit.
+/// - `time_of_last_save`: The point in time the save file was last written (or loaded).
+pub(crate) struct SaveFile {
+    path: PathBuf,
+    autosave_interval: Duration,
+    time_of_last_save: Instant,
+}
+
+impl SaveFile {
+    /// Creates a new instance of SaveFile with the provided path and autosave interval in seconds.
+    pub(crate) fn new(path: PathBuf, autosave_interval_in_secs: u32) -> SaveFile {
+        SaveFile {
+            path,
+            autosave_interval: Duration::from_secs(autosave_interval_in_secs as u64),
+            time_of_last_save: Instant::now(),
+        }
+    }
+
+    /// Returns the path of the provided save state slot. The save states are stored next to the
+    /// save file with the extensions `.ss0` - `.ss9`, see [crate::save_state].
+    pub(crate) fn save_state_path(&self, slot: u8) -> PathBuf {
+        self.path.with_extension(format!("ss{slot}"))
+    }
+}
+
+impl RustBoy {
+    /// Loads the save file into the battery backed external RAM of the cartridge, if a save file
+    /// is set and exists.
+    pub(crate) fn load_save_file(&mut self) {
+        let Some(save_file) = &mut self.save_file else {
+            return;
+        };
+        if self.memory_bus.get_battery_backed_ram().is_none() {
+            return;
+        }
+        match std::fs::read(&save_file.path) {
+            Ok(data) => {
+                log::info!("Loaded save file {}", save_file.path.display());
+                self.memory_bus.load_battery_backed_ram(&data);
+                save_file.time_of_last_save = Instant::now();
+            }
+            Err(error) => log::info!(
+                "No save file loaded from {}: {}",
+                save_file.path.display(),
+                error
+            ),
+        }
+    }
+
+    /// Writes the battery backed external RAM of the cartridge to the save file, if a save file is
+    /// set and the external RAM was written to since the last save.
+    pub(crate) fn write_save_file(&mut self) {
+        let Some(save_file) = &mut self.save_file else {
+            return;
+        };
+        if !self.memory_bus.external_ram_written {
+            return;
+        }
+        let Some(battery_backed_ram) = self.memory_bus.get_battery_backed_ram() else {
+            return;
+        };
+        match std::fs::write(&save_file.path, battery_backed_ram) {
+            Ok(()) => {
+                log::debug!("Wrote save file {}", save_file.path.display());
+                self.memory_bus.external_ram_written = false;
+            }
+            Err(error) => log::warn!(
+                "Failed to write save file {}: {}",
+                save_file.path.display(),
+                error
+            ),
+        }
+        // We also reset the timer if writing failed, to not retry on every frame
+        save_file.time_of_last_save = Instant::now();
+    }
+
+    /// Writes the save file if the autosave interval has passed since the last save. Since
+    /// [RustBoy::write_save_file] is used, nothing is written if the external RAM did not change.
+    pub(crate) fn autosave(&mut self) {
+        let autosave_is_due = self.save_file.as_ref().is_some_and(|save_file| {
+            !save_file.autosave_interval.is_zero()
+                && save_file.time_of_last_save.elapsed() >= save_file.autosave_interval
+        });
+        if autosave_is_due {
+            self.write_save_file();
+        }
+    }
+}
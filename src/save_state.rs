@@ -0,0 +1,288 @@
+//! This module contains the methods used to save and load the state of the RustBoy, that is, save
+//! states. In contrast to the save file (see [crate::save_file]), which only persists the battery
+//! backed external RAM the game itself saves to, a save state captures the entire machine, so the
+//! game can be resumed at the exact point the state was saved.
+//!
+//! The state is serialized to a compact binary format, see [StateWriter]. It starts with
+//! [SAVE_STATE_MAGIC], the [SAVE_STATE_VERSION] and the title and global checksum of the
+//! cartridge, so states of other games or of incompatible versions of the format are rejected
+//! when loading. The components of the RustBoy then each write their own part of the state,
+//! see for example [CPU::save_state](crate::CPU::save_state).
+//!
+//! The states are serialized to and restored from byte buffers (see
+//! [RustBoy::save_state_to_bytes] and [RustBoy::load_state_from_bytes]), so embedders can store
+//! them wherever they like, e.g. in the IndexedDB of the browser or on another machine. With the
+//! `frontend` feature, the states are additionally written to numbered slots next to the save
+//! file, see [NUMBER_OF_SAVE_STATE_SLOTS]. Both use the same versioned format.
+
+use crate::ppu::information_for_shader::ChangesToPropagateToShader;
+use crate::{RustBoy, RustBoyError};
+
+/// The bytes every save state starts with.
+const SAVE_STATE_MAGIC: [u8; 4] = *b"RBSS";
+/// The version of the save state format. Has to be incremented whenever the format changes.
+const SAVE_STATE_VERSION: u8 = 13;
+/// The number of save state slots, which are bound to the number keys 0 - 9.
+#[cfg(feature = "frontend")]
+pub(crate) const NUMBER_OF_SAVE_STATE_SLOTS: u8 = 10;
+
+/// Struct to serialize the state of the RustBoy. The values are written in little endian, slices
+/// are prefixed by their length.
+pub(crate) struct StateWriter {
+    data: Vec<u8>,
+}
+
+impl StateWriter {
+    /// Creates a new instance of StateWriter with no data written yet.
+    fn new() -> StateWriter {
+        StateWriter { data: Vec::new() }
+    }
+
+    pub(crate) fn write_u8(&mut self, value: u8) {
+        self.data.push(value);
+    }
+
+    pub(crate) fn write_bool(&mut self, value: bool) {
+        self.write_u8(value as u8);
+    }
+
+    pub(crate) fn write_u16(&mut self, value: u16) {
+        self.data.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub(crate) fn write_u32(&mut self, value: u32) {
+        self.data.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub(crate) fn write_u64(&mut self, value: u64) {
+        self.data.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub(crate) fn write_u128(&mut self, value: u128) {
+        self.data.extend_from_slice(&value.to_le_bytes());
+    }
+
+    /// Writes the provided slice prefixed by its length.
+    pub(crate) fn write_bytes(&mut self, bytes: &[u8]) {
+        self.write_u32(bytes.len() as u32);
+        self.data.extend_from_slice(bytes);
+    }
+}
+
+/// Struct to deserialize the state of the RustBoy written by a [StateWriter]. The values have to
+/// be read in the same order they were written in.
+///
+/// All methods return [RustBoyError::InvalidSaveState], if the state ends prematurely.
+pub(crate) struct StateReader<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> StateReader<'a> {
+    /// Creates a new instance of StateReader reading from the start of the provided data.
+    fn new(data: &'a [u8]) -> StateReader<'a> {
+        StateReader { data, position: 0 }
+    }
+
+    /// Reads the next `N` bytes.
+    fn read_array<const N: usize>(&mut self) -> Result<[u8; N], RustBoyError> {
+        let bytes = self
+            .data
+            .get(self.position..self.position + N)
+            .ok_or(RustBoyError::InvalidSaveState("the state ends prematurely"))?;
+        self.position += N;
+        Ok(bytes.try_into().expect("Slice should have a length of N"))
+    }
+
+    pub(crate) fn read_u8(&mut self) -> Result<u8, RustBoyError> {
+        Ok(u8::from_le_bytes(self.read_array()?))
+    }
+
+    pub(crate) fn read_bool(&mut self) -> Result<bool, RustBoyError> {
+        Ok(self.read_u8()? != 0)
+    }
+
+    pub(crate) fn read_u16(&mut self) -> Result<u16, RustBoyError> {
+        Ok(u16::from_le_bytes(self.read_array()?))
+    }
+
+    pub(crate) fn read_u32(&mut self) -> Result<u32, RustBoyError> {
+        Ok(u32::from_le_bytes(self.read_array()?))
+    }
+
+    pub(crate) fn read_u64(&mut self) -> Result<u64, RustBoyError> {
+        Ok(u64::from_le_bytes(self.read_array()?))
+    }
+
+    pub(crate) fn read_u128(&mut self) -> Result<u128, RustBoyError> {
+        Ok(u128::from_le_bytes(self.read_array()?))
+    }
+
+    /// Reads a slice written by [StateWriter::write_bytes].
+    pub(crate) fn read_bytes(&mut self) -> Result<&'a [u8], RustBoyError> {
+        let length = self.read_u32()? as usize;
+        let bytes = self
+            .data
+            .get(self.position..self.position + length)
+            .ok_or(RustBoyError::InvalidSaveState("the state ends prematurely"))?;
+        self.position += length;
+        Ok(bytes)
+    }
+
+    /// Reads a slice written by [StateWriter::write_bytes] into the provided buffer. Returns an
+    /// error, if the length of the slice does not match the length of the buffer.
+    pub(crate) fn read_bytes_into(&mut self, buffer: &mut [u8]) -> Result<(), RustBoyError> {
+        let bytes = self.read_bytes()?;
+        if bytes.len() != buffer.len() {
+            return Err(RustBoyError::InvalidSaveState(
+                "the size of a memory area does not match",
+            ));
+        }
+        buffer.copy_from_slice(bytes);
+        Ok(())
+    }
+}
+
+impl RustBoy {
+    /// Serializes the state of the RustBoy. The state can be restored using
+    /// [RustBoy::load_state_from_bytes].
+    ///
+    /// The debugging flags, the currently pressed buttons and the active cheats are not part of
+    /// the state.
+    pub fn save_state_to_bytes(&self) -> Vec<u8> {
+        let mut writer = StateWriter::new();
+        writer.data.extend_from_slice(&SAVE_STATE_MAGIC);
+        writer.write_u8(SAVE_STATE_VERSION);
+        let (global_checksum, header_checksum, title) = self.cartridge_identification();
+        writer.write_u16(global_checksum);
+        writer.write_u8(header_checksum);
+        writer.write_bytes(title);
+
+        self.cpu.save_state(&mut writer);
+        self.memory_bus.save_state(&mut writer);
+        self.ppu.save_state(&mut writer);
+        writer.data
+    }
+
+    /// Restores the state serialized by [RustBoy::save_state_to_bytes]. Returns an error, if the state is
+    /// invalid, was saved with another version of the format or belongs to another cartridge. In
+    /// that case, the state of the RustBoy is left unchanged.
+    pub fn load_state_from_bytes(&mut self, data: &[u8]) -> Result<(), RustBoyError> {
+        let mut reader = StateReader::new(data);
+        if reader.read_array::<4>()? != SAVE_STATE_MAGIC {
+            return Err(RustBoyError::InvalidSaveState(
+                "the data is not a save state",
+            ));
+        }
+        if reader.read_u8()? != SAVE_STATE_VERSION {
+            return Err(RustBoyError::InvalidSaveState(
+                "the state was saved with an incompatible version",
+            ));
+        }
+        let cartridge_identification =
+            (reader.read_u16()?, reader.read_u8()?, reader.read_bytes()?);
+        if cartridge_identification != self.cartridge_identification() {
+            return Err(RustBoyError::InvalidSaveState(
+                "the state belongs to another cartridge",
+            ));
+        }
+
+        // The components are restored one after another, so if the state turns out to be
+        // truncated, the previous state is restored to not leave the RustBoy half loaded
+        let previous_state = self.save_state_to_bytes();
+        if let Err(error) = self.load_components_state(&mut reader) {
+            self.load_state_from_bytes(&previous_state)
+                .expect("Restoring the previous state should not fail");
+            return Err(error);
+        }
+        Ok(())
+    }
+
+    /// Restores the state of the components written by [RustBoy::save_state_to_bytes] after the header.
+    fn load_components_state(&mut self, reader: &mut StateReader) -> Result<(), RustBoyError> {
+        self.cpu.load_state(reader)?;
+        self.memory_bus.load_state(reader)?;
+        self.ppu.load_state(reader)?;
+
+        // The buffers of the shaders still hold the data of the state before loading, so all of
+        // them are uploaded again when the next scanline is rendered. Since everything is marked
+        // as changed, the flags themselves are not part of the save state.
+        self.memory_bus.memory_changed = ChangesToPropagateToShader::new_true();
+        Ok(())
+    }
+
+    /// Returns the global checksum, the header checksum and the title of the loaded cartridge,
+    /// which identify the cartridge a state belongs to.
+    fn cartridge_identification(&self) -> (u16, u8, &[u8]) {
+        match &self.memory_bus.cartridge_header {
+            Some(cartridge_header) => (
+                cartridge_header.global_checksum,
+                cartridge_header.header_checksum,
+                cartridge_header.title.as_bytes(),
+            ),
+            None => (0, 0, &[]),
+        }
+    }
+}
+
+#[cfg(feature = "frontend")]
+impl RustBoy {
+    /// Saves the state of the RustBoy to the provided slot (0 - 9). The state is written next to
+    /// the save file, see [crate::save_file::SaveFile::save_state_path]. Does nothing, if no save
+    /// file is set.
+    pub(crate) fn save_state_to_slot(&mut self, slot: u8) {
+        let Some(path) = self.save_state_path(slot) else {
+            log::warn!("Cannot save state: No save file path is set");
+            self.notifications
+                .post("Cannot save state without a save file");
+            return;
+        };
+        match std::fs::write(&path, self.save_state_to_bytes()) {
+            Ok(()) => {
+                self.notifications
+                    .post(format!("Saved state to slot {slot}"));
+                log::debug!("Save state path: {}", path.display());
+            }
+            Err(error) => {
+                log::warn!("Failed to save state to {}: {}", path.display(), error);
+                self.notifications
+                    .post(format!("Failed to save state to slot {slot}"));
+            }
+        }
+    }
+
+    /// Loads the state of the RustBoy from the provided slot (0 - 9), if it exists.
+    pub(crate) fn load_state_from_slot(&mut self, slot: u8) {
+        let Some(path) = self.save_state_path(slot) else {
+            log::warn!("Cannot load state: No save file path is set");
+            self.notifications
+                .post("Cannot load state without a save file");
+            return;
+        };
+        let result = std::fs::read(&path)
+            .map_err(|error| error.to_string())
+            .and_then(|data| {
+                self.load_state_from_bytes(&data)
+                    .map_err(|error| error.to_string())
+            });
+        match result {
+            Ok(()) => {
+                self.notifications
+                    .post(format!("Loaded state from slot {slot}"));
+                log::debug!("Save state path: {}", path.display());
+            }
+            Err(error) => {
+                log::warn!("Failed to load state from {}: {}", path.display(), error);
+                self.notifications
+                    .post(format!("Failed to load state from slot {slot}"));
+            }
+        }
+    }
+
+    /// Returns the path of the provided save state slot, if a save file is set.
+    fn save_state_path(&self, slot: u8) -> Option<std::path::PathBuf> {
+        self.save_file
+            .as_ref()
+            .map(|save_file| save_file.save_state_path(slot))
+    }
+}
@@ -0,0 +1,218 @@
+//! This module contains the [Serial] struct, which emulates the serial port (link cable) of the
+//! Game Boy. For more information, please refer to
+//! [Pan Docs - Serial Data Transfer](https://gbdev.io/pandocs/Serial_Data_Transfer_(Link_Cable).html).
+//!
+//! A transfer is requested by setting the transfer start bit of the serial transfer control
+//! register (SC 0xFF02). Each transfer shifts the 8 bits of the serial transfer data register
+//! (SB 0xFF01) out, while the 8 bits of the link partner are shifted in. How the transfer proceeds
+//! depends on the clock select bit of SC:
+//! - With the internal clock (bit 0 set), the Game Boy drives the clock at 8192 Hz itself, so
+//!   the transfer completes after 8 bits even if no link partner is connected. Since nothing
+//!   drives the data line then, 1 bits are shifted in, so SB reads 0xFF afterward.
+//! - With the external clock (bit 0 clear), the link partner drives the clock. If no link
+//!   partner is connected, which corresponds to a disconnected link cable, the transfer never
+//!   completes, that is, the transfer start bit stays set and no serial interrupt is requested, so
+//!   games do not falsely receive data.
+//!
+//! Another RustBoy instance can be connected as the link partner over TCP, see [link_cable].
+
+pub(crate) mod link_cable;
+
+use crate::RustBoyError;
+use crate::interrupts::{Interrupt, InterruptFlagRegister};
+use crate::memory_bus::MemoryBus;
+use crate::save_state::{StateReader, StateWriter};
+use link_cable::{LinkCable, PartnerTransfer, Reply};
+
+/// The address of the serial transfer data register (SB).
+pub(crate) const SERIAL_TRANSFER_DATA_REGISTER: u16 = 0xFF01;
+/// The address of the serial transfer control register (SC).
+pub(crate) const SERIAL_TRANSFER_CONTROL_REGISTER: u16 = 0xFF02;
+/// The bit of SC which requests a transfer and stays set while the transfer is in progress.
+const TRANSFER_START_BIT: u8 = 0b1000_0000;
+/// The bit of SC which selects the internal clock (if set) or the external clock (if clear).
+const INTERNAL_CLOCK_BIT: u8 = 0b0000_0001;
+/// The number of m-cycles it takes to transfer a bit using the internal clock of 8192 Hz.
+const M_CYCLES_PER_BIT: u32 = 128;
+/// The number of bits of each transfer.
+const BITS_PER_TRANSFER: u8 = 8;
+/// The number of m-cycles between two polls of the link cable for messages of the link partner.
+/// Polling once per transfer duration keeps the number of system calls low, while still reacting
+/// to the link partner in time.
+const M_CYCLES_PER_LINK_CABLE_POLL: u32 = M_CYCLES_PER_BIT * BITS_PER_TRANSFER as u32;
+
+/// Struct to represent the state of the serial port which is not part of its registers.
+///
+/// - `bits_remaining`: The number of bits of the current transfer which were not shifted yet. Is
+///   0, if no transfer driven by the internal clock is in progress.
+/// - `m_cycles_until_next_bit`: The number of m-cycles until the next bit is shifted.
+/// - `link_cable`: The connection to another RustBoy instance, if one is connected as the link
+///   partner. Is not part of save states.
+/// - `m_cycles_until_link_cable_poll`: The number of m-cycles until the link cable is polled for
+///   messages of the link partner, see [M_CYCLES_PER_LINK_CABLE_POLL].
+#[derive(Debug, Default)]
+pub(crate) struct Serial {
+    bits_remaining: u8,
+    m_cycles_until_next_bit: u32,
+    pub(crate) link_cable: Option<LinkCable>,
+    m_cycles_until_link_cable_poll: u32,
+}
+
+impl Serial {
+    /// Writes the state of the serial port to the provided save state. The registers are part of
+    /// the memory, which is saved by [MemoryBus::save_state].
+    pub(crate) fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_u8(self.bits_remaining);
+        writer.write_u32(self.m_cycles_until_next_bit);
+    }
+
+    /// Restores the state of the serial port written by [Serial::save_state]. A transfer sent
+    /// over the link cable before is cancelled, since the link partner does not know about the
+    /// restored state.
+    pub(crate) fn load_state(&mut self, reader: &mut StateReader) -> Result<(), RustBoyError> {
+        self.bits_remaining = reader.read_u8()?.min(BITS_PER_TRANSFER);
+        self.m_cycles_until_next_bit = reader.read_u32()?.min(M_CYCLES_PER_BIT);
+        if let Some(link_cable) = &mut self.link_cable {
+            link_cable.cancel_transfer();
+        }
+        Ok(())
+    }
+}
+
+impl MemoryBus {
+    /// Writes the serial transfer control register (SC 0xFF02). If the transfer start bit is set
+    /// and the internal clock is selected, a transfer is started, see [MemoryBus::handle_serial].
+    /// If a link partner is connected, SB is sent to it right away. With the external clock, the
+    /// transfer stays pending until the link partner starts a transfer, if ever. Clearing the
+    /// transfer start bit aborts a running transfer.
+    pub(super) fn write_serial_control_register(&mut self, value: u8) {
+        self.memory[SERIAL_TRANSFER_CONTROL_REGISTER as usize] = value;
+        if value & TRANSFER_START_BIT != 0 && value & INTERNAL_CLOCK_BIT != 0 {
+            self.serial.bits_remaining = BITS_PER_TRANSFER;
+            self.serial.m_cycles_until_next_bit = M_CYCLES_PER_BIT;
+            let data = self.memory[SERIAL_TRANSFER_DATA_REGISTER as usize];
+            if let Some(link_cable) = &mut self.serial.link_cable
+                && let Err(error) = link_cable.send_transfer(data)
+            {
+                self.disconnect_link_cable(error);
+            }
+        } else {
+            self.serial.bits_remaining = 0;
+            if let Some(link_cable) = &mut self.serial.link_cable {
+                link_cable.cancel_transfer();
+            }
+        }
+    }
+
+    /// Advances a running transfer driven by the internal clock by the provided number of
+    /// m-cycles. For each bit, SB is shifted left and a 1 is shifted in, since nothing drives the
+    /// data line without a link partner. Once all bits are shifted, the transfer start bit of SC
+    /// is cleared and a serial interrupt is requested.
+    ///
+    /// If the transfer was sent over the link cable, SB is replaced by the reply of the link
+    /// partner at the end instead. If the reply did not arrive yet, the transfer is stalled before
+    /// its last bit until it does, see [link_cable].
+    pub(crate) fn handle_serial(&mut self, m_cycles: u32) {
+        self.poll_link_cable(m_cycles);
+
+        let mut m_cycles = m_cycles;
+        while self.serial.bits_remaining > 0 && m_cycles >= self.serial.m_cycles_until_next_bit {
+            let reply = match (&mut self.serial.link_cable, self.serial.bits_remaining) {
+                (Some(link_cable), 1) => link_cable.take_reply(),
+                _ => Reply::NotExpected,
+            };
+            if reply == Reply::Pending {
+                // The last bit is shifted as soon as the reply arrives
+                self.serial.m_cycles_until_next_bit = 0;
+                return;
+            }
+
+            m_cycles -= self.serial.m_cycles_until_next_bit;
+            self.serial.m_cycles_until_next_bit = M_CYCLES_PER_BIT;
+            self.serial.bits_remaining -= 1;
+
+            let data = &mut self.memory[SERIAL_TRANSFER_DATA_REGISTER as usize];
+            *data = match reply {
+                Reply::Received(reply_data) => reply_data,
+                _ => (*data << 1) | 1,
+            };
+
+            if self.serial.bits_remaining == 0 {
+                self.memory[SERIAL_TRANSFER_CONTROL_REGISTER as usize] &= !TRANSFER_START_BIT;
+                InterruptFlagRegister::set_flag(self, Interrupt::Serial, true);
+            }
+        }
+        if self.serial.bits_remaining > 0 {
+            self.serial.m_cycles_until_next_bit -= m_cycles;
+        }
+    }
+
+    /// Polls the link cable for messages of the link partner every
+    /// [M_CYCLES_PER_LINK_CABLE_POLL] m-cycles and handles the transfers it started, see
+    /// [MemoryBus::handle_transfer_of_link_partner]. Disconnects the link cable, if the link
+    /// partner disconnected.
+    fn poll_link_cable(&mut self, m_cycles: u32) {
+        let Some(link_cable) = &mut self.serial.link_cable else {
+            return;
+        };
+        if let Some(m_cycles_until_poll) = self
+            .serial
+            .m_cycles_until_link_cable_poll
+            .checked_sub(m_cycles)
+        {
+            self.serial.m_cycles_until_link_cable_poll = m_cycles_until_poll;
+            return;
+        }
+        self.serial.m_cycles_until_link_cable_poll = M_CYCLES_PER_LINK_CABLE_POLL;
+
+        match link_cable.receive() {
+            Ok(transfers) => {
+                for transfer in transfers {
+                    self.handle_transfer_of_link_partner(transfer);
+                }
+            }
+            Err(error) => self.disconnect_link_cable(error),
+        }
+    }
+
+    /// Handles a transfer the link partner started using its internal clock:
+    /// - If a transfer using the external clock is pending, SB is exchanged with the byte of the
+    ///   link partner and the transfer completes, that is, the transfer start bit of SC is
+    ///   cleared and a serial interrupt is requested.
+    /// - If a transfer using the internal clock is running as well, both instances drive the clock,
+    ///   so the byte of the link partner is used as the reply to the own transfer. The link
+    ///   partner does the same with the transfer of this instance.
+    /// - Otherwise, the serial port does not shift, so the link partner receives 0xFF.
+    fn handle_transfer_of_link_partner(&mut self, transfer: PartnerTransfer) {
+        let Some(link_cable) = &mut self.serial.link_cable else {
+            return;
+        };
+        let control = self.memory[SERIAL_TRANSFER_CONTROL_REGISTER as usize];
+        let transfer_pending = control & TRANSFER_START_BIT != 0;
+
+        let result = if transfer_pending && control & INTERNAL_CLOCK_BIT == 0 {
+            let data = &mut self.memory[SERIAL_TRANSFER_DATA_REGISTER as usize];
+            let reply_data = *data;
+            *data = transfer.data;
+            self.memory[SERIAL_TRANSFER_CONTROL_REGISTER as usize] &= !TRANSFER_START_BIT;
+            let result = link_cable.send_reply(&transfer, reply_data);
+            InterruptFlagRegister::set_flag(self, Interrupt::Serial, true);
+            result
+        } else if transfer_pending && link_cable.accept_transfer_as_reply(&transfer) {
+            Ok(())
+        } else {
+            link_cable.send_reply(&transfer, 0xFF)
+        };
+
+        if let Err(error) = result {
+            self.disconnect_link_cable(error);
+        }
+    }
+
+    /// Disconnects the link cable after the provided error occurred. Afterward, the serial port
+    /// behaves like with a disconnected link cable again.
+    fn disconnect_link_cable(&mut self, error: std::io::Error) {
+        log::warn!("Disconnected the link cable: {error}");
+        self.serial.link_cable = None;
+    }
+}
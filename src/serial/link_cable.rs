@@ -0,0 +1,345 @@
+//! This module contains the [LinkCable] struct, which connects the serial ports of two RustBoy
+//! instances over TCP, so link cable games (e.g. Tetris versus mode or Pokémon trades) can be
+//! played by two players. One instance listens on a port (see the `--LINK-HOST` option) and the
+//! other one connects to it (see the `--LINK-CONNECT` option).
+//!
+//! Once connected, both instances send a handshake consisting of [HANDSHAKE_MAGIC] and the
+//! [PROTOCOL_VERSION], so instances speaking different protocols refuse to link. Afterward,
+//! messages of [MESSAGE_LENGTH] bytes are exchanged, each consisting of the kind of the message,
+//! the id of the transfer and the transferred byte:
+//! - [TRANSFER_MESSAGE]: Sent by the instance which starts a transfer using the internal clock,
+//!   that is, the clock master of this transfer. Contains the byte it shifts out.
+//! - [REPLY_MESSAGE]: Sent by the other instance in response to a transfer. Contains the byte it
+//!   shifted out in exchange, which is 0xFF if it was not waiting for a transfer using the external
+//!   clock.
+//!
+//! There is no fixed clock master, since games (e.g. Pokémon) negotiate which side drives the
+//! clock themselves and switch it between transfers. Instead, each transfer is driven by the
+//! instance which started it using the internal clock. If both instances do so at the same time,
+//! the two transfer messages are treated as each other's reply, like on the hardware, where both
+//! Game Boys then shift their bytes into each other.
+//!
+//! Since the two instances run independently, the reply can arrive later than the 8 bits of the
+//! transfer take on the hardware. The transfer is stalled until then (see
+//! [MemoryBus::handle_serial](crate::memory_bus::MemoryBus::handle_serial)), or until
+//! [REPLY_TIMEOUT] passed, after which it completes like with a disconnected link cable.
+
+use std::io::{Error, ErrorKind, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::time::Duration;
+
+use wasm_timer::Instant;
+
+/// The bytes the handshake starts with.
+const HANDSHAKE_MAGIC: [u8; 4] = *b"RBLC";
+/// The version of the protocol. Has to be incremented whenever the messages change.
+const PROTOCOL_VERSION: u8 = 1;
+/// The time to wait for the handshake of the link partner after connecting.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(5);
+/// The time to wait for the reply of the link partner, after which the transfer completes as if
+/// no link partner was connected.
+const REPLY_TIMEOUT: Duration = Duration::from_secs(1);
+/// The number of bytes of each message (kind, transfer id and data).
+const MESSAGE_LENGTH: usize = 3;
+/// The kind of the message starting a transfer.
+const TRANSFER_MESSAGE: u8 = 0x01;
+/// The kind of the message replying to a transfer.
+const REPLY_MESSAGE: u8 = 0x02;
+
+/// A transfer started by the link partner using its internal clock, see [TRANSFER_MESSAGE].
+///
+/// - `id`: The id of the transfer, which has to be sent back with the reply.
+/// - `data`: The byte the link partner shifted out.
+#[derive(Debug)]
+pub(crate) struct PartnerTransfer {
+    pub(crate) id: u8,
+    pub(crate) data: u8,
+}
+
+/// The state of the reply to the transfer started by this instance, see [LinkCable::take_reply].
+#[derive(Debug, PartialEq)]
+pub(crate) enum Reply {
+    /// The reply arrived with the byte the link partner shifted out.
+    Received(u8),
+    /// The reply did not arrive yet.
+    Pending,
+    /// No transfer was sent over the link cable, so there is no reply to wait for.
+    NotExpected,
+}
+
+/// Struct to represent the connection to the link partner.
+///
+/// - `stream`: The non-blocking TCP connection to the link partner.
+/// - `received_bytes`: The bytes which were received, but do not form a complete message yet.
+/// - `unsent_bytes`: The bytes of the messages which could not be sent yet, because the send
+///   buffer of the connection was full. They are sent before anything else, see
+///   [LinkCable::send_unsent_bytes].
+/// - `next_transfer_id`: The id of the next transfer started by this instance. Used to ignore
+///   replies which arrive after [REPLY_TIMEOUT].
+/// - `awaited_reply`: The id of the transfer started by this instance and the point in time it
+///   was sent, if its reply did not arrive yet.
+/// - `reply`: The byte the link partner replied with, if it was not taken yet.
+#[derive(Debug)]
+pub(crate) struct LinkCable {
+    stream: TcpStream,
+    received_bytes: Vec<u8>,
+    unsent_bytes: Vec<u8>,
+    next_transfer_id: u8,
+    awaited_reply: Option<(u8, Instant)>,
+    reply: Option<u8>,
+}
+
+impl LinkCable {
+    /// Listens on the provided port and blocks until the link partner connected.
+    pub(crate) fn host(port: u16) -> std::io::Result<LinkCable> {
+        let listener = TcpListener::bind(("0.0.0.0", port))?;
+        let (stream, address) = listener.accept()?;
+        log::info!("Link partner connected from {address}");
+        LinkCable::handshake(stream)
+    }
+
+    /// Connects to the link partner listening at the provided address (e.g. `127.0.0.1:5555`).
+    pub(crate) fn connect(address: &str) -> std::io::Result<LinkCable> {
+        LinkCable::handshake(TcpStream::connect(address)?)
+    }
+
+    /// Exchanges the handshake with the link partner and makes the connection non-blocking
+    /// afterward, so it can be polled while emulating.
+    fn handshake(mut stream: TcpStream) -> std::io::Result<LinkCable> {
+        // Each message is tiny and time critical, so it is sent right away
+        stream.set_nodelay(true)?;
+        stream.set_read_timeout(Some(HANDSHAKE_TIMEOUT))?;
+
+        stream.write_all(&HANDSHAKE_MAGIC)?;
+        stream.write_all(&[PROTOCOL_VERSION])?;
+        let mut handshake = [0; HANDSHAKE_MAGIC.len() + 1];
+        stream.read_exact(&mut handshake)?;
+        if handshake[..HANDSHAKE_MAGIC.len()] != HANDSHAKE_MAGIC {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                "the link partner is not a RustBoy",
+            ));
+        }
+        let partner_version = handshake[HANDSHAKE_MAGIC.len()];
+        if partner_version != PROTOCOL_VERSION {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!(
+                    "the link partner uses protocol version {partner_version} instead of {PROTOCOL_VERSION}"
+                ),
+            ));
+        }
+
+        stream.set_read_timeout(None)?;
+        stream.set_nonblocking(true)?;
+        Ok(LinkCable {
+            stream,
+            received_bytes: Vec::new(),
+            unsent_bytes: Vec::new(),
+            next_transfer_id: 0,
+            awaited_reply: None,
+            reply: None,
+        })
+    }
+
+    /// Starts a transfer driven by the internal clock of this instance by sending the provided
+    /// byte to the link partner. Its reply can be taken using [LinkCable::take_reply].
+    pub(crate) fn send_transfer(&mut self, data: u8) -> std::io::Result<()> {
+        let id = self.next_transfer_id;
+        self.next_transfer_id = self.next_transfer_id.wrapping_add(1);
+        self.awaited_reply = Some((id, Instant::now()));
+        self.reply = None;
+        self.send_message([TRANSFER_MESSAGE, id, data])
+    }
+
+    /// Replies to the provided transfer of the link partner with the byte this instance shifted
+    /// out in exchange.
+    pub(crate) fn send_reply(
+        &mut self,
+        transfer: &PartnerTransfer,
+        data: u8,
+    ) -> std::io::Result<()> {
+        self.send_message([REPLY_MESSAGE, transfer.id, data])
+    }
+
+    /// Sends the provided message after the bytes which could not be sent yet. If the send buffer
+    /// of the connection is full, the rest of the message is kept and sent by a later call of
+    /// [LinkCable::send_unsent_bytes].
+    fn send_message(&mut self, message: [u8; MESSAGE_LENGTH]) -> std::io::Result<()> {
+        self.unsent_bytes.extend_from_slice(&message);
+        self.send_unsent_bytes()
+    }
+
+    /// Sends as many of the bytes which could not be sent yet as possible without blocking, see
+    /// [LinkCable::send_message].
+    ///
+    /// Returns an error, if the link partner disconnected.
+    fn send_unsent_bytes(&mut self) -> std::io::Result<()> {
+        while !self.unsent_bytes.is_empty() {
+            match self.stream.write(&self.unsent_bytes) {
+                Ok(0) => {
+                    return Err(Error::new(
+                        ErrorKind::WriteZero,
+                        "the link partner does not accept any more data",
+                    ));
+                }
+                Ok(number_of_bytes) => {
+                    self.unsent_bytes.drain(..number_of_bytes);
+                }
+                Err(error) if error.kind() == ErrorKind::WouldBlock => break,
+                Err(error) if error.kind() == ErrorKind::Interrupted => continue,
+                Err(error) => return Err(error),
+            }
+        }
+        Ok(())
+    }
+
+    /// Stops waiting for the reply to the transfer started by this instance, e.g. because the
+    /// game aborted it. A reply which arrives afterward is ignored.
+    pub(crate) fn cancel_transfer(&mut self) {
+        self.awaited_reply = None;
+        self.reply = None;
+    }
+
+    /// Uses the byte of a transfer the link partner started while this instance waits for the
+    /// reply to its own transfer as the reply, since both instances then drive the clock at the
+    /// same time. Returns false, if this instance does not wait for a reply.
+    pub(crate) fn accept_transfer_as_reply(&mut self, transfer: &PartnerTransfer) -> bool {
+        if self.awaited_reply.take().is_some() {
+            self.reply = Some(transfer.data);
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Returns the state of the reply to the transfer started by this instance. Once the reply is
+    /// returned, it is consumed. If the reply did not arrive within [REPLY_TIMEOUT], the transfer
+    /// is given up on and 0xFF is returned, like with a disconnected link cable.
+    pub(crate) fn take_reply(&mut self) -> Reply {
+        if let Some(data) = self.reply.take() {
+            return Reply::Received(data);
+        }
+        match self.awaited_reply {
+            Some((_, time_of_transfer)) if time_of_transfer.elapsed() >= REPLY_TIMEOUT => {
+                log::warn!("The link partner did not reply in time, so the transfer is dropped");
+                self.awaited_reply = None;
+                Reply::Received(0xFF)
+            }
+            Some(_) => Reply::Pending,
+            None => Reply::NotExpected,
+        }
+    }
+
+    /// Sends the bytes of the messages which could not be sent yet (see
+    /// [LinkCable::send_unsent_bytes]) and reads all messages which arrived since the last call
+    /// without blocking. Replies to the
+    /// transfer started by this instance are stored for [LinkCable::take_reply], while the
+    /// transfers started by the link partner are returned in the order they arrived.
+    ///
+    /// Returns an error, if the link partner disconnected or sent an invalid message.
+    pub(crate) fn receive(&mut self) -> std::io::Result<Vec<PartnerTransfer>> {
+        self.send_unsent_bytes()?;
+
+        let mut buffer = [0; 64];
+        loop {
+            match self.stream.read(&mut buffer) {
+                Ok(0) => {
+                    return Err(Error::new(
+                        ErrorKind::UnexpectedEof,
+                        "the link partner closed the connection",
+                    ));
+                }
+                Ok(number_of_bytes) => self
+                    .received_bytes
+                    .extend_from_slice(&buffer[..number_of_bytes]),
+                Err(error) if error.kind() == ErrorKind::WouldBlock => break,
+                Err(error) if error.kind() == ErrorKind::Interrupted => continue,
+                Err(error) => return Err(error),
+            }
+        }
+
+        let mut transfers = Vec::new();
+        let number_of_complete_bytes =
+            self.received_bytes.len() - self.received_bytes.len() % MESSAGE_LENGTH;
+        for message in self.received_bytes[..number_of_complete_bytes].chunks_exact(MESSAGE_LENGTH)
+        {
+            let (kind, id, data) = (message[0], message[1], message[2]);
+            match kind {
+                TRANSFER_MESSAGE => transfers.push(PartnerTransfer { id, data }),
+                REPLY_MESSAGE => {
+                    // Replies to transfers which were given up on or cancelled are ignored
+                    if self
+                        .awaited_reply
+                        .is_some_and(|(awaited_id, _)| awaited_id == id)
+                    {
+                        self.awaited_reply = None;
+                        self.reply = Some(data);
+                    }
+                }
+                _ => {
+                    return Err(Error::new(
+                        ErrorKind::InvalidData,
+                        format!("the link partner sent a message of unknown kind {kind:#04X}"),
+                    ));
+                }
+            }
+        }
+        self.received_bytes.drain(..number_of_complete_bytes);
+        Ok(transfers)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns two link cables connected to each other over the loopback interface.
+    fn connected_link_cables() -> (LinkCable, LinkCable) {
+        let listener = TcpListener::bind(("127.0.0.1", 0)).expect("Binding should succeed");
+        let address = listener
+            .local_addr()
+            .expect("The listener should have an address");
+        std::thread::scope(|scope| {
+            let host = scope.spawn(|| {
+                let (stream, _) = listener.accept().expect("The partner should connect");
+                LinkCable::handshake(stream).expect("The handshake should succeed")
+            });
+            let partner = LinkCable::connect(&address.to_string())
+                .expect("Connecting to the host should succeed");
+            (host.join().expect("The host should not panic"), partner)
+        })
+    }
+
+    #[test]
+    fn messages_are_queued_while_the_send_buffer_is_full() {
+        let (mut host, mut partner) = connected_link_cables();
+        // The partner does not read anything, until the send and receive buffers of the
+        // connection are full
+        let mut number_of_transfers = 0;
+        while host.unsent_bytes.is_empty() {
+            host.send_transfer(number_of_transfers as u8)
+                .expect("A full send buffer should not be an error");
+            number_of_transfers += 1;
+        }
+        host.send_transfer(number_of_transfers as u8)
+            .expect("A full send buffer should not be an error");
+        number_of_transfers += 1;
+
+        let mut received_transfers = Vec::new();
+        while received_transfers.len() < number_of_transfers {
+            host.receive().expect("The host should stay connected");
+            received_transfers.extend(
+                partner
+                    .receive()
+                    .expect("The partner should stay connected"),
+            );
+        }
+        assert!(host.unsent_bytes.is_empty());
+        assert_eq!(received_transfers.len(), number_of_transfers);
+        for (transfer, received_transfer) in received_transfers.iter().enumerate() {
+            assert_eq!(received_transfer.id, transfer as u8);
+            assert_eq!(received_transfer.data, transfer as u8);
+        }
+    }
+}
@@ -0,0 +1,430 @@
+//! This module contains the [SgbPacketReceiver] struct, which captures the command packets a
+//! Super Game Boy (SGB) aware game sends to the SGB. For more information, please refer to
+//! [Pan Docs - SGB Command Packet Transfers](https://gbdev.io/pandocs/SGB_Command_Packet.html).
+//!
+//! The packets are transferred bit by bit via pulses on bits 4 and 5 (P14 and P15) of the joypad
+//! register 0xFF00. Each packet is 16 bytes long and is preceded by a reset pulse (P14 and P15 low)
+//! and followed by a stop bit (0). A command consists of 1 to 7 packets, the number of which is
+//! encoded in the lower 3 bits of the first byte of the first packet. The upper 5 bits encode the
+//! command.
+//!
+//! The decoded commands are logged and stored in a debug buffer, see [SgbPacketReceiver::packets].
+//! Apart from that, only the palette commands (PAL01, PAL23, PAL03, PAL12, PAL_SET and PAL_TRN)
+//! are emulated by [SgbPalettes], if the cartridge supports SGB functions. Since the attribute
+//! commands (ATTR_*) are not emulated, the whole playfield is colored with palette 0.
+
+use std::collections::VecDeque;
+
+use crate::ppu::registers::LCDCRegister;
+use crate::save_state::{StateReader, StateWriter};
+use crate::{MemoryBus, RustBoyError};
+
+const BYTES_PER_PACKET: usize = 16;
+const BITS_PER_PACKET: usize = BYTES_PER_PACKET * 8;
+/// The maximum number of commands kept in the debug buffer. If more commands are received,
+/// the oldest ones are dropped.
+const MAX_NUMBER_OF_BUFFERED_COMMANDS: usize = 64;
+
+/// The value of bits 4 and 5 of the joypad register if both P14 and P15 are low (reset pulse).
+const RESET_PULSE: u8 = 0b0000_0000;
+/// The value of bits 4 and 5 of the joypad register if only P14 is low (bit 0).
+const ZERO_PULSE: u8 = 0b0010_0000;
+/// The value of bits 4 and 5 of the joypad register if only P15 is low (bit 1).
+const ONE_PULSE: u8 = 0b0001_0000;
+/// The value of bits 4 and 5 of the joypad register if both P14 and P15 are high. Is written
+/// between two pulses.
+const IDLE: u8 = 0b0011_0000;
+
+const PAL01: u8 = 0x00;
+const PAL23: u8 = 0x01;
+const PAL03: u8 = 0x02;
+const PAL12: u8 = 0x03;
+const PAL_SET: u8 = 0x0A;
+const PAL_TRN: u8 = 0x0B;
+
+const NUMBER_OF_PALETTES: usize = 4;
+const COLORS_PER_PALETTE: usize = 4;
+/// The number of system palettes which are transferred using PAL_TRN and can be selected with
+/// PAL_SET.
+const NUMBER_OF_SYSTEM_PALETTES: usize = 512;
+/// The number of bytes transferred from VRAM by the *_TRN commands.
+const VRAM_TRANSFER_SIZE: usize = 4096;
+
+/// Struct to represent a complete SGB command, that is, the data of all of its packets.
+///
+/// - `command`: The command code, that is, the upper 5 bits of the first byte of the first packet.
+/// - `data`: The bytes of all packets of the command, including the command and length byte.
+#[derive(Clone, Debug)]
+pub struct SgbPacket {
+    pub command: u8,
+    pub data: Vec<u8>,
+}
+
+impl SgbPacket {
+    /// Returns the name of the command of the packet as listed in
+    /// [Pan Docs - SGB Command Summary](https://gbdev.io/pandocs/SGB_Command_Summary.html).
+    pub fn command_name(&self) -> &'static str {
+        match self.command {
+            0x00 => "PAL01",
+            0x01 => "PAL23",
+            0x02 => "PAL03",
+            0x03 => "PAL12",
+            0x04 => "ATTR_BLK",
+            0x05 => "ATTR_LIN",
+            0x06 => "ATTR_DIV",
+            0x07 => "ATTR_CHR",
+            0x08 => "SOUND",
+            0x09 => "SOU_TRN",
+            0x0A => "PAL_SET",
+            0x0B => "PAL_TRN",
+            0x0C => "ATRC_EN",
+            0x0D => "TEST_EN",
+            0x0E => "ICON_EN",
+            0x0F => "DATA_SND",
+            0x10 => "DATA_TRN",
+            0x11 => "MLT_REQ",
+            0x12 => "JUMP",
+            0x13 => "CHR_TRN",
+            0x14 => "PCT_TRN",
+            0x15 => "ATTR_TRN",
+            0x16 => "ATTR_SET",
+            0x17 => "MASK_EN",
+            0x18 => "OBJ_TRN",
+            0x19 => "PAL_PRI",
+            _ => "UNKNOWN",
+        }
+    }
+}
+
+/// Struct to keep track of the SGB packet transfer via the joypad register.
+///
+/// - `receiving`: A flag indicating if a packet is currently being received, that is, a reset
+///   pulse was written and the packet is not complete yet.
+/// - `ready_for_next_bit`: A flag indicating if P14 and P15 were set high after the last pulse,
+///   such that the next pulse is a new bit.
+/// - `current_packet`: The bytes of the packet currently being received.
+/// - `bit_index`: The index of the next bit of the current packet.
+/// - `current_command`: The data of the command currently being received and the number of packets
+///   which are still missing.
+/// - `packets`: The debug buffer of the last [MAX_NUMBER_OF_BUFFERED_COMMANDS] completely received
+///   commands.
+#[derive(Debug)]
+pub(crate) struct SgbPacketReceiver {
+    receiving: bool,
+    ready_for_next_bit: bool,
+    current_packet: [u8; BYTES_PER_PACKET],
+    bit_index: usize,
+    current_command: Option<(Vec<u8>, u8)>,
+    packets: VecDeque<SgbPacket>,
+}
+
+impl SgbPacketReceiver {
+    /// Creates a new instance of SgbPacketReceiver which is not receiving and has an empty
+    /// debug buffer.
+    pub(crate) fn new() -> SgbPacketReceiver {
+        SgbPacketReceiver {
+            receiving: false,
+            ready_for_next_bit: false,
+            current_packet: [0; BYTES_PER_PACKET],
+            bit_index: 0,
+            current_command: None,
+            packets: VecDeque::new(),
+        }
+    }
+
+    /// Returns the debug buffer of the last completely received commands, oldest first.
+    pub(crate) fn packets(&self) -> &VecDeque<SgbPacket> {
+        &self.packets
+    }
+
+    /// Handles a write to the joypad register. Only bits 4 and 5 (P14 and P15) of the value
+    /// are considered. Returns the command, if this write completed one.
+    fn handle_joypad_write(&mut self, value: u8) -> Option<&SgbPacket> {
+        match value & IDLE {
+            RESET_PULSE => {
+                self.receiving = true;
+                self.ready_for_next_bit = false;
+                self.current_packet = [0; BYTES_PER_PACKET];
+                self.bit_index = 0;
+                None
+            }
+            IDLE => {
+                self.ready_for_next_bit = true;
+                None
+            }
+            pulse if self.receiving && self.ready_for_next_bit => {
+                self.ready_for_next_bit = false;
+                self.handle_bit(pulse == ONE_PULSE)
+            }
+            // A pulse which is not preceded by setting P14 and P15 high is not a new bit. This
+            // also covers the regular reading of the joypad.
+            ZERO_PULSE | ONE_PULSE => None,
+            _ => unreachable!(),
+        }
+    }
+
+    /// Handles a single transferred bit. The bits of each byte are transferred starting with the
+    /// least significant bit. After [BITS_PER_PACKET] bits, a stop bit (0) is expected.
+    ///
+    /// Returns the command, if this bit completed one.
+    fn handle_bit(&mut self, bit: bool) -> Option<&SgbPacket> {
+        if self.bit_index < BITS_PER_PACKET {
+            if bit {
+                self.current_packet[self.bit_index / 8] |= 1 << (self.bit_index % 8);
+            }
+            self.bit_index += 1;
+            return None;
+        }
+
+        self.receiving = false;
+        if bit {
+            log::debug!("SGB packet discarded, since the stop bit was not 0");
+            return None;
+        }
+        self.handle_packet()
+    }
+
+    /// Handles a completely received packet by appending it to the current command. If the command
+    /// is complete, it is logged, pushed to the debug buffer and returned.
+    fn handle_packet(&mut self) -> Option<&SgbPacket> {
+        let (mut data, remaining_packets) = match self.current_command.take() {
+            Some(current_command) => current_command,
+            None => {
+                let number_of_packets = self.current_packet[0] & 0b0000_0111;
+                if number_of_packets == 0 {
+                    log::debug!(
+                        "SGB packet discarded, since its length is 0: {:02X?}",
+                        self.current_packet
+                    );
+                    return None;
+                }
+                (Vec::new(), number_of_packets)
+            }
+        };
+        data.extend_from_slice(&self.current_packet);
+
+        if remaining_packets > 1 {
+            self.current_command = Some((data, remaining_packets - 1));
+            return None;
+        }
+
+        let packet = SgbPacket {
+            command: data[0] >> 3,
+            data,
+        };
+        log::debug!(
+            "SGB command {:#04X} {} received: {:02X?}",
+            packet.command,
+            packet.command_name(),
+            packet.data
+        );
+        if self.packets.len() == MAX_NUMBER_OF_BUFFERED_COMMANDS {
+            self.packets.pop_front();
+        }
+        self.packets.push_back(packet);
+        self.packets.back()
+    }
+}
+
+/// Struct to represent the state of the SGB palettes. See
+/// [Pan Docs - SGB Palette Commands](https://gbdev.io/pandocs/SGB_Command_Palettes.html).
+///
+/// The colors are stored in the format of the SGB, that is, as 15 bit RGB values (RGB555) with
+/// red in the lowest 5 bits.
+///
+/// - `palettes`: The four palettes which can be used to color the playfield. Color 0 is shared
+///   by all palettes.
+/// - `system_palettes`: The 512 palettes transferred using PAL_TRN, which can be copied to
+///   `palettes` using PAL_SET.
+/// - `active`: A flag indicating if the game has set any palette yet. Until then, the default
+///   colors of the RustBoy are used.
+#[derive(Debug)]
+pub(crate) struct SgbPalettes {
+    palettes: [[u16; COLORS_PER_PALETTE]; NUMBER_OF_PALETTES],
+    system_palettes: Box<[[u16; COLORS_PER_PALETTE]; NUMBER_OF_SYSTEM_PALETTES]>,
+    active: bool,
+}
+
+impl SgbPalettes {
+    /// Creates a new instance of SgbPalettes with all colors set to black and no palette set yet.
+    pub(crate) fn new() -> SgbPalettes {
+        SgbPalettes {
+            palettes: [[0; COLORS_PER_PALETTE]; NUMBER_OF_PALETTES],
+            system_palettes: Box::new([[0; COLORS_PER_PALETTE]; NUMBER_OF_SYSTEM_PALETTES]),
+            active: false,
+        }
+    }
+
+    /// Writes the palettes and the system palettes to the provided save state.
+    pub(crate) fn save_state(&self, writer: &mut StateWriter) {
+        for color in self
+            .palettes
+            .iter()
+            .chain(self.system_palettes.iter())
+            .flatten()
+        {
+            writer.write_u16(*color);
+        }
+        writer.write_bool(self.active);
+    }
+
+    /// Restores the palettes and the system palettes written by [SgbPalettes::save_state].
+    pub(crate) fn load_state(&mut self, reader: &mut StateReader) -> Result<(), RustBoyError> {
+        for color in self
+            .palettes
+            .iter_mut()
+            .chain(self.system_palettes.iter_mut())
+            .flatten()
+        {
+            *color = reader.read_u16()?;
+        }
+        self.active = reader.read_bool()?;
+        Ok(())
+    }
+
+    /// Returns the RGBA colors the four shades of the playfield are mapped to, if the game
+    /// has set any palette yet. Otherwise, returns None.
+    pub(crate) fn playfield_colors(&self) -> Option<[[u8; 4]; COLORS_PER_PALETTE]> {
+        if !self.active {
+            return None;
+        }
+        Some(self.palettes[0].map(rgb555_to_rgba8))
+    }
+
+    /// Handles the palette commands among the provided command. Other commands are ignored.
+    /// `vram_transfer_data` is only read by PAL_TRN, which transfers the system palettes from VRAM.
+    ///
+    /// Returns true, if the palettes changed.
+    fn handle_command(&mut self, packet: &SgbPacket, vram_transfer_data: &[u8]) -> bool {
+        let data = &packet.data;
+        match packet.command {
+            PAL01 => self.set_palette_pair(data, 0, 1),
+            PAL23 => self.set_palette_pair(data, 2, 3),
+            PAL03 => self.set_palette_pair(data, 0, 3),
+            PAL12 => self.set_palette_pair(data, 1, 2),
+            PAL_SET => {
+                for (palette_index, palette) in self.palettes.iter_mut().enumerate() {
+                    let system_palette_index = (u16::from_le_bytes([
+                        data[1 + palette_index * 2],
+                        data[2 + palette_index * 2],
+                    ]) & 0x01FF) as usize;
+                    *palette = self.system_palettes[system_palette_index];
+                }
+                // Color 0 of palette 0 is used for all palettes
+                let color_zero = self.palettes[0][0];
+                self.palettes
+                    .iter_mut()
+                    .for_each(|palette| palette[0] = color_zero);
+            }
+            PAL_TRN => {
+                for (system_palette, bytes) in self
+                    .system_palettes
+                    .iter_mut()
+                    .zip(vram_transfer_data.chunks_exact(COLORS_PER_PALETTE * 2))
+                {
+                    for (color, color_bytes) in system_palette.iter_mut().zip(bytes.chunks_exact(2))
+                    {
+                        *color = u16::from_le_bytes([color_bytes[0], color_bytes[1]]);
+                    }
+                }
+                // PAL_TRN only transfers the system palettes, the displayed colors stay the same
+                return false;
+            }
+            _ => return false,
+        }
+        self.active = true;
+        true
+    }
+
+    /// Sets the colors of the two provided palettes from the data of a PAL01, PAL23, PAL03 or
+    /// PAL12 command. Bytes 1 - 2 hold color 0 (which is shared by all palettes), bytes 3 - 8
+    /// colors 1 - 3 of the first palette and bytes 9 - 14 colors 1 - 3 of the second palette.
+    fn set_palette_pair(&mut self, data: &[u8], first_palette: usize, second_palette: usize) {
+        let color_at = |index: usize| u16::from_le_bytes([data[index], data[index + 1]]);
+
+        let color_zero = color_at(1);
+        self.palettes
+            .iter_mut()
+            .for_each(|palette| palette[0] = color_zero);
+        for color in 1..COLORS_PER_PALETTE {
+            self.palettes[first_palette][color] = color_at(1 + color * 2);
+            self.palettes[second_palette][color] = color_at(7 + color * 2);
+        }
+    }
+}
+
+/// Handles a write to the joypad register by passing it on to the [SgbPacketReceiver]. If this
+/// completes a command and the cartridge supports SGB functions, the command is passed on to
+/// the [SgbPalettes].
+pub(crate) fn handle_joypad_write(memory_bus: &mut MemoryBus, value: u8) {
+    let tile_data_flag = LCDCRegister::get_background_and_window_tile_data_flag(memory_bus);
+    let Some(packet) = memory_bus.sgb_packet_receiver.handle_joypad_write(value) else {
+        return;
+    };
+    let Some(sgb_palettes) = &mut memory_bus.sgb_palettes else {
+        return;
+    };
+    let vram_transfer_data = vram_transfer_data(&memory_bus.memory, tile_data_flag);
+    if sgb_palettes.handle_command(packet, &vram_transfer_data) {
+        memory_bus.memory_changed.palette_changed = true;
+    }
+}
+
+/// Returns the data the *_TRN commands transfer. On the SGB, the data is taken from the screen,
+/// which games fill with the tiles 0x00 - 0xFF in order. We approximate this by transferring the
+/// tile data of these tiles, depending on LCDC bit 4. With the signed addressing (LCDC bit 4
+/// cleared), the tiles 0x00 - 0x7F lie at 0x9000 - 0x97FF and the tiles 0x80 - 0xFF at
+/// 0x8800 - 0x8FFF, see https://gbdev.io/pandocs/Tile_Data.html
+fn vram_transfer_data(memory: &[u8], tile_data_flag: bool) -> Vec<u8> {
+    if tile_data_flag {
+        memory[0x8000..0x8000 + VRAM_TRANSFER_SIZE].to_vec()
+    } else {
+        [&memory[0x9000..0x9800], &memory[0x8800..0x9000]].concat()
+    }
+}
+
+/// Converts a color in the SGB format (RGB555, red in the lowest bits) to an RGBA color with
+/// 8 bits per channel.
+fn rgb555_to_rgba8(color: u16) -> [u8; 4] {
+    let channel = |shift: u16| {
+        let value = ((color >> shift) & 0x1F) as u8;
+        (value << 3) | (value >> 2)
+    };
+    [channel(0), channel(5), channel(10), 255]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns a memory, in which each byte of the tile data holds the index of its tile.
+    fn memory_with_tile_indices() -> Vec<u8> {
+        let mut memory = vec![0; 0x10000];
+        for (offset, byte) in memory[0x8000..0x9800].iter_mut().enumerate() {
+            *byte = (offset / 16) as u8;
+        }
+        memory
+    }
+
+    #[test]
+    fn vram_transfer_data_is_in_tile_order_with_unsigned_addressing() {
+        let data = vram_transfer_data(&memory_with_tile_indices(), true);
+        assert_eq!(data.len(), VRAM_TRANSFER_SIZE);
+        assert_eq!(data[0], 0x00);
+        assert_eq!(data[0x7F * 16], 0x7F);
+        assert_eq!(data[0x80 * 16], 0x80);
+        assert_eq!(data[0xFF * 16], 0xFF);
+    }
+
+    #[test]
+    fn vram_transfer_data_is_in_tile_order_with_signed_addressing() {
+        // With the signed addressing, the tile data at 0x9000 (tile 0x100 of the unsigned
+        // addressing) is the data of tile 0x00
+        let data = vram_transfer_data(&memory_with_tile_indices(), false);
+        assert_eq!(data.len(), VRAM_TRANSFER_SIZE);
+        assert_eq!(data[0], 0x00);
+        assert_eq!(data[0x7F * 16], 0x7F);
+        assert_eq!(data[0x80 * 16], 0x80);
+        assert_eq!(data[0xFF * 16], 0xFF);
+    }
+}
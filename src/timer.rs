@@ -1,123 +1,330 @@
-//! This module contains the [TimerInfo] struct and its methods, which are used to handle the Timer
-//! and Divider registers in the RustBoy. For more information on this, please refer to
+//! This module contains the [TimerRegisters] struct and its methods, which are used to handle the
+//! Timer and Divider registers in the RustBoy. For more information on this, please refer to
 //! [Pan Docs - Timer and Divider Registers](https://gbdev.io/pandocs/Timer_and_Divider_Registers.html)
+//!
+//! The registers are registered on the memory bus as a [MemoryMappedDevice]. Like on the real
+//! hardware, the divider register is the upper byte of an internal counter and the timer counter
+//! is clocked by the falling edges of one of the bits of this counter, see
+//! [TimerRegisters::timer_input].
 
 use crate::interrupts::{Interrupt, InterruptFlagRegister};
-use crate::{M_CYCLES_PER_SECOND, RustBoy};
+use crate::memory_bus::MemoryBus;
+use crate::memory_bus::memory_mapped_device::MemoryMappedDevice;
+use crate::save_state::{StateReader, StateWriter};
+use crate::{RustBoy, RustBoyError};
 
-const DIVIDER_REGISTER_FREQUENCY: u32 = 16_384;
-const M_CYCLES_FOR_DIVIDER_REGISTER_INCREMENT: u32 =
-    M_CYCLES_PER_SECOND / DIVIDER_REGISTER_FREQUENCY;
-const DIVIDER_REGISTER_ADDRESS: usize = 0xFF04;
+const DIVIDER_REGISTER_ADDRESS: u16 = 0xFF04;
 const TIMER_ADDRESS: u16 = 0xFF05;
 const TIMER_MODULO_ADDRESS: u16 = 0xFF06;
 const TIMER_CONTROL_ADDRESS: u16 = 0xFF07;
 
-const TIMER_FREQUENCY_ZERO: u32 = 4_096;
-const TIMER_FREQUENCY_ONE: u32 = 262_144;
-const TIMER_FREQUENCY_TWO: u32 = 65_536;
-const TIMER_FREQUENCY_THREE: u32 = 16_384;
-const TIMER_FREQUENCY_ZERO_IN_M_CYCLES: u32 = M_CYCLES_PER_SECOND / TIMER_FREQUENCY_ZERO;
-const TIMER_FREQUENCY_ONE_IN_M_CYCLES: u32 = M_CYCLES_PER_SECOND / TIMER_FREQUENCY_ONE;
-const TIMER_FREQUENCY_TWO_IN_M_CYCLES: u32 = M_CYCLES_PER_SECOND / TIMER_FREQUENCY_TWO;
-const TIMER_FREQUENCY_THREE_IN_M_CYCLES: u32 = M_CYCLES_PER_SECOND / TIMER_FREQUENCY_THREE;
-
-/// Struct to keep track of the timer and divider registers.
-pub struct TimerInfo {
-    divider_running_m_cycle_counter: u32,
-    timer_running_m_cycle_counter: u32,
+/// The number of t-cycles the internal divider counter is incremented by per m-cycle.
+const T_CYCLES_PER_M_CYCLE: u16 = 4;
+/// The bit of the timer control (TAC 0xFF07) which enables the timer.
+const TIMER_ENABLE_BIT: u8 = 0b100;
+/// The bits of the timer control (TAC 0xFF07) which are used, that is, the enable bit and the two
+/// bits selecting the frequency. The other bits always read as 1, see
+/// `UNUSED_IO_REGISTER_BITS` of the memory bus.
+const TIMER_CONTROL_BITS: u8 = 0b111;
+
+/// Struct to represent the timer and divider registers, which are mapped to 0xFF04 - 0xFF07.
+///
+/// - `divider_counter`: The internal 16-bit divider counter, which is incremented every t-cycle.
+///   The divider register (DIV 0xFF04) holds its upper 8 bits. Writing any value to DIV resets
+///   the whole counter to 0.
+/// - `timer`: The timer counter (TIMA 0xFF05), which is incremented whenever the bit of
+///   `divider_counter` selected by `timer_control` goes from 1 to 0, see
+///   [TimerRegisters::timer_input], and requests a timer interrupt when it overflows.
+/// - `timer_modulo`: The timer modulo (TMA 0xFF06), which the timer counter is reset to when it
+///   overflows.
+/// - `timer_control`: The timer control (TAC 0xFF07), which enables the timer and selects its
+///   frequency. Only its lower 3 bits are stored, see [TIMER_CONTROL_BITS].
+/// - `before_write`: The divider register and the [TimerRegisters::timer_input] before the
+///   registers were written, if they were written since the timer last advanced. Writing DIV or
+///   TAC can cause falling edges, which are handled by the memory bus before the timer advances
+///   next, see [MemoryBus::handle_timer].
+#[derive(Debug, Default)]
+pub(crate) struct TimerRegisters {
+    pub(crate) divider_counter: u16,
+    pub(crate) timer: u8,
+    pub(crate) timer_modulo: u8,
+    pub(crate) timer_control: u8,
+    before_write: Option<(u8, bool)>,
 }
 
-impl TimerInfo {
-    /// Creates a new instance of TimerInfo with the divider and timer cycle counters set to 0.
-    pub fn new() -> TimerInfo {
-        TimerInfo {
-            divider_running_m_cycle_counter: 0,
-            timer_running_m_cycle_counter: 0,
+impl TimerRegisters {
+    /// Returns the values of the registers after the boot ROM ran, see
+    /// [Pan Docs - Power up Sequence](https://gbdev.io/pandocs/Power_Up_Sequence.html#hardware-registers).
+    /// The lower 8 bits of the divider counter are the ones the DMG boot ROM leaves behind.
+    pub(crate) fn after_boot() -> Self {
+        TimerRegisters {
+            divider_counter: 0xABCC,
+            timer: 0x00,
+            timer_modulo: 0x00,
+            timer_control: 0x00,
+            before_write: None,
         }
     }
+
+    /// Returns the divider register (DIV 0xFF04), that is, the upper 8 bits of the internal
+    /// divider counter.
+    pub(crate) fn divider(&self) -> u8 {
+        (self.divider_counter >> 8) as u8
+    }
+
+    /// Returns the input of the timer counter, that is, the bit of the divider counter selected by
+    /// the timer control, if the timer is enabled, and false otherwise. The timer counter is
+    /// incremented whenever this goes from true to false, which happens at the following
+    /// frequencies, if only the divider counter advances:
+    /// - `0b00`: Bit 9, 4096 Hz.
+    /// - `0b01`: Bit 3, 262144 Hz.
+    /// - `0b10`: Bit 5, 65536 Hz.
+    /// - `0b11`: Bit 7, 16384 Hz.
+    ///
+    /// Since resetting the divider or changing the timer control can also make the input go from
+    /// true to false, both can increment the timer counter early, see
+    /// [Pan Docs - Timer obscure behaviour](https://gbdev.io/pandocs/Timer_Obscure_Behaviour.html).
+    pub(crate) fn timer_input(&self) -> bool {
+        let selected_bit = match self.timer_control & 0b11 {
+            0b00 => 9,
+            0b01 => 3,
+            0b10 => 5,
+            _ => 7,
+        };
+        self.timer_control & TIMER_ENABLE_BIT != 0
+            && self.divider_counter & (1 << selected_bit) != 0
+    }
+
+    /// Writes the registers to the provided save state.
+    pub(crate) fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_u16(self.divider_counter);
+        writer.write_u8(self.timer);
+        writer.write_u8(self.timer_modulo);
+        writer.write_u8(self.timer_control);
+    }
+
+    /// Restores the registers written by [TimerRegisters::save_state].
+    pub(crate) fn load_state(&mut self, reader: &mut StateReader) -> Result<(), RustBoyError> {
+        self.divider_counter = reader.read_u16()?;
+        self.timer = reader.read_u8()?;
+        self.timer_modulo = reader.read_u8()?;
+        self.timer_control = reader.read_u8()?;
+        Ok(())
+    }
 }
 
-impl RustBoy {
-    /// Handles the timer and divider registers. This function is called every time the CPU makes
-    /// a step, that is, executes an instruction, to check whether the timer and divider registers
-    /// should be incremented. To do so, the functions [RustBoy::handle_divider] and
-    /// [RustBoy::handle_timer] are called.
-    pub fn handle_timer_and_divider(&mut self, cycles_passed: u32) {
-        self.handle_divider(cycles_passed);
-        self.handle_timer(cycles_passed);
-    }
-
-    /// Handles the incrementing of divider register. This register is incremented at a rate of
-    /// [DIVIDER_REGISTER_FREQUENCY] Hz. This function is called every time the CPU makes
-    /// a step, that is executes an instruction, to check whether the divider register should be
-    /// incremented (converting Hz to CPU cycles, the divider register needs to be incremented every
-    /// [M_CYCLES_FOR_DIVIDER_REGISTER_INCREMENT] cycles).
-    fn handle_divider(&mut self, cycles_passed: u32) {
-        self.timer_info.divider_running_m_cycle_counter += cycles_passed;
-        if self.timer_info.divider_running_m_cycle_counter
-            >= M_CYCLES_FOR_DIVIDER_REGISTER_INCREMENT
-        {
-            self.memory_bus.memory[DIVIDER_REGISTER_ADDRESS] =
-                self.memory_bus.memory[DIVIDER_REGISTER_ADDRESS].wrapping_add(1);
-            self.timer_info.divider_running_m_cycle_counter -=
-                M_CYCLES_FOR_DIVIDER_REGISTER_INCREMENT;
+impl MemoryMappedDevice for TimerRegisters {
+    fn read(&self, address: u16) -> Option<u8> {
+        match address {
+            DIVIDER_REGISTER_ADDRESS => Some(self.divider()),
+            TIMER_ADDRESS => Some(self.timer),
+            TIMER_MODULO_ADDRESS => Some(self.timer_modulo),
+            TIMER_CONTROL_ADDRESS => Some(self.timer_control),
+            _ => None,
         }
     }
 
-    /// Handles the incrementing of the timer register at [TIMER_ADDRESS]. This register is
-    /// incremented at the rate configured by the [TIMER_CONTROL_ADDRESS]. For more information, see
+    fn write(&mut self, address: u16, value: u8) -> bool {
+        let before_write = (self.divider(), self.timer_input());
+        match address {
+            // When a write happens to the divider register, the whole internal counter is reset,
+            // regardless of the written value
+            DIVIDER_REGISTER_ADDRESS => self.divider_counter = 0,
+            TIMER_ADDRESS => self.timer = value,
+            TIMER_MODULO_ADDRESS => self.timer_modulo = value,
+            TIMER_CONTROL_ADDRESS => self.timer_control = value & TIMER_CONTROL_BITS,
+            _ => return false,
+        }
+        // Writing the divider register resets it and writing the timer control can change the
+        // selected bit of it, which can clock the frame sequencer of the APU and the timer. If
+        // the registers are written multiple times, the state before the first write counts
+        self.before_write.get_or_insert(before_write);
+        true
+    }
+}
+
+impl MemoryBus {
+    /// Advances the internal divider counter by the provided number of m-cycles. This function is
+    /// called every time the CPU makes a step, that is, executes an instruction. The counter is
+    /// advanced one m-cycle at a time, so each falling edge of the divider and the timer input is
+    /// handled, see [MemoryBus::handle_timer_change]. For more information, see
     /// https://gbdev.io/pandocs/Timer_and_Divider_Registers.html#timer-and-divider-registers
-    fn handle_timer(&mut self, cycles_passed: u32) {
-        if self.is_timer_enabled() {
-            self.timer_info.timer_running_m_cycle_counter += cycles_passed;
-            let timer_frequency_in_m_cycles = self.get_timer_frequency_in_m_cycles();
-            if self.timer_info.timer_running_m_cycle_counter >= timer_frequency_in_m_cycles {
-                self.increment_timer();
-                self.timer_info.timer_running_m_cycle_counter -= timer_frequency_in_m_cycles;
-            }
+    ///
+    /// The falling edges caused by writing the registers are handled first. The CPU writes them
+    /// in the last m-cycle of an instruction and the timer is advanced by this m-cycle afterward,
+    /// so they are handled before the next instruction, like on the real hardware.
+    pub(crate) fn handle_timer(&mut self, m_cycles: u32) {
+        if let Some((old_divider, old_timer_input)) = self.timer_registers.before_write.take() {
+            self.handle_timer_change(old_divider, old_timer_input);
+        }
+        for _ in 0..m_cycles {
+            let old_divider = self.timer_registers.divider();
+            let old_timer_input = self.timer_registers.timer_input();
+            self.timer_registers.divider_counter = self
+                .timer_registers
+                .divider_counter
+                .wrapping_add(T_CYCLES_PER_M_CYCLE);
+            self.handle_timer_change(old_divider, old_timer_input);
+        }
+    }
+
+    /// Handles a change of the internal divider counter or the timer control, either because the
+    /// divider counter advanced or because DIV or TAC were written. `old_divider` and
+    /// `old_timer_input` are the divider register and the [TimerRegisters::timer_input] before
+    /// the change. A falling edge of the divider clocks the frame sequencer of the APU and a
+    /// falling edge of the timer input increments the timer counter.
+    fn handle_timer_change(&mut self, old_divider: u8, old_timer_input: bool) {
+        self.handle_divider_change(old_divider, self.timer_registers.divider());
+        if old_timer_input && !self.timer_registers.timer_input() {
+            self.increment_timer();
         }
     }
 
     /// Increment the timer register and handle an overflow by setting the timer to the value
     /// provided in the [TIMER_MODULO_ADDRESS].
     fn increment_timer(&mut self) {
-        let current_timer_value = self.memory_bus.read_byte(TIMER_ADDRESS);
+        let current_timer_value = self.timer_registers.timer;
         // Check if overflow is imminent
         if current_timer_value == 0xFF {
             // TODO: Possibly handle case, where TIMER MODULE REGISTER is edited in same m-cycle
             // as this happens and then old value is supposed to be used, see:
             // https://gbdev.io/pandocs/Timer_and_Divider_Registers.html#ff06--tma-timer-modulo
-            self.memory_bus
-                .write_byte(TIMER_ADDRESS, self.get_timer_wraparound_value());
+            self.timer_registers.timer = self.timer_registers.timer_modulo;
             // Request a timer interrupt
-            InterruptFlagRegister::set_flag(&mut self.memory_bus, Interrupt::Timer, true);
+            InterruptFlagRegister::set_flag(self, Interrupt::Timer, true);
         } else {
-            self.memory_bus
-                .write_byte(TIMER_ADDRESS, current_timer_value.wrapping_add(1));
+            self.timer_registers.timer = current_timer_value.wrapping_add(1);
         }
     }
+}
+
+impl RustBoy {
+    /// Returns the internal 16-bit divider counter, which is incremented every t-cycle. The
+    /// divider register (DIV 0xFF04) holds its upper 8 bits.
+    pub fn divider_counter(&self) -> u16 {
+        self.memory_bus.timer_registers.divider_counter
+    }
 
-    /// Checks the timer control for whether the timer enabled bit is set and returns the result
-    fn is_timer_enabled(&self) -> bool {
-        self.memory_bus.read_byte(TIMER_CONTROL_ADDRESS) & 0b100 != 0
+    /// Overrides the internal 16-bit divider counter, see [RustBoy::divider_counter]. The timer
+    /// counter is not incremented, even if the selected bit of the counter goes from 1 to 0.
+    ///
+    /// Many games seed their random number generator from DIV at the moment a button is pressed.
+    /// Setting the divider at a fixed frame (that is, between two calls of [RustBoy::run_frame])
+    /// makes such runs reproducible, e.g. for tool-assisted runs or tests. This is only meant for
+    /// testing and debugging, since the real hardware offers no way of doing this.
+    pub fn set_divider_counter(&mut self, value: u16) {
+        self.memory_bus.timer_registers.divider_counter = value;
     }
+}
 
-    /// Checks the timer control for which timer frequency is selected and returns the frequency in
-    /// #M-Cycles per Increment
-    fn get_timer_frequency_in_m_cycles(&self) -> u32 {
-        match self.memory_bus.read_byte(TIMER_CONTROL_ADDRESS) & 0b11 {
-            0b00 => TIMER_FREQUENCY_ZERO_IN_M_CYCLES,
-            0b01 => TIMER_FREQUENCY_ONE_IN_M_CYCLES,
-            0b10 => TIMER_FREQUENCY_TWO_IN_M_CYCLES,
-            0b11 => TIMER_FREQUENCY_THREE_IN_M_CYCLES,
-            _ => unreachable!(),
-        }
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::debugging::DebugInfo;
+    use crate::tests::rust_boy_with_program;
+
+    /// Returns a memory bus with the provided internal divider counter and timer control.
+    fn memory_bus_with_timer(divider_counter: u16, timer_control: u8) -> MemoryBus {
+        let mut memory_bus = MemoryBus::new_before_boot(&DebugInfo::default());
+        memory_bus.being_initialized = false;
+        memory_bus.timer_registers.divider_counter = divider_counter;
+        memory_bus.timer_registers.timer_control = timer_control;
+        memory_bus
+    }
+
+    #[test]
+    fn writing_the_divider_register_resets_the_whole_counter() {
+        let mut memory_bus = memory_bus_with_timer(0xABCC, 0x00);
+        memory_bus.write_byte(DIVIDER_REGISTER_ADDRESS, 0x42);
+        assert_eq!(memory_bus.timer_registers.divider_counter, 0);
+        assert_eq!(memory_bus.read_byte(DIVIDER_REGISTER_ADDRESS), 0);
+        // The lower 8 bits of the counter are reset as well, so it takes a full 64 m-cycles until
+        // DIV is incremented
+        memory_bus.handle_timer(63);
+        assert_eq!(memory_bus.read_byte(DIVIDER_REGISTER_ADDRESS), 0);
+        memory_bus.handle_timer(1);
+        assert_eq!(memory_bus.read_byte(DIVIDER_REGISTER_ADDRESS), 1);
+    }
+
+    #[test]
+    fn timer_is_incremented_on_falling_edges_of_the_selected_bit() {
+        // Bit 3 of the divider counter is selected, which falls every 4 m-cycles
+        let mut memory_bus = memory_bus_with_timer(0x0000, 0b101);
+        memory_bus.handle_timer(3);
+        assert_eq!(memory_bus.timer_registers.timer, 0);
+        memory_bus.handle_timer(1);
+        assert_eq!(memory_bus.timer_registers.timer, 1);
+        memory_bus.handle_timer(8);
+        assert_eq!(memory_bus.timer_registers.timer, 3);
+    }
+
+    #[test]
+    fn resetting_the_divider_while_the_selected_bit_is_set_increments_the_timer() {
+        let mut memory_bus = memory_bus_with_timer(0b1000, 0b101);
+        memory_bus.write_byte(DIVIDER_REGISTER_ADDRESS, 0x00);
+        memory_bus.handle_timer(0);
+        assert_eq!(memory_bus.timer_registers.timer, 1);
+    }
+
+    #[test]
+    fn changing_the_timer_control_can_increment_the_timer() {
+        // Bit 9 is set and selected, bit 3 is not set
+        let mut memory_bus = memory_bus_with_timer(0x0200, 0b100);
+        memory_bus.write_byte(TIMER_CONTROL_ADDRESS, 0b101);
+        memory_bus.handle_timer(0);
+        assert_eq!(memory_bus.timer_registers.timer, 1);
+
+        // Disabling the timer while the selected bit is set increments it as well
+        let mut memory_bus = memory_bus_with_timer(0x0200, 0b100);
+        memory_bus.write_byte(TIMER_CONTROL_ADDRESS, 0b000);
+        memory_bus.handle_timer(0);
+        assert_eq!(memory_bus.timer_registers.timer, 1);
+
+        // Selecting another bit which is set does not
+        let mut memory_bus = memory_bus_with_timer(0x0208, 0b100);
+        memory_bus.write_byte(TIMER_CONTROL_ADDRESS, 0b101);
+        memory_bus.handle_timer(0);
+        assert_eq!(memory_bus.timer_registers.timer, 0);
+    }
+
+    #[test]
+    fn timer_overflow_reloads_the_modulo_and_requests_an_interrupt() {
+        let mut memory_bus = memory_bus_with_timer(0x0000, 0b101);
+        memory_bus.timer_registers.timer = 0xFF;
+        memory_bus.timer_registers.timer_modulo = 0x42;
+        memory_bus.handle_timer(4);
+        assert_eq!(memory_bus.timer_registers.timer, 0x42);
+        assert!(InterruptFlagRegister::get_flag(
+            &memory_bus,
+            Interrupt::Timer
+        ));
+    }
+
+    #[test]
+    fn overriding_the_divider_counter_makes_div_reads_reproducible() {
+        // LDH A, [DIV], twice
+        let mut rust_boy = rust_boy_with_program(&[0xF0, 0x04, 0xF0, 0x04]);
+        rust_boy.set_divider_counter(0x42F0);
+        assert_eq!(rust_boy.divider_counter(), 0x42F0);
+        rust_boy.step().expect("LDH should be executed");
+        assert_eq!(rust_boy.cpu.registers.a, 0x42);
+        // LDH takes 3 m-cycles
+        assert_eq!(rust_boy.divider_counter(), 0x42F0 + 3 * 4);
+
+        rust_boy.set_divider_counter(0x1234);
+        rust_boy.step().expect("LDH should be executed");
+        assert_eq!(rust_boy.cpu.registers.a, 0x12);
     }
 
-    /// Checks the timer modulo address [TIMER_MODULO_ADDRESS] to determine the value the timer should reset to when it
-    /// wraps around.
-    fn get_timer_wraparound_value(&self) -> u8 {
-        self.memory_bus.read_byte(TIMER_MODULO_ADDRESS)
+    #[test]
+    fn overriding_the_divider_counter_does_not_increment_the_timer() {
+        let mut rust_boy = rust_boy_with_program(&[]);
+        // Bit 3 of the divider counter is selected
+        rust_boy.memory_bus.write_byte(TIMER_CONTROL_ADDRESS, 0b101);
+        rust_boy.set_divider_counter(0x0008);
+        rust_boy.set_divider_counter(0x0000);
+        rust_boy.step().expect("NOP should be executed");
+        assert_eq!(rust_boy.memory_bus.timer_registers.timer, 0);
+        assert_eq!(rust_boy.divider_counter(), 4);
     }
 }
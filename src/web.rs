@@ -0,0 +1,65 @@
+//! This module contains the functions the web build exports to JavaScript in addition to
+//! [crate::run], which let the web frontend save and load states, e.g. to store them in the
+//! browser. Since the event loop started by [crate::run] never returns on the web, the RustBoy it
+//! runs is shared with these functions, see [share_rust_boy].
+//!
+//! The states are passed as `Uint8Array`s in the same versioned format as the save state slots,
+//! see [crate::save_state]. For example, to store a state in the local storage of the browser:
+//! ```js
+//! import {run, saveState, loadState} from './pkg/rustboy.js';
+//!
+//! const state = saveState();
+//! localStorage.setItem("state", btoa(String.fromCharCode(...state)));
+//!
+//! const storedState = localStorage.getItem("state");
+//! loadState(Uint8Array.from(atob(storedState), (char) => char.charCodeAt(0)));
+//! ```
+//! Both functions throw an error, if no ROM is running yet. `loadState` also throws, if the state
+//! is invalid or belongs to another cartridge, in which case the running game is left unchanged.
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use wasm_bindgen::prelude::*;
+
+use crate::RustBoy;
+
+thread_local! {
+    /// The RustBoy run by the event loop, if a ROM was started. The event loop runs on the same
+    /// (only) thread as JavaScript and only borrows it while handling an event, so it is never
+    /// borrowed while one of the exported functions is called.
+    static RUNNING_RUST_BOY: RefCell<Option<Rc<RefCell<RustBoy>>>> = const { RefCell::new(None) };
+}
+
+/// Shares the provided RustBoy with the exported functions. Returns the handle the event loop
+/// runs the RustBoy with. If a ROM was started before, its RustBoy is replaced.
+pub(crate) fn share_rust_boy(rust_boy: RustBoy) -> Rc<RefCell<RustBoy>> {
+    let rust_boy = Rc::new(RefCell::new(rust_boy));
+    RUNNING_RUST_BOY.with_borrow_mut(|running_rust_boy| {
+        *running_rust_boy = Some(Rc::clone(&rust_boy));
+    });
+    rust_boy
+}
+
+/// Calls the provided function with the running RustBoy. Returns an error, if no ROM was started.
+fn with_running_rust_boy<T>(function: impl FnOnce(&mut RustBoy) -> T) -> Result<T, JsError> {
+    RUNNING_RUST_BOY.with_borrow(|running_rust_boy| match running_rust_boy {
+        Some(rust_boy) => Ok(function(&mut rust_boy.borrow_mut())),
+        None => Err(JsError::new("No ROM is running")),
+    })
+}
+
+/// Returns the state of the running RustBoy as a `Uint8Array`, see
+/// [RustBoy::save_state_to_bytes].
+#[wasm_bindgen(js_name = saveState)]
+pub fn save_state() -> Result<Vec<u8>, JsError> {
+    with_running_rust_boy(|rust_boy| rust_boy.save_state_to_bytes())
+}
+
+/// Restores the state of the running RustBoy from the provided `Uint8Array`, see
+/// [RustBoy::load_state_from_bytes].
+#[wasm_bindgen(js_name = loadState)]
+pub fn load_state(data: &[u8]) -> Result<(), JsError> {
+    with_running_rust_boy(|rust_boy| rust_boy.load_state_from_bytes(data))?
+        .map_err(|error| JsError::new(&error.to_string()))
+}